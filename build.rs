@@ -0,0 +1,8 @@
+/* pass the build configuration cargo only tells build scripts on to the crate, for buildinfo.rs */
+fn main() {
+    for variable in ["TARGET", "PROFILE", "OPT_LEVEL"] {
+        let value = std::env::var(variable).unwrap_or_else(|_| "unknown".to_string());
+        println!("cargo:rustc-env=BUILD_{}={}", variable, value);
+    }
+    println!("cargo:rerun-if-changed=build.rs");
+}
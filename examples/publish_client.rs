@@ -0,0 +1,17 @@
+// Minimal client for the `--publish unix:<path>` option of the `new` subcommand.
+// Run with: cargo run --example publish_client -- /tmp/lgt.sock
+use std::io::{BufRead, BufReader};
+use std::os::unix::net::UnixStream;
+
+fn main() -> std::io::Result<()> {
+    let path = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| "/tmp/lgt.sock".to_string());
+    let stream = UnixStream::connect(&path)?;
+
+    for line in BufReader::new(stream).lines() {
+        println!("{}", line?);
+    }
+
+    Ok(())
+}
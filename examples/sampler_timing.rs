@@ -0,0 +1,50 @@
+// Time the elementary functions of the heatbath rejection sampler, exact against the fast-math
+// approximations, and the sampler itself as built. Build once with and once without
+// `--features fast-math` to see what the feature gains for a given beta range.
+// Run with: cargo run --release --example sampler_timing [--features fast-math] -- <draws>
+#![allow(clippy::needless_return)]
+use fastrand::Rng;
+use lattice_rust::approx::{fast_cos, fast_exp};
+use lattice_rust::lattice::sample_theta;
+use std::f64::consts::PI;
+use std::hint::black_box;
+use std::time::Instant;
+
+/* nanoseconds per call of f over the arguments */
+fn time_per_call(arguments: &[f64], f: impl Fn(f64) -> f64) -> f64 {
+    let start = Instant::now();
+    let mut sum = 0f64;
+    for &x in arguments {
+        sum += f(black_box(x));
+    }
+    black_box(sum);
+    return start.elapsed().as_secs_f64() * 1e9 / arguments.len() as f64;
+}
+
+fn main() {
+    let draws = std::env::args().nth(1).map_or(1_000_000, |arg| arg.parse().expect("draws must be an integer"));
+    let mut rng = Rng::with_seed(1);
+
+    /* the argument ranges of acceptance_probability */
+    let angles: Vec<f64> = (0..draws).map(|_| PI / 2.0 * rng.f64()).collect();
+    let exponents: Vec<f64> = (0..draws).map(|_| -20.0 * rng.f64()).collect();
+    println!("{:>10} {:>12} {:>12}", "function", "exact ns", "fast ns");
+    println!("{:>10} {:>12.2} {:>12.2}", "cos", time_per_call(&angles, f64::cos), time_per_call(&angles, fast_cos));
+    println!("{:>10} {:>12.2} {:>12.2}", "exp", time_per_call(&exponents, f64::exp), time_per_call(&exponents, fast_exp));
+
+    let variant = if cfg!(feature = "fast-math") { "fast-math" } else { "exact" };
+    println!("sample_theta with the {} functions:", variant);
+    for prefactor in [0.1, 0.5, 1.0, 2.0, 5.0, 10.0, 19.0] {
+        let start = Instant::now();
+        let mut sum = 0f64;
+        for _ in 0..draws {
+            sum += sample_theta(black_box(prefactor), 1.0, &mut rng);
+        }
+        black_box(sum);
+        println!(
+            "{:>10} {:>12.2} ns per draw",
+            prefactor,
+            start.elapsed().as_secs_f64() * 1e9 / draws as f64
+        );
+    }
+}
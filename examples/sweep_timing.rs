@@ -0,0 +1,31 @@
+// Time heatbath sweeps on a random start.
+// Run with: cargo run --release --example sweep_timing -- <width> <sweeps> <beta>
+use fastrand::Rng;
+use lattice_rust::action::Couplings;
+use lattice_rust::Lattice;
+use std::time::Instant;
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let width = args.next().map_or(12, |arg| arg.parse().expect("width must be an integer"));
+    let sweeps = args.next().map_or(10, |arg| arg.parse().expect("sweeps must be an integer"));
+    let beta = args.next().map_or(1.0, |arg| arg.parse().expect("beta must be a number"));
+
+    let mut rng = Rng::with_seed(1);
+    let mut lattice = Lattice::new_random(width, &mut rng);
+
+    let start = Instant::now();
+    for _ in 0..sweeps {
+        lattice.heatbath_sweep(Couplings::isotropic(beta), &mut rng);
+    }
+    let elapsed = start.elapsed();
+
+    println!(
+        "{}^4 at beta {}: {:.2} ms per sweep, {:.1} ns per link, average action {}",
+        width,
+        beta,
+        elapsed.as_secs_f64() * 1e3 / sweeps as f64,
+        elapsed.as_secs_f64() * 1e9 / (sweeps * 4 * width.pow(4)) as f64,
+        lattice.average_action()
+    );
+}
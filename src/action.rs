@@ -0,0 +1,224 @@
+use crate::lattice::{Lattice, Site, PLANES, TIME_DIRECTION};
+use crate::scalar::ScalarField;
+use num_complex::Complex;
+
+/* local environment of a single link: its conditional weight is
+ * exp(coupling * Re(e^{i theta} staple) + double_coupling * Re(e^{2 i theta} double_staple)) with
+ * everything else held fixed */
+#[derive(Copy, Clone, Debug)]
+pub struct LinkEnvironment {
+    pub staple: Complex<f64>,
+    pub coupling: f64,
+    pub double_staple: Complex<f64>,
+    pub double_coupling: f64,
+    /* the unweighted staple of the plaquettes through the link where the environment has it, for
+     * the tracked action of the lattice */
+    pub plaquette_staple: Option<Complex<f64>>,
+}
+
+/* an action given both as the local environment used by the updates and as the global
+ * action used by measurements and accept/reject steps, the two must describe the same weight
+ * exp(-total_action) */
+pub trait LocalAction: Sync {
+    fn link_environment(&self, lattice: &Lattice, site: Site, direction: usize) -> LinkEnvironment;
+
+    fn total_action(&self, lattice: &Lattice) -> f64;
+}
+
+/* couplings of the spatial plaquettes and of the temporal ones, whose planes contain the time
+ * direction. Equal couplings are the isotropic Wilson action */
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Couplings {
+    pub spatial: f64,
+    pub temporal: f64,
+}
+
+impl Couplings {
+    pub fn isotropic(beta: f64) -> Self {
+        return Self { spatial: beta, temporal: beta };
+    }
+
+    pub fn is_isotropic(&self) -> bool {
+        return self.spatial == self.temporal;
+    }
+
+    /* coupling of the plaquettes in the (mu, nu) plane */
+    pub fn plane(&self, mu: usize, nu: usize) -> f64 {
+        if mu == TIME_DIRECTION || nu == TIME_DIRECTION {
+            return self.temporal;
+        }
+        return self.spatial;
+    }
+
+    /* the largest coupling of the planes through a link in direction mu, and the coupling of every
+     * plane (mu, nu) relative to it. The link environment folds the relative couplings into the
+     * staple and keeps the largest one as its coupling, so that with equal couplings every weight
+     * is exactly 1 and the staple is the unweighted one bit for bit */
+    pub fn link_weights(&self, mu: usize) -> (f64, [f64; 4]) {
+        let largest = (0..4).filter(|&nu| nu != mu).map(|nu| self.plane(mu, nu)).fold(0.0, f64::max);
+        let weights = [0, 1, 2, 3].map(|nu| if largest == 0.0 { 1.0 } else { self.plane(mu, nu) / largest });
+        return (largest, weights);
+    }
+
+    /* sum_P beta_P (1 - cos(theta_P)) over all plaquettes */
+    pub fn wilson_action(&self, lattice: &Lattice) -> f64 {
+        if self.is_isotropic() {
+            let num_plaquettes = (6 * lattice.volume()) as f64;
+            return self.spatial * num_plaquettes * lattice.average_action();
+        }
+        let by_plane = lattice.average_action_by_plane();
+        let sum: f64 = PLANES.iter().map(|&(m, n)| self.plane(m, n) * by_plane[m][n]).sum();
+        return sum * lattice.volume() as f64;
+    }
+}
+
+/* the plain Wilson action sum_P beta_P (1 - cos(theta_P)), beta_P the spatial or temporal coupling */
+pub struct WilsonAction {
+    pub couplings: Couplings,
+}
+
+impl WilsonAction {
+    pub fn isotropic(beta: f64) -> Self {
+        return Self { couplings: Couplings::isotropic(beta) };
+    }
+}
+
+/* environment of the link U_mu(n) under the Wilson action with the given couplings */
+fn wilson_environment(couplings: &Couplings, lattice: &Lattice, site: Site, direction: usize) -> LinkEnvironment {
+    let (coupling, weights) = couplings.link_weights(direction);
+    let [i, j, k, l] = site.coords();
+    let staple = lattice.weighted_plaquettes_without_link(i, j, k, l, direction, weights);
+    return LinkEnvironment {
+        staple,
+        coupling,
+        double_staple: Complex::new(0.0, 0.0),
+        double_coupling: 0.0,
+        plaquette_staple: couplings.is_isotropic().then_some(staple),
+    };
+}
+
+impl LocalAction for WilsonAction {
+    fn link_environment(&self, lattice: &Lattice, site: Site, direction: usize) -> LinkEnvironment {
+        return wilson_environment(&self.couplings, lattice, site, direction);
+    }
+
+    fn total_action(&self, lattice: &Lattice) -> f64 {
+        return self.couplings.wilson_action(lattice);
+    }
+}
+
+/* the extended U(1) action sum_P beta_P (1 - cos(theta_P)) + gamma * sum_P (1 - cos(2 theta_P))
+ * with a double charge plaquette term, the Wilson action at gamma = 0 */
+pub struct ExtendedAction {
+    pub couplings: Couplings,
+    pub gamma: f64,
+}
+
+impl LocalAction for ExtendedAction {
+    fn link_environment(&self, lattice: &Lattice, site: Site, direction: usize) -> LinkEnvironment {
+        let double_staple = if self.gamma == 0.0 {
+            Complex::new(0.0, 0.0)
+        } else {
+            let [i, j, k, l] = site.coords();
+            lattice.charged_plaquettes_without_link(i, j, k, l, direction, 2.0)
+        };
+
+        return LinkEnvironment {
+            double_staple,
+            double_coupling: self.gamma,
+            ..wilson_environment(&self.couplings, lattice, site, direction)
+        };
+    }
+
+    fn total_action(&self, lattice: &Lattice) -> f64 {
+        let num_plaquettes = (6 * lattice.volume()) as f64;
+        let wilson = self.couplings.wilson_action(lattice);
+        return wilson + self.gamma * num_plaquettes * lattice.average_double_action();
+    }
+}
+
+/* the Wilson action plus the hopping term -kappa * sum_{n, mu} cos(phi(n) + theta_mu(n) - phi(n + mu))
+ * of a scalar field, which enters the link staple weighted by kappa / beta */
+pub struct HiggsAction<'a> {
+    pub beta: f64,
+    pub kappa: f64,
+    pub field: &'a ScalarField,
+}
+
+impl LocalAction for HiggsAction<'_> {
+    fn link_environment(&self, lattice: &Lattice, site: Site, direction: usize) -> LinkEnvironment {
+        let plaquette_staple = lattice.staple_sum(site, direction);
+        let staple = plaquette_staple
+            + Complex::from_polar(
+                self.kappa / self.beta,
+                self.field.link_term_phase(lattice, lattice.position(site), direction),
+            );
+
+        return LinkEnvironment {
+            staple,
+            coupling: self.beta,
+            double_staple: Complex::new(0.0, 0.0),
+            double_coupling: 0.0,
+            plaquette_staple: Some(plaquette_staple),
+        };
+    }
+
+    fn total_action(&self, lattice: &Lattice) -> f64 {
+        let num_links = (4 * lattice.volume()) as f64;
+        let wilson = WilsonAction::isotropic(self.beta).total_action(lattice);
+        return wilson - self.kappa * num_links * self.field.average_hopping(lattice);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fastrand::Rng;
+
+    /* the derivative of the total action with respect to the phase of one link by central
+     * differences, against the derivative coupling * Im(e^{i theta} staple)
+     * + 2 double_coupling * Im(e^{2 i theta} double_staple) of the environment, and the same for the
+     * plaquette staple against the plain plaquette action */
+    fn check_environment<A: LocalAction>(action: &A, lattice: &Lattice, rng: &mut Rng) {
+        const STEP: f64 = 1e-5;
+        let phases = lattice.to_array();
+        let with_phase = |index: usize, theta: f64| {
+            let mut shifted = phases.clone();
+            shifted[index] = theta;
+            Lattice::from_array_dims(lattice.dims(), &shifted).unwrap()
+        };
+
+        for _ in 0..20 {
+            let (site, direction) = (lattice.site(rng.usize(0..lattice.volume())), rng.usize(0..4));
+            let index = 4 * lattice.position(site) + direction;
+            let theta = phases[index];
+            let environment = action.link_environment(lattice, site, direction);
+
+            let (up, down) = (with_phase(index, theta + STEP), with_phase(index, theta - STEP));
+            let numerical = (action.total_action(&up) - action.total_action(&down)) / (2.0 * STEP);
+            let expected = environment.coupling * (Complex::from_polar(1.0, theta) * environment.staple).im
+                + 2.0 * environment.double_coupling * (Complex::from_polar(1.0, 2.0 * theta) * environment.double_staple).im;
+            assert!((numerical - expected).abs() < 1e-6 * (1.0 + expected.abs()), "{} vs {}", numerical, expected);
+
+            if let Some(plaquette_staple) = environment.plaquette_staple {
+                let numerical = (up.total_action() - down.total_action()) / (2.0 * STEP);
+                let expected = (Complex::from_polar(1.0, theta) * plaquette_staple).im;
+                assert!((numerical - expected).abs() < 1e-6 * (1.0 + expected.abs()), "{} vs {}", numerical, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn every_action_agrees_with_its_link_environment() {
+        let mut rng = Rng::with_seed(31);
+        let lattice = Lattice::new_random_dims([4, 2, 4, 2], &mut rng);
+        let field = ScalarField::new_random(lattice.dims(), &mut rng);
+        let anisotropic = Couplings { spatial: 0.8, temporal: 1.7 };
+
+        check_environment(&WilsonAction::isotropic(1.3), &lattice, &mut rng);
+        check_environment(&WilsonAction { couplings: anisotropic }, &lattice, &mut rng);
+        check_environment(&ExtendedAction { couplings: Couplings::isotropic(1.1), gamma: -0.3 }, &lattice, &mut rng);
+        check_environment(&ExtendedAction { couplings: anisotropic, gamma: 0.4 }, &lattice, &mut rng);
+        check_environment(&HiggsAction { beta: 0.9, kappa: 0.6, field: &field }, &lattice, &mut rng);
+    }
+}
@@ -0,0 +1,327 @@
+use rayon::prelude::*;
+
+/* window factor of the automatic windowing procedure, the sum is cut at the smallest W >= c tau_int(W) */
+const WINDOW_FACTOR: f64 = 6.0;
+
+/* terms per chunk of the parallel sums. The chunks are fixed, not chosen per thread, and their
+ * partial sums are added in order, so a sum does not depend on the number of threads. Series up to
+ * one chunk are summed exactly like a serial loop, longer ones differ from it only by rounding */
+const SUM_CHUNK: usize = 1 << 16;
+
+/* sum of term(index) over 0..len, the chunks are summed in parallel */
+fn chunked_sum<F: Fn(usize) -> f64 + Sync>(len: usize, term: F) -> f64 {
+    let partial_sums: Vec<f64> = (0..len.div_ceil(SUM_CHUNK))
+        .into_par_iter()
+        .map(|chunk| (chunk * SUM_CHUNK..len.min((chunk + 1) * SUM_CHUNK)).map(&term).sum::<f64>())
+        .collect();
+    return partial_sums.iter().sum();
+}
+
+pub fn mean(series: &[f64]) -> f64 {
+    return chunked_sum(series.len(), |index| series[index]) / series.len() as f64;
+}
+
+/* true if every value is identical, rounding in the mean would otherwise turn such a series into
+ * tiny nonzero fluctuations */
+fn is_constant(series: &[f64]) -> bool {
+    return series.iter().all(|x| *x == series[0]);
+}
+
+/* unbiased sample variance, exactly zero for a constant series */
+pub fn variance(series: &[f64]) -> f64 {
+    if is_constant(series) {
+        return 0.0;
+    }
+    let mean = mean(series);
+    return chunked_sum(series.len(), |index| (series[index] - mean).powi(2)) / (series.len() - 1) as f64;
+}
+
+/* susceptibility volume * (<x^2> - <x>^2) of a series of volume averages, e.g. of |P| with the
+ * spatial volume for the Polyakov loop */
+pub fn susceptibility(series: &[f64], volume: f64) -> f64 {
+    let mean_square = chunked_sum(series.len(), |index| series[index] * series[index]) / series.len() as f64;
+    return volume * (mean_square - mean(series).powi(2));
+}
+
+/* result of the automatic windowing procedure */
+pub struct Autocorrelation {
+    pub tau: f64,
+    pub error: f64,
+    /* last lag included in the sum */
+    pub window: usize,
+    /* normalized autocorrelation function rho(t) for t = 0..=window */
+    pub rho: Vec<f64>,
+}
+
+impl Autocorrelation {
+    /* number of independent measurements the series is worth, N / (2 tau_int) */
+    pub fn effective_samples(&self, len: usize) -> f64 {
+        return len as f64 / (2.0 * self.tau);
+    }
+}
+
+/* integrated autocorrelation time tau_int = 1/2 + sum_t rho(t) and its error, with the window
+ * chosen self-consistently (Madras-Sokal), the error is tau_int sqrt(2 (2W + 1) / N). None for
+ * a series without fluctuations, where the normalized autocorrelation is undefined */
+pub fn autocorrelation(series: &[f64]) -> Option<Autocorrelation> {
+    if is_constant(series) {
+        return None;
+    }
+
+    let n = series.len();
+    let mean = mean(series);
+    /* the chunks split the products, each one reads across its end as far as the lag needs */
+    let autocovariance = |t: usize| {
+        chunked_sum(n - t, |index| (series[index] - mean) * (series[index + t] - mean)) / (n - t) as f64
+    };
+
+    let c0 = autocovariance(0);
+
+    let mut rho = vec![1.0];
+    let mut tau = 0.5;
+    let mut window = 0;
+    for t in 1..n {
+        rho.push(autocovariance(t) / c0);
+        tau += rho[t];
+        window = t;
+        if t as f64 >= WINDOW_FACTOR * tau {
+            break;
+        }
+    }
+
+    let error = tau * (2.0 * (2 * window + 1) as f64 / n as f64).sqrt();
+    return Some(Autocorrelation {
+        tau,
+        error,
+        window,
+        rho,
+    });
+}
+
+/* tau_int and its error, see autocorrelation */
+pub fn integrated_autocorrelation(series: &[f64]) -> Option<(f64, f64)> {
+    return autocorrelation(series).map(|result| (result.tau, result.error));
+}
+
+/* standard error of the mean assuming independent measurements, None for fewer than two */
+pub fn naive_error(series: &[f64]) -> Option<f64> {
+    if series.len() < 2 {
+        return None;
+    }
+    return Some((variance(series) / series.len() as f64).sqrt());
+}
+
+/* jackknife error of the mean with the series cut into bins of bin_size consecutive measurements,
+ * a remainder that does not fill a bin is dropped. None for fewer than two bins, exactly zero for a
+ * constant series */
+pub fn jackknife_error(series: &[f64], bin_size: usize) -> Option<f64> {
+    if bin_size == 0 || series.len() / bin_size < 2 {
+        return None;
+    }
+    let num_bins = series.len() / bin_size;
+    let used = &series[..num_bins * bin_size];
+    if is_constant(used) {
+        return Some(0.0);
+    }
+
+    let bin_sums: Vec<f64> = used
+        .chunks_exact(bin_size)
+        .map(|bin| bin.iter().sum())
+        .collect();
+    let total: f64 = bin_sums.iter().sum();
+    let samples = ((num_bins - 1) * bin_size) as f64;
+    let estimates: Vec<f64> = bin_sums.iter().map(|sum| (total - sum) / samples).collect();
+    let estimate_mean = estimates.iter().sum::<f64>() / num_bins as f64;
+    let spread: f64 = estimates.iter().map(|estimate| (estimate - estimate_mean).powi(2)).sum();
+
+    return Some(((num_bins - 1) as f64 / num_bins as f64 * spread).sqrt());
+}
+
+/* bins the jackknife error is estimated with at the largest bin size */
+pub const MIN_JACKKNIFE_BINS: usize = 8;
+
+/* powers of two up to the bin size that still leaves MIN_JACKKNIFE_BINS bins, at least bin size
+ * one for any series with two measurements */
+pub fn bin_sizes(len: usize) -> Vec<usize> {
+    let mut sizes = Vec::new();
+    let mut size = 1;
+    while len / size >= MIN_JACKKNIFE_BINS || (size == 1 && len >= 2) {
+        sizes.push(size);
+        size *= 2;
+    }
+    return sizes;
+}
+
+/* jackknife errors of the mean of a table of measurements x regions, see two_way_jackknife */
+#[derive(Clone, Copy, Debug)]
+pub struct TwoWayJackknife {
+    /* over bins of consecutive measurements of the region averaged series */
+    pub time: f64,
+    /* over the regions of the time averaged series */
+    pub region: f64,
+}
+
+impl TwoWayJackknife {
+    /* both errors added in quadrature. The fluctuations of single cells enter both, so this errs on
+     * the large side by about the naive error of the whole table */
+    pub fn error(&self) -> f64 {
+        return self.time.hypot(self.region);
+    }
+}
+
+/* two-way (time x region) jackknife of the mean of a table stored row by row with num_regions
+ * equal volume regions per measurement, like the region plaquette averages. Both jackknives use
+ * the same measurements, a remainder that does not fill a bin is dropped. None for fewer than two
+ * bins or two regions */
+pub fn two_way_jackknife(table: &[f64], num_regions: usize, bin_size: usize) -> Option<TwoWayJackknife> {
+    if num_regions < 2 || bin_size == 0 {
+        return None;
+    }
+    let measurements = table.len() / num_regions;
+    let used = &table[..(measurements / bin_size) * bin_size * num_regions];
+    let rows = used.len() / num_regions;
+
+    let row_means: Vec<f64> = used
+        .chunks_exact(num_regions)
+        .map(|row| row.iter().sum::<f64>() / num_regions as f64)
+        .collect();
+    let region_means: Vec<f64> = (0..num_regions)
+        .map(|region| used.iter().skip(region).step_by(num_regions).sum::<f64>() / rows as f64)
+        .collect();
+
+    return Some(TwoWayJackknife {
+        time: jackknife_error(&row_means, bin_size)?,
+        region: jackknife_error(&region_means, 1)?,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fastrand::Rng;
+
+    /* stationary AR(1) series x_t = phi x_{t-1} + sqrt(1 - phi^2) e_t with unit variance and
+     * standard normal e_t, whose error of the mean is sqrt((1 + phi) / ((1 - phi) N)) and whose
+     * tau_int is (1 + phi) / (2 (1 - phi)) */
+    fn ar1(phi: f64, len: usize, seed: u64) -> Vec<f64> {
+        let rng = Rng::with_seed(seed);
+        /* Box-Muller */
+        let normal = || (-2.0 * (1.0 - rng.f64()).ln()).sqrt() * (2.0 * std::f64::consts::PI * rng.f64()).cos();
+        let mut x = normal();
+        return (0..len)
+            .map(|_| {
+                x = phi * x + (1.0 - phi * phi).sqrt() * normal();
+                x
+            })
+            .collect();
+    }
+
+    #[test]
+    fn jackknife_of_a_hand_computed_series() {
+        /* bins of two: sums 3, 7, 11, leave one out means 4.5, 3.5, 2.5, spread 2 */
+        let series = [1.0, 2.0, 3.0, 4.0, 5.0, 6.0];
+        let expected = (2.0f64 / 3.0 * 2.0).sqrt();
+        assert!((jackknife_error(&series, 2).unwrap() - expected).abs() < 1e-15);
+        assert!(jackknife_error(&series, 4).is_none());
+    }
+
+    #[test]
+    fn two_way_jackknife_of_a_hand_computed_table() {
+        /* 4 measurements x 2 regions, x[t][r] = t + 10 r, with a fifth row dropped by the bins */
+        let table = [0.0, 10.0, 1.0, 11.0, 2.0, 12.0, 3.0, 13.0, 99.0, 99.0];
+        let result = two_way_jackknife(&table, 2, 2).unwrap();
+
+        /* row means 5, 6, 7, 8 in bins (5, 6), (7, 8): leave one out means 7.5, 5.5, spread 2 */
+        assert!((result.time - 1.0).abs() < 1e-15, "{:?}", result);
+        /* region means 1.5, 11.5: leave one out means 11.5, 1.5, spread 50 */
+        assert!((result.region - 5.0).abs() < 1e-15, "{:?}", result);
+        assert!((result.error() - 26f64.sqrt()).abs() < 1e-15);
+
+        assert!(two_way_jackknife(&table, 1, 1).is_none());
+        assert!(two_way_jackknife(&table[..4], 2, 2).is_none());
+    }
+
+    #[test]
+    fn susceptibility_of_a_hand_computed_series() {
+        /* <x^2> = 7.5, <x>^2 = 6.25 */
+        assert!((susceptibility(&[1.0, 2.0, 3.0, 4.0], 8.0) - 10.0).abs() < 1e-14);
+        assert_eq!(susceptibility(&[0.25; 5], 64.0), 0.0);
+    }
+
+    #[test]
+    fn binned_jackknife_errors_reach_the_ar1_value() {
+        let (phi, len) = (0.9, 1 << 18);
+        let series = ar1(phi, len, 20);
+        let exact = ((1.0 + phi) / ((1.0 - phi) * len as f64)).sqrt();
+
+        /* single measurements underestimate the error by sqrt(2 tau_int) = 4.36 */
+        let naive = naive_error(&series).unwrap();
+        assert!((exact / naive - 19f64.sqrt()).abs() < 0.2, "naive error {} against {}", naive, exact);
+        /* bins much longer than tau_int = 9.5 reach the plateau, known to about 1 / sqrt(2 bins) */
+        for bin_size in [256, 512, 1024] {
+            let error = jackknife_error(&series, bin_size).unwrap();
+            assert!((error / exact - 1.0).abs() < 0.15, "bin size {}: {} against {}", bin_size, error, exact);
+        }
+        assert!(jackknife_error(&series, 1).unwrap() < 0.3 * exact);
+    }
+
+    #[test]
+    fn short_series_get_the_bin_sizes_they_can_afford() {
+        assert!(bin_sizes(0).is_empty() && bin_sizes(1).is_empty());
+        assert_eq!(bin_sizes(2), vec![1]);
+        assert_eq!(bin_sizes(16), vec![1, 2]);
+        assert_eq!(bin_sizes(17), vec![1, 2]);
+        assert_eq!(bin_sizes(64), vec![1, 2, 4, 8]);
+        assert_eq!(naive_error(&[1.0]), None);
+        assert_eq!(jackknife_error(&[1.0, 3.0], 1), Some(1.0));
+    }
+
+    #[test]
+    fn a_constant_series_has_no_variance_and_no_autocorrelation() {
+        /* 0.1 is not a binary fraction, the sums of these series round */
+        for value in [0.1, 0.7316, -3.0e-5] {
+            for len in [1, 2, 17, 1000] {
+                let series = vec![value; len];
+                assert!(is_constant(&series));
+                assert_eq!(variance(&series), 0.0);
+                assert!(autocorrelation(&series).is_none());
+                assert!(integrated_autocorrelation(&series).is_none());
+                if len > 1 {
+                    assert_eq!(naive_error(&series), Some(0.0));
+                }
+                for size in bin_sizes(len) {
+                    let error = jackknife_error(&series, size).unwrap();
+                    assert!(error == 0.0, "{} x {}, bins of {}: {}", value, len, size, error);
+                }
+            }
+        }
+
+        /* a single differing value is a fluctuation */
+        let mut series = vec![0.1; 100];
+        series[37] = 0.1 + f64::EPSILON;
+        assert!(!is_constant(&series));
+        assert!(variance(&series) > 0.0);
+        let (tau, error) = integrated_autocorrelation(&series).unwrap();
+        assert!(tau.is_finite() && error.is_finite());
+    }
+
+    #[test]
+    fn integrated_autocorrelation_of_ar1_series_is_within_ten_percent() {
+        for (phi, seed) in [(0.0, 30), (0.5, 31), (0.8, 32), (0.95, 33)] {
+            let series = ar1(phi, 1 << 18, seed);
+            let exact = (1.0 + phi) / (2.0 * (1.0 - phi));
+            let (tau, error) = integrated_autocorrelation(&series).unwrap();
+            assert!((tau / exact - 1.0).abs() < 0.1, "phi {}: tau {} +- {} against {}", phi, tau, error, exact);
+            assert!(error > 0.0 && error < 0.1 * exact, "phi {}: error {}", phi, error);
+
+            /* rho(t) = phi^t, the lag one value carries the statistical noise of 1 / sqrt(N) */
+            let result = autocorrelation(&series).unwrap();
+            assert_eq!(result.rho.len(), result.window + 1);
+            assert_eq!(result.rho[0], 1.0);
+            assert!((result.rho[1] - phi).abs() < 0.01, "phi {}: rho(1) = {}", phi, result.rho[1]);
+            let effective = result.effective_samples(series.len());
+            assert!((effective * 2.0 * exact / series.len() as f64 - 1.0).abs() < 0.1);
+        }
+        assert!(integrated_autocorrelation(&[0.5; 100]).is_none());
+    }
+}
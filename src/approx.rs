@@ -0,0 +1,92 @@
+/* elementary functions used by the heatbath rejection sampler, with the `fast-math` feature the
+ * exact library versions are swapped for polynomial approximations tuned to the ranges in
+ * `acceptance_probability`. Both variants are always compiled, so that they can be compared */
+
+#[cfg(not(feature = "fast-math"))]
+#[inline]
+pub fn cos(x: f64) -> f64 {
+    return x.cos();
+}
+
+#[cfg(not(feature = "fast-math"))]
+#[inline]
+pub fn exp(x: f64) -> f64 {
+    return x.exp();
+}
+
+#[cfg(feature = "fast-math")]
+#[inline]
+pub fn cos(x: f64) -> f64 {
+    return fast_cos(x);
+}
+
+#[cfg(feature = "fast-math")]
+#[inline]
+pub fn exp(x: f64) -> f64 {
+    return fast_exp(x);
+}
+
+/* cosine for x in [0, pi], via -sin(x - pi/2) and its Taylor series up to degree 13,
+ * the absolute error is below 1e-9 on that range */
+#[inline]
+pub fn fast_cos(x: f64) -> f64 {
+    let y = x - std::f64::consts::FRAC_PI_2;
+    let y2 = y * y;
+    let sin = y
+        * (1.0
+            + y2 * (-1.0 / 6.0
+                + y2 * (1.0 / 120.0
+                    + y2 * (-1.0 / 5040.0
+                        + y2 * (1.0 / 362880.0
+                            + y2 * (-1.0 / 39916800.0 + y2 * (1.0 / 6227020800.0)))))));
+    return -sin;
+}
+
+/* exponential via x = k ln(2) + r with |r| <= ln(2)/2 and a degree 8 Taylor series for e^r,
+ * the relative error is below 1e-9, results outside the normal f64 range saturate to 0 or inf */
+#[inline]
+pub fn fast_exp(x: f64) -> f64 {
+    let k = (x * std::f64::consts::LOG2_E).round();
+    if k < -1022.0 {
+        return 0.0;
+    }
+    if k > 1023.0 {
+        return f64::INFINITY;
+    }
+
+    let r = x - k * std::f64::consts::LN_2;
+    let exp_r = 1.0
+        + r * (1.0
+            + r * (1.0 / 2.0
+                + r * (1.0 / 6.0
+                    + r * (1.0 / 24.0
+                        + r * (1.0 / 120.0
+                            + r * (1.0 / 720.0 + r * (1.0 / 5040.0 + r * (1.0 / 40320.0))))))));
+    let two_to_k = f64::from_bits(((k as i64 + 1023) as u64) << 52);
+
+    return exp_r * two_to_k;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::PI;
+
+    #[test]
+    fn fast_cos_is_accurate_on_zero_to_pi() {
+        for step in 0..=100_000 {
+            let x = PI * step as f64 / 100_000.0;
+            assert!((fast_cos(x) - x.cos()).abs() < 1e-9, "cos({})", x);
+        }
+    }
+
+    #[test]
+    fn fast_exp_is_accurate_for_the_acceptance_arguments() {
+        for step in 0..=100_000 {
+            let x = -700.0 * step as f64 / 100_000.0;
+            assert!((fast_exp(x) / x.exp() - 1.0).abs() < 1e-9, "exp({})", x);
+        }
+        assert_eq!(fast_exp(-1e4), 0.0);
+        assert_eq!(fast_exp(0.0), 1.0);
+    }
+}
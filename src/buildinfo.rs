@@ -0,0 +1,77 @@
+use crate::config::json_string;
+use std::fmt;
+
+/* cargo features that change the generated code, with whether this build enables them */
+const FEATURES: [(&str, bool); 1] = [("fast-math", cfg!(feature = "fast-math"))];
+
+/* what was actually run, so that timings from different machines and builds can be compared */
+pub struct BuildInfo {
+    pub version: &'static str,
+    pub target: &'static str,
+    pub profile: &'static str,
+    pub opt_level: &'static str,
+    pub features: Vec<&'static str>,
+    /* None where /proc/cpuinfo is not available */
+    pub cpu_model: Option<String>,
+    pub cores: usize,
+    /* threads of the parallel checkerboard sweep, None for the sequential sweep */
+    pub sweep_threads: Option<usize>,
+}
+
+impl BuildInfo {
+    pub fn collect(sweep_threads: Option<usize>) -> Self {
+        return Self {
+            version: env!("CARGO_PKG_VERSION"),
+            target: env!("BUILD_TARGET"),
+            profile: env!("BUILD_PROFILE"),
+            opt_level: env!("BUILD_OPT_LEVEL"),
+            features: FEATURES.iter().filter(|(_, enabled)| *enabled).map(|(name, _)| *name).collect(),
+            cpu_model: cpu_model(),
+            cores: std::thread::available_parallelism().map_or(1, |cores| cores.get()),
+            sweep_threads,
+        };
+    }
+
+    pub fn to_json(&self) -> String {
+        return format!(
+            "{{\"version\":{},\"target\":{},\"profile\":{},\"opt_level\":{},\"features\":[{}],\"cpu_model\":{},\"cores\":{},\"parallel_sweep\":{},\"sweep_threads\":{}}}",
+            json_string(self.version),
+            json_string(self.target),
+            json_string(self.profile),
+            json_string(self.opt_level),
+            self.features.iter().map(|feature| json_string(feature)).collect::<Vec<_>>().join(","),
+            self.cpu_model.as_deref().map_or("null".to_string(), json_string),
+            self.cores,
+            self.sweep_threads.is_some(),
+            self.sweep_threads.map_or("null".to_string(), |threads| threads.to_string())
+        );
+    }
+}
+
+impl fmt::Display for BuildInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "target: {}", self.target)?;
+        writeln!(f, "profile: {}, opt-level {}", self.profile, self.opt_level)?;
+        if self.features.is_empty() {
+            writeln!(f, "features: none")?;
+        } else {
+            writeln!(f, "features: {}", self.features.join(", "))?;
+        }
+        writeln!(f, "cpu: {}", self.cpu_model.as_deref().unwrap_or("unknown"))?;
+        writeln!(f, "cores: {}", self.cores)?;
+        match self.sweep_threads {
+            Some(threads) => write!(f, "sweep: parallel checkerboard on {} threads", threads),
+            None => write!(f, "sweep: sequential"),
+        }
+    }
+}
+
+/* model name of the first processor in /proc/cpuinfo */
+fn cpu_model() -> Option<String> {
+    let cpuinfo = std::fs::read_to_string("/proc/cpuinfo").ok()?;
+    return cpuinfo
+        .lines()
+        .find(|line| line.starts_with("model name"))
+        .and_then(|line| line.split_once(':'))
+        .map(|(_, model)| model.trim().to_string());
+}
@@ -0,0 +1,115 @@
+use std::time::Duration;
+
+const DURATION_GRAMMAR: &str =
+    "expected a number of seconds or a sequence of <number><unit> with units d, h, m, s, e.g. 90s, 1h30m, 2d";
+
+/* human friendly durations like 90, 90s, 1h30m or 1.5d */
+pub fn parse_duration(value: &str) -> Result<Duration, String> {
+    let value = value.trim();
+    if value.is_empty() {
+        return Err(format!("empty duration, {}", DURATION_GRAMMAR));
+    }
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Ok(Duration::from_secs(seconds));
+    }
+
+    let mut seconds = 0f64;
+    let mut rest = value;
+    while !rest.is_empty() {
+        let number_length = rest
+            .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+            .ok_or_else(|| format!("{} is missing a unit, {}", value, DURATION_GRAMMAR))?;
+        let number = rest[..number_length]
+            .parse::<f64>()
+            .map_err(|_| format!("invalid duration {}, {}", value, DURATION_GRAMMAR))?;
+        let unit = match rest[number_length..].chars().next() {
+            Some('d') => 86400.0,
+            Some('h') => 3600.0,
+            Some('m') => 60.0,
+            Some('s') => 1.0,
+            _ => return Err(format!("invalid duration {}, {}", value, DURATION_GRAMMAR)),
+        };
+        seconds += number * unit;
+        rest = &rest[number_length + 1..];
+    }
+
+    return Duration::try_from_secs_f64(seconds)
+        .map_err(|_| format!("duration {} is out of range", value));
+}
+
+/* clap value parser for options stored as whole seconds */
+pub fn parse_seconds(value: &str) -> Result<usize, String> {
+    return parse_duration(value).map(|duration| duration.as_secs_f64().round() as usize);
+}
+
+/* the inverse of parse_duration, e.g. 1h30m, sub-second durations keep one decimal */
+pub fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs_f64();
+    if seconds < 1.0 {
+        return format!("{:.1}s", seconds);
+    }
+
+    let mut remaining = seconds.round() as u64;
+    let mut formatted = String::new();
+    for (unit, length) in [("d", 86400), ("h", 3600), ("m", 60), ("s", 1)] {
+        if remaining >= length {
+            formatted.push_str(&format!("{}{}", remaining / length, unit));
+            remaining %= length;
+        }
+    }
+    return formatted;
+}
+
+const SIZE_GRAMMAR: &str = "expected a number of bytes or <number><unit> with units K, M, G, T, e.g. 512M, 4G";
+
+/* memory sizes like 4096, 512M, 1.5G or 4GiB, the units are powers of 1024 as in format_size */
+pub fn parse_size(value: &str) -> Result<u64, String> {
+    let value = value.trim();
+    let number_length = value
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(value.len());
+    let number = value[..number_length]
+        .parse::<f64>()
+        .map_err(|_| format!("invalid size {}, {}", value, SIZE_GRAMMAR))?;
+    let exponent = match value[number_length..].trim().trim_end_matches("iB").trim_end_matches('B') {
+        "" => 0,
+        "K" | "k" => 1,
+        "M" => 2,
+        "G" => 3,
+        "T" => 4,
+        _ => return Err(format!("invalid size {}, {}", value, SIZE_GRAMMAR)),
+    };
+    return Ok((number * 1024f64.powi(exponent)).round() as u64);
+}
+
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    return format!("{:.1} {}", value, UNITS[unit]);
+}
+
+/* block size and amplitude of the coarse update like 4,0.1 */
+pub fn parse_coarse_update(value: &str) -> Result<(usize, f64), String> {
+    let invalid = || format!("invalid coarse update {}, expected block,amplitude e.g. 4,0.1", value);
+    let (block, amplitude) = value.split_once(',').ok_or_else(invalid)?;
+    let block = block.trim().parse::<usize>().map_err(|_| invalid())?;
+    let amplitude = amplitude.trim().parse::<f64>().map_err(|_| invalid())?;
+    return Ok((block, amplitude));
+}
+
+/* lattice extents nx,ny,nz,nt like 16,16,16,4, also accepted with x as the separator */
+pub fn parse_dims(value: &str) -> Result<[usize; 4], String> {
+    let extents = value
+        .split([',', 'x'])
+        .map(|extent| extent.trim().parse::<usize>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| format!("invalid extents {}, expected four numbers nx,ny,nz,nt e.g. 16,16,16,4", value))?;
+    return extents
+        .try_into()
+        .map_err(|extents: Vec<usize>| format!("expected four extents nx,ny,nz,nt, got {}", extents.len()));
+}
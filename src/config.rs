@@ -0,0 +1,547 @@
+use crate::action::Couplings;
+use crate::simulation::Algorithm;
+use crate::start::StartSpec;
+use anyhow::{bail, Context, Result};
+
+/* fully resolved parameters of a run, after presets and command line flags are combined */
+#[derive(Clone, Debug)]
+pub struct RunConfig {
+    pub name: String,
+    pub beta: f64,
+    /* couplings of the spatial and the temporal plaquettes where they differ from beta */
+    pub beta_spatial: Option<f64>,
+    pub beta_temporal: Option<f64>,
+    /* extents (nx, ny, nz, nt), all equal unless --dims was given */
+    pub lattice_dims: [usize; 4],
+    pub start: StartSpec,
+    pub measurements: usize,
+    pub equilibration_sweeps: usize,
+    pub sweeps_between_measurements: usize,
+    /* seconds between saves */
+    pub interval: usize,
+    pub publish: Option<String>,
+    pub region_blocks: Option<usize>,
+    /* largest side of the recorded R x T Wilson loops */
+    pub wilson_loops: Option<usize>,
+    pub strict_equilibration: bool,
+    pub kappa: Option<f64>,
+    /* coupling of the double charge plaquette term */
+    pub gamma: Option<f64>,
+    pub topological_charge: bool,
+    pub polyakov: bool,
+    pub monopoles: bool,
+    /* also record the plaquette action of every (mu, nu) plane and its spatial and temporal means */
+    pub plane_resolved: bool,
+    pub frozen: bool,
+    pub derive: Vec<String>,
+    pub seed: Option<u64>,
+    /* parallel checkerboard sweeps on this many threads, sequential sweeps if None */
+    pub threads: Option<usize>,
+    pub algorithm: Algorithm,
+    /* initial half width of Metropolis proposals, tuned during the burn in */
+    pub step_size: f64,
+    pub overrelaxation_per_heatbath: usize,
+    /* experimental extra heatbath hits on about this fraction of the links, those with the largest
+     * staple deficit */
+    pub targeted_fraction: Option<f64>,
+    pub targeted_hits: usize,
+    /* sweeps between refreshes of the score threshold */
+    pub targeted_refresh: usize,
+    /* block size and amplitude of a coarse update after every sweep */
+    pub coarse_update: Option<(usize, f64)>,
+    /* measure the Wilson loops with integrated links on their temporal sides */
+    pub integrated_links: bool,
+}
+
+/* checks shared by every command that simulates, naming the offending flag. An extent of 1 makes
+ * links their own neighbors and a negative beta turns the heatbath distribution into NaNs */
+pub fn validate_lattice(dims: [usize; 4], beta: f64) -> Result<()> {
+    if dims.iter().all(|&extent| extent == dims[0]) {
+        if dims[0] < 2 {
+            bail!("--width must be at least 2, got {}", dims[0]);
+        }
+    } else if dims.iter().any(|&extent| extent < 2) {
+        bail!("every extent of --dims must be at least 2, got {:?}", dims);
+    }
+    if !beta.is_finite() || beta < 0.0 {
+        bail!("--beta must be finite and at least 0, got {}", beta);
+    }
+    Ok(())
+}
+
+impl RunConfig {
+    /* the common extent of a hypercubic lattice, None if the extents differ */
+    pub fn width(&self) -> Option<usize> {
+        let width = self.lattice_dims[0];
+        return self.lattice_dims.iter().all(|&extent| extent == width).then_some(width);
+    }
+
+    /* number of sites nx ny nz nt */
+    pub fn volume(&self) -> usize {
+        return self.lattice_dims.iter().product();
+    }
+
+    pub fn couplings(&self) -> Couplings {
+        return Couplings {
+            spatial: self.beta_spatial.unwrap_or(self.beta),
+            temporal: self.beta_temporal.unwrap_or(self.beta),
+        };
+    }
+
+    /* reject parameters that would panic deep inside a sweep or silently produce nonsense, before
+     * any file is touched */
+    pub fn validate(&self) -> Result<()> {
+        validate_lattice(self.lattice_dims, self.beta)?;
+        for (flag, coupling) in [("beta-spatial", self.beta_spatial), ("beta-temporal", self.beta_temporal)] {
+            if coupling.is_some_and(|coupling| !coupling.is_finite() || coupling < 0.0) {
+                bail!("--{} must be finite and at least 0", flag);
+            }
+        }
+        if self.beta == 0.0 && self.kappa.is_some() {
+            bail!("--kappa needs a positive --beta, the hopping term enters the link staple as kappa / beta");
+        }
+        if !self.couplings().is_isotropic() && self.kappa.is_some() {
+            bail!("the scalar field only couples to isotropic links, --beta-spatial and --beta-temporal can not differ with --kappa");
+        }
+        if self.measurements == 0 {
+            bail!("--measurements must be at least 1");
+        }
+        if self.sweeps_between_measurements == 0 {
+            bail!("--sweeps-per-measurement must be at least 1");
+        }
+        if self.interval == 0 {
+            bail!("--flush-every must be at least 1s");
+        }
+        for (flag, coupling) in [("kappa", self.kappa), ("gamma", self.gamma)] {
+            if coupling.is_some_and(|coupling| !coupling.is_finite()) {
+                bail!("--{} must be finite", flag);
+            }
+        }
+        if let Some(threads) = self.threads {
+            if threads == 0 || self.lattice_dims.iter().any(|extent| extent % 2 != 0) {
+                bail!("--threads needs at least one thread and even lattice extents");
+            }
+        }
+        if self.algorithm == Algorithm::Metropolis {
+            if self.threads.is_some() || self.kappa.is_some() {
+                bail!("the metropolis algorithm only updates pure gauge links sequentially, it can not be combined with --threads or --kappa");
+            }
+            if !self.step_size.is_finite() || self.step_size <= 0.0 {
+                bail!("--step-size must be positive");
+            }
+        }
+        if let Some(fraction) = self.targeted_fraction {
+            if !(fraction > 0.0 && fraction <= 1.0) || self.targeted_refresh == 0 {
+                bail!("--targeted-fraction must be in (0, 1] and --targeted-refresh at least 1");
+            }
+            if self.kappa.is_some() || self.gamma.is_some() {
+                bail!("targeted hits only update pure Wilson links, they can not be combined with --kappa or --gamma");
+            }
+        }
+        if self.wilson_loops == Some(0) {
+            bail!("--wilson-loops needs a loop size of at least 1");
+        }
+        if self.overrelaxation_per_heatbath > 0 && (self.kappa.is_some() || self.gamma.is_some()) {
+            bail!("overrelaxation only preserves the Wilson action, it can not be combined with --kappa or --gamma");
+        }
+        if let Some((block, amplitude)) = self.coarse_update {
+            if block == 0 || self.lattice_dims.iter().any(|extent| extent % block != 0) {
+                bail!("the block size {} of --coarse-update must divide every lattice extent", block);
+            }
+            if !(amplitude.is_finite() && amplitude > 0.0) {
+                bail!("the amplitude of --coarse-update must be positive");
+            }
+            if self.kappa.is_some() {
+                bail!("the coarse update only shifts links of a pure gauge run, it can not be combined with --kappa");
+            }
+        }
+        if self.integrated_links {
+            if self.wilson_loops.is_none() {
+                bail!("--integrated-links only changes the Wilson loop measurement, it needs --wilson-loops");
+            }
+            if self.kappa.is_some() || self.gamma.is_some() || !self.couplings().is_isotropic() {
+                bail!("--integrated-links integrates links under the isotropic Wilson action, it can not be combined with --kappa, --gamma or anisotropic couplings");
+            }
+        }
+        Ok(())
+    }
+
+    /* arguments of the new subcommand that reproduce this configuration with every option explicit */
+    pub fn to_cli_args(&self) -> Vec<String> {
+        let (size_flag, size) = match self.width() {
+            Some(width) => ("--width", width.to_string()),
+            None => ("--dims", self.lattice_dims.map(|extent| extent.to_string()).join(",")),
+        };
+        let mut args = vec![
+            "new".to_string(),
+            "--name".to_string(),
+            self.name.clone(),
+            "--beta".to_string(),
+            self.beta.to_string(),
+            size_flag.to_string(),
+            size,
+            "--measurements".to_string(),
+            self.measurements.to_string(),
+            "--equilibration-sweeps".to_string(),
+            self.equilibration_sweeps.to_string(),
+            "--sweeps-per-measurement".to_string(),
+            self.sweeps_between_measurements.to_string(),
+            "--flush-every".to_string(),
+            self.interval.to_string(),
+        ];
+
+        for (flag, coupling) in [("--beta-spatial", self.beta_spatial), ("--beta-temporal", self.beta_temporal)] {
+            if let Some(coupling) = coupling {
+                args.push(flag.to_string());
+                args.push(coupling.to_string());
+            }
+        }
+        match &self.start {
+            StartSpec::Ordered => args.push("--ordered".to_string()),
+            StartSpec::Random => {}
+            StartSpec::File(path) => {
+                args.push("--initial-config".to_string());
+                args.push(path.clone());
+            }
+            StartSpec::WarmStart { path, segment } => {
+                args.push("--warm-start-from".to_string());
+                args.push(match segment {
+                    Some(segment) => format!("{}:{}", path, segment),
+                    None => path.clone(),
+                });
+            }
+            analytic => {
+                args.push("--start".to_string());
+                args.push(analytic.to_string());
+            }
+        }
+        if self.strict_equilibration {
+            args.push("--strict-equilibration".to_string());
+        }
+        if let Some(blocks) = self.region_blocks {
+            args.push("--region-blocks".to_string());
+            args.push(blocks.to_string());
+        }
+        if let Some(gamma) = self.gamma {
+            args.push("--gamma".to_string());
+            args.push(gamma.to_string());
+        }
+        if let Some(r_max) = self.wilson_loops {
+            args.push("--wilson-loops".to_string());
+            args.push(r_max.to_string());
+        }
+        if self.topological_charge {
+            args.push("--topological-charge".to_string());
+        }
+        if self.polyakov {
+            args.push("--polyakov".to_string());
+        }
+        if self.monopoles {
+            args.push("--monopoles".to_string());
+        }
+        if self.plane_resolved {
+            args.push("--plane-resolved".to_string());
+        }
+        if let Some(kappa) = self.kappa {
+            args.push("--kappa".to_string());
+            args.push(kappa.to_string());
+        }
+        if self.frozen {
+            args.push("--frozen".to_string());
+        }
+        for definition in &self.derive {
+            args.push("--derive".to_string());
+            args.push(definition.clone());
+        }
+        if let Some(seed) = self.seed {
+            args.push("--seed".to_string());
+            args.push(seed.to_string());
+        }
+        if let Some(threads) = self.threads {
+            args.push("--threads".to_string());
+            args.push(threads.to_string());
+        }
+        if self.algorithm != Algorithm::Heatbath {
+            args.push("--algorithm".to_string());
+            args.push(self.algorithm.name().to_string());
+            args.push("--step-size".to_string());
+            args.push(self.step_size.to_string());
+        }
+        if self.overrelaxation_per_heatbath > 0 {
+            args.push("--overrelaxation-per-heatbath".to_string());
+            args.push(self.overrelaxation_per_heatbath.to_string());
+        }
+        if let Some(fraction) = self.targeted_fraction {
+            args.push("--targeted-fraction".to_string());
+            args.push(fraction.to_string());
+            args.push("--targeted-hits".to_string());
+            args.push(self.targeted_hits.to_string());
+            args.push("--targeted-refresh".to_string());
+            args.push(self.targeted_refresh.to_string());
+        }
+        if let Some((block, amplitude)) = self.coarse_update {
+            args.push("--coarse-update".to_string());
+            args.push(format!("{},{}", block, amplitude));
+        }
+        if self.integrated_links {
+            args.push("--integrated-links".to_string());
+        }
+
+        return args;
+    }
+
+    /* shell command line for rerunning, arguments are single quoted where needed */
+    pub fn rerun_command(&self) -> String {
+        let mut command = env!("CARGO_PKG_NAME").to_string();
+
+        for arg in self.to_cli_args() {
+            command.push(' ');
+            if arg
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "-_./:=+".contains(c))
+            {
+                command.push_str(&arg);
+            } else {
+                command.push_str(&format!("'{}'", arg.replace('\'', "'\\''")));
+            }
+        }
+
+        return command;
+    }
+}
+
+/* split a command line produced by rerun_command back into its arguments */
+pub fn split_rerun_command(command: &str) -> Result<Vec<String>> {
+    let mut args = Vec::new();
+    let mut current = String::new();
+    let mut in_word = false;
+    let mut quoted = false;
+    let mut chars = command.chars();
+
+    while let Some(c) = chars.next() {
+        if quoted {
+            if c == '\'' {
+                quoted = false;
+            } else {
+                current.push(c);
+            }
+        } else if c == '\'' {
+            quoted = true;
+            in_word = true;
+        } else if c == '\\' {
+            current.push(chars.next().context("command ends with a backslash")?);
+            in_word = true;
+        } else if c.is_whitespace() {
+            if in_word {
+                args.push(std::mem::take(&mut current));
+                in_word = false;
+            }
+        } else {
+            current.push(c);
+            in_word = true;
+        }
+    }
+    if quoted {
+        bail!("unterminated quote in command {}", command);
+    }
+    if in_word {
+        args.push(current);
+    }
+
+    return Ok(args);
+}
+
+/* quote a string for embedding in JSON */
+pub fn json_string(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    return quoted;
+}
+
+impl RunConfig {
+    pub fn to_json(&self) -> String {
+        let optional = |value: Option<String>| value.unwrap_or_else(|| "null".to_string());
+
+        return format!(
+            "{{\"name\":{},\"beta\":{},\"beta_spatial\":{},\"beta_temporal\":{},\"width\":{},\"dims\":[{}],\"ordered\":{},\"start\":{},\"measurements\":{},\"equilibration_sweeps\":{},\"sweeps_per_measurement\":{},\"flush_every\":{},\"publish\":{},\"region_blocks\":{},\"wilson_loops\":{},\"strict_equilibration\":{},\"kappa\":{},\"gamma\":{},\"topological_charge\":{},\"polyakov\":{},\"monopoles\":{},\"plane_resolved\":{},\"frozen\":{},\"derive\":[{}],\"seed\":{},\"threads\":{},\"algorithm\":{},\"step_size\":{},\"overrelaxation_per_heatbath\":{},\"targeted_fraction\":{},\"targeted_hits\":{},\"targeted_refresh\":{},\"coarse_update\":{},\"integrated_links\":{}}}",
+            json_string(&self.name),
+            self.beta,
+            self.couplings().spatial,
+            self.couplings().temporal,
+            optional(self.width().map(|width| width.to_string())),
+            self.lattice_dims.map(|extent| extent.to_string()).join(","),
+            self.start == StartSpec::Ordered,
+            json_string(&self.start.to_string()),
+            self.measurements,
+            self.equilibration_sweeps,
+            self.sweeps_between_measurements,
+            self.interval,
+            optional(self.publish.as_deref().map(json_string)),
+            optional(self.region_blocks.map(|blocks| blocks.to_string())),
+            optional(self.wilson_loops.map(|r_max| r_max.to_string())),
+            self.strict_equilibration,
+            optional(self.kappa.map(|kappa| kappa.to_string())),
+            optional(self.gamma.map(|gamma| gamma.to_string())),
+            self.topological_charge,
+            self.polyakov,
+            self.monopoles,
+            self.plane_resolved,
+            self.frozen,
+            self.derive.iter().map(|definition| json_string(definition)).collect::<Vec<_>>().join(","),
+            optional(self.seed.map(|seed| seed.to_string())),
+            optional(self.threads.map(|threads| threads.to_string())),
+            json_string(self.algorithm.name()),
+            self.step_size,
+            self.overrelaxation_per_heatbath,
+            optional(self.targeted_fraction.map(|fraction| fraction.to_string())),
+            self.targeted_hits,
+            self.targeted_refresh,
+            optional(self.coarse_update.map(|(block, amplitude)| format!("[{},{}]", block, amplitude))),
+            self.integrated_links
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Change = fn(&mut RunConfig);
+
+    fn valid() -> RunConfig {
+        return RunConfig {
+            name: "run.h5".to_string(),
+            beta: 1.0,
+            beta_spatial: None,
+            beta_temporal: None,
+            lattice_dims: [4; 4],
+            start: StartSpec::Random,
+            measurements: 10,
+            equilibration_sweeps: 0,
+            sweeps_between_measurements: 1,
+            interval: 60,
+            publish: None,
+            region_blocks: None,
+            wilson_loops: None,
+            strict_equilibration: false,
+            kappa: None,
+            gamma: None,
+            topological_charge: false,
+            polyakov: false,
+            monopoles: false,
+            plane_resolved: false,
+            frozen: false,
+            derive: Vec::new(),
+            seed: None,
+            threads: None,
+            algorithm: Algorithm::Heatbath,
+            step_size: 1.0,
+            overrelaxation_per_heatbath: 0,
+            targeted_fraction: None,
+            targeted_hits: 1,
+            targeted_refresh: 10,
+            coarse_update: None,
+            integrated_links: false,
+        };
+    }
+
+    #[test]
+    fn the_lattice_checks_name_the_offending_flag() {
+        validate_lattice([2; 4], 0.0).unwrap();
+        validate_lattice([2, 3, 4, 5], 1.0).unwrap();
+        for (dims, beta, flag) in [
+            ([0; 4], 1.0, "--width"),
+            ([1; 4], 1.0, "--width"),
+            ([4, 4, 1, 4], 1.0, "--dims"),
+            ([4, 0, 4, 4], 1.0, "--dims"),
+            ([4; 4], -3.0, "--beta"),
+            ([4; 4], f64::NAN, "--beta"),
+            ([4; 4], f64::INFINITY, "--beta"),
+        ] {
+            let error = validate_lattice(dims, beta).unwrap_err().to_string();
+            assert!(error.contains(flag), "{:?} {}: {}", dims, beta, error);
+        }
+    }
+
+    #[test]
+    fn every_rejection_names_its_flag() {
+        valid().validate().unwrap();
+        let cases: Vec<(&str, Change)> = vec![
+            ("--width", |config| config.lattice_dims = [1; 4]),
+            ("--dims", |config| config.lattice_dims = [4, 4, 4, 1]),
+            ("--beta", |config| config.beta = -3.0),
+            ("--beta-spatial", |config| config.beta_spatial = Some(-1.0)),
+            ("--beta-temporal", |config| config.beta_temporal = Some(f64::NAN)),
+            ("--kappa needs a positive --beta", |config| {
+                config.beta = 0.0;
+                config.kappa = Some(0.5);
+            }),
+            ("--beta-spatial and --beta-temporal can not differ with --kappa", |config| {
+                config.beta_temporal = Some(2.0);
+                config.kappa = Some(0.5);
+            }),
+            ("--measurements", |config| config.measurements = 0),
+            ("--sweeps-per-measurement", |config| config.sweeps_between_measurements = 0),
+            ("--flush-every", |config| config.interval = 0),
+            ("--kappa must be finite", |config| config.kappa = Some(f64::INFINITY)),
+            ("--gamma must be finite", |config| config.gamma = Some(f64::NAN)),
+            ("--threads", |config| config.threads = Some(0)),
+            ("--threads", |config| {
+                config.threads = Some(2);
+                config.lattice_dims = [4, 4, 4, 3];
+            }),
+            ("--threads or --kappa", |config| {
+                config.algorithm = Algorithm::Metropolis;
+                config.threads = Some(2);
+            }),
+            ("--step-size", |config| {
+                config.algorithm = Algorithm::Metropolis;
+                config.step_size = 0.0;
+            }),
+            ("--targeted-fraction", |config| config.targeted_fraction = Some(0.0)),
+            ("--targeted-fraction", |config| config.targeted_fraction = Some(1.5)),
+            ("--targeted-refresh", |config| {
+                config.targeted_fraction = Some(0.1);
+                config.targeted_refresh = 0;
+            }),
+            ("targeted hits only update pure Wilson links", |config| {
+                config.targeted_fraction = Some(0.1);
+                config.gamma = Some(0.5);
+            }),
+            ("--wilson-loops", |config| config.wilson_loops = Some(0)),
+            ("overrelaxation", |config| {
+                config.overrelaxation_per_heatbath = 1;
+                config.kappa = Some(0.5);
+            }),
+            ("must divide every lattice extent", |config| config.coarse_update = Some((3, 0.1))),
+            ("must divide every lattice extent", |config| config.coarse_update = Some((0, 0.1))),
+            ("amplitude of --coarse-update", |config| config.coarse_update = Some((2, -0.1))),
+            ("coarse update only shifts links of a pure gauge run", |config| {
+                config.coarse_update = Some((2, 0.1));
+                config.kappa = Some(0.5);
+            }),
+            ("it needs --wilson-loops", |config| config.integrated_links = true),
+            ("--integrated-links integrates links", |config| {
+                config.integrated_links = true;
+                config.wilson_loops = Some(2);
+                config.beta_spatial = Some(2.0);
+            }),
+        ];
+        for (flag, change) in cases {
+            let mut config = valid();
+            change(&mut config);
+            let error = config.validate().unwrap_err().to_string();
+            assert!(error.contains(flag), "expected {}: {}", flag, error);
+        }
+    }
+}
@@ -0,0 +1,250 @@
+use crate::cli::{format_duration, format_size};
+use crate::config::json_string;
+use crate::progress::{Phase, Progress};
+use anyhow::{bail, Result};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/* time between checks of the free space while a run waits for it */
+const POLL_INTERVAL: Duration = Duration::from_secs(10);
+/* time between the warnings repeated while a run waits for free space */
+const WARNING_INTERVAL: Duration = Duration::from_secs(60);
+
+/* free bytes for unprivileged users on the filesystem holding path, from df since the standard
+ * library has no statvfs. None where df is missing or its output can not be read */
+pub fn available_disk_bytes(path: &Path) -> Option<u64> {
+    let output = Command::new("df").arg("-Pk").arg(path).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let stdout = String::from_utf8(output.stdout).ok()?;
+    let kilobytes = stdout.lines().nth(1)?.split_whitespace().nth(3)?.parse::<u64>().ok()?;
+    return Some(kilobytes * 1024);
+}
+
+/* what the output file may take while the disk fills up. The checkpoints hold the whole
+ * configuration and are the bulk of every save, so they go first, the measurements are kept as long
+ * as possible */
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum DiskState {
+    Normal,
+    CheckpointsSuspended,
+    /* waiting for free space with a checkpoint owed */
+    Paused,
+}
+
+impl DiskState {
+    pub fn name(&self) -> &'static str {
+        match self {
+            DiskState::Normal => "normal",
+            DiskState::CheckpointsSuspended => "checkpoints-suspended",
+            DiskState::Paused => "paused",
+        }
+    }
+}
+
+/* what the next save writes */
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum SaveAction {
+    Everything,
+    MeasurementsOnly,
+    /* flush the measurements and then wait_for_space before writing the checkpoint */
+    Pause,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct DiskTransition {
+    pub timestamp: f64,
+    pub free_bytes: u64,
+    pub from: DiskState,
+    pub to: DiskState,
+}
+
+/* checks the free space next to the output file before every save. Below the threshold the
+ * checkpoints are suspended, below half of it the run pauses until the space is back above the
+ * threshold, and gives up after the grace period. The free space comes from a function that can be
+ * replaced, e.g. to simulate a full disk */
+pub struct DiskWatchdog {
+    directory: PathBuf,
+    threshold: u64,
+    grace: Duration,
+    poll_interval: Duration,
+    free_space: fn(&Path) -> Option<u64>,
+    state: DiskState,
+    unknown_reported: bool,
+    transitions: Vec<DiskTransition>,
+}
+
+impl DiskWatchdog {
+    pub fn new(output: &str, threshold: u64, grace: Duration) -> Self {
+        let directory = match Path::new(output).parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        return Self {
+            directory,
+            threshold,
+            grace,
+            poll_interval: POLL_INTERVAL,
+            free_space: available_disk_bytes,
+            state: DiskState::Normal,
+            unknown_reported: false,
+            transitions: Vec::new(),
+        };
+    }
+
+    pub fn with_reporter(mut self, free_space: fn(&Path) -> Option<u64>, poll_interval: Duration) -> Self {
+        self.free_space = free_space;
+        self.poll_interval = poll_interval;
+        return self;
+    }
+
+    pub fn state(&self) -> DiskState {
+        return self.state;
+    }
+
+    pub fn transitions(&self) -> &[DiskTransition] {
+        return &self.transitions;
+    }
+
+    fn free_bytes(&mut self) -> Option<u64> {
+        let free = (self.free_space)(&self.directory);
+        if free.is_none() && !self.unknown_reported {
+            println!(
+                "Warning: could not determine the free disk space in {}, the disk space watchdog is off",
+                self.directory.display()
+            );
+            self.unknown_reported = true;
+        }
+        return free;
+    }
+
+    fn transition(&mut self, to: DiskState, free_bytes: u64) {
+        if to == self.state {
+            return;
+        }
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0.0, |since| since.as_secs_f64());
+        self.transitions.push(DiskTransition { timestamp, free_bytes, from: self.state, to });
+        match to {
+            DiskState::Normal => println!(
+                "{} free in {} again, writing checkpoints",
+                format_size(free_bytes),
+                self.directory.display()
+            ),
+            DiskState::CheckpointsSuspended => println!(
+                "WARNING: only {} free in {}, below --min-free-disk {}. Checkpoints are suspended, measurements are still saved but a resume redoes those after the last checkpoint",
+                format_size(free_bytes),
+                self.directory.display(),
+                format_size(self.threshold)
+            ),
+            DiskState::Paused => println!(
+                "WARNING: only {} free in {}, the run is paused with a checkpoint pending and continues once {} are free, it stops in {} otherwise",
+                format_size(free_bytes),
+                self.directory.display(),
+                format_size(self.threshold),
+                format_duration(self.grace)
+            ),
+        }
+        self.state = to;
+    }
+
+    /* decide what the next save may write */
+    pub fn before_save(&mut self) -> SaveAction {
+        let free = match self.free_bytes() {
+            Some(free) => free,
+            None => return SaveAction::Everything,
+        };
+        let next = if free < self.threshold / 2 {
+            DiskState::Paused
+        } else if free < self.threshold {
+            DiskState::CheckpointsSuspended
+        } else {
+            DiskState::Normal
+        };
+        self.transition(next, free);
+
+        return match next {
+            DiskState::Normal => SaveAction::Everything,
+            DiskState::CheckpointsSuspended => SaveAction::MeasurementsOnly,
+            DiskState::Paused => SaveAction::Pause,
+        };
+    }
+
+    /* block while paused until the free space is back above the threshold, the heartbeat reports
+     * the paused phase meanwhile. Fails once the grace period is over */
+    pub fn wait_for_space(&mut self, progress: &Progress) -> Result<()> {
+        let previous_phase = progress.phase();
+        progress.set_phase(Phase::Paused);
+        let paused = Instant::now();
+        let mut last_warning = paused;
+
+        loop {
+            std::thread::sleep(self.poll_interval);
+            /* an unknown free space counts as still too little, the grace period bounds the wait */
+            let free = self.free_bytes();
+            if let Some(free) = free.filter(|&free| free >= self.threshold) {
+                self.transition(DiskState::Normal, free);
+                break;
+            }
+            let free = free.map_or("an unknown amount".to_string(), format_size);
+            let waited = paused.elapsed();
+            if waited >= self.grace {
+                progress.set_phase(Phase::Aborted);
+                bail!(
+                    "stopped after waiting {} for free disk space, {} is free in {}; everything but the pending checkpoint is stored, resume continues from the last checkpoint once space is freed",
+                    format_duration(waited),
+                    free,
+                    self.directory.display()
+                );
+            }
+            if last_warning.elapsed() >= WARNING_INTERVAL {
+                println!(
+                    "WARNING: still paused, {} free in {}, {} needed, stopping in {}",
+                    free,
+                    self.directory.display(),
+                    format_size(self.threshold),
+                    format_duration(self.grace - waited)
+                );
+                last_warning = Instant::now();
+            }
+        }
+
+        progress.set_phase(previous_phase);
+        return Ok(());
+    }
+
+    pub fn to_json(&self) -> String {
+        let transitions: Vec<String> = self
+            .transitions
+            .iter()
+            .map(|transition| {
+                format!(
+                    "{{\"timestamp\":{},\"free_bytes\":{},\"from\":{},\"to\":{}}}",
+                    transition.timestamp,
+                    transition.free_bytes,
+                    json_string(transition.from.name()),
+                    json_string(transition.to.name())
+                )
+            })
+            .collect();
+
+        return format!(
+            "{{\"threshold_bytes\":{},\"grace_seconds\":{},\"state\":{},\"transitions\":[{}]}}",
+            self.threshold,
+            self.grace.as_secs_f64(),
+            json_string(self.state.name()),
+            transitions.join(",")
+        );
+    }
+}
+
+/* keep the heartbeat flag in step with the watchdog */
+pub fn report_state(watchdog: &DiskWatchdog, progress: &Progress) {
+    progress
+        .checkpoints_suspended
+        .store(watchdog.state() != DiskState::Normal, Ordering::Relaxed);
+}
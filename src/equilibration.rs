@@ -0,0 +1,105 @@
+/* number of measurements right after the burn in phase that are checked for drift */
+pub const PROBATION_WINDOW: usize = 100;
+/* halves of the window differing by more than this many standard errors are flagged. With the
+ * error taken from five blocks the significance of an equilibrated window has heavy tails, at 6
+ * about one in 300 equilibrated runs on 4^4 is flagged while slowly heating cold starts are */
+pub const DRIFT_THRESHOLD: f64 = 6.0;
+/* each half is binned into this many blocks so that autocorrelations do not inflate the significance */
+pub const BLOCKS_PER_HALF: usize = 5;
+
+/* mean and squared standard error of a series, estimated from the means of consecutive blocks */
+fn blocked_mean_and_error(series: &[f64]) -> (f64, f64) {
+    let block_size = series.len() / BLOCKS_PER_HALF;
+    let block_means: Vec<f64> = series
+        .chunks_exact(block_size)
+        .take(BLOCKS_PER_HALF)
+        .map(|block| block.iter().sum::<f64>() / block_size as f64)
+        .collect();
+
+    let mean = block_means.iter().sum::<f64>() / BLOCKS_PER_HALF as f64;
+    let variance = block_means.iter().map(|x| (x - mean).powi(2)).sum::<f64>()
+        / (BLOCKS_PER_HALF - 1) as f64;
+
+    return (mean, variance / BLOCKS_PER_HALF as f64);
+}
+
+/* difference between the means of the first and second half of the window in units of its
+ * standard error, None if the window is too short to say anything */
+pub fn drift_significance(window: &[f64]) -> Option<f64> {
+    if window.len() < 4 * BLOCKS_PER_HALF {
+        return None;
+    }
+
+    let (first, second) = window.split_at(window.len() / 2);
+    let (first_mean, _) = blocked_mean_and_error(first);
+    let (second_mean, second_error) = blocked_mean_and_error(second);
+    let difference = (first_mean - second_mean).abs();
+    /* the spread of the first half grows with the drift it is meant to reveal, so the error of
+     * both halves is estimated from the second, which is closer to equilibrium */
+    let error = (2.0 * second_error).sqrt();
+
+    if error == 0.0 {
+        return Some(if difference == 0.0 { 0.0 } else { f64::INFINITY });
+    }
+
+    return Some(difference / error);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::action::Couplings;
+    use crate::lattice::Lattice;
+    use fastrand::Rng;
+
+    /* the average action after each of PROBATION_WINDOW sweeps following burn_in sweeps */
+    fn probation_window(mut lattice: Lattice, burn_in: usize, sweep: impl Fn(&mut Lattice, &mut Rng), rng: &mut Rng) -> Vec<f64> {
+        for _ in 0..burn_in {
+            sweep(&mut lattice, rng);
+        }
+        return (0..PROBATION_WINDOW)
+            .map(|_| {
+                sweep(&mut lattice, rng);
+                lattice.average_action()
+            })
+            .collect();
+    }
+
+    #[test]
+    fn the_threshold_passes_equilibrated_runs_and_flags_cold_starts() {
+        for (seed, beta) in (100..110).zip([0.8, 1.2].into_iter().cycle()) {
+            let mut rng = Rng::with_seed(seed);
+            let heatbath = |lattice: &mut Lattice, rng: &mut Rng| lattice.heatbath_sweep(Couplings::isotropic(beta), rng);
+            let lattice = Lattice::new_random(4, &mut rng);
+            let significance = drift_significance(&probation_window(lattice, 200, heatbath, &mut rng)).unwrap();
+            assert!(significance < DRIFT_THRESHOLD, "seed {}: equilibrated run at {}", seed, significance);
+
+            /* small Metropolis steps from an ordered start are still heating up during the window */
+            let metropolis = |lattice: &mut Lattice, rng: &mut Rng| {
+                lattice.metropolis_sweep(beta, 0.15, rng);
+            };
+            let significance = drift_significance(&probation_window(Lattice::new_uniform(4), 0, metropolis, &mut rng)).unwrap();
+            assert!(significance > DRIFT_THRESHOLD, "seed {}: cold start at {}", seed, significance);
+        }
+    }
+
+    #[test]
+    fn short_windows_have_no_significance() {
+        assert_eq!(drift_significance(&[]), None);
+        assert_eq!(drift_significance(&[0.5; 4 * BLOCKS_PER_HALF - 1]), None);
+        assert_eq!(drift_significance(&[0.5; 4 * BLOCKS_PER_HALF]), Some(0.0));
+    }
+
+    #[test]
+    fn constant_halves_are_infinitely_significant_unless_equal() {
+        /* the blocks of each half agree, so the error vanishes */
+        let mut window = vec![0.4; 2 * BLOCKS_PER_HALF * 3];
+        assert_eq!(drift_significance(&window), Some(0.0));
+        let half = window.len() / 2;
+        window[half..].fill(0.6);
+        assert_eq!(drift_significance(&window), Some(f64::INFINITY));
+        /* the sign of the drift does not matter */
+        window.reverse();
+        assert_eq!(drift_significance(&window), Some(f64::INFINITY));
+    }
+}
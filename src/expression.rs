@@ -0,0 +1,383 @@
+use anyhow::{anyhow, bail, Result};
+use std::fmt;
+
+/* arithmetic expression over named observables, parsed from numbers, names, + - * / ^ and
+ * parentheses with the usual precedence, ^ binds tightest and is right associative */
+#[derive(Clone, Debug)]
+pub enum Expression {
+    Number(f64),
+    Variable(String),
+    Negate(Box<Expression>),
+    Binary(Operator, Box<Expression>, Box<Expression>),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Operator {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Power,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Number(f64),
+    Name(String),
+    Operator(Operator),
+    Open,
+    Close,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Token::Number(value) => write!(f, "number {}", value),
+            Token::Name(name) => write!(f, "name {}", name),
+            Token::Operator(operator) => {
+                let symbol = match operator {
+                    Operator::Add => '+',
+                    Operator::Subtract => '-',
+                    Operator::Multiply => '*',
+                    Operator::Divide => '/',
+                    Operator::Power => '^',
+                };
+                write!(f, "'{}'", symbol)
+            }
+            Token::Open => write!(f, "'('"),
+            Token::Close => write!(f, "')'"),
+        }
+    }
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_digit() || c == '.' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                i += 1;
+            }
+            /* exponent, only if followed by digits so that names like e1 stay separate */
+            if i < chars.len() && (chars[i] == 'e' || chars[i] == 'E') {
+                let mut j = i + 1;
+                if j < chars.len() && (chars[j] == '+' || chars[j] == '-') {
+                    j += 1;
+                }
+                if j < chars.len() && chars[j].is_ascii_digit() {
+                    i = j;
+                    while i < chars.len() && chars[i].is_ascii_digit() {
+                        i += 1;
+                    }
+                }
+            }
+            let literal: String = chars[start..i].iter().collect();
+            let value = literal
+                .parse::<f64>()
+                .map_err(|_| anyhow!("invalid number {} at position {}", literal, start))?;
+            tokens.push(Token::Number(value));
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push(Token::Name(chars[start..i].iter().collect()));
+        } else {
+            tokens.push(match c {
+                '+' => Token::Operator(Operator::Add),
+                '-' => Token::Operator(Operator::Subtract),
+                '*' => Token::Operator(Operator::Multiply),
+                '/' => Token::Operator(Operator::Divide),
+                '^' => Token::Operator(Operator::Power),
+                '(' => Token::Open,
+                ')' => Token::Close,
+                _ => bail!("unexpected character '{}' at position {}", c, i),
+            });
+            i += 1;
+        }
+    }
+
+    return Ok(tokens);
+}
+
+/* recursive descent over the token list, one method per precedence level */
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        return self.tokens.get(self.position);
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        return token;
+    }
+
+    fn accept(&mut self, operators: &[Operator]) -> Option<Operator> {
+        match self.peek() {
+            Some(Token::Operator(operator)) if operators.contains(operator) => {
+                let operator = *operator;
+                self.position += 1;
+                Some(operator)
+            }
+            _ => None,
+        }
+    }
+
+    /* sum := product (('+' | '-') product)* */
+    fn sum(&mut self) -> Result<Expression> {
+        let mut left = self.product()?;
+        while let Some(operator) = self.accept(&[Operator::Add, Operator::Subtract]) {
+            let right = self.product()?;
+            left = Expression::Binary(operator, Box::new(left), Box::new(right));
+        }
+        return Ok(left);
+    }
+
+    /* product := unary (('*' | '/') unary)* */
+    fn product(&mut self) -> Result<Expression> {
+        let mut left = self.unary()?;
+        while let Some(operator) = self.accept(&[Operator::Multiply, Operator::Divide]) {
+            let right = self.unary()?;
+            left = Expression::Binary(operator, Box::new(left), Box::new(right));
+        }
+        return Ok(left);
+    }
+
+    /* unary := '-' unary | power, so that -x^2 = -(x^2) */
+    fn unary(&mut self) -> Result<Expression> {
+        if self.accept(&[Operator::Subtract]).is_some() {
+            return Ok(Expression::Negate(Box::new(self.unary()?)));
+        }
+        return self.power();
+    }
+
+    /* power := primary ('^' unary)? */
+    fn power(&mut self) -> Result<Expression> {
+        let base = self.primary()?;
+        if self.accept(&[Operator::Power]).is_some() {
+            let exponent = self.unary()?;
+            return Ok(Expression::Binary(
+                Operator::Power,
+                Box::new(base),
+                Box::new(exponent),
+            ));
+        }
+        return Ok(base);
+    }
+
+    /* primary := number | name | '(' sum ')' */
+    fn primary(&mut self) -> Result<Expression> {
+        match self.next() {
+            Some(Token::Number(value)) => Ok(Expression::Number(value)),
+            Some(Token::Name(name)) => Ok(Expression::Variable(name)),
+            Some(Token::Open) => {
+                let inner = self.sum()?;
+                match self.next() {
+                    Some(Token::Close) => Ok(inner),
+                    _ => bail!("missing closing parenthesis"),
+                }
+            }
+            Some(token) => bail!("unexpected {}, expected a number, name or '('", token),
+            None => bail!("unexpected end of expression"),
+        }
+    }
+}
+
+impl Expression {
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut parser = Parser {
+            tokens: tokenize(text)?,
+            position: 0,
+        };
+        let expression = parser.sum()?;
+        if let Some(token) = parser.peek() {
+            bail!("unexpected {} after the end of the expression", token);
+        }
+        return Ok(expression);
+    }
+
+    /* names of all observables the expression refers to */
+    pub fn variables(&self) -> Vec<&str> {
+        let mut names = Vec::new();
+        self.collect_variables(&mut names);
+        return names;
+    }
+
+    fn collect_variables<'a>(&'a self, names: &mut Vec<&'a str>) {
+        match self {
+            Expression::Number(_) => {}
+            Expression::Variable(name) => {
+                if !names.contains(&name.as_str()) {
+                    names.push(name);
+                }
+            }
+            Expression::Negate(inner) => inner.collect_variables(names),
+            Expression::Binary(_, left, right) => {
+                left.collect_variables(names);
+                right.collect_variables(names);
+            }
+        }
+    }
+
+    /* evaluate with the current observable values, unknown names evaluate to NaN, so check
+     * them with variables() beforehand */
+    pub fn evaluate(&self, values: &[(&str, f64)]) -> f64 {
+        match self {
+            Expression::Number(value) => *value,
+            Expression::Variable(name) => values
+                .iter()
+                .find(|(candidate, _)| candidate == name)
+                .map_or(f64::NAN, |(_, value)| *value),
+            Expression::Negate(inner) => -inner.evaluate(values),
+            Expression::Binary(operator, left, right) => {
+                let (left, right) = (left.evaluate(values), right.evaluate(values));
+                match operator {
+                    Operator::Add => left + right,
+                    Operator::Subtract => left - right,
+                    Operator::Multiply => left * right,
+                    Operator::Divide => left / right,
+                    Operator::Power => left.powf(right),
+                }
+            }
+        }
+    }
+}
+
+/* a named derived observable from --derive name=expression */
+#[derive(Clone, Debug)]
+pub struct Derived {
+    pub name: String,
+    pub source: String,
+    pub expression: Expression,
+}
+
+impl Derived {
+    /* parse name=expression and check that it only refers to the available observables */
+    pub fn parse(definition: &str, available: &[&str]) -> Result<Self> {
+        let (name, source) = definition.split_once('=').ok_or_else(|| {
+            anyhow!(
+                "derived observable must look like name=expression, got {}",
+                definition
+            )
+        })?;
+        let name = name.trim();
+        if name.is_empty()
+            || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+            || name.starts_with(|c: char| c.is_ascii_digit())
+        {
+            bail!("derived observable name '{}' must be an identifier", name);
+        }
+        /* the derived values are published next to the observables, under their own names */
+        if available.contains(&name) {
+            bail!("derived observable {} has the name of a recorded observable", name);
+        }
+
+        let expression = Expression::parse(source)
+            .map_err(|error| anyhow!("in derived observable {}: {}", name, error))?;
+        for variable in expression.variables() {
+            if !available.contains(&variable) {
+                bail!(
+                    "derived observable {} refers to unknown observable {}, available are: {}",
+                    name,
+                    variable,
+                    available.join(", ")
+                );
+            }
+        }
+
+        return Ok(Self {
+            name: name.to_string(),
+            source: source.trim().to_string(),
+            expression,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn value_of(text: &str) -> f64 {
+        return Expression::parse(text).unwrap().evaluate(&[("x", 3.0), ("wilson_1x1", 0.5)]);
+    }
+
+    #[test]
+    fn operators_follow_the_usual_precedence() {
+        assert_eq!(value_of("1 + 2 * 3"), 7.0);
+        assert_eq!(value_of("(1 + 2) * 3"), 9.0);
+        assert_eq!(value_of("8 - 3 - 2"), 3.0);
+        assert_eq!(value_of("12 / 3 / 2"), 2.0);
+        assert_eq!(value_of("2 * 3 ^ 2"), 18.0);
+        /* ^ is right associative and binds tighter than the unary minus */
+        assert_eq!(value_of("2 ^ 3 ^ 2"), 512.0);
+        assert_eq!(value_of("-x ^ 2"), -9.0);
+        assert_eq!(value_of("(-x) ^ 2"), 9.0);
+        assert_eq!(value_of("2 ^ -1"), 0.5);
+        assert_eq!(value_of("--x"), 3.0);
+        assert_eq!(value_of("x - -x"), 6.0);
+    }
+
+    #[test]
+    fn numbers_names_and_whitespace() {
+        assert_eq!(value_of("1.5e2"), 150.0);
+        assert_eq!(value_of("2E-1"), 0.2);
+        assert_eq!(value_of(".25"), 0.25);
+        assert_eq!(value_of("wilson_1x1*x"), 1.5);
+        assert_eq!(value_of("  x\t+\n1 "), 4.0);
+        /* a name right after a number is a second operand, with or without an exponent between */
+        assert!(Expression::parse("2e1x").is_err());
+        assert!(Expression::parse("2ex").is_err());
+        assert!(value_of("y + 1").is_nan());
+        assert_eq!(value_of("1 / 0"), f64::INFINITY);
+    }
+
+    #[test]
+    fn malformed_expressions_are_rejected() {
+        for text in ["", "1 +", "(1 + 2", "1 + 2)", "1 2", "x y", "* 2", "()", "1.2.3", "2 # 3", "x ^", "(1)(2)"] {
+            assert!(Expression::parse(text).is_err(), "{:?} parsed", text);
+        }
+        let error = Expression::parse("1 $ 2").unwrap_err();
+        assert!(error.to_string().contains("position 2"), "{}", error);
+    }
+
+    #[test]
+    fn variables_are_listed_once_in_order() {
+        let expression = Expression::parse("b * (a + b) - a ^ c").unwrap();
+        assert_eq!(expression.variables(), vec!["b", "a", "c"]);
+        assert!(Expression::parse("1 + 2").unwrap().variables().is_empty());
+    }
+
+    #[test]
+    fn derived_observables_are_checked_against_the_recorded_ones() {
+        let available = ["action", "topological_charge"];
+        let derived = Derived::parse(" chi = topological_charge ^ 2 / action ", &available).unwrap();
+        assert_eq!(derived.name, "chi");
+        assert_eq!(derived.source, "topological_charge ^ 2 / action");
+        assert_eq!(derived.expression.evaluate(&[("action", 0.5), ("topological_charge", -2.0)]), 8.0);
+
+        for definition in [
+            "no equals sign",
+            "=action",
+            "2x=action",
+            "a-b=action",
+            "action=2 * action",
+            "x=hopping",
+            "x=action +",
+        ] {
+            assert!(Derived::parse(definition, &available).is_err(), "{:?} accepted", definition);
+        }
+        let error = Derived::parse("x=hopping", &available).unwrap_err();
+        assert!(error.to_string().contains("hopping"), "{}", error);
+    }
+}
@@ -0,0 +1,89 @@
+use crate::config::json_string;
+use crate::progress::Progress;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/* rewrites a small JSON status file from its own thread every `every`, so that job monitoring can
+ * detect a hung run by a stale timestamp even while the process is still alive. The thread only
+ * reads the progress atomics and never blocks the simulation */
+pub struct Heartbeat {
+    stop: Sender<()>,
+    handle: JoinHandle<()>,
+}
+
+impl Heartbeat {
+    pub fn start(path: String, every: Duration, progress: Arc<Progress>) -> Self {
+        let (stop, stopped) = mpsc::channel();
+
+        let handle = thread::spawn(move || {
+            let mut last = (Instant::now(), progress.sweeps.load(Ordering::Relaxed));
+            loop {
+                let finished = !matches!(stopped.recv_timeout(every), Err(RecvTimeoutError::Timeout));
+
+                /* sweep rate over the window since the previous beat */
+                let now = Instant::now();
+                let sweeps = progress.sweeps.load(Ordering::Relaxed);
+                let elapsed = now.duration_since(last.0).as_secs_f64();
+                let rate = if elapsed > 0.0 {
+                    (sweeps - last.1) as f64 / elapsed
+                } else {
+                    0.0
+                };
+                last = (now, sweeps);
+
+                if let Err(error) = write_heartbeat(&path, &progress, rate) {
+                    eprintln!("could not write heartbeat {}: {}", path, error);
+                }
+                if finished {
+                    break;
+                }
+            }
+        });
+
+        return Self { stop, handle };
+    }
+
+    /* write a last beat with the final phase and wait for the thread to finish */
+    pub fn stop(self) {
+        let _ = self.stop.send(());
+        let _ = self.handle.join();
+    }
+}
+
+/* replace the heartbeat file through a temporary file and a rename, so that monitors never read
+ * a half written beat */
+fn write_heartbeat(path: &str, progress: &Progress, sweeps_per_second: f64) -> std::io::Result<()> {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0.0, |since| since.as_secs_f64());
+
+    let percentile = |q: f64| {
+        progress
+            .latency
+            .percentile(q)
+            .map_or("null".to_string(), |latency| latency.as_secs_f64().to_string())
+    };
+
+    let contents = format!(
+        "{{\"sweep\":{},\"measurements\":{},\"saved_measurements\":{},\"sweeps_per_second\":{},\"sweep_latency_p50\":{},\"sweep_latency_p90\":{},\"sweep_latency_p99\":{},\"interference_events\":{},\"checkpoints_suspended\":{},\"timestamp\":{},\"phase\":{}}}\n",
+        progress.sweeps.load(Ordering::Relaxed),
+        progress.measurements.load(Ordering::Relaxed),
+        progress.saved_measurements.load(Ordering::Relaxed),
+        sweeps_per_second,
+        percentile(0.5),
+        percentile(0.9),
+        percentile(0.99),
+        progress.latency.interference_events(),
+        progress.checkpoints_suspended.load(Ordering::Relaxed),
+        timestamp,
+        json_string(progress.phase().name())
+    );
+
+    let temporary_path = format!("{}.tmp", path);
+    std::fs::write(&temporary_path, contents)?;
+    std::fs::rename(&temporary_path, path)?;
+    return Ok(());
+}
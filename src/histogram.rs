@@ -0,0 +1,254 @@
+use anyhow::{bail, Result};
+
+/* histogram with a fixed bin width whose range grows by whole bins at either end, so that no value
+ * is ever dropped. The bins lie on the grid of edges origin + n * bin_width for all integers n,
+ * bin n holds the values in [origin + n * bin_width, origin + (n + 1) * bin_width), a value on an
+ * edge belongs to the bin above it. Histograms on the same grid can be merged. NaN and infinite
+ * values have no bin and are only counted */
+#[derive(Clone, Debug, PartialEq)]
+pub struct StreamingHistogram {
+    bin_width: f64,
+    origin: f64,
+    /* grid index n of counts[0] */
+    first_bin: i64,
+    counts: Vec<u64>,
+    non_finite: u64,
+}
+
+impl StreamingHistogram {
+    pub fn new(bin_width: f64, origin: f64) -> Self {
+        assert!(bin_width.is_finite() && bin_width > 0.0, "the bin width must be positive, got {}", bin_width);
+        assert!(origin.is_finite(), "the origin of the bins must be finite, got {}", origin);
+        return Self { bin_width, origin, first_bin: 0, counts: Vec::new(), non_finite: 0 };
+    }
+
+    /* a histogram from its stored parts, see first_bin and counts */
+    pub fn from_parts(bin_width: f64, origin: f64, first_bin: i64, counts: Vec<u64>, non_finite: u64) -> Result<Self> {
+        if !(bin_width.is_finite() && bin_width > 0.0 && origin.is_finite()) {
+            bail!("a histogram needs a positive bin width and a finite origin, got {} and {}", bin_width, origin);
+        }
+        return Ok(Self { bin_width, origin, first_bin, counts, non_finite });
+    }
+
+    /* grid index of the bin holding a finite value */
+    fn grid_index(&self, value: f64) -> i64 {
+        return ((value - self.origin) / self.bin_width).floor() as i64;
+    }
+
+    /* add empty bins so that the grid indices first..=last are covered */
+    fn cover(&mut self, first: i64, last: i64) {
+        if self.counts.is_empty() {
+            self.first_bin = first;
+            self.counts = vec![0; (last - first + 1) as usize];
+            return;
+        }
+        if first < self.first_bin {
+            let mut extended = vec![0; (self.first_bin - first) as usize];
+            extended.extend_from_slice(&self.counts);
+            self.counts = extended;
+            self.first_bin = first;
+        }
+        let end = self.first_bin + self.counts.len() as i64;
+        if last >= end {
+            self.counts.resize(self.counts.len() + (last - end + 1) as usize, 0);
+        }
+    }
+
+    pub fn insert(&mut self, value: f64) {
+        self.insert_count(value, 1);
+    }
+
+    /* count a value `count` times */
+    pub fn insert_count(&mut self, value: f64, count: u64) {
+        if !value.is_finite() {
+            self.non_finite += count;
+            return;
+        }
+        let index = self.grid_index(value);
+        self.cover(index, index);
+        self.counts[(index - self.first_bin) as usize] += count;
+    }
+
+    /* add the counts of a histogram on the same grid */
+    pub fn merge(&mut self, other: &StreamingHistogram) -> Result<()> {
+        if self.bin_width != other.bin_width || self.origin != other.origin {
+            bail!(
+                "histograms with bin width {} and origin {} can not be merged into bin width {} and origin {}",
+                other.bin_width,
+                other.origin,
+                self.bin_width,
+                self.origin
+            );
+        }
+        if !other.counts.is_empty() {
+            self.cover(other.first_bin, other.first_bin + other.counts.len() as i64 - 1);
+            let offset = (other.first_bin - self.first_bin) as usize;
+            for (count, other_count) in self.counts[offset..].iter_mut().zip(&other.counts) {
+                *count += other_count;
+            }
+        }
+        self.non_finite += other.non_finite;
+        return Ok(());
+    }
+
+    pub fn bin_width(&self) -> f64 {
+        return self.bin_width;
+    }
+
+    pub fn origin(&self) -> f64 {
+        return self.origin;
+    }
+
+    pub fn first_bin(&self) -> i64 {
+        return self.first_bin;
+    }
+
+    /* counts of the bins from the lowest to the highest one ever reached */
+    pub fn counts(&self) -> &[u64] {
+        return &self.counts;
+    }
+
+    pub fn non_finite(&self) -> u64 {
+        return self.non_finite;
+    }
+
+    /* every value ever inserted, the non-finite ones included */
+    pub fn total(&self) -> u64 {
+        return self.counts.iter().sum::<u64>() + self.non_finite;
+    }
+
+    /* lower edge of the bin counts[index] */
+    pub fn lower_edge(&self, index: usize) -> f64 {
+        return self.origin + (self.first_bin + index as i64) as f64 * self.bin_width;
+    }
+
+    /* the counts.len() + 1 edges of the bins */
+    pub fn edges(&self) -> Vec<f64> {
+        return (0..=self.counts.len()).map(|index| self.lower_edge(index)).collect();
+    }
+
+    /* upper edge of the bin holding the q-th quantile of the finite values, None before the first */
+    pub fn quantile(&self, q: f64) -> Option<f64> {
+        let total: u64 = self.counts.iter().sum();
+        if total == 0 {
+            return None;
+        }
+
+        let rank = ((q * total as f64).ceil() as u64).clamp(1, total);
+        let mut cumulative = 0;
+        for (index, count) in self.counts.iter().enumerate() {
+            cumulative += count;
+            if cumulative >= rank {
+                return Some(self.lower_edge(index + 1));
+            }
+        }
+        return Some(self.lower_edge(self.counts.len()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fastrand::Rng;
+
+    #[test]
+    fn the_range_grows_at_both_ends_without_moving_counts() {
+        let mut histogram = StreamingHistogram::new(0.5, 0.25);
+        histogram.insert(1.0);
+        assert_eq!((histogram.first_bin(), histogram.counts()), (1, &[1][..]));
+        histogram.insert(-0.3);
+        histogram.insert(2.25);
+        /* -0.3 is in [-0.75, -0.25), 2.25 on the edge of [2.25, 2.75) */
+        assert_eq!(histogram.first_bin(), -2);
+        assert_eq!(histogram.counts(), &[1, 0, 0, 1, 0, 0, 1]);
+        assert_eq!(histogram.edges(), vec![-0.75, -0.25, 0.25, 0.75, 1.25, 1.75, 2.25, 2.75]);
+        histogram.insert(0.25);
+        histogram.insert(f64::NAN);
+        histogram.insert_count(f64::INFINITY, 2);
+        assert_eq!(histogram.counts(), &[1, 0, 1, 1, 0, 0, 1]);
+        assert_eq!((histogram.non_finite(), histogram.total()), (3, 7));
+    }
+
+    #[test]
+    fn merging_adds_the_counts_of_the_same_grid() {
+        let mut left = StreamingHistogram::new(1.0, 0.0);
+        let mut right = StreamingHistogram::new(1.0, 0.0);
+        for value in [0.5, 1.5, 1.7] {
+            left.insert(value);
+        }
+        for value in [-2.5, 1.0, 4.0] {
+            right.insert(value);
+        }
+        left.merge(&right).unwrap();
+        assert_eq!(left.first_bin(), -3);
+        assert_eq!(left.counts(), &[1, 0, 0, 1, 3, 0, 0, 1]);
+        left.merge(&StreamingHistogram::new(1.0, 0.0)).unwrap();
+        assert_eq!(left.total(), 6);
+
+        assert!(left.merge(&StreamingHistogram::new(0.5, 0.0)).is_err());
+        assert!(left.merge(&StreamingHistogram::new(1.0, 0.5)).is_err());
+        let mut empty = StreamingHistogram::new(1.0, 0.0);
+        empty.merge(&right).unwrap();
+        assert_eq!(empty, right);
+    }
+
+    #[test]
+    fn the_parts_rebuild_the_histogram() {
+        let mut histogram = StreamingHistogram::new(0.1, -0.05);
+        for value in [0.3, -1.2, 7.0, f64::NAN] {
+            histogram.insert(value);
+        }
+        let rebuilt = StreamingHistogram::from_parts(
+            histogram.bin_width(),
+            histogram.origin(),
+            histogram.first_bin(),
+            histogram.counts().to_vec(),
+            histogram.non_finite(),
+        )
+        .unwrap();
+        assert_eq!(rebuilt, histogram);
+        assert!(StreamingHistogram::from_parts(0.0, 0.0, 0, vec![], 0).is_err());
+        assert!(StreamingHistogram::from_parts(1.0, f64::NAN, 0, vec![], 0).is_err());
+    }
+
+    #[test]
+    fn quantiles_are_upper_bin_edges() {
+        let mut histogram = StreamingHistogram::new(1.0, 0.0);
+        assert_eq!(histogram.quantile(0.5), None);
+        for value in [0.5, 1.5, 2.5, 2.6] {
+            histogram.insert(value);
+        }
+        assert_eq!(histogram.quantile(0.0), Some(1.0));
+        assert_eq!(histogram.quantile(0.25), Some(1.0));
+        assert_eq!(histogram.quantile(0.5), Some(2.0));
+        assert_eq!(histogram.quantile(0.75), Some(3.0));
+        assert_eq!(histogram.quantile(1.0), Some(3.0));
+    }
+
+    #[test]
+    fn inserts_and_merges_conserve_the_total_count() {
+        let rng = Rng::with_seed(5);
+        for _ in 0..200 {
+            let mut histograms = vec![StreamingHistogram::new(0.3, 0.1); 3];
+            let mut inserted = 0;
+            for _ in 0..rng.usize(0..60) {
+                let target = rng.usize(0..histograms.len());
+                if rng.usize(0..5) == 0 {
+                    let source = histograms[rng.usize(0..histograms.len())].clone();
+                    inserted += source.total();
+                    histograms[target].merge(&source).unwrap();
+                } else {
+                    let value = match rng.usize(0..20) {
+                        0 => f64::NAN,
+                        _ => (rng.f64() - 0.5) * 10f64.powi(rng.i32(-2..3)),
+                    };
+                    let count = rng.u64(1..4);
+                    histograms[target].insert_count(value, count);
+                    inserted += count;
+                }
+            }
+            let total: u64 = histograms.iter().map(|histogram| histogram.total()).sum();
+            assert_eq!(total, inserted);
+        }
+    }
+}
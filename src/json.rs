@@ -0,0 +1,297 @@
+use crate::config::json_string;
+use anyhow::{bail, Context, Result};
+
+/* a parsed JSON document. Numbers keep their text, so that values written by this crate compare
+ * exactly and u64 seeds do not pass through a float */
+#[derive(Clone, Debug, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(String),
+    String(String),
+    Array(Vec<Json>),
+    /* the keys in the order of the document */
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(members) => members.iter().find(|(name, _)| name == key).map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /* compact text of the value, the inverse of parse */
+    pub fn to_json(&self) -> String {
+        match self {
+            Json::Null => "null".to_string(),
+            Json::Bool(value) => value.to_string(),
+            Json::Number(text) => text.clone(),
+            Json::String(value) => json_string(value),
+            Json::Array(elements) => {
+                format!("[{}]", elements.iter().map(Json::to_json).collect::<Vec<_>>().join(","))
+            }
+            Json::Object(members) => format!(
+                "{{{}}}",
+                members
+                    .iter()
+                    .map(|(name, value)| format!("{}:{}", json_string(name), value.to_json()))
+                    .collect::<Vec<_>>()
+                    .join(",")
+            ),
+        }
+    }
+
+    /* indented text for files meant to be read by people as well, arrays without objects inside
+     * stay on one line */
+    pub fn to_pretty_json(&self) -> String {
+        let mut text = String::new();
+        self.write_pretty(&mut text, 0);
+        text.push('\n');
+        return text;
+    }
+
+    fn write_pretty(&self, text: &mut String, depth: usize) {
+        let indent = |depth: usize| "  ".repeat(depth);
+        match self {
+            Json::Object(members) if !members.is_empty() => {
+                text.push_str("{\n");
+                for (index, (name, value)) in members.iter().enumerate() {
+                    text.push_str(&indent(depth + 1));
+                    text.push_str(&json_string(name));
+                    text.push_str(": ");
+                    value.write_pretty(text, depth + 1);
+                    text.push_str(if index + 1 < members.len() { ",\n" } else { "\n" });
+                }
+                text.push_str(&indent(depth));
+                text.push('}');
+            }
+            Json::Array(elements) if elements.iter().any(|element| matches!(element, Json::Object(_))) => {
+                text.push_str("[\n");
+                for (index, element) in elements.iter().enumerate() {
+                    text.push_str(&indent(depth + 1));
+                    element.write_pretty(text, depth + 1);
+                    text.push_str(if index + 1 < elements.len() { ",\n" } else { "\n" });
+                }
+                text.push_str(&indent(depth));
+                text.push(']');
+            }
+            other => text.push_str(&other.to_json()),
+        }
+    }
+}
+
+pub fn parse(text: &str) -> Result<Json> {
+    let mut parser = Parser { chars: text.chars().collect(), position: 0 };
+    let value = parser.value()?;
+    parser.skip_whitespace();
+    if parser.position < parser.chars.len() {
+        bail!("unexpected {:?} after the JSON value at character {}", parser.chars[parser.position], parser.position);
+    }
+    return Ok(value);
+}
+
+struct Parser {
+    chars: Vec<char>,
+    position: usize,
+}
+
+impl Parser {
+    fn skip_whitespace(&mut self) {
+        while self.position < self.chars.len() && self.chars[self.position].is_whitespace() {
+            self.position += 1;
+        }
+    }
+
+    fn next(&mut self) -> Result<char> {
+        let c = *self.chars.get(self.position).context("unexpected end of the JSON text")?;
+        self.position += 1;
+        return Ok(c);
+    }
+
+    fn expect(&mut self, expected: char) -> Result<()> {
+        self.skip_whitespace();
+        let position = self.position;
+        let c = self.next()?;
+        if c != expected {
+            bail!("expected {:?} at character {}, found {:?}", expected, position, c);
+        }
+        return Ok(());
+    }
+
+    fn keyword(&mut self, word: &str, value: Json) -> Result<Json> {
+        let position = self.position;
+        for expected in word.chars() {
+            if self.next()? != expected {
+                bail!("invalid literal at character {}, expected {}", position, word);
+            }
+        }
+        return Ok(value);
+    }
+
+    fn value(&mut self) -> Result<Json> {
+        self.skip_whitespace();
+        let position = self.position;
+        match self.chars.get(position) {
+            None => bail!("unexpected end of the JSON text"),
+            Some('n') => self.keyword("null", Json::Null),
+            Some('t') => self.keyword("true", Json::Bool(true)),
+            Some('f') => self.keyword("false", Json::Bool(false)),
+            Some('"') => Ok(Json::String(self.string()?)),
+            Some('[') => {
+                self.position += 1;
+                let mut elements = Vec::new();
+                self.skip_whitespace();
+                if self.chars.get(self.position) == Some(&']') {
+                    self.position += 1;
+                    return Ok(Json::Array(elements));
+                }
+                loop {
+                    elements.push(self.value()?);
+                    self.skip_whitespace();
+                    match self.next()? {
+                        ',' => continue,
+                        ']' => return Ok(Json::Array(elements)),
+                        c => bail!("expected , or ] at character {}, found {:?}", self.position - 1, c),
+                    }
+                }
+            }
+            Some('{') => {
+                self.position += 1;
+                let mut members: Vec<(String, Json)> = Vec::new();
+                self.skip_whitespace();
+                if self.chars.get(self.position) == Some(&'}') {
+                    self.position += 1;
+                    return Ok(Json::Object(members));
+                }
+                loop {
+                    self.skip_whitespace();
+                    let name = self.string()?;
+                    if members.iter().any(|(existing, _)| *existing == name) {
+                        bail!("key {} appears twice in an object", json_string(&name));
+                    }
+                    self.expect(':')?;
+                    members.push((name, self.value()?));
+                    self.skip_whitespace();
+                    match self.next()? {
+                        ',' => continue,
+                        '}' => return Ok(Json::Object(members)),
+                        c => bail!("expected , or }} at character {}, found {:?}", self.position - 1, c),
+                    }
+                }
+            }
+            Some(c) if *c == '-' || c.is_ascii_digit() => {
+                while self
+                    .chars
+                    .get(self.position)
+                    .is_some_and(|c| c.is_ascii_digit() || "+-.eE".contains(*c))
+                {
+                    self.position += 1;
+                }
+                let text: String = self.chars[position..self.position].iter().collect();
+                if text.parse::<f64>().is_err() {
+                    bail!("invalid number {} at character {}", text, position);
+                }
+                Ok(Json::Number(text))
+            }
+            Some(c) => bail!("unexpected {:?} at character {}", c, position),
+        }
+    }
+
+    fn hex_escape(&mut self) -> Result<u32> {
+        let position = self.position;
+        let digits: String = (0..4).map(|_| self.next()).collect::<Result<_>>()?;
+        return u32::from_str_radix(&digits, 16)
+            .with_context(|| format!("invalid \\u escape at character {}", position));
+    }
+
+    fn string(&mut self) -> Result<String> {
+        self.expect('"')?;
+        let mut value = String::new();
+        loop {
+            match self.next()? {
+                '"' => return Ok(value),
+                '\\' => {
+                    let position = self.position;
+                    let c = match self.next()? {
+                        '"' => '"',
+                        '\\' => '\\',
+                        '/' => '/',
+                        'b' => '\u{8}',
+                        'f' => '\u{c}',
+                        'n' => '\n',
+                        'r' => '\r',
+                        't' => '\t',
+                        'u' => {
+                            let mut code = self.hex_escape()?;
+                            /* characters outside the basic plane come as a surrogate pair */
+                            if (0xd800..0xdc00).contains(&code) {
+                                if self.next()? != '\\' || self.next()? != 'u' {
+                                    bail!("unpaired surrogate in the \\u escape at character {}", position);
+                                }
+                                let low = self.hex_escape()?;
+                                code = 0x10000 + ((code - 0xd800) << 10) + (low.wrapping_sub(0xdc00) & 0x3ff);
+                            }
+                            char::from_u32(code)
+                                .with_context(|| format!("invalid \\u escape at character {}", position))?
+                        }
+                        c => bail!("invalid escape \\{} at character {}", c, position),
+                    };
+                    value.push(c);
+                }
+                c => value.push(c),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn documents_survive_compact_and_pretty_text() {
+        let text = r#"{"seed":18446744073709551557,"beta":1.0e-3,"name":"a \"quoted\" \\ path\n","clef":"\ud834\udd1e",
+            "empty":{},"list":[1,-2.5,true,null,[]],"runs":[{"x":"é"}]}"#;
+        let value = parse(text).unwrap();
+        assert_eq!(value.get("seed"), Some(&Json::Number("18446744073709551557".to_string())));
+        assert_eq!(value.get("name").and_then(Json::as_str), Some("a \"quoted\" \\ path\n"));
+        assert_eq!(value.get("clef").and_then(Json::as_str), Some("\u{1d11e}"));
+        assert_eq!(value.get("beta"), Some(&Json::Number("1.0e-3".to_string())));
+        assert_eq!(parse(&value.to_json()).unwrap(), value);
+        assert_eq!(parse(&value.to_pretty_json()).unwrap(), value);
+        /* objects keep the order of their keys */
+        let Json::Object(members) = &value else { panic!("not an object") };
+        assert_eq!(members.iter().map(|(name, _)| name.as_str()).collect::<Vec<_>>(), ["seed", "beta", "name", "clef", "empty", "list", "runs"]);
+        assert_eq!(value.get("missing"), None);
+    }
+
+    #[test]
+    fn malformed_text_is_rejected_with_its_position() {
+        for (text, message) in [
+            ("", "unexpected end"),
+            ("{\"a\":1,}", "at character 7"),
+            ("[1 2]", "expected , or ] at character 3"),
+            ("{\"a\" 1}", "expected ':' at character 5"),
+            ("{\"a\":1,\"a\":2}", "appears twice"),
+            ("tru", "unexpected end"),
+            ("nul!", "invalid literal at character 0"),
+            ("1.2.3", "invalid number 1.2.3 at character 0"),
+            ("\"\\x\"", "invalid escape \\x at character 2"),
+            ("\"\\ud834x\"", "unpaired surrogate"),
+            ("\"open", "unexpected end"),
+            ("{} []", "after the JSON value at character 3"),
+        ] {
+            let error = format!("{:#}", parse(text).unwrap_err());
+            assert!(error.contains(message), "{:?}: {}", text, error);
+        }
+    }
+}
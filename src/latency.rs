@@ -0,0 +1,127 @@
+use crate::histogram::StreamingHistogram;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/* bins of log2 of the sweep time in microseconds, four per octave */
+const BIN_WIDTH: f64 = 0.25;
+/* sweeps recorded before spikes are judged against the median, and between median updates */
+const MEDIAN_SAMPLES: u64 = 16;
+
+/* log spaced histogram of sweep wall times, filled by the run and read by the heartbeat thread.
+ * A sweep of t us lands in the bin [b / 4, (b + 1) / 4) of log2(t), so percentiles are resolved
+ * to about 19 %. Sweeps below the 1 ns resolution of Duration count as 1 ns */
+pub struct SweepLatency {
+    histogram: Mutex<StreamingHistogram>,
+    count: AtomicU64,
+    median_nanos: AtomicU64,
+    interference_events: AtomicU64,
+}
+
+impl Default for SweepLatency {
+    fn default() -> Self {
+        return Self {
+            histogram: Mutex::new(StreamingHistogram::new(BIN_WIDTH, 0.0)),
+            count: AtomicU64::new(0),
+            median_nanos: AtomicU64::new(0),
+            interference_events: AtomicU64::new(0),
+        };
+    }
+}
+
+impl SweepLatency {
+    /* count a sweep, and an interference event if it took more than `interference_factor` times
+     * the median sweep */
+    pub fn record(&self, elapsed: Duration, interference_factor: f64) {
+        let micros = elapsed.max(Duration::from_nanos(1)).as_secs_f64() * 1e6;
+        self.histogram.lock().unwrap().insert(micros.log2());
+        let count = self.count.fetch_add(1, Ordering::Relaxed) + 1;
+
+        let median = self.median_nanos.load(Ordering::Relaxed);
+        if median > 0 && elapsed.as_nanos() as f64 > interference_factor * median as f64 {
+            self.interference_events.fetch_add(1, Ordering::Relaxed);
+        }
+        if count.is_multiple_of(MEDIAN_SAMPLES) {
+            let median = self.percentile(0.5).map_or(0, |median| median.as_nanos() as u64);
+            self.median_nanos.store(median, Ordering::Relaxed);
+        }
+    }
+
+    pub fn count(&self) -> u64 {
+        return self.count.load(Ordering::Relaxed);
+    }
+
+    pub fn interference_events(&self) -> u64 {
+        return self.interference_events.load(Ordering::Relaxed);
+    }
+
+    /* upper edge of the bin holding the q-th quantile, None before the first sweep */
+    pub fn percentile(&self, q: f64) -> Option<Duration> {
+        let log2_micros = self.histogram.lock().unwrap().quantile(q)?;
+        return Some(Duration::from_secs_f64(2f64.powf(log2_micros) * 1e-6));
+    }
+
+    /* a copy of the histogram of log2 of the sweep times in microseconds */
+    pub fn histogram(&self) -> StreamingHistogram {
+        return self.histogram.lock().unwrap().clone();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NO_SPIKES: f64 = f64::INFINITY;
+
+    #[test]
+    fn percentiles_are_the_upper_edges_of_their_bins() {
+        let latency = SweepLatency::default();
+        assert_eq!(latency.percentile(0.5), None);
+        for _ in 0..90 {
+            latency.record(Duration::from_millis(1), NO_SPIKES);
+        }
+        for _ in 0..10 {
+            latency.record(Duration::from_millis(100), NO_SPIKES);
+        }
+
+        /* 1000 us has log2 9.97 in the bin [9.75, 10), 1e5 us has log2 16.61 in [16.5, 16.75).
+         * Duration rounds the edges to whole nanoseconds */
+        let micros = |q: f64| latency.percentile(q).unwrap().as_secs_f64() * 1e6;
+        assert!((micros(0.5) - 1024.0).abs() < 1e-3);
+        assert!((micros(0.9) - 1024.0).abs() < 1e-3);
+        assert!((micros(0.91) - 2f64.powf(16.75)).abs() < 1e-3);
+        assert!((micros(0.99) - 2f64.powf(16.75)).abs() < 1e-3);
+        assert_eq!(latency.count(), 100);
+        assert_eq!(latency.histogram().total(), 100);
+    }
+
+    #[test]
+    fn every_percentile_bounds_its_sweep_within_a_quarter_octave() {
+        for nanos in [0, 1, 999, 1_000, 123_456, 7_000_000_000] {
+            let latency = SweepLatency::default();
+            latency.record(Duration::from_nanos(nanos), NO_SPIKES);
+            let bound = latency.percentile(0.5).unwrap().as_secs_f64() * 1e9;
+            let nanos = nanos.max(1) as f64;
+            let (lowest, highest) = (nanos - 0.5, nanos * 2f64.powf(BIN_WIDTH) + 0.5);
+            assert!(bound >= lowest && bound <= highest, "{} ns bounded by {}", nanos, bound);
+        }
+    }
+
+    #[test]
+    fn spikes_above_the_factor_count_once_the_median_is_known() {
+        let latency = SweepLatency::default();
+        /* no median yet, so the early spike is not judged */
+        latency.record(Duration::from_millis(50), 5.0);
+        for _ in 1..MEDIAN_SAMPLES {
+            latency.record(Duration::from_millis(2), 5.0);
+        }
+        assert_eq!(latency.interference_events(), 0);
+
+        /* the median bin ends at 2^11 us, so spikes start above 5 * 2.048 ms */
+        latency.record(Duration::from_millis(10), 5.0);
+        latency.record(Duration::from_millis(11), 5.0);
+        latency.record(Duration::from_millis(200), 5.0);
+        assert_eq!(latency.interference_events(), 2);
+        assert_eq!(latency.count(), MEDIAN_SAMPLES + 3);
+    }
+}
@@ -1,260 +1,1591 @@
+use crate::action::{Couplings, LinkEnvironment, LocalAction, WilsonAction};
+use crate::approx;
 use crate::phasevector::PhaseVector;
+use crate::rng::mix_seed;
 use anyhow::Ok;
 use num_complex::{Complex, ComplexFloat};
 use fastrand::Rng;
+use rayon::prelude::*;
 use std::f64::consts::PI;
 use std::fs::File;
-use std::io::Write;
+use std::io::{Read, Write};
 
-const UNIT_VECTORS: [[usize; 4]; 4] = [[1, 0, 0, 0], [0, 1, 0, 0], [0, 0, 1, 0], [0, 0, 0, 1]];
+pub(crate) const UNIT_VECTORS: [[usize; 4]; 4] = [[1, 0, 0, 0], [0, 1, 0, 0], [0, 0, 1, 0], [0, 0, 0, 1]];
 const ACCEPTANCE_CONSTANT: f64 = 0.2105137;
+/* prefactors of sample_theta below which exp(2 prefactor) - 1 is computed as exp_m1, and above
+ * which the Gaussian envelope takes over. Every link of a run with beta <= 10 / 3 stays below the
+ * latter, the staple has at most magnitude 6 */
+const SMALL_PREFACTOR: f64 = 1e-4;
+const GAUSSIAN_PREFACTOR: f64 = 20.0;
+const CONFIG_MAGIC: &[u8] = b"U1LATCFG";
+/* version 1 stores a single width, version 2 the four extents */
+const CONFIG_VERSION: u64 = 2;
+
+/* the six (mu, nu) planes with mu < nu, the order of plane resolved measurements */
+pub const PLANES: [(usize, usize); 6] = [(0, 1), (0, 2), (0, 3), (1, 2), (1, 3), (2, 3)];
+/* the direction treated as time by the Polyakov loop, Coulomb gauge fixing and the temporal
+ * plaquettes */
+pub const TIME_DIRECTION: usize = 3;
+
+/* outcome of a gauge fixing run, the residual is the mean squared lattice divergence */
+#[derive(Copy, Clone, Debug)]
+pub struct GaugeFixReport {
+    pub iterations: usize,
+    pub residual: f64,
+    pub converged: bool,
+}
+
+/* proposals and acceptances of Metropolis updates */
+#[derive(Copy, Clone, Debug, Default)]
+pub struct MetropolisStats {
+    pub proposals: usize,
+    pub accepted: usize,
+}
+
+impl MetropolisStats {
+    pub fn add(&mut self, other: MetropolisStats) {
+        self.proposals += other.proposals;
+        self.accepted += other.accepted;
+    }
+
+    /* fraction of accepted proposals, 0 before the first proposal */
+    pub fn acceptance_rate(&self) -> f64 {
+        if self.proposals == 0 {
+            return 0.0;
+        }
+        return self.accepted as f64 / self.proposals as f64;
+    }
+}
+
+/* score function of targeted updates together with the threshold a link has to reach to be hit,
+ * the lowest score among the top fraction of the links at the last refresh. A score function must
+ * not depend on the link it scores, only on its surroundings */
+#[derive(Clone, Debug)]
+pub struct LinkScores {
+    score: fn(&Lattice, usize, usize) -> f64,
+    threshold: f64,
+}
+
+impl LinkScores {
+    /* the threshold of the top fraction of the links of a configuration, infinite for an empty
+     * fraction */
+    pub fn compute(lattice: &Lattice, score: fn(&Lattice, usize, usize) -> f64, fraction: f64) -> Self {
+        let mut sorted: Vec<f64> = (0..lattice.volume())
+            .flat_map(|site| (0..4).map(move |mu| (site, mu)))
+            .map(|(site, mu)| score(lattice, site, mu))
+            .collect();
+        sorted.sort_by(f64::total_cmp);
+        let count = (fraction.clamp(0.0, 1.0) * sorted.len() as f64).round() as usize;
+        let threshold = if count == 0 { f64::INFINITY } else { sorted[sorted.len() - count] };
+        return Self { score, threshold };
+    }
+
+    /* scores with a threshold from an earlier refresh, e.g. one stored in a checkpoint */
+    pub fn frozen(score: fn(&Lattice, usize, usize) -> f64, threshold: f64) -> Self {
+        return Self { score, threshold };
+    }
+
+    pub fn threshold(&self) -> f64 {
+        return self.threshold;
+    }
+
+    pub fn score(&self, lattice: &Lattice, site: usize, mu: usize) -> f64 {
+        return (self.score)(lattice, site, mu);
+    }
+}
+
+/* a site n = (i, j, k, l) of a lattice with the given extents. Every coordinate is always reduced
+ * into 0..extent of its direction, so every site built from any integers is a valid site of that
+ * lattice */
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Site {
+    coords: [usize; 4],
+    dims: [usize; 4],
+}
+
+impl Site {
+    /* the site with every coordinate taken modulo the extent of its direction */
+    pub fn new(coords: [usize; 4], dims: [usize; 4]) -> Self {
+        assert!(dims.iter().all(|&extent| extent > 0), "a lattice needs extents of at least 1");
+        return Self { coords: [0, 1, 2, 3].map(|mu| coords[mu] % dims[mu]), dims };
+    }
+
+    /* the site with every coordinate taken modulo the extent of its direction, negative ones count
+     * back from the far edge */
+    pub fn wrapped(coords: [i64; 4], dims: [usize; 4]) -> Self {
+        assert!(dims.iter().all(|&extent| extent > 0), "a lattice needs extents of at least 1");
+        return Self { coords: [0, 1, 2, 3].map(|mu| coords[mu].rem_euclid(dims[mu] as i64) as usize), dims };
+    }
+
+    pub fn coords(&self) -> [usize; 4] {
+        return self.coords;
+    }
+
+    /* the site n + steps * mu, wrapping around the torus */
+    pub fn shift(&self, direction: usize, steps: i64) -> Self {
+        let mut coords = self.coords;
+        coords[direction] = (coords[direction] as i64 + steps).rem_euclid(self.dims[direction] as i64) as usize;
+        return Self { coords, dims: self.dims };
+    }
+
+    pub fn dims(&self) -> [usize; 4] {
+        return self.dims;
+    }
+
+    fn from_index(index: usize, dims: [usize; 4]) -> Self {
+        let [_, ny, nz, nt] = dims;
+        return Self { coords: [index / (ny * nz * nt), index / (nz * nt) % ny, index / nt % nz, index % nt], dims };
+    }
+}
 
 #[derive(Clone, Debug)]
 pub struct Lattice {
-    /* the actual lattice holding the configuration */
-    lattice: Vec<Vec<Vec<Vec<PhaseVector>>>>,
-    width: usize,
+    /* the actual lattice holding the configuration, one entry per site in the order of site_index */
+    lattice: Vec<PhaseVector>,
+    /* extents (nx, ny, nz, nt) of the four directions */
+    dims: [usize; 4],
+    /* periodic neighbors n + \hat{\mu} and n - \hat{\mu} of every site, by site and direction */
+    neighbor_up: Vec<[usize; 4]>,
+    neighbor_down: Vec<[usize; 4]>,
+    /* paranoid mode: links already updated in the current sweep, in (i, j, k, l, mu) order */
+    updated: Option<Vec<bool>>,
+    /* update the links in parallel in checkerboard order instead of one after the other */
+    checkerboard: bool,
+    /* incremental mode: total_action kept up to date by the link updates */
+    tracked_action: Option<ActionTracker>,
+}
+
+/* running sum_P (1 - cos(theta_P)), resynchronized with the sum over all plaquettes every
+ * resync_interval sweeps against the rounding of the many small changes */
+#[derive(Clone, Debug)]
+struct ActionTracker {
+    total: f64,
+    resync_interval: usize,
+    sweeps_since_resync: usize,
 }
 
 impl Lattice {
     pub fn new_uniform(width: usize) -> Self {
+        return Lattice::new_uniform_dims([width; 4]);
+    }
+
+    /* ordered configuration with its own extent in every direction, e.g. [16, 16, 16, 4] */
+    pub fn new_uniform_dims(dims: [usize; 4]) -> Self {
+        let (neighbor_up, neighbor_down) = neighbor_tables(dims);
         Self {
-            lattice: vec![vec![vec![vec![PhaseVector::new_uniform(); width]; width]; width]; width],
-            width: width,
+            lattice: vec![PhaseVector::new_uniform(); dims.iter().product()],
+            dims,
+            neighbor_up,
+            neighbor_down,
+            updated: None,
+            checkerboard: false,
+            tracked_action: None,
         }
     }
 
     pub fn new_random(width: usize, rng: &mut Rng) -> Self {
+        return Lattice::new_random_dims([width; 4], rng);
+    }
+
+    pub fn new_random_dims(dims: [usize; 4], rng: &mut Rng) -> Self {
+        let mut new_lattice = Lattice::new_uniform_dims(dims);
+
+        for phase_vector in new_lattice.lattice.iter_mut() {
+            *phase_vector = PhaseVector::new_random(rng);
+        }
+
+        return new_lattice;
+    }
+
+    /* single mode configuration theta_mu(n) = amplitude * cos(2 pi k.n / width) on the links in
+     * `direction`, all other links are zero */
+    pub fn plane_wave(width: usize, momentum: [usize; 4], amplitude: f64, direction: usize) -> Self {
         let mut new_lattice = Lattice::new_uniform(width);
 
-        for axis_1 in new_lattice.lattice.iter_mut() {
-            for axis_2 in axis_1.iter_mut() {
-                for axis_3 in axis_2.iter_mut() {
-                    for phase_vector in axis_3.iter_mut() {
-                        *phase_vector = PhaseVector::new_random(rng);
+        for i in 0..width {
+            for j in 0..width {
+                for k in 0..width {
+                    for l in 0..width {
+                        let k_dot_n = momentum[0] * i + momentum[1] * j + momentum[2] * k + momentum[3] * l;
+                        let index = new_lattice.site_index(i, j, k, l);
+                        new_lattice.lattice[index].phases[direction] =
+                            amplitude * (2.0 * PI * (k_dot_n % width) as f64 / width as f64).cos();
                     }
                 }
             }
         }
+        new_lattice.wrap_phases();
 
         return new_lattice;
     }
 
+    /* uniform field strength, every (mu, nu) plaquette has angle f[mu][nu] (mod 2 pi). The field
+     * is realized by theta_nu(n) = f[mu][nu] n_mu, plus a compensating jump in theta_mu on the last
+     * slice n_mu = width - 1, which is only consistent with periodicity when the total flux
+     * f[mu][nu] width^2 through each plane is a multiple of 2 pi */
+    pub fn constant_field(width: usize, f: [[f64; 4]; 4]) -> anyhow::Result<Self> {
+        let mut new_lattice = Lattice::new_uniform(width);
+
+        for (m, row) in f.iter().enumerate() {
+            if row[m] != 0.0 {
+                anyhow::bail!("field strength must vanish on the diagonal, f[{0}][{0}] = {1}", m, row[m]);
+            }
+        }
+        for &(m, n) in PLANES.iter() {
+            if (f[m][n] + f[n][m]).abs() > 1e-12 {
+                anyhow::bail!("field strength must be antisymmetric, f[{0}][{1}] = {2} but f[{1}][{0}] = {3}", m, n, f[m][n], f[n][m]);
+            }
+            let flux_quanta = f[m][n] * (width * width) as f64 / (2.0 * PI);
+            if (flux_quanta - flux_quanta.round()).abs() > 1e-9 {
+                anyhow::bail!(
+                    "flux f[{}][{}] * width^2 = 2 pi * {} is not quantized, use f = 2 pi n / width^2",
+                    m,
+                    n,
+                    flux_quanta
+                );
+            }
+        }
+
+        for i in 0..width {
+            for j in 0..width {
+                for k in 0..width {
+                    for l in 0..width {
+                        let site = [i, j, k, l];
+                        let index = new_lattice.site_index(i, j, k, l);
+                        for &(m, n) in PLANES.iter() {
+                            new_lattice.lattice[index].phases[n] += f[m][n] * site[m] as f64;
+                            if site[m] == width - 1 {
+                                new_lattice.lattice[index].phases[m] -= f[m][n] * (width * site[n]) as f64;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        new_lattice.wrap_phases();
+
+        Ok(new_lattice)
+    }
+
+    /* replicate a configuration periodically, every link is copied to its factor^4 images */
+    pub fn tile_from(smaller: &Lattice, factor: usize) -> anyhow::Result<Self> {
+        if factor == 0 {
+            anyhow::bail!("tiling factor must be at least 1");
+        }
+        let mut new_lattice = Lattice::new_uniform_dims(smaller.dims.map(|extent| extent * factor));
+
+        for site in new_lattice.sites() {
+            let index = new_lattice.position(site);
+            new_lattice.lattice[index] = smaller.lattice[smaller.position(Site::new(site.coords(), smaller.dims))];
+        }
+
+        Ok(new_lattice)
+    }
+
+    /* reduce every link phase into (-pi, pi]. The updates keep the phases there on their own, this
+     * is for configurations put together from arbitrary phases */
+    pub fn wrap_phases(&mut self) {
+        for phase_vector in self.lattice.iter_mut() {
+            phase_vector.wrap();
+        }
+    }
+
+    /* the common extent of a lattice with the same extent in every direction */
+    pub fn width(&self) -> usize {
+        assert!(
+            self.is_hypercubic(),
+            "lattice with extents {:?} has no single width",
+            self.dims
+        );
+        return self.dims[0];
+    }
+
+    pub fn dims(&self) -> [usize; 4] {
+        return self.dims;
+    }
+
+    pub fn is_hypercubic(&self) -> bool {
+        return self.dims.iter().all(|&extent| extent == self.dims[0]);
+    }
+
+    /* number of sites nx ny nz nt */
+    pub fn volume(&self) -> usize {
+        return self.lattice.len();
+    }
+
+    /* inverse of site_index */
+    pub fn site_coordinates(&self, site: usize) -> [usize; 4] {
+        return Site::from_index(site, self.dims).coords;
+    }
+
+    /* position of site n in the flat storage, lexicographic in (i, j, k, l) */
+    pub fn site_index(&self, i: usize, j: usize, k: usize, l: usize) -> usize {
+        let [_, ny, nz, nt] = self.dims;
+        return ((i * ny + j) * nz + k) * nt + l;
+    }
+
+    /* the site at a position in the flat storage */
+    pub fn site(&self, index: usize) -> Site {
+        return Site::from_index(index, self.dims);
+    }
+
+    /* position of a site in the flat storage */
+    pub fn position(&self, site: Site) -> usize {
+        assert_eq!(
+            site.dims, self.dims,
+            "site of a lattice with extents {:?} used on a lattice with extents {:?}",
+            site.dims, self.dims
+        );
+        let [i, j, k, l] = site.coords;
+        return self.site_index(i, j, k, l);
+    }
+
+    /* all sites in the order of the flat storage, lexicographic in (i, j, k, l). The iterator does
+     * not borrow the lattice, so the links can be updated while walking it */
+    pub fn sites(&self) -> impl Iterator<Item = Site> {
+        let dims = self.dims;
+        return (0..self.volume()).map(move |index| Site::from_index(index, dims));
+    }
+
+    /* all links (n, mu) in (i, j, k, l, mu) order, the order of a sequential sweep */
+    pub fn links(&self) -> impl Iterator<Item = (Site, usize)> {
+        return self.sites().flat_map(|site| (0..4).map(move |mu| (site, mu)));
+    }
+
+    /* phase of the link U_\mu(n) */
+    pub fn link_phase(&self, i: usize, j: usize, k: usize, l: usize, m: usize) -> f64 {
+        return self.lattice[self.site_index(i, j, k, l)].phases[m];
+    }
+
+    /* phase of the link U_\mu(n) of the site at a position in the flat storage */
+    pub fn link_phase_at(&self, site: usize, m: usize) -> f64 {
+        return self.lattice[site].phases[m];
+    }
+
+    /* position of n + \hat{\mu} for the site n at a position in the flat storage */
+    pub fn neighbor_up(&self, site: usize, m: usize) -> usize {
+        return self.neighbor_up[site][m];
+    }
+
+    /* position of n - \hat{\mu} for the site n at a position in the flat storage */
+    pub fn neighbor_down(&self, site: usize, m: usize) -> usize {
+        return self.neighbor_down[site][m];
+    }
+
     /* compute the average action per plaquette */
     pub fn average_action(&self) -> f64 {
-        let mut sum = 0f64;
-        /* in 4d there are 6 plaquettes per vertex */
-        let num_plaquettes = (6 * self.width.pow(4)) as f64;
+        return self.plaquette_average(1.0);
+    }
+
+    /* average of 1 - cos(2 theta_P) per plaquette, the observable of the double charge term */
+    pub fn average_double_action(&self) -> f64 {
+        return self.plaquette_average(2.0);
+    }
+
+    /* average action of the plaquettes in every (mu, nu) plane, symmetric with zeros on the
+     * diagonal. Every plane has one plaquette per site, the time slices i are summed in parallel
+     * and added in order as in plaquette_average */
+    pub fn average_action_by_plane(&self) -> [[f64; 4]; 4] {
+        let slice_volume = self.volume() / self.dims[0];
+        let slice_sums: Vec<[[f64; 4]; 4]> = (0..self.dims[0])
+            .into_par_iter()
+            .map(|i| {
+                let mut sums = [[0f64; 4]; 4];
+                for site in (i * slice_volume..(i + 1) * slice_volume).map(|index| self.site(index)) {
+                    for &(m, n) in PLANES.iter() {
+                        sums[m][n] += 1.0 - self.plaquette_angle(site, m, n).cos();
+                    }
+                }
+                sums
+            })
+            .collect();
+
+        let mut averages = [[0f64; 4]; 4];
+        for &(m, n) in PLANES.iter() {
+            averages[m][n] = slice_sums.iter().map(|sums| sums[m][n]).sum::<f64>() / self.volume() as f64;
+            averages[n][m] = averages[m][n];
+        }
+        return averages;
+    }
+
+    /* average action of the planes (0, 1), (0, 2) and (1, 2) that do not contain the time direction */
+    pub fn spatial_average_action(&self) -> f64 {
+        return spatial_average(&self.average_action_by_plane());
+    }
+
+    /* average action of the planes (mu, 3) containing the time direction */
+    pub fn temporal_average_action(&self) -> f64 {
+        return temporal_average(&self.average_action_by_plane());
+    }
+
+    /* average of 1 - cos(charge theta_P) over all plaquettes */
+    fn plaquette_average(&self, charge: f64) -> f64 {
+        /* in 4d there are 6 plaquettes per vertex, counted in floating point since 6 nx ny nz nt
+         * overflows usize long before the f64 loses precision that matters here */
+        let num_plaquettes = 6.0 * self.dims.iter().map(|&extent| extent as f64).product::<f64>();
+        return self.plaquette_sum(charge) / num_plaquettes;
+    }
 
+    /* sum of 1 - cos(theta_P) over all plaquettes, the Wilson action without the coupling. This
+     * is the S of the Boltzmann weight exp(-beta S) of an isotropic pure gauge run */
+    pub fn total_action(&self) -> f64 {
+        return self.plaquette_sum(1.0);
+    }
+
+    /* sum of 1 - cos(charge theta_P) over all plaquettes. The time slices i are summed in parallel
+     * and their partial sums added in order, so the result does not depend on the number of
+     * threads */
+    fn plaquette_sum(&self, charge: f64) -> f64 {
         /* Sum over all vertices and plaquettes at those vertices */
-        for i in 0..self.width {
-            for j in 0..self.width {
-                for k in 0..self.width {
-                    for l in 0..self.width {
-                        for m in 0..3 {
-                            for n in m + 1..4 {
-                                let phase1 = self.lattice[i][j][k][l].phases[m]; /* U_\mu(n) */
-                                let phase2 = self.lattice[(i + UNIT_VECTORS[m][0]) % self.width]
-                                    [(j + UNIT_VECTORS[m][1]) % self.width]
-                                    [(k + UNIT_VECTORS[m][2]) % self.width]
-                                    [(l + UNIT_VECTORS[m][3]) % self.width]
-                                    .phases[n]; /* U_\nu(n+ \hat{\mu}) */
-                                let phase3 = self.lattice[(i + UNIT_VECTORS[n][0]) % self.width]
-                                    [(j + UNIT_VECTORS[n][1]) % self.width]
-                                    [(k + UNIT_VECTORS[n][2]) % self.width]
-                                    [(l + UNIT_VECTORS[n][3]) % self.width]
-                                    .phases[m]; /* U_\mu(n+ \hat{\nu}) */
-                                let phase4 = self.lattice[i][j][k][l].phases[n];
-                                /* U_\nu(n) */
-
-                                sum += 1.0 - (phase1 + phase2 - phase3 - phase4).cos();
-                                /* take complex conjugate of last two */
-                            }
+        let slice_volume = self.volume() / self.dims[0];
+        let slice_sums: Vec<f64> = (0..self.dims[0])
+            .into_par_iter()
+            .map(|i| {
+                let mut sum = 0f64;
+                for site in (i * slice_volume..(i + 1) * slice_volume).map(|index| self.site(index)) {
+                    for m in 0..3 {
+                        for n in m + 1..4 {
+                            sum += 1.0 - (charge * self.plaquette_angle(site, m, n)).cos();
                         }
                     }
                 }
+                sum
+            })
+            .collect();
+
+        return slice_sums.iter().sum::<f64>();
+    }
+
+    /* average action of the plaquettes in each of blocks_per_dim^4 regions, every extent is cut
+     * into blocks_per_dim blocks. Every plaquette belongs to the region containing its base site n,
+     * regions are ordered lexicographically by block coordinates */
+    pub fn region_plaquette_averages(&self, blocks_per_dim: usize) -> Vec<f64> {
+        assert!(
+            blocks_per_dim > 0 && self.dims.iter().all(|extent| extent % blocks_per_dim == 0),
+            "blocks per dimension must divide every lattice extent"
+        );
+        let block_dims = self.dims.map(|extent| extent / blocks_per_dim);
+        let mut sums = vec![0f64; blocks_per_dim.pow(4)];
+        let plaquettes_per_region = (6 * block_dims.iter().product::<usize>()) as f64;
+
+        for site in self.sites() {
+            let [i, j, k, l] = site.coords();
+            let region = ((i / block_dims[0] * blocks_per_dim + j / block_dims[1]) * blocks_per_dim + k / block_dims[2])
+                * blocks_per_dim
+                + l / block_dims[3];
+
+            for m in 0..3 {
+                for n in m + 1..4 {
+                    sums[region] += 1.0 - self.plaquette_angle(site, m, n).cos();
+                }
+            }
+        }
+
+        return sums.iter().map(|sum| sum / plaquettes_per_region).collect();
+    }
+
+    /* sum of the phases of the `length` links from the site along mu and the site where the line
+     * ends, wrapping around the torus as often as needed */
+    fn line_phase(&self, site: usize, mu: usize, length: usize) -> (f64, usize) {
+        let mut phase = 0f64;
+        let mut end = site;
+        for _ in 0..length {
+            phase += self.lattice[end].phases[mu];
+            end = self.neighbor_up[end][mu];
+        }
+        return (phase, end);
+    }
+
+    /* average of cos of the r x t Wilson loop with sides r along mu and t along nu over all base
+     * sites, for any r and t since the sides simply wrap around the torus. The time slices are
+     * summed in parallel and added in order like the average action */
+    pub fn wilson_loop(&self, r: usize, t: usize, mu: usize, nu: usize) -> f64 {
+        assert!(r > 0 && t > 0 && mu != nu && mu < 4 && nu < 4, "invalid Wilson loop");
+        let sites_per_slice = self.volume() / self.dims[0];
+
+        let slice_sums: Vec<f64> = (0..self.dims[0])
+            .into_par_iter()
+            .map(|i| {
+                (i * sites_per_slice..(i + 1) * sites_per_slice)
+                    .map(|site| {
+                        let (bottom, corner) = self.line_phase(site, mu, r);
+                        let (right, _) = self.line_phase(corner, nu, t);
+                        let (left, corner) = self.line_phase(site, nu, t);
+                        let (top, _) = self.line_phase(corner, mu, r);
+                        (bottom + right - top - left).cos()
+                    })
+                    .sum::<f64>()
+            })
+            .collect();
+
+        return slice_sums.iter().sum::<f64>() / self.volume() as f64;
+    }
+
+    /* product of the `length` integrated links from the site along nu and the site where the line
+     * ends */
+    fn integrated_line(&self, integrated: &[[Complex<f64>; 4]], site: usize, nu: usize, length: usize) -> (Complex<f64>, usize) {
+        let mut product = Complex::new(1.0, 0.0);
+        let mut end = site;
+        for _ in 0..length {
+            product *= integrated[end][nu];
+            end = self.neighbor_up[end][nu];
+        }
+        return (product, end);
+    }
+
+    /* whether the two sides along nu of the r x t loop with sides r along mu can be replaced by
+     * integrated links. They must not share a plaquette, so the sides are at least two sites apart
+     * along mu, also the short way around the torus, and neither side may wind onto itself. The
+     * links of one side never share a plaquette */
+    pub fn integrated_loop_allowed(&self, r: usize, t: usize, mu: usize, nu: usize) -> bool {
+        let offset = r % self.dims[mu];
+        return offset.min(self.dims[mu] - offset) >= 2 && t <= self.dims[nu];
+    }
+
+    /* wilson_loop with the links of both sides along nu replaced by the integrated links, which
+     * measures the same expectation value with less noise. Panics unless integrated_loop_allowed */
+    pub fn integrated_wilson_loop(&self, r: usize, t: usize, mu: usize, nu: usize, integrated: &[[Complex<f64>; 4]]) -> f64 {
+        assert!(r > 0 && t > 0 && mu != nu && mu < 4 && nu < 4, "invalid Wilson loop");
+        assert!(
+            self.integrated_loop_allowed(r, t, mu, nu),
+            "the sides of the {} x {} loop share plaquettes on a lattice with extents {:?}, they can not both be integrated",
+            r, t, self.dims
+        );
+        let sites_per_slice = self.volume() / self.dims[0];
+
+        let slice_sums: Vec<f64> = (0..self.dims[0])
+            .into_par_iter()
+            .map(|i| {
+                (i * sites_per_slice..(i + 1) * sites_per_slice)
+                    .map(|site| {
+                        let (bottom, corner) = self.line_phase(site, mu, r);
+                        let (right, _) = self.integrated_line(integrated, corner, nu, t);
+                        let (left, corner) = self.integrated_line(integrated, site, nu, t);
+                        let (top, _) = self.line_phase(corner, mu, r);
+                        (Complex::from_polar(1.0, bottom - top) * right * left.conj()).re
+                    })
+                    .sum::<f64>()
+            })
+            .collect();
+
+        return slice_sums.iter().sum::<f64>() / self.volume() as f64;
+    }
+
+    /* W(r, t) for 1 <= r <= r_max and 1 <= t <= t_max, at [r - 1][t - 1], averaged over the twelve
+     * ordered pairs of directions. W(1, 1) is 1 - average_action. With a coupling the sides along
+     * the second direction are integrated wherever integrated_loop_allowed, the other loops are
+     * measured as they are */
+    pub fn wilson_loops_up_to(&self, r_max: usize, t_max: usize, integrate_at: Option<f64>) -> Vec<Vec<f64>> {
+        let pairs: Vec<(usize, usize)> = (0..4)
+            .flat_map(|mu| (0..4).filter(move |&nu| nu != mu).map(move |nu| (mu, nu)))
+            .collect();
+        let integrated = integrate_at.map(|beta| self.integrated_links(beta));
+
+        let loop_average = |r: usize, t: usize, mu: usize, nu: usize| match &integrated {
+            Some(integrated) if self.integrated_loop_allowed(r, t, mu, nu) => {
+                self.integrated_wilson_loop(r, t, mu, nu, integrated)
+            }
+            _ => self.wilson_loop(r, t, mu, nu),
+        };
+
+        return (1..=r_max)
+            .map(|r| {
+                (1..=t_max)
+                    .map(|t| {
+                        pairs.iter().map(|&(mu, nu)| loop_average(r, t, mu, nu)).sum::<f64>() / pairs.len() as f64
+                    })
+                    .collect()
+            })
+            .collect();
+    }
+
+    /* product of the links winding once around the lattice along direction, averaged over the
+     * sites of the transverse volume, i.e. those with coordinate 0 along direction */
+    pub fn polyakov_loop(&self, direction: usize) -> Complex<f64> {
+        assert!(direction < 4, "invalid direction {}", direction);
+        let mut sum = Complex::new(0.0, 0.0);
+
+        for site in 0..self.lattice.len() {
+            if self.site_coordinates(site)[direction] == 0 {
+                let (phase, _) = self.line_phase(site, direction, self.dims[direction]);
+                sum += Complex::from_polar(1.0, phase);
             }
         }
 
-        return sum / num_plaquettes;
+        return sum / (self.volume() / self.dims[direction]) as f64;
+    }
+
+    /* oriented angle of the plaquette in the (mu, nu) plane at site n */
+    fn plaquette_angle(&self, site: Site, m: usize, n: usize) -> f64 {
+        return self.plaquette_angle_at(self.position(site), m, n);
+    }
+
+    fn plaquette_angle_at(&self, site: usize, m: usize, n: usize) -> f64 {
+        let phase1 = self.lattice[site].phases[m]; /* U_\mu(n) */
+        let phase2 = self.lattice[self.neighbor_up[site][m]].phases[n]; /* U_\nu(n+ \hat{\mu}) */
+        let phase3 = self.lattice[self.neighbor_up[site][n]].phases[m]; /* U_\mu(n+ \hat{\nu}) */
+        let phase4 = self.lattice[site].phases[n]; /* U_\nu(n) */
+
+        /* take complex conjugate of last two */
+        return phase1 + phase2 - phase3 - phase4;
+    }
+
+    /* DeGrand-Toussaint monopole charge of the elementary 3-cube at a site orthogonal to mu: the
+     * plaquette angles on its boundary are reduced to (-pi, pi] and their oriented sum is 2 pi
+     * times the net number of Dirac strings leaving the cube, since the unreduced angles cancel */
+    pub fn monopole_charge(&self, site: usize, mu: usize) -> i64 {
+        let [a, b, c] = match mu {
+            0 => [1, 2, 3],
+            1 => [0, 2, 3],
+            2 => [0, 1, 3],
+            _ => [0, 1, 2],
+        };
+        /* boundary of the cube spanned by a < b < c, the face without direction d is taken with
+         * sign (-1)^d at n + d minus at n */
+        let faces = [(a, b, c, 1.0), (b, a, c, -1.0), (c, a, b, 1.0)];
+
+        let mut flux = 0f64;
+        for (shift, first, second, sign) in faces {
+            let far = principal_angle(self.plaquette_angle_at(self.neighbor_up[site][shift], first, second));
+            let near = principal_angle(self.plaquette_angle_at(site, first, second));
+            flux += sign * (far - near);
+        }
+
+        let charge = flux / (2.0 * PI);
+        debug_assert!((charge - charge.round()).abs() < 1e-6, "monopole flux {} is not quantized", charge);
+        return charge.round() as i64;
+    }
+
+    /* average |monopole charge| per elementary 3-cube, over the four cubes orthogonal to each
+     * direction at every site */
+    pub fn monopole_density(&self) -> f64 {
+        let total: i64 = (0..self.lattice.len())
+            .into_par_iter()
+            .map(|site| (0..4).map(|mu| self.monopole_charge(site, mu).abs()).sum::<i64>())
+            .sum();
+        return total as f64 / (4 * self.lattice.len()) as f64;
+    }
+
+    /* naive topological charge density q(n) = 1/(32 pi^2) eps_{mu nu rho sigma} F_{mu nu}(n) F_{rho sigma}(n)
+     * built from principal-branch plaquette angles, sites in lexicographic order. On the torus
+     * the sum approaches n_01 n_23 - n_02 n_13 + n_03 n_12 for smooth fields with fluxes 2 pi n_{mu nu} */
+    pub fn topological_charge_density(&self) -> Vec<f64> {
+        let mut density = Vec::with_capacity(self.volume());
+
+        for site in self.sites() {
+            let field_strength = |m: usize, n: usize| principal_angle(self.plaquette_angle(site, m, n));
+
+            /* the 24 terms of the epsilon contraction reduce to 8 times these three */
+            density.push(
+                (field_strength(0, 1) * field_strength(2, 3) - field_strength(0, 2) * field_strength(1, 3)
+                    + field_strength(0, 3) * field_strength(1, 2))
+                    / (4.0 * PI * PI),
+            );
+        }
+
+        return density;
+    }
+
+    pub fn topological_charge(&self) -> f64 {
+        return self.topological_charge_density().iter().sum();
+    }
+
+    /* expectation value of U_mu(n) with all other links fixed under the Wilson action at coupling
+     * beta, e^{-i arg(S)} I_1(beta |S|) / I_0(beta |S|) for the staple sum S; replaces the link in
+     * estimators as long as no two replaced links share a plaquette */
+    pub fn integrated_link(&self, site: Site, direction: usize, beta: f64) -> Complex<f64> {
+        let staple = self.staple_sum(site, direction);
+        return Complex::from_polar(bessel_ratio(beta * staple.abs()), -staple.arg());
+    }
+
+    /* integrated_link of every link, indexed like the lattice */
+    pub fn integrated_links(&self, beta: f64) -> Vec<[Complex<f64>; 4]> {
+        return self.sites().map(|site| [0, 1, 2, 3].map(|mu| self.integrated_link(site, mu, beta))).collect();
+    }
+
+    /* staple sum S of the link U_mu(n), the sum of the other three links of every plaquette that
+     * contains it. The plaquette angle is theta_P(n) = theta_mu(n) + theta_nu(n + mu)
+     * - theta_mu(n + nu) - theta_nu(n) for mu < nu, and
+     *   S = sum_{nu != mu} e^{i (theta_nu(n + mu) - theta_mu(n + nu) - theta_nu(n))}
+     *                    + e^{i (theta_nu(n - nu) - theta_mu(n - nu) - theta_nu(n - nu + mu))}
+     * so that e^{i theta_mu(n)} S is the sum of e^{+-i theta_P} over the six plaquettes through
+     * the link, each with its own orientation. The link only enters the Wilson action through
+     * -beta Re(e^{i theta_mu(n)} S), and changing it from theta to theta' changes
+     * sum_P (1 - cos(theta_P)) by -Re((e^{i theta'} - e^{i theta}) S). S does not depend on the
+     * link itself, the heatbath draws theta with weight exp(beta |S| cos(theta + arg(S))) */
+    pub fn staple_sum(&self, site: Site, direction: usize) -> Complex<f64> {
+        assert_eq!(
+            site.dims, self.dims,
+            "site of a lattice with extents {:?} used on a lattice with extents {:?}",
+            site.dims, self.dims
+        );
+        assert!(direction < 4, "direction {} out of range, expected 0 to 3", direction);
+        let [i, j, k, l] = site.coords;
+        return self.charged_plaquettes_without_link(i, j, k, l, direction, 1.0);
+    }
+
+    pub(crate) fn plaquettes_without_link(
+        &self,
+        i: usize,
+        j: usize,
+        k: usize,
+        l: usize,
+        m: usize,
+    ) -> Complex<f64> {
+        return self.staple_sum(Site { coords: [i, j, k, l], dims: self.dims }, m);
+    }
+
+    /* staple with the two plaquettes of every plane (mu, nu) weighted by weights[nu], so that the
+     * plaquettes through U_mu(n) contribute sum_P w_P cos(theta_P) = Re(e^{i theta} staple). An
+     * anisotropic action folds its couplings in here */
+    pub(crate) fn weighted_plaquettes_without_link(
+        &self,
+        i: usize,
+        j: usize,
+        k: usize,
+        l: usize,
+        m: usize,
+        weights: [f64; 4],
+    ) -> Complex<f64> {
+        return self.weighted_charged_staple(self.site_index(i, j, k, l), m, 1.0, weights);
     }
 
-    fn plaquettes_without_link(
+    /* staple of the plaquettes taken to the power charge, so that the plaquettes through U_mu(n)
+     * contribute sum_P cos(charge theta_P) = Re(e^{i charge theta} staple) */
+    pub(crate) fn charged_plaquettes_without_link(
         &self,
         i: usize,
         j: usize,
         k: usize,
         l: usize,
         m: usize,
+        charge: f64,
     ) -> Complex<f64> {
+        return self.weighted_charged_staple(self.site_index(i, j, k, l), m, charge, [1.0; 4]);
+    }
+
+    fn weighted_charged_staple(&self, site: usize, m: usize, charge: f64, weights: [f64; 4]) -> Complex<f64> {
         let mut lambda_sum = Complex::from_polar(0.0, 0.0);
 
-        for n in 0..4 {
+        for (n, &weight) in weights.iter().enumerate() {
             if m != n {
-                let phase1 = self.lattice[(i + UNIT_VECTORS[m][0]) % self.width]
-                    [(j + UNIT_VECTORS[m][1]) % self.width][(k + UNIT_VECTORS[m][2]) % self.width]
-                    [(l + UNIT_VECTORS[m][3]) % self.width]
-                    .phases[n]; /* U_\nu(n+ \hat{\mu}) */
-                let phase2 = self.lattice[(i + UNIT_VECTORS[n][0]) % self.width]
-                    [(j + UNIT_VECTORS[n][1]) % self.width][(k + UNIT_VECTORS[n][2]) % self.width]
-                    [(l + UNIT_VECTORS[n][3]) % self.width]
-                    .phases[m]; /* U_\mu(n+ \hat{\nu}) */
-                let phase3 = self.lattice[i][j][k][l].phases[n]; /* U_\mu(n) */
-
-                let lambda1 = Complex::from_polar(1.0, phase1 - phase2 - phase3);
+                let phase1 = self.lattice[self.neighbor_up[site][m]].phases[n]; /* U_\nu(n+ \hat{\mu}) */
+                let phase2 = self.lattice[self.neighbor_up[site][n]].phases[m]; /* U_\mu(n+ \hat{\nu}) */
+                let phase3 = self.lattice[site].phases[n]; /* U_\nu(n) */
+
+                let lambda1 = Complex::from_polar(weight, charge * (phase1 - phase2 - phase3));
                 lambda_sum += lambda1;
 
-                let phase4 = self.lattice[(i + self.width - UNIT_VECTORS[n][0]) % self.width]
-                    [(j + self.width - UNIT_VECTORS[n][1]) % self.width]
-                    [(k + self.width - UNIT_VECTORS[n][2]) % self.width]
-                    [(l + self.width - UNIT_VECTORS[n][3]) % self.width]
-                    .phases[m]; /* U_\mu(n+ \hat{\nu}) */
-                let phase5 = self.lattice
-                    [(i + self.width - UNIT_VECTORS[n][0] + UNIT_VECTORS[m][0]) % self.width]
-                    [(j + self.width - UNIT_VECTORS[n][1] + UNIT_VECTORS[m][1]) % self.width]
-                    [(k + self.width - UNIT_VECTORS[n][2] + UNIT_VECTORS[m][2]) % self.width]
-                    [(l + self.width - UNIT_VECTORS[n][3] + UNIT_VECTORS[m][3]) % self.width]
-                    .phases[n]; /* U_\nu(n - \hat{\nu} + \hat{\mu}) */
-                let phase6 = self.lattice[(i + self.width - UNIT_VECTORS[n][0]) % self.width]
-                    [(j + self.width - UNIT_VECTORS[n][1]) % self.width]
-                    [(k + self.width - UNIT_VECTORS[n][2]) % self.width]
-                    [(l + self.width - UNIT_VECTORS[n][3]) % self.width]
-                    .phases[n]; /* U_\nu(n - \hat{\nu}) */
-
-                let lambda2 = Complex::from_polar(1.0, -phase4 - phase5 + phase6);
+                let back = self.neighbor_down[site][n];
+                let phase4 = self.lattice[back].phases[m]; /* U_\mu(n - \hat{\nu}) */
+                let phase5 = self.lattice[self.neighbor_up[back][m]].phases[n]; /* U_\nu(n - \hat{\nu} + \hat{\mu}) */
+                let phase6 = self.lattice[back].phases[n]; /* U_\nu(n - \hat{\nu}) */
+
+                let lambda2 = Complex::from_polar(weight, charge * (-phase4 - phase5 + phase6));
                 lambda_sum += lambda2;
             }
         }
         return lambda_sum;
     }
 
-    pub fn heatbath_sweep(&mut self, beta: f64, rng: &mut Rng) {
-        for i in 0..self.width {
-            for j in 0..self.width {
-                for k in 0..self.width {
-                    for l in 0..(self.width) {
-                        for m in 0..4 {
-                            let other_plaquettes = self.plaquettes_without_link(i, j, k, l, m);
-                            let alpha = other_plaquettes.abs();
-                            let theta_0 = -other_plaquettes.arg();
+    pub fn heatbath_sweep(&mut self, couplings: Couplings, rng: &mut Rng) {
+        self.heatbath_sweep_with_action(&WilsonAction { couplings }, rng);
+    }
 
-                            let new_theta = sample_theta(alpha, beta, rng);
+    pub fn heatbath_sweep_with_action<A: LocalAction>(&mut self, action: &A, rng: &mut Rng) {
+        if self.checkerboard {
+            self.checkerboard_sweep(action, rng);
+            return;
+        }
+        let mut updates = 0;
 
-                            (*self).lattice[i][j][k][l].phases[m] = new_theta + theta_0;
-                        }
-                    }
-                }
-            }
+        for (site, m) in self.links() {
+            let [i, j, k, l] = site.coords();
+            let environment = action.link_environment(self, site, m);
+            let new_theta = sample_link(&environment, rng);
+
+            let index = self.position(site);
+            let old_theta = self.lattice[index].phases[m];
+            self.lattice[index].phases[m] = new_theta;
+            self.track_link_update(environment.plaquette_staple, index, m, old_theta, new_theta);
+            self.mark_updated(i, j, k, l, m);
+            updates += 1;
         }
-    }
 
-    pub fn visualize_3d_lattice(&self, file: &mut File) -> anyhow::Result<()>  {
-        writeln!(file, "\\tdplotsetmaincoords{{22}}{{22}}")?;
-        writeln!(file, "\\begin{{tikzpicture}}[tdplot_main_coords]")?;
-        let plane_index = self.width / 2; // take a plane somewhere in the middle
+        /* cheap invariant kept in every build, the paranoid bitset also catches a link updated
+         * twice while another one is skipped */
+        assert_eq!(
+            updates,
+            4 * self.volume(),
+            "heatbath sweep made {} link updates instead of 4 V",
+            updates
+        );
+        self.check_all_updated();
+        self.end_tracked_sweep();
+    }
 
-        for i in 0..self.width {
-            for j in 0..self.width {
-                for k in 0..self.width{
-                writeln!(file, "\\filldraw[black] ({},{},{}) circle (2pt) ;", i,j,k)?;
-                let color_x1 = phase_to_rgb(self.lattice[i][j][k][plane_index].phases[0]);
-                let color_x2 = phase_to_rgb(self.lattice[i][j][k][plane_index].phases[1]);
-                let color_x3 = phase_to_rgb(self.lattice[i][j][k][plane_index].phases[2]);
+    /* 6 - |staple| of the link U_mu at a site, large where the plaquettes around the link disagree
+     * and its conditional distribution is broad. Independent of the link itself, the default score
+     * of targeted updates */
+    pub fn staple_deficit(&self, site: usize, mu: usize) -> f64 {
+        let [i, j, k, l] = self.site_coordinates(site);
+        return 6.0 - self.plaquettes_without_link(i, j, k, l, mu).abs();
+    }
 
-                writeln!(file, "\\definecolor{{color{}{}{}1}}{{RGB}}{{{},{},{}}} ;",i,j,k, color_x1.0, color_x1.1, color_x1.2)?;
-                writeln!(file, "\\draw[color{0}{1}{2}1, thick] ({0},{1},{2}) -- ({3},{1},{2}) ;",i,j,k,i+1)?;
-                writeln!(file, "\\definecolor{{color{}{}{}2}}{{RGB}}{{{},{},{}}} ;",i,j,k, color_x2.0, color_x2.1, color_x2.2)?;
-                writeln!(file, "\\draw[color{0}{1}{2}2, thick] ({0},{1},{2}) -- ({0},{3},{2}) ;",i,j,k,j+1)?;
+    /* `hits` extra heatbath updates of every link whose current score reaches the threshold of
+     * the scores, returns the number of updates. Whether a link is hit only depends on the other
+     * links and the threshold, so for a fixed threshold every hit is a heatbath update mixed with
+     * the identity by a weight independent of the updated link, which leaves the Boltzmann weight
+     * invariant. A threshold refreshed from the configuration depends on the very links it selects
+     * and biases the chain, so it must stay frozen while measuring, see Simulation::refresh_targeting */
+    pub fn targeted_hits(&mut self, couplings: Couplings, scores: &LinkScores, hits: usize, rng: &mut Rng) -> usize {
+        let action = WilsonAction { couplings };
+        let threshold = scores.threshold();
+        let mut updates = 0;
 
-                writeln!(file, "\\definecolor{{color{}{}{}3}}{{RGB}}{{{},{},{}}} ;",i,j,k, color_x3.0, color_x3.1, color_x3.2)?;
-                writeln!(file, "\\draw[color{0}{1}{2}3, thick] ({0},{1},{2}) -- ({0},{1},{3}) ;",i,j,k,k+1)?;
+        for site in 0..self.lattice.len() {
+            for mu in 0..4 {
+                if scores.score(self, site, mu) < threshold {
+                    continue;
+                }
+                for _ in 0..hits {
+                    let environment = action.link_environment(self, self.site(site), mu);
+                    let old_theta = self.lattice[site].phases[mu];
+                    let new_theta = sample_link(&environment, rng);
+                    self.lattice[site].phases[mu] = new_theta;
+                    self.track_link_update(environment.plaquette_staple, site, mu, old_theta, new_theta);
+                    updates += 1;
+                }
             }
         }
+
+        return updates;
     }
-        writeln!(file, "\\end{{tikzpicture}}")?;
-        Ok(())
+
+    /* the reflection does not depend on the coupling, any beta gives the same sweep */
+    pub fn overrelaxation_sweep(&mut self) {
+        self.overrelaxation_sweep_with_action(&WilsonAction::isotropic(1.0));
     }
 
-    pub fn visualize_plaquettes_plane(&self, file: &mut File) -> anyhow::Result<()> {
-        writeln!(file, "\\begin{{tikzpicture}}")?;
-        let plane_index = self.width/2;
+    /* reflect every link in turn about the maximum of its conditional weight, theta -> 2 theta_0 - theta
+     * with theta_0 = -arg(staple), which leaves Re(e^{i theta} staple) and so the action unchanged.
+     * Deterministic and not ergodic on its own, it only decorrelates when mixed with a heatbath.
+     * A double charge term is not symmetric about theta_0, so the action must not have one */
+    pub fn overrelaxation_sweep_with_action<A: LocalAction>(&mut self, action: &A) {
+        for (site, m) in self.links() {
+            let [i, j, k, l] = site.coords();
+            let environment = action.link_environment(self, site, m);
+            assert!(
+                environment.double_coupling == 0.0,
+                "overrelaxation does not preserve a double charge term"
+            );
+            let theta_0 = -environment.staple.arg();
 
-        for i in 0..self.width {
-            for j in 0..self.width {
+            let index = self.position(site);
+            let old_theta = self.lattice[index].phases[m];
+            let new_theta = principal_angle(2.0 * theta_0 - old_theta);
+            self.lattice[index].phases[m] = new_theta;
+            self.track_link_update(environment.plaquette_staple, index, m, old_theta, new_theta);
+            self.mark_updated(i, j, k, l, m);
+        }
 
-                let mut plaquette = self.lattice[i][j][plane_index][plane_index].phases[0]
-                                        +self.lattice[(i + 1) % self.width][j][plane_index][plane_index].phases[1]
-                                        -self.lattice[i][(j + 1) % self.width][plane_index][plane_index].phases[0]
-                                        -self.lattice[i][j][plane_index][plane_index].phases[1];
+        self.check_all_updated();
+        self.end_tracked_sweep();
+    }
 
-                while plaquette < 0.0 {
-                    plaquette += 2.0*PI;
-                }
+    pub fn metropolis_sweep(&mut self, beta: f64, step: f64, rng: &mut Rng) -> MetropolisStats {
+        return self.metropolis_sweep_with_action(&WilsonAction::isotropic(beta), step, rng);
+    }
 
-                while plaquette > 2.0 *PI {
-                    plaquette -= 2.0 * PI;
-                }
+    /* propose theta -> theta + step * (2u - 1) for every link in turn and accept with
+     * min(1, exp(-delta S)), where delta S only involves the staple of the link */
+    pub fn metropolis_sweep_with_action<A: LocalAction>(
+        &mut self,
+        action: &A,
+        step: f64,
+        rng: &mut Rng,
+    ) -> MetropolisStats {
+        let mut stats = MetropolisStats::default();
 
-                let color = phase_to_rgb(plaquette);
-                writeln!(file, "\\definecolor{{color{}{}}}{{RGB}}{{{},{},{}}} ;",i,j, color.0, color.1, color.2)?;
-                writeln!(file, "\\fill[color{0}{1}] ({0},{1}) rectangle ({2},{3}) ;",i,j,i+1,j+1)?;
-            }
-        }
+        for (site, m) in self.links() {
+            let [i, j, k, l] = site.coords();
+            let environment = action.link_environment(self, site, m);
+            let index = self.position(site);
+            let old_theta = self.lattice[index].phases[m];
+            let new_theta = old_theta + step * (2.0 * rng.f64() - 1.0);
 
-        for i in 0..self.width {
-            for j in 0..self.width {
-                writeln!(file, "\\filldraw[black] ({},{}) circle (2pt) ;", i ,j)?;
+            /* the link enters the action as -coupling * Re(e^{i theta} staple)
+             * - double_coupling * Re(e^{2 i theta} double_staple) */
+            let change = Complex::from_polar(1.0, new_theta) - Complex::from_polar(1.0, old_theta);
+            let double_change = Complex::from_polar(1.0, 2.0 * new_theta) - Complex::from_polar(1.0, 2.0 * old_theta);
+            let delta_action = -environment.coupling * (change * environment.staple).re
+                - environment.double_coupling * (double_change * environment.double_staple).re;
+
+            stats.proposals += 1;
+            if delta_action <= 0.0 || rng.f64() < (-delta_action).exp() {
+                self.lattice[index].phases[m] = principal_angle(new_theta);
+                self.track_link_update(environment.plaquette_staple, index, m, old_theta, new_theta);
+                stats.accepted += 1;
             }
+            self.mark_updated(i, j, k, l, m);
         }
-        writeln!(file,"\\end{{tikzpicture}}")?;
-        Ok(())
+
+        assert_eq!(
+            stats.proposals,
+            4 * self.volume(),
+            "metropolis sweep made {} link proposals instead of 4 V",
+            stats.proposals
+        );
+        self.check_all_updated();
+        self.end_tracked_sweep();
+        return stats;
     }
 
-    pub fn visualize_plaquettes_plane_svg(&self, file: &mut File) -> anyhow::Result<()> {
-        writeln!(file, "<svg width=\"{0}\" height=\"{0}\">", 50*self.width+20)?;
-        let plane_index = self.width/2;
+    /* switch between the sequential sweep and the parallel checkerboard sweep, which needs even
+     * extents so that neighbors across the periodic boundary have opposite parity */
+    pub fn set_checkerboard(&mut self, checkerboard: bool) {
+        assert!(
+            !checkerboard || self.dims.iter().all(|extent| extent % 2 == 0),
+            "the checkerboard sweep needs even lattice extents"
+        );
+        self.checkerboard = checkerboard;
+    }
 
-        for i in 0..self.width {
-            for j in 0..self.width {
+    /* the staple of U_mu(n) only holds links in other directions and U_mu at sites of the opposite
+     * parity, so all links of one direction on sites of one parity are updated at once. Each link
+     * draws from its own stream seeded from the chain, which makes the sweep independent of the
+     * number of threads, but it is a different chain from the sequential sweep */
+    fn checkerboard_sweep<A: LocalAction>(&mut self, action: &A, rng: &mut Rng) {
+        let num_sites = self.volume();
+        let mut updates = 0;
 
-                let mut plaquette = self.lattice[i][j][plane_index][plane_index].phases[0]
-                +self.lattice[(i + 1) % self.width][j][plane_index][plane_index].phases[1]
-                -self.lattice[i][(j + 1) % self.width][plane_index][plane_index].phases[0]
-                -self.lattice[i][j][plane_index][plane_index].phases[1];
+        for m in 0..4 {
+            for parity in 0..2 {
+                let color_seed = rng.u64(..);
+                let lattice = &*self;
+                let new_phases: Vec<(usize, f64, Option<Complex<f64>>)> = (0..num_sites)
+                    .into_par_iter()
+                    .filter(|&site| lattice.site_coordinates(site).iter().sum::<usize>() % 2 == parity)
+                    .map(|site| {
+                        let environment = action.link_environment(lattice, lattice.site(site), m);
+                        let mut link_rng = Rng::with_seed(mix_seed(color_seed, site as u64));
+                        (site, sample_link(&environment, &mut link_rng), environment.plaquette_staple)
+                    })
+                    .collect();
 
-                while plaquette < 0.0 {
-                    plaquette += 2.0*PI;
+                /* the staples do not hold links of the updated color, so the changes add up */
+                for (site, phase, plaquette_staple) in new_phases {
+                    let [i, j, k, l] = self.site_coordinates(site);
+                    let old_theta = self.lattice[site].phases[m];
+                    self.lattice[site].phases[m] = phase;
+                    self.track_link_update(plaquette_staple, site, m, old_theta, phase);
+                    self.mark_updated(i, j, k, l, m);
+                    updates += 1;
                 }
+            }
+        }
 
-                while plaquette > 2.0 *PI {
-                    plaquette -= 2.0 * PI;
-                }
+        assert_eq!(
+            updates,
+            4 * num_sites,
+            "checkerboard sweep made {} link updates instead of 4 V",
+            updates
+        );
+        self.check_all_updated();
+        self.end_tracked_sweep();
+    }
 
-                let (r,g,b) = phase_to_rgb(plaquette);
-                writeln!(file, "<rect x=\"{0}\" y=\"{1}\" width=\"50\" height=\"50\" fill=\"#{r:02X?}{g:02X?}{b:02X?}\"/>",i*50+10, j*50+10)?;
-            }
+    /* track every link update with a bitset and panic as soon as a sweep updates a link twice or
+     * leaves one out, for catching indexing bugs that the average action does not reveal */
+    pub fn set_paranoid(&mut self, paranoid: bool) {
+        self.updated = paranoid.then(|| vec![false; 4 * self.volume()]);
+    }
+
+    fn mark_updated(&mut self, i: usize, j: usize, k: usize, l: usize, m: usize) {
+        let index = self.site_index(i, j, k, l) * 4 + m;
+        if let Some(updated) = self.updated.as_mut() {
+            assert!(
+                !updated[index],
+                "link ({}, {}, {}, {}) in direction {} was updated twice in one sweep",
+                i, j, k, l, m
+            );
+            updated[index] = true;
         }
+    }
 
-        for i in 0..self.width {
-            for j in 0..self.width {
-                writeln!(file, "<circle cx=\"{}\" cy=\"{}\" r=\"5\" fill=\"#FFFFF\"/>", i*50+10 ,j*50+10)?;
+    /* end of a sweep in paranoid mode, every link must have been updated, reset for the next one */
+    fn check_all_updated(&mut self) {
+        if let Some(updated) = self.updated.as_mut() {
+            if let Some(index) = updated.iter().position(|updated| !updated) {
+                let [i, j, k, l] = self.site_coordinates(index / 4);
+                panic!(
+                    "link ({}, {}, {}, {}) in direction {} was not updated in the sweep",
+                    i,
+                    j,
+                    k,
+                    l,
+                    index % 4
+                );
             }
+            updated.fill(false);
         }
-        writeln!(file,"</svg>")?;
-        Ok(())
     }
-}
 
-fn acceptance_probability(x: f64, prefactor: f64) -> f64 {
-    return ((((PI/2.0)*(1.0-x)).cos() - x) * prefactor).exp() / (ACCEPTANCE_CONSTANT * prefactor).exp();
-}
+    /* switch the incremental mode on, resynchronizing every resync_interval sweeps, or off. The
+     * tracked action then costs one staple per update only for actions whose environment does not
+     * hold the unweighted staple, the anisotropic ones */
+    pub fn set_action_tracking(&mut self, resync_interval: Option<usize>) {
+        self.tracked_action = resync_interval.map(|resync_interval| {
+            assert!(resync_interval > 0, "the tracked action needs a resync interval of at least 1 sweep");
+            ActionTracker {
+                total: self.total_action(),
+                resync_interval,
+                sweeps_since_resync: 0,
+            }
+        });
+    }
 
-pub fn sample_theta(alpha: f64, beta: f64, rng: &mut Rng) -> f64 {
-    let prefactor = alpha * beta;
+    /* the total action kept up to date by the link updates, None outside the incremental mode */
+    pub fn tracked_action(&self) -> Option<f64> {
+        return self.tracked_action.as_ref().map(|tracker| tracker.total);
+    }
+
+    /* resynchronize the tracked action with the sum over all plaquettes */
+    pub fn recompute(&mut self) {
+        let total = self.total_action();
+        if let Some(tracker) = self.tracked_action.as_mut() {
+            tracker.total = total;
+            tracker.sweeps_since_resync = 0;
+        }
+    }
+
+    /* changing the link from old_theta to new_theta changes sum_P (1 - cos(theta_P)) by
+     * -Re((e^{i new_theta} - e^{i old_theta}) S) with S the unweighted staple, see staple_sum */
+    fn track_link_update(
+        &mut self,
+        plaquette_staple: Option<Complex<f64>>,
+        site: usize,
+        m: usize,
+        old_theta: f64,
+        new_theta: f64,
+    ) {
+        if self.tracked_action.is_none() {
+            return;
+        }
+        let staple = plaquette_staple.unwrap_or_else(|| self.weighted_charged_staple(site, m, 1.0, [1.0; 4]));
+        let change = Complex::from_polar(1.0, new_theta) - Complex::from_polar(1.0, old_theta);
+        if let Some(tracker) = self.tracked_action.as_mut() {
+            tracker.total -= (change * staple).re;
+        }
+    }
+
+    fn end_tracked_sweep(&mut self) {
+        let resync = self.tracked_action.as_mut().is_some_and(|tracker| {
+            tracker.sweeps_since_resync += 1;
+            tracker.sweeps_since_resync >= tracker.resync_interval
+        });
+        if resync {
+            self.recompute();
+        }
+    }
+
+    /* global Metropolis move shifting every link by a random amount that is constant on blocks of
+     * block^4 sites (per direction), uniform in [-amplitude, amplitude], which keeps the proposal
+     * symmetric. Only the plaquettes crossing a block boundary change, so the change of the action
+     * sum_P beta_P (1 - cos(theta_P)) + gamma (1 - cos(2 theta_P)) is summed over those before
+     * any link is shifted. Returns whether the move was accepted */
+    pub fn coarse_update(
+        &mut self,
+        couplings: Couplings,
+        gamma: f64,
+        block: usize,
+        amplitude: f64,
+        rng: &mut Rng,
+    ) -> bool {
+        assert!(
+            block > 0 && self.dims.iter().all(|extent| extent % block == 0),
+            "block size must divide every lattice extent"
+        );
+        let blocks = self.dims.map(|extent| extent / block);
+
+        let shifts: Vec<[f64; 4]> = (0..blocks.iter().product::<usize>())
+            .map(|_| {
+                let mut shift = [0f64; 4];
+                for value in shift.iter_mut() {
+                    *value = amplitude * (2.0 * rng.f64() - 1.0);
+                }
+                shift
+            })
+            .collect();
+        let block_of: Vec<usize> = self
+            .sites()
+            .map(|site| {
+                let [i, j, k, l] = site.coords();
+                ((i / block * blocks[1] + j / block) * blocks[2] + k / block) * blocks[3] + l / block
+            })
+            .collect();
+
+        let (action_change, plaquette_change) = self.coarse_shift_change(couplings, gamma, &block_of, &shifts);
+        if rng.f64() >= (-action_change).exp() {
+            return false;
+        }
+        self.apply_coarse_shift(&block_of, &shifts);
+        if let Some(tracker) = self.tracked_action.as_mut() {
+            tracker.total += plaquette_change;
+        }
+        return true;
+    }
+
+    /* change of the action and of the unweighted plaquette sum if the links at every site were
+     * shifted by the shifts of its block, from the plaquettes crossing a block boundary */
+    fn coarse_shift_change(&self, couplings: Couplings, gamma: f64, block_of: &[usize], shifts: &[[f64; 4]]) -> (f64, f64) {
+        let mut action_change = 0f64;
+        let mut plaquette_change = 0f64;
+        for site in 0..self.lattice.len() {
+            let up = self.neighbor_up[site];
+            for &(m, n) in PLANES.iter() {
+                let (here, up_m, up_n) = (block_of[site], block_of[up[m]], block_of[up[n]]);
+                if up_m == here && up_n == here {
+                    continue;
+                }
+                let shift = shifts[here][m] + shifts[up_m][n] - shifts[up_n][m] - shifts[here][n];
+                let old = self.plaquette_angle_at(site, m, n);
+                let change = old.cos() - (old + shift).cos();
+                plaquette_change += change;
+                action_change += couplings.plane(m, n) * change;
+                if gamma != 0.0 {
+                    action_change += gamma * ((2.0 * old).cos() - (2.0 * (old + shift)).cos());
+                }
+            }
+        }
+        return (action_change, plaquette_change);
+    }
+
+    fn apply_coarse_shift(&mut self, block_of: &[usize], shifts: &[[f64; 4]]) {
+        for (site, phase_vector) in self.lattice.iter_mut().enumerate() {
+            for (phase, shift) in phase_vector.phases.iter_mut().zip(shifts[block_of[site]]) {
+                *phase = principal_angle(*phase + shift);
+            }
+        }
+    }
+
+    /* maximize sum_{n, mu} cos(theta_mu(n)) over all four directions */
+    pub fn fix_landau_gauge(&mut self, tolerance: f64, max_iters: usize) -> GaugeFixReport {
+        return self.gauge_fix_relaxation(&[0, 1, 2, 3], None, tolerance, max_iters);
+    }
+
+    /* maximize the spatial functional sum_{n, i} cos(theta_i(n)) independently on every
+     * time slice, returning one report per slice */
+    pub fn fix_coulomb_gauge(&mut self, tolerance: f64, max_iters: usize) -> Vec<GaugeFixReport> {
+        let spatial: Vec<usize> = (0..4).filter(|&direction| direction != TIME_DIRECTION).collect();
+        let mut reports = Vec::with_capacity(self.dims[TIME_DIRECTION]);
+
+        for t in 0..self.dims[TIME_DIRECTION] {
+            reports.push(self.gauge_fix_relaxation(&spatial, Some(t), tolerance, max_iters));
+        }
+
+        return reports;
+    }
+
+    /* gauge transformation g(n) = exp(i alpha) at a single site, acting on all eight links touching it */
+    fn rotate_site(&mut self, i: usize, j: usize, k: usize, l: usize, alpha: f64) {
+        for m in 0..4 {
+            let site = self.site_index(i, j, k, l);
+            let backward = self.neighbor_down[site][m];
+            /* U_\mu(n) -> g(n) U_\mu(n) */
+            self.lattice[site].phases[m] = principal_angle(self.lattice[site].phases[m] + alpha);
+            /* U_\mu(n - \hat{\mu}) -> U_\mu(n - \hat{\mu}) g(n)^* */
+            self.lattice[backward].phases[m] = principal_angle(self.lattice[backward].phases[m] - alpha);
+        }
+    }
+
+    /* sum of the links in `directions` entering and leaving site n, the imaginary part is the
+     * lattice divergence that vanishes in the fixed gauge */
+    fn gauge_functional_gradient(
+        &self,
+        directions: &[usize],
+        i: usize,
+        j: usize,
+        k: usize,
+        l: usize,
+    ) -> Complex<f64> {
+        let mut w = Complex::from_polar(0.0, 0.0);
+        let site = self.site_index(i, j, k, l);
+
+        for &m in directions {
+            w += Complex::from_polar(1.0, self.lattice[site].phases[m]);
+            w += Complex::from_polar(1.0, -self.lattice[self.neighbor_down[site][m]].phases[m]);
+        }
+
+        return w;
+    }
+
+    /* relaxation kernel shared by the Landau and Coulomb gauges: `directions` selects the links
+     * entering the functional and `time_slice` restricts the sweep to a single slice */
+    fn gauge_fix_relaxation(
+        &mut self,
+        directions: &[usize],
+        time_slice: Option<usize>,
+        tolerance: f64,
+        max_iters: usize,
+    ) -> GaugeFixReport {
+        let slices = match time_slice {
+            Some(t) => t..t + 1,
+            None => 0..self.dims[TIME_DIRECTION],
+        };
+        let num_sites = (self.dims[0] * self.dims[1] * self.dims[2] * slices.len()) as f64;
+        let mut report = GaugeFixReport {
+            iterations: 0,
+            residual: f64::INFINITY,
+            converged: false,
+        };
+
+        while report.iterations < max_iters {
+            for i in 0..self.dims[0] {
+                for j in 0..self.dims[1] {
+                    for k in 0..self.dims[2] {
+                        for l in slices.clone() {
+                            /* rotating by -arg(w) makes the local functional Re(e^{i alpha} w) maximal */
+                            let w = self.gauge_functional_gradient(directions, i, j, k, l);
+                            self.rotate_site(i, j, k, l, -w.arg());
+                        }
+                    }
+                }
+            }
+            report.iterations += 1;
+
+            let mut divergence_squared = 0f64;
+            for i in 0..self.dims[0] {
+                for j in 0..self.dims[1] {
+                    for k in 0..self.dims[2] {
+                        for l in slices.clone() {
+                            divergence_squared +=
+                                self.gauge_functional_gradient(directions, i, j, k, l).im.powi(2);
+                        }
+                    }
+                }
+            }
+            report.residual = divergence_squared / num_sites;
+
+            if report.residual < tolerance {
+                report.converged = true;
+                break;
+            }
+        }
+
+        return report;
+    }
+
+    /* every link phase in (i, j, k, l, mu) order, the layout of a [nx, ny, nz, nt, 4] array */
+    pub fn to_array(&self) -> Vec<f64> {
+        let mut phases = Vec::with_capacity(4 * self.volume());
+
+        for phase_vector in self.lattice.iter() {
+            phases.extend_from_slice(&phase_vector.phases);
+        }
+
+        return phases;
+    }
+
+    pub fn from_array(width: usize, phases: &[f64]) -> anyhow::Result<Self> {
+        if phases.len() != 4 * width.pow(4) {
+            anyhow::bail!(
+                "{} link phases do not make up a configuration of width {}",
+                phases.len(),
+                width
+            );
+        }
+        return Lattice::from_array_dims([width; 4], phases);
+    }
+
+    pub fn from_array_dims(dims: [usize; 4], phases: &[f64]) -> anyhow::Result<Self> {
+        if phases.len() != 4 * dims.iter().product::<usize>() {
+            anyhow::bail!(
+                "{} link phases do not make up a configuration with extents {:?}",
+                phases.len(),
+                dims
+            );
+        }
+
+        let mut new_lattice = Lattice::new_uniform_dims(dims);
+        for (phase_vector, link) in new_lattice.lattice.iter_mut().zip(phases.chunks_exact(4)) {
+            phase_vector.phases.copy_from_slice(link);
+        }
+        new_lattice.wrap_phases();
+
+        Ok(new_lattice)
+    }
+
+    /* raw configuration dump: magic, format version and the extents as little endian u64, then
+     * every link phase as a little endian f64 in (i, j, k, l, mu) order. Hypercubic lattices keep
+     * the version 1 layout with a single width, which older builds can read */
+    pub fn write_config(&self, file: &mut File) -> anyhow::Result<()> {
+        let extents: Vec<usize> = if self.is_hypercubic() { vec![self.dims[0]] } else { self.dims.to_vec() };
+        let version: u64 = if self.is_hypercubic() { 1 } else { CONFIG_VERSION };
+        let mut buffer = Vec::with_capacity(CONFIG_MAGIC.len() + 8 * (1 + extents.len()) + 32 * self.volume());
+        buffer.extend_from_slice(CONFIG_MAGIC);
+        buffer.extend_from_slice(&version.to_le_bytes());
+        for extent in extents {
+            buffer.extend_from_slice(&(extent as u64).to_le_bytes());
+        }
+
+        for phase_vector in self.lattice.iter() {
+            for phase in phase_vector.phases.iter() {
+                buffer.extend_from_slice(&phase.to_le_bytes());
+            }
+        }
+
+        file.write_all(&buffer)?;
+        Ok(())
+    }
+
+    pub fn read_config(file: &mut File) -> anyhow::Result<Self> {
+        let mut buffer = Vec::new();
+        file.read_to_end(&mut buffer)?;
+
+        if buffer.len() < CONFIG_MAGIC.len() + 8 || &buffer[..CONFIG_MAGIC.len()] != CONFIG_MAGIC {
+            anyhow::bail!("not a lattice configuration file");
+        }
+        let word = |offset: usize| u64::from_le_bytes(buffer[offset..offset + 8].try_into().unwrap());
+        let version = word(CONFIG_MAGIC.len());
+        let num_extents = match version {
+            1 => 1,
+            CONFIG_VERSION => 4,
+            _ => anyhow::bail!(
+                "unsupported configuration format version {}, expected at most {}",
+                version,
+                CONFIG_VERSION
+            ),
+        };
+        let header_length = CONFIG_MAGIC.len() + 8 * (1 + num_extents);
+        if buffer.len() < header_length {
+            anyhow::bail!("configuration file is truncated");
+        }
+        let extents: Vec<usize> = (0..num_extents).map(|n| word(CONFIG_MAGIC.len() + 8 * (1 + n)) as usize).collect();
+        let dims: [usize; 4] = if num_extents == 1 { [extents[0]; 4] } else { extents.try_into().unwrap() };
+        let expected_length = dims
+            .iter()
+            .try_fold(32usize, |length, &extent| length.checked_mul(extent));
+        if expected_length != Some(buffer.len() - header_length) {
+            anyhow::bail!("configuration file is truncated or has trailing data for extents {:?}", dims);
+        }
+
+        let mut new_lattice = Lattice::new_uniform_dims(dims);
+        let mut offset = header_length;
+        for phase_vector in new_lattice.lattice.iter_mut() {
+            for phase in phase_vector.phases.iter_mut() {
+                *phase = f64::from_bits(word(offset));
+                offset += 8;
+            }
+        }
+        new_lattice.wrap_phases();
+
+        Ok(new_lattice)
+    }
+
+    pub fn visualize_3d_lattice(&self, file: &mut File) -> anyhow::Result<()>  {
+        writeln!(file, "\\tdplotsetmaincoords{{22}}{{22}}")?;
+        writeln!(file, "\\begin{{tikzpicture}}[tdplot_main_coords]")?;
+        let plane_index = self.dims[3] / 2; // take a plane somewhere in the middle
+
+        for site in self.sites().filter(|site| site.coords()[3] == plane_index) {
+            let [i, j, k, _] = site.coords();
+            let phases = self.lattice[self.position(site)].phases;
+            writeln!(file, "\\filldraw[black] ({},{},{}) circle (2pt) ;", i,j,k)?;
+            let color_x1 = phase_to_rgb(phases[0]);
+            let color_x2 = phase_to_rgb(phases[1]);
+            let color_x3 = phase_to_rgb(phases[2]);
+
+            writeln!(file, "\\definecolor{{color{}{}{}1}}{{RGB}}{{{},{},{}}} ;",i,j,k, color_x1.0, color_x1.1, color_x1.2)?;
+            writeln!(file, "\\draw[color{0}{1}{2}1, thick] ({0},{1},{2}) -- ({3},{1},{2}) ;",i,j,k,i+1)?;
+            writeln!(file, "\\definecolor{{color{}{}{}2}}{{RGB}}{{{},{},{}}} ;",i,j,k, color_x2.0, color_x2.1, color_x2.2)?;
+            writeln!(file, "\\draw[color{0}{1}{2}2, thick] ({0},{1},{2}) -- ({0},{3},{2}) ;",i,j,k,j+1)?;
+
+            writeln!(file, "\\definecolor{{color{}{}{}3}}{{RGB}}{{{},{},{}}} ;",i,j,k, color_x3.0, color_x3.1, color_x3.2)?;
+            writeln!(file, "\\draw[color{0}{1}{2}3, thick] ({0},{1},{2}) -- ({0},{1},{3}) ;",i,j,k,k+1)?;
+        }
+        writeln!(file, "\\end{{tikzpicture}}")?;
+        Ok(())
+    }
+
+    /* the sites of the (0, 1) plane through the middle of the lattice, in (i, j) order */
+    fn middle_plane_sites(&self) -> impl Iterator<Item = Site> {
+        let [_, _, nz, nt] = self.dims;
+        return self.sites().filter(move |site| site.coords()[2] == nz / 2 && site.coords()[3] == nt / 2);
+    }
+
+    /* angle of the (0, 1) plaquette at a site in [0, 2 pi] for the color wheel */
+    fn plaquette_color_angle(&self, site: Site) -> f64 {
+        let mut plaquette = self.plaquette_angle(site, 0, 1);
+
+        while plaquette < 0.0 {
+            plaquette += 2.0*PI;
+        }
+
+        while plaquette > 2.0 *PI {
+            plaquette -= 2.0 * PI;
+        }
+
+        return plaquette;
+    }
+
+    pub fn visualize_plaquettes_plane(&self, file: &mut File) -> anyhow::Result<()> {
+        writeln!(file, "\\begin{{tikzpicture}}")?;
+
+        for site in self.middle_plane_sites() {
+            let [i, j, _, _] = site.coords();
+            let color = phase_to_rgb(self.plaquette_color_angle(site));
+            writeln!(file, "\\definecolor{{color{}{}}}{{RGB}}{{{},{},{}}} ;",i,j, color.0, color.1, color.2)?;
+            writeln!(file, "\\fill[color{0}{1}] ({0},{1}) rectangle ({2},{3}) ;",i,j,i+1,j+1)?;
+        }
+
+        for site in self.middle_plane_sites() {
+            let [i, j, _, _] = site.coords();
+            writeln!(file, "\\filldraw[black] ({},{}) circle (2pt) ;", i ,j)?;
+        }
+        writeln!(file,"\\end{{tikzpicture}}")?;
+        Ok(())
+    }
+
+    pub fn visualize_plaquettes_plane_svg(&self, file: &mut File) -> anyhow::Result<()> {
+        writeln!(file, "<svg width=\"{}\" height=\"{}\">", 50*self.dims[0]+20, 50*self.dims[1]+20)?;
+
+        for site in self.middle_plane_sites() {
+            let [i, j, _, _] = site.coords();
+            let (r,g,b) = phase_to_rgb(self.plaquette_color_angle(site));
+            writeln!(file, "<rect x=\"{0}\" y=\"{1}\" width=\"50\" height=\"50\" fill=\"#{r:02X?}{g:02X?}{b:02X?}\"/>",i*50+10, j*50+10)?;
+        }
+
+        for site in self.middle_plane_sites() {
+            let [i, j, _, _] = site.coords();
+            writeln!(file, "<circle cx=\"{}\" cy=\"{}\" r=\"5\" fill=\"#FFFFF\"/>", i*50+10 ,j*50+10)?;
+        }
+        writeln!(file,"</svg>")?;
+        Ok(())
+    }
+}
+
+/* mean of the spatial planes of average_action_by_plane */
+pub fn spatial_average(by_plane: &[[f64; 4]; 4]) -> f64 {
+    return (by_plane[0][1] + by_plane[0][2] + by_plane[1][2]) / 3.0;
+}
+
+/* mean of the temporal planes of average_action_by_plane */
+pub fn temporal_average(by_plane: &[[f64; 4]; 4]) -> f64 {
+    return (by_plane[0][TIME_DIRECTION] + by_plane[1][TIME_DIRECTION] + by_plane[2][TIME_DIRECTION]) / 3.0;
+}
+
+/* the size of a lattice for messages, "width 16" if all extents agree and "extents 16x16x16x4"
+ * otherwise */
+pub fn format_extents(dims: [usize; 4]) -> String {
+    if dims.iter().all(|&extent| extent == dims[0]) {
+        return format!("width {}", dims[0]);
+    }
+    return format!("extents {}x{}x{}x{}", dims[0], dims[1], dims[2], dims[3]);
+}
+
+/* forward and backward periodic neighbors of every site in the order of site_index, so that the
+ * update loops do not wrap coordinates with modulo arithmetic */
+fn neighbor_tables(dims: [usize; 4]) -> (Vec<[usize; 4]>, Vec<[usize; 4]>) {
+    let [nx, ny, nz, nt] = dims;
+    let index = |i: usize, j: usize, k: usize, l: usize| ((i * ny + j) * nz + k) * nt + l;
+    let volume = dims.iter().product();
+    let mut up = Vec::with_capacity(volume);
+    let mut down = Vec::with_capacity(volume);
+
+    for i in 0..nx {
+        for j in 0..ny {
+            for k in 0..nz {
+                for l in 0..nt {
+                    let site_up = std::array::from_fn(|m| {
+                        let unit = UNIT_VECTORS[m];
+                        index((i + unit[0]) % nx, (j + unit[1]) % ny, (k + unit[2]) % nz, (l + unit[3]) % nt)
+                    });
+                    let site_down = std::array::from_fn(|m| {
+                        let unit = UNIT_VECTORS[m];
+                        index((i + nx - unit[0]) % nx, (j + ny - unit[1]) % ny, (k + nz - unit[2]) % nz, (l + nt - unit[3]) % nt)
+                    });
+                    up.push(site_up);
+                    down.push(site_down);
+                }
+            }
+        }
+    }
+
+    return (up, down);
+}
+
+/* ratio of modified Bessel functions I_1(x) / I_0(x) for x >= 0, from the backward recurrence
+ * I_n / I_{n-1} = x / (2n + x I_{n+1} / I_n) started deep enough for the continued fraction to converge */
+pub fn bessel_ratio(x: f64) -> f64 {
+    let depth = 40 + x as usize;
+    let mut ratio = 0f64;
+
+    for n in (1..=depth).rev() {
+        ratio = x / (2.0 * n as f64 + x * ratio);
+    }
+
+    return ratio;
+}
+
+/* reduce an angle into the principal interval (-pi, pi] */
+pub fn principal_angle(phi: f64) -> f64 {
+    let reduced = phi - 2.0 * PI * (phi / (2.0 * PI)).round();
+    if reduced <= -PI {
+        return reduced + 2.0 * PI;
+    }
+    return reduced;
+}
+
+fn acceptance_probability(x: f64, prefactor: f64) -> f64 {
+    return approx::exp((approx::cos((PI/2.0)*(1.0-x)) - x) * prefactor) / approx::exp(ACCEPTANCE_CONSTANT * prefactor);
+}
+
+/* draw a link phase from its conditional weight exp(coupling Re(e^{i theta} staple)
+ * + double_coupling Re(e^{2 i theta} double_staple)). Without a double charge term this is the
+ * plain heatbath, with one the heatbath draw for the first term, or a uniform one if it vanishes,
+ * is accepted with probability exp(b cos(2 theta + arg(double_staple)) - |b|), b = double_coupling
+ * |double_staple|, which is at most one. The phase is returned in (-pi, pi] */
+pub fn sample_link(environment: &LinkEnvironment, rng: &mut Rng) -> f64 {
+    let alpha = environment.staple.abs();
+    let theta_0 = -environment.staple.arg();
+    if environment.double_coupling == 0.0 {
+        return principal_angle(sample_theta(alpha, environment.coupling, rng) + theta_0);
+    }
+
+    let bound = (environment.double_coupling * environment.double_staple.abs()).abs();
+    loop {
+        let theta = if alpha * environment.coupling > 0.0 {
+            sample_theta(alpha, environment.coupling, rng) + theta_0
+        } else {
+            PI * (2.0 * rng.f64() - 1.0)
+        };
+        let double_term =
+            environment.double_coupling * (Complex::from_polar(1.0, 2.0 * theta) * environment.double_staple).re;
+        if rng.f64() < (double_term - bound).exp() {
+            return principal_angle(theta);
+        }
+    }
+}
+
+/* draw theta in (-pi, pi] with weight exp(alpha beta cos(theta)) */
+pub fn sample_theta(alpha: f64, beta: f64, rng: &mut Rng) -> f64 {
+    let prefactor = alpha * beta;
+    /* the weight is flat to double precision, and 1 / prefactor would not be finite */
+    if prefactor < f64::MIN_POSITIVE {
+        return PI * (1.0 - 2.0 * rng.f64());
+    }
+    if prefactor > GAUSSIAN_PREFACTOR {
+        return sample_theta_gaussian(prefactor, rng);
+    }
 
     loop {
-        let sample_x = -1.0
-            + (1.0 / prefactor) * (1.0 + ((2.0 * prefactor).exp() - 1.0) * rng.f64()).ln();
+        let sample_x = if prefactor < SMALL_PREFACTOR {
+            /* exp(2 prefactor) - 1 cancels for tiny prefactors */
+            -1.0 + ((2.0 * prefactor).exp_m1() * rng.f64()).ln_1p() / prefactor
+        } else {
+            -1.0 + (1.0 / prefactor) * (1.0 + ((2.0 * prefactor).exp() - 1.0) * rng.f64()).ln()
+        };
 
         if rng.f64() < acceptance_probability(sample_x, prefactor) {
             let mut theta = (PI / 2.0) * (1.0 - sample_x);
@@ -267,7 +1598,29 @@ pub fn sample_theta(alpha: f64, beta: f64, rng: &mut Rng) -> f64 {
     }
 }
 
+/* for large prefactors the envelope exp(prefactor x) above only accepts about exp(-0.21 prefactor)
+ * of its draws. 1 - cos(theta) >= 2 theta^2 / pi^2 on [0, pi], so the Gaussian exp(-2 prefactor
+ * theta^2 / pi^2) bounds exp(prefactor (cos(theta) - 1)) from above and accepts at least 2 / pi of
+ * its draws for any prefactor. Nothing is exponentiated but non-positive numbers */
+fn sample_theta_gaussian(prefactor: f64, rng: &mut Rng) -> f64 {
+    let sigma = PI / (2.0 * prefactor.sqrt());
+
+    loop {
+        let theta = (sigma * (-2.0 * (1.0 - rng.f64()).ln()).sqrt() * (2.0 * PI * rng.f64()).cos()).abs();
+        if theta > PI {
+            continue;
+        }
+        let half_sine = (theta / 2.0).sin();
+        let log_acceptance = 2.0 * prefactor * ((theta / PI).powi(2) - half_sine * half_sine);
+        if rng.f64() < log_acceptance.exp() {
+            return if rng.bool() { -theta } else { theta };
+        }
+    }
+}
+
 fn phase_to_rgb(phi: f64) -> (u8,u8,u8)  {
+    /* the color wheel covers [0, 2 pi), link phases live in (-pi, pi] */
+    let phi = phi.rem_euclid(2.0 * PI);
     let division = PI / 3.0;
      if phi >= 0.0 && phi <= division {
         return (255, (phi * 255.0 / division) as u8, 0);
@@ -286,4 +1639,1117 @@ fn phase_to_rgb(phi: f64) -> (u8,u8,u8)  {
     else {
         return (0,0,0);
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tiling_copies_every_link_and_keeps_the_action() {
+        let smaller = Lattice::new_random_dims([3, 2, 3, 2], &mut Rng::with_seed(1));
+        let tiled = Lattice::tile_from(&smaller, 2).unwrap();
+        assert_eq!(tiled.dims(), [6, 4, 6, 4]);
+
+        for site in tiled.sites() {
+            let image = Site::new(site.coords(), smaller.dims());
+            assert_eq!(tiled.lattice[tiled.position(site)].phases, smaller.lattice[smaller.position(image)].phases);
+        }
+        /* the same plaquettes, only summed in a different order */
+        assert!((tiled.average_action() - smaller.average_action()).abs() < 1e-14);
+        assert!(Lattice::tile_from(&smaller, 0).is_err());
+    }
+
+    #[test]
+    fn the_flat_layout_reproduces_the_nested_one() {
+        /* average_action of the nested Vec<Vec<Vec<Vec<PhaseVector>>>> layout, on the random start
+         * and after three heatbath sweeps from the same seed */
+        let cases = [
+            (4, 7, 1.0, 0x3ff06702712a3b54u64, 0x3fdf502d413740a8u64),
+            (3, 11, 0.5, 0x3ff0425e18d12dab, 0x3fe89b61d0d2a04a),
+        ];
+        for (width, seed, beta, start, after) in cases {
+            let mut rng = Rng::with_seed(seed);
+            let mut lattice = Lattice::new_random(width, &mut rng);
+            /* the plaquettes are summed by time slice now, so only the rounding may differ */
+            assert!((lattice.average_action() - f64::from_bits(start)).abs() < 1e-14);
+            for _ in 0..3 {
+                lattice.heatbath_sweep(Couplings::isotropic(beta), &mut rng);
+            }
+            assert!((lattice.average_action() - f64::from_bits(after)).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn the_site_iterators_reproduce_the_index_loops() {
+        /* bits and visualization checksums of the nested index loops before sites() and links(),
+         * on the random start, after five heatbath sweeps at beta 1 and after a further Metropolis
+         * sweep with step 0.5 and an overrelaxation sweep */
+        struct Golden {
+            width: usize,
+            seed: u64,
+            start: [u64; 3],
+            heatbath: [u64; 2],
+            regions: &'static [u64],
+            updates: u64,
+            visualizations: [u64; 3],
+        }
+        let cases = [
+            Golden {
+                width: 3,
+                seed: 31,
+                start: [0x3fee80289ca38bb0, 0x3ff0289d2ff4cfcd, 0x3fe62e25a7dad729],
+                heatbath: [0x3fdd62c34880b935, 0x3fdf88003c14b418],
+                regions: &[0x3fdd62c34880b92f],
+                updates: 0x3fdffdc175a17a66,
+                visualizations: [0x5b962adbb17083ac, 0xc53cfce9e2a9b846, 0x8c477d3bf7cd0317],
+            },
+            Golden {
+                width: 4,
+                seed: 32,
+                start: [0x3fefcde37a4be41b, 0x3feff3fcad11657c, 0x3f75222ce0f00b90],
+                heatbath: [0x3fdf26a97f1a950b, 0xbfd32ffa65306eeb],
+                regions: &[
+                    0x3fe2066509c6d1f7, 0x3fe0632ee5b8f8fc, 0x3fd9a7abe6a2146d, 0x3fdbc7154db0b7c9,
+                    0x3fdd4d6bceb85775, 0x3fe2ffda8f3fec37, 0x3fde39cde93f9e41, 0x3fdd4b1a98e6a72c,
+                    0x3fd780d08e0f37d5, 0x3fdaf5517b22a725, 0x3fdf157a4389cbff, 0x3fdad008b2c56a79,
+                    0x3fe2591ac083a5b1, 0x3fe1536027624f83, 0x3fe33855643ccccb, 0x3fdf315fd731dff7,
+                ],
+                updates: 0x3fdf050113bcb553,
+                visualizations: [0x95e81ab485084009, 0x27d8ac9f06f0967b, 0xdddde4218a35f92c],
+            },
+        ];
+        for golden in cases {
+            let mut rng = Rng::with_seed(golden.seed);
+            let mut lattice = Lattice::new_random(golden.width, &mut rng);
+            let start = [lattice.average_action(), lattice.average_double_action(), lattice.topological_charge()];
+            assert_eq!(start.map(f64::to_bits), golden.start);
+            for _ in 0..5 {
+                lattice.heatbath_sweep(Couplings::isotropic(1.0), &mut rng);
+            }
+            assert_eq!([lattice.average_action(), lattice.topological_charge()].map(f64::to_bits), golden.heatbath);
+            let blocks = if golden.width == 4 { 2 } else { 1 };
+            let regions: Vec<u64> = lattice.region_plaquette_averages(blocks).into_iter().map(f64::to_bits).collect();
+            assert_eq!(regions, golden.regions);
+            lattice.metropolis_sweep(1.0, 0.5, &mut rng);
+            lattice.overrelaxation_sweep();
+            assert_eq!(lattice.average_action().to_bits(), golden.updates);
+
+            let path = std::env::temp_dir().join(format!("lattice-rust-visualization-{}", std::process::id()));
+            let writers: [fn(&Lattice, &mut File) -> anyhow::Result<()>; 3] = [
+                Lattice::visualize_3d_lattice,
+                Lattice::visualize_plaquettes_plane,
+                Lattice::visualize_plaquettes_plane_svg,
+            ];
+            for (write, checksum) in writers.into_iter().zip(golden.visualizations) {
+                write(&lattice, &mut File::create(&path).unwrap()).unwrap();
+                assert_eq!(crate::portable::xxh64(&std::fs::read(&path).unwrap(), 0), checksum);
+            }
+            let _ = std::fs::remove_file(&path);
+
+            /* sites in storage order, links site by site */
+            let positions: Vec<usize> = lattice.sites().map(|site| lattice.position(site)).collect();
+            assert_eq!(positions, (0..lattice.volume()).collect::<Vec<_>>());
+            let links: Vec<(usize, usize)> = lattice.links().map(|(site, mu)| (lattice.position(site), mu)).collect();
+            assert_eq!(links, (0..4 * lattice.volume()).map(|link| (link / 4, link % 4)).collect::<Vec<_>>());
+        }
+    }
+
+    /* mean and standard error of the average action over independent starts from the given seeds,
+     * after `sweeps` heatbath sweeps at beta */
+    fn action_over_seeds(dims: [usize; 4], seeds: std::ops::Range<u64>, beta: f64, sweeps: usize) -> (f64, f64) {
+        let actions: Vec<f64> = seeds
+            .map(|seed| {
+                let mut rng = Rng::with_seed(seed);
+                let mut lattice = Lattice::new_random_dims(dims, &mut rng);
+                for _ in 0..sweeps {
+                    lattice.heatbath_sweep(Couplings::isotropic(beta), &mut rng);
+                }
+                lattice.average_action()
+            })
+            .collect();
+        return (crate::analysis::mean(&actions), crate::analysis::naive_error(&actions).unwrap());
+    }
+
+    #[test]
+    fn a_short_time_extent_gives_the_same_action_as_the_hypercube() {
+        /* hot starts average to 1, and after a few sweeps both approach the same bulk value */
+        for (beta, sweeps) in [(0.0, 0), (0.5, 10)] {
+            let (short, short_error) = action_over_seeds([4, 4, 4, 2], 0..100, beta, sweeps);
+            let (cube, cube_error) = action_over_seeds([4; 4], 100..200, beta, sweeps);
+            let sigma = (short_error.powi(2) + cube_error.powi(2)).sqrt();
+            assert!((short - cube).abs() < 4.0 * sigma, "beta {}: {} +- {} against {} +- {}", beta, short, short_error, cube, cube_error);
+        }
+    }
+
+    #[test]
+    fn the_average_action_normalizes_by_every_extent() {
+        let lattice = Lattice::new_random_dims([4, 2, 6, 2], &mut Rng::with_seed(25));
+        assert_eq!(lattice.volume(), 96);
+        /* the plaquettes summed one by one through Site::shift */
+        let mut sum = 0.0;
+        for site in lattice.sites() {
+            for mu in 0..4 {
+                for nu in mu + 1..4 {
+                    let phase = |site: Site, direction: usize| lattice.lattice[lattice.position(site)].phases[direction];
+                    let angle = phase(site, mu) + phase(site.shift(mu, 1), nu) - phase(site.shift(nu, 1), mu) - phase(site, nu);
+                    sum += 1.0 - angle.cos();
+                }
+            }
+        }
+        assert!((lattice.average_action() - sum / (6.0 * 96.0)).abs() < 1e-14);
+        assert!((lattice.total_action() - sum).abs() < 1e-11);
+        assert_eq!(Lattice::new_uniform_dims([4, 2, 6, 2]).average_action(), 0.0);
+    }
+
+    /* uniform field strength with fluxes 2 pi n_{mu nu} through the planes of a width^4 lattice,
+     * the flux quanta in the order of PLANES */
+    fn quantized_field(width: usize, quanta: [i32; 6]) -> Lattice {
+        let mut f = [[0f64; 4]; 4];
+        for (&(m, n), quanta) in PLANES.iter().zip(quanta) {
+            f[m][n] = 2.0 * PI * quanta as f64 / (width * width) as f64;
+            f[n][m] = -f[m][n];
+        }
+        return Lattice::constant_field(width, f).unwrap();
+    }
+
+    #[test]
+    fn plane_wave_plaquettes_follow_the_closed_form() {
+        for (width, momentum, amplitude, direction) in [(4, [1, 0, 0, 0], 0.3, 0), (5, [1, 2, 0, 3], 1.1, 2), (6, [0, 3, 2, 1], -2.5, 3)] {
+            let lattice = Lattice::plane_wave(width, momentum, amplitude, direction);
+            let wave = |site: Site, shift: usize| {
+                let k_dot_n: usize = (0..4).map(|mu| momentum[mu] * site.coords()[mu]).sum();
+                amplitude * (2.0 * PI * (k_dot_n + shift) as f64 / width as f64).cos()
+            };
+            for site in lattice.sites() {
+                for (m, n) in PLANES {
+                    /* only the links in direction enter, theta_m(n) - theta_m(n + n) or
+                     * theta_n(n + m) - theta_n(n) */
+                    let expected = if m == direction {
+                        wave(site, 0) - wave(site, momentum[n])
+                    } else if n == direction {
+                        wave(site, momentum[m]) - wave(site, 0)
+                    } else {
+                        0.0
+                    };
+                    let angle = lattice.plaquette_angle(site, m, n);
+                    assert!((angle - expected).abs() < 1e-12, "{:?} plane {:?}: {} against {}", site, (m, n), angle, expected);
+                }
+            }
+            for links in &lattice.lattice {
+                assert!((0..4).filter(|&mu| mu != direction).all(|mu| links.phases[mu] == 0.0));
+            }
+        }
+    }
+
+    #[test]
+    fn constant_fields_need_quantized_antisymmetric_field_strengths() {
+        let quantum = 2.0 * PI / 16.0;
+        let field = |entries: &[(usize, usize, f64)]| {
+            let mut f = [[0f64; 4]; 4];
+            for &(m, n, value) in entries {
+                f[m][n] = value;
+            }
+            return Lattice::constant_field(4, f);
+        };
+        assert!(field(&[(0, 1, quantum), (1, 0, -quantum)]).is_ok());
+
+        let error = field(&[(0, 1, 0.5 * quantum), (1, 0, -0.5 * quantum)]).unwrap_err().to_string();
+        assert!(error.contains("not quantized"), "{}", error);
+        let error = field(&[(0, 2, quantum), (2, 0, quantum)]).unwrap_err().to_string();
+        assert!(error.contains("antisymmetric"), "{}", error);
+        let error = field(&[(1, 3, quantum)]).unwrap_err().to_string();
+        assert!(error.contains("antisymmetric"), "{}", error);
+        let error = field(&[(2, 2, quantum)]).unwrap_err().to_string();
+        assert!(error.contains("diagonal"), "{}", error);
+    }
+
+    #[test]
+    fn the_ordered_lattice_has_no_topological_charge() {
+        let lattice = Lattice::new_uniform_dims([4, 2, 3, 2]);
+        assert!(lattice.topological_charge_density().iter().all(|&density| density == 0.0));
+        assert_eq!(lattice.topological_charge(), 0.0);
+        assert_eq!(Lattice::new_uniform(3).topological_charge(), 0.0);
+    }
+
+    #[test]
+    fn constant_fields_carry_the_charge_of_their_fluxes() {
+        for quanta in [[1, 0, 0, 0, 0, 1], [2, 0, 0, 0, 0, -1], [1, 1, 0, 0, 3, 2], [0, 1, 2, 2, 1, 0], [1, 1, 1, 1, 1, 1]] {
+            let [n01, n02, n03, n12, n13, n23] = quanta;
+            let expected = (n01 * n23 - n02 * n13 + n03 * n12) as f64;
+            let lattice = quantized_field(5, quanta);
+            let charge = lattice.topological_charge();
+            assert!((charge - expected).abs() < 1e-10, "fluxes {:?}: {} instead of {}", quanta, charge, expected);
+            /* the density is the same on every site */
+            let density = lattice.topological_charge_density();
+            assert!(density.iter().all(|&q| (q - expected / 625.0).abs() < 1e-12), "fluxes {:?}", quanta);
+        }
+    }
+
+    #[test]
+    fn cooling_a_disturbed_charged_field_brings_the_charge_back_to_its_integer() {
+        let mut rng = Rng::with_seed(28);
+        let mut lattice = quantized_field(4, [1, 0, 0, 0, 0, -1]);
+        for links in lattice.lattice.iter_mut() {
+            for phase in links.phases.iter_mut() {
+                *phase = principal_angle(*phase + 0.5 * (2.0 * rng.f64() - 1.0));
+            }
+        }
+        let disturbed = lattice.topological_charge();
+        assert!((disturbed + 1.0).abs() > 0.01, "the noise moves the charge off its integer, {}", disturbed);
+
+        /* sweeps at a huge coupling set every link to the minimum of its plaquettes, i.e. cooling */
+        for _ in 0..50 {
+            lattice.heatbath_sweep(Couplings::isotropic(1e9), &mut rng);
+        }
+        let cooled = lattice.topological_charge();
+        assert!((cooled + 1.0).abs() < 0.01, "cooled charge {}", cooled);
+
+        /* thermalized configurations cool into a sector as well */
+        for seed in 0..3 {
+            let mut rng = Rng::with_seed(seed);
+            let mut lattice = Lattice::new_random(4, &mut rng);
+            for _ in 0..30 {
+                lattice.heatbath_sweep(Couplings::isotropic(1.2), &mut rng);
+            }
+            for _ in 0..100 {
+                lattice.heatbath_sweep(Couplings::isotropic(1e9), &mut rng);
+            }
+            let charge = lattice.topological_charge();
+            assert!((charge - charge.round()).abs() < 1e-3, "seed {}: cooled charge {}", seed, charge);
+            assert!(lattice.average_action() < 1e-3, "seed {}: cooled action {}", seed, lattice.average_action());
+        }
+    }
+
+    #[test]
+    fn the_action_by_plane_sees_exactly_the_planes_of_a_twisted_direction() {
+        let ordered = Lattice::new_uniform_dims([4, 2, 4, 2]);
+        assert_eq!(ordered.average_action_by_plane(), [[0.0; 4]; 4]);
+        assert_eq!((ordered.spatial_average_action(), ordered.temporal_average_action()), (0.0, 0.0));
+
+        for direction in 0..4 {
+            /* links of the direction at pi / 2 on a checkerboard of the other coordinates, so every
+             * plaquette with a side along it has angle +-pi / 2 and action 1 */
+            let mut lattice = Lattice::new_uniform_dims([4, 2, 4, 2]);
+            for site in lattice.sites().collect::<Vec<_>>() {
+                let parity: usize = (0..4).filter(|&mu| mu != direction).map(|mu| site.coords()[mu]).sum();
+                if parity.is_multiple_of(2) {
+                    let index = lattice.position(site);
+                    lattice.lattice[index].phases[direction] = PI / 2.0;
+                }
+            }
+            let by_plane = lattice.average_action_by_plane();
+            for (m, row) in by_plane.iter().enumerate() {
+                for (n, &action) in row.iter().enumerate() {
+                    let expected = if m != n && (m == direction || n == direction) { 1.0 } else { 0.0 };
+                    assert!((action - expected).abs() < 1e-15, "direction {}, plane ({}, {}): {}", direction, m, n, action);
+                }
+            }
+            let temporal = if direction == TIME_DIRECTION { 1.0 } else { 1.0 / 3.0 };
+            let spatial = if direction == TIME_DIRECTION { 0.0 } else { 2.0 / 3.0 };
+            assert!((lattice.temporal_average_action() - temporal).abs() < 1e-15);
+            assert!((lattice.spatial_average_action() - spatial).abs() < 1e-15);
+            assert!((lattice.average_action() - 0.5).abs() < 1e-15);
+        }
+    }
+
+    /* staple of U_mu(n) with the neighbors wrapped by modulo arithmetic instead of the tables */
+    fn modular_staple(lattice: &Lattice, coords: [usize; 4], m: usize) -> Complex<f64> {
+        let dims = lattice.dims();
+        let phase = |coords: [i64; 4], direction: usize| {
+            let site = Site::wrapped(coords, dims);
+            return lattice.link_phase(site.coords[0], site.coords[1], site.coords[2], site.coords[3], direction);
+        };
+        let n = coords.map(|x| x as i64);
+        let step = |from: [i64; 4], direction: usize, steps: i64| {
+            let mut to = from;
+            to[direction] += steps;
+            return to;
+        };
+
+        let mut staple = Complex::new(0.0, 0.0);
+        for nu in (0..4).filter(|&nu| nu != m) {
+            let up = phase(step(n, m, 1), nu) - phase(step(n, nu, 1), m) - phase(n, nu);
+            let back = step(n, nu, -1);
+            let down = phase(back, nu) - phase(back, m) - phase(step(back, m, 1), nu);
+            staple += Complex::from_polar(1.0, up) + Complex::from_polar(1.0, down);
+        }
+        return staple;
+    }
+
+    #[test]
+    fn table_staples_match_modular_arithmetic() {
+        let mut rng = Rng::with_seed(12);
+        for dims in [[4; 4], [3, 4, 2, 5]] {
+            let lattice = Lattice::new_random_dims(dims, &mut rng);
+            /* every site includes the ones on the boundary where the neighbors wrap around */
+            for (site, m) in lattice.links() {
+                let difference = lattice.staple_sum(site, m) - modular_staple(&lattice, site.coords(), m);
+                assert!(difference.norm() < 1e-12, "staple of {:?} in direction {} differs by {}", site.coords(), m, difference);
+            }
+        }
+    }
+
+    #[test]
+    fn overrelaxation_preserves_the_action() {
+        let mut rng = Rng::with_seed(9);
+        let mut lattice = Lattice::new_random(4, &mut rng);
+        for _ in 0..3 {
+            lattice.heatbath_sweep(Couplings::isotropic(1.0), &mut rng);
+        }
+        let before = lattice.clone();
+        let action = lattice.average_action();
+        lattice.overrelaxation_sweep();
+        assert!((lattice.average_action() - action).abs() < 1e-14);
+        /* the reflection still moves the links */
+        let moved = lattice.links().filter(|&(site, m)| {
+            let [i, j, k, l] = site.coords();
+            (lattice.link_phase(i, j, k, l, m) - before.link_phase(i, j, k, l, m)).abs() > 1e-6
+        });
+        assert!(moved.count() > 3 * lattice.volume());
+
+        /* with anisotropic couplings it preserves the weighted action */
+        let couplings = Couplings { spatial: 1.2, temporal: 0.7 };
+        let weighted = |lattice: &Lattice| {
+            let by_plane = lattice.average_action_by_plane();
+            return couplings.spatial * spatial_average(&by_plane) + couplings.temporal * temporal_average(&by_plane);
+        };
+        let action = weighted(&lattice);
+        lattice.overrelaxation_sweep_with_action(&WilsonAction { couplings });
+        assert!((weighted(&lattice) - action).abs() < 1e-14);
+    }
+
+    /* Wilson loop from the coordinates of every link on its boundary, wrapped with modulo arithmetic */
+    fn modular_wilson_loop(lattice: &Lattice, r: usize, t: usize, mu: usize, nu: usize) -> f64 {
+        let dims = lattice.dims();
+        let line = |start: [i64; 4], direction: usize, length: usize| {
+            let mut coords = start;
+            let mut phase = 0.0;
+            for _ in 0..length {
+                let [i, j, k, l] = Site::wrapped(coords, dims).coords();
+                phase += lattice.link_phase(i, j, k, l, direction);
+                coords[direction] += 1;
+            }
+            return (phase, coords);
+        };
+
+        let mut sum = 0.0;
+        for site in lattice.sites() {
+            let start = site.coords().map(|x| x as i64);
+            let (bottom, corner) = line(start, mu, r);
+            let (right, _) = line(corner, nu, t);
+            let (left, corner) = line(start, nu, t);
+            let (top, _) = line(corner, mu, r);
+            sum += (bottom + right - top - left).cos();
+        }
+        return sum / lattice.volume() as f64;
+    }
+
+    #[test]
+    fn the_unit_wilson_loop_is_one_minus_the_action() {
+        let mut rng = Rng::with_seed(14);
+        let mut lattice = Lattice::new_random_dims([4, 3, 4, 5], &mut rng);
+        lattice.heatbath_sweep(Couplings::isotropic(1.0), &mut rng);
+        let loops = lattice.wilson_loops_up_to(2, 2, None);
+        assert!((loops[0][0] - (1.0 - lattice.average_action())).abs() < 1e-14);
+
+        /* every plane on its own, both orientations of the plaquette */
+        let by_plane = lattice.average_action_by_plane();
+        for &(mu, nu) in PLANES.iter() {
+            assert!((lattice.wilson_loop(1, 1, mu, nu) - (1.0 - by_plane[mu][nu])).abs() < 1e-14);
+            assert!((lattice.wilson_loop(1, 1, nu, mu) - (1.0 - by_plane[mu][nu])).abs() < 1e-14);
+        }
+    }
+
+    #[test]
+    fn wilson_loops_wrap_around_the_torus() {
+        let mut rng = Rng::with_seed(15);
+        let lattice = Lattice::new_random_dims([3, 4, 2, 5], &mut rng);
+        /* sides beyond half the extent, of the full extent and winding more than once */
+        for (r, t) in [(2, 3), (3, 4), (4, 2), (5, 6), (7, 1)] {
+            for mu in 0..4 {
+                for nu in (0..4).filter(|&nu| nu != mu) {
+                    let difference = lattice.wilson_loop(r, t, mu, nu) - modular_wilson_loop(&lattice, r, t, mu, nu);
+                    assert!(difference.abs() < 1e-12, "W({}, {}) in ({}, {}) differs by {}", r, t, mu, nu, difference);
+                }
+            }
+        }
+
+        /* a loop of the full extents retraces its sides, the opposite sides are the same lines */
+        let small = Lattice::new_random(2, &mut rng);
+        assert!((small.wilson_loop(2, 2, 0, 1) - 1.0).abs() < 1e-14);
+        assert!((small.wilson_loop(2, 4, 3, 2) - 1.0).abs() < 1e-14);
+    }
+
+    #[test]
+    fn polyakov_loops_of_hand_computed_configurations() {
+        for direction in 0..4 {
+            assert_eq!(Lattice::new_uniform(2).polyakov_loop(direction), Complex::new(1.0, 0.0));
+        }
+
+        /* on 2 x 3 x 2 x 2 every link along 1 has phase 0.4 except the ones leaving (0, 0, 0, 0) with
+         * 0.7 and (1, 2, 1, 1) with 1.0, so two of the eight lines along 1 wind to 1.5 and 1.8
+         * instead of 1.2 */
+        let dims = [2, 3, 2, 2];
+        let mut lattice = Lattice::new_uniform_dims(dims);
+        for site in 0..lattice.volume() {
+            lattice.lattice[site].phases[1] = 0.4;
+            lattice.lattice[site].phases[2] = 0.7;
+        }
+        for (coords, phase) in [([0, 0, 0, 0], 0.7), ([1, 2, 1, 1], 1.0)] {
+            let index = lattice.position(Site::new(coords, dims));
+            lattice.lattice[index].phases[1] = phase;
+        }
+        let winding = |phase: f64| Complex::from_polar(1.0, phase);
+        let expected = (winding(1.5) + winding(1.8) + 6.0 * winding(1.2)) / 8.0;
+        assert!((lattice.polyakov_loop(1) - expected).norm() < 1e-14);
+        /* lines of length 2 along 2, the other directions are ordered */
+        assert!((lattice.polyakov_loop(2) - Complex::from_polar(1.0, 1.4)).norm() < 1e-14);
+        assert_eq!(lattice.polyakov_loop(0), Complex::new(1.0, 0.0));
+        assert_eq!(lattice.polyakov_loop(3), Complex::new(1.0, 0.0));
+    }
+
+    #[test]
+    fn the_threshold_selects_the_top_fraction_of_the_links() {
+        /* scores 0, 1, ..., 4 V - 1 by link */
+        fn link_number(_: &Lattice, site: usize, mu: usize) -> f64 {
+            return (4 * site + mu) as f64;
+        }
+        let lattice = Lattice::new_uniform(2);
+        assert_eq!(LinkScores::compute(&lattice, link_number, 0.25).threshold(), 48.0);
+        assert_eq!(LinkScores::compute(&lattice, link_number, 1.0).threshold(), 0.0);
+        assert_eq!(LinkScores::compute(&lattice, link_number, 0.001).threshold(), f64::INFINITY);
+
+        /* 16 links reach 48, each hit twice */
+        let mut lattice = lattice;
+        let scores = LinkScores::frozen(link_number, 48.0);
+        assert_eq!(lattice.targeted_hits(Couplings::isotropic(1.0), &scores, 2, &mut Rng::with_seed(1)), 32);
+        for site in 0..12 {
+            assert_eq!(lattice.lattice[site].phases, [0.0; 4]);
+        }
+    }
+
+    #[test]
+    fn ordered_and_pure_gauge_lattices_have_no_monopoles() {
+        let dims = [3, 4, 2, 5];
+        assert_eq!(Lattice::new_uniform_dims(dims).monopole_density(), 0.0);
+
+        /* theta_mu(n) = alpha(n + mu) - alpha(n) has vanishing plaquettes for any alpha */
+        let rng = Rng::with_seed(16);
+        let alpha: Vec<f64> = (0..dims.iter().product()).map(|_| 20.0 * (rng.f64() - 0.5)).collect();
+        let mut gauge = Lattice::new_uniform_dims(dims);
+        for site in gauge.sites() {
+            let index = gauge.position(site);
+            for mu in 0..4 {
+                gauge.lattice[index].phases[mu] = alpha[gauge.position(site.shift(mu, 1))] - alpha[index];
+            }
+        }
+        assert!(gauge.average_action() < 1e-12);
+        assert_eq!(gauge.monopole_density(), 0.0);
+    }
+
+    #[test]
+    fn monopole_charge_is_conserved_on_every_slice() {
+        let mut rng = Rng::with_seed(17);
+        let lattice = Lattice::new_random_dims([3, 4, 2, 5], &mut rng);
+        assert!(lattice.monopole_density() > 0.0);
+        /* the cubes orthogonal to mu at a fixed x_mu close the 3-torus, no flux can leave it */
+        for mu in 0..4 {
+            for slice in 0..lattice.dims()[mu] {
+                let charge: i64 = (0..lattice.volume())
+                    .filter(|&site| lattice.site_coordinates(site)[mu] == slice)
+                    .map(|site| lattice.monopole_charge(site, mu))
+                    .sum();
+                assert_eq!(charge, 0, "net monopole charge in slice {} orthogonal to {}", slice, mu);
+            }
+        }
+    }
+
+    #[test]
+    fn hot_lattices_reach_the_strong_coupling_density() {
+        /* at beta = 0 the links are independent and uniform, a single cube with twelve such links
+         * sampled 10^6 times gives <|charge|> = 0.467(1) */
+        let mut rng = Rng::with_seed(18);
+        let mut lattice = Lattice::new_random(4, &mut rng);
+        let densities: Vec<f64> = (0..20)
+            .map(|_| {
+                lattice.heatbath_sweep(Couplings::isotropic(0.01), &mut rng);
+                lattice.monopole_density()
+            })
+            .collect();
+        let density = densities.iter().sum::<f64>() / densities.len() as f64;
+        assert!((density - 0.467).abs() < 0.015, "monopole density {} at beta 0.01", density);
+    }
+
+    #[test]
+    fn region_averages_add_up_to_the_average_action() {
+        let lattice = Lattice::new_random_dims([4, 4, 6, 2], &mut Rng::with_seed(3));
+        for blocks in [1, 2] {
+            let averages = lattice.region_plaquette_averages(blocks);
+            assert_eq!(averages.len(), blocks.pow(4));
+            /* equal volume regions, so the volume weighted mean is the plain mean */
+            let mean = averages.iter().sum::<f64>() / averages.len() as f64;
+            assert!((mean - lattice.average_action()).abs() < 1e-12);
+        }
+    }
+
+    /* cumulative distribution of the single link weight exp(prefactor cos(theta)) on (-pi, pi],
+     * integrated with the trapezoidal rule, which converges exponentially for periodic integrands */
+    fn link_cdf(prefactor: f64) -> impl Fn(f64) -> f64 {
+        const STEPS: usize = 1 << 14;
+        let step = 2.0 * PI / STEPS as f64;
+        let weight = |theta: f64| (prefactor * (theta.cos() - 1.0)).exp();
+        let mut cumulative = vec![0.0];
+        for index in 0..STEPS {
+            let theta = -PI + index as f64 * step;
+            cumulative.push(cumulative[index] + 0.5 * step * (weight(theta) + weight(theta + step)));
+        }
+        let total = cumulative[STEPS];
+        return move |theta: f64| {
+            let position = ((theta + PI) / step).clamp(0.0, STEPS as f64);
+            let index = (position as usize).min(STEPS - 1);
+            let fraction = position - index as f64;
+            /* within a step the weight is close to linear, the quadratic term is far below the
+             * resolution of the test */
+            let left = cumulative[index];
+            let right = cumulative[index + 1];
+            return (left + fraction * (right - left)) / total;
+        };
+    }
+
+    /* Kolmogorov-Smirnov distance between the samples and a continuous distribution */
+    fn ks_statistic(mut samples: Vec<f64>, cdf: impl Fn(f64) -> f64) -> f64 {
+        samples.sort_by(f64::total_cmp);
+        let n = samples.len() as f64;
+        return samples
+            .iter()
+            .enumerate()
+            .map(|(index, &sample)| {
+                let expected = cdf(sample);
+                ((index + 1) as f64 / n - expected).max(expected - index as f64 / n)
+            })
+            .fold(0.0, f64::max);
+    }
+
+    /* critical KS distance times sqrt(n), at 0.1 % significance for the exact functions and at 1 %
+     * with the approximations of the fast-math feature, which must not be told apart either */
+    const KS_CRITICAL: f64 = if cfg!(feature = "fast-math") { 1.63 } else { 1.95 };
+
+    #[test]
+    fn sampled_links_follow_the_exact_single_link_distribution() {
+        let mut rng = Rng::with_seed(17);
+        let samples = 20_000;
+        /* both envelopes, on either side of GAUSSIAN_PREFACTOR */
+        for prefactor in [0.5, 2.0, 10.0, 40.0] {
+            let thetas: Vec<f64> = (0..samples).map(|_| sample_theta(prefactor, 1.0, &mut rng)).collect();
+            let distance = ks_statistic(thetas, link_cdf(prefactor));
+            assert!(distance * (samples as f64).sqrt() < KS_CRITICAL, "prefactor {}: KS distance {}", prefactor, distance);
+        }
+    }
+
+    /* Pearson statistic of the samples in `bins` bins of equal probability under the cdf, whose
+     * edges are found by bisection */
+    fn chi_square(samples: &[f64], cdf: impl Fn(f64) -> f64, bins: usize) -> f64 {
+        let edges: Vec<f64> = (1..bins)
+            .map(|bin| {
+                let (mut low, mut high) = (-PI, PI);
+                for _ in 0..60 {
+                    let middle = 0.5 * (low + high);
+                    if cdf(middle) < bin as f64 / bins as f64 {
+                        low = middle;
+                    } else {
+                        high = middle;
+                    }
+                }
+                0.5 * (low + high)
+            })
+            .collect();
+        let mut counts = vec![0usize; bins];
+        for &sample in samples {
+            counts[edges.partition_point(|&edge| edge < sample)] += 1;
+        }
+        let expected = samples.len() as f64 / bins as f64;
+        return counts.iter().map(|&count| (count as f64 - expected).powi(2) / expected).sum();
+    }
+
+    #[test]
+    fn sampled_links_stay_exact_for_vanishing_and_huge_prefactors() {
+        let mut rng = Rng::with_seed(21);
+        let samples = 20_000;
+        /* chi square with 19 degrees of freedom at 0.1 % significance */
+        const CHI_SQUARE_CRITICAL: f64 = 43.8;
+        for prefactor in [0.0, 1e-8, 1.0, 50.0, 500.0] {
+            let thetas: Vec<f64> = (0..samples).map(|_| sample_theta(prefactor, 1.0, &mut rng)).collect();
+            assert!(thetas.iter().all(|theta| theta.is_finite() && theta.abs() <= PI), "prefactor {}", prefactor);
+
+            let distance = ks_statistic(thetas.clone(), link_cdf(prefactor));
+            assert!(distance * (samples as f64).sqrt() < KS_CRITICAL, "prefactor {}: KS distance {}", prefactor, distance);
+            let statistic = chi_square(&thetas, link_cdf(prefactor), 20);
+            assert!(statistic < CHI_SQUARE_CRITICAL, "prefactor {}: chi square {}", prefactor, statistic);
+        }
+
+        /* a staple that cancels exactly gives a uniform angle whatever the coupling */
+        let thetas: Vec<f64> = (0..samples).map(|_| sample_theta(0.0, 1e6, &mut rng)).collect();
+        assert!(ks_statistic(thetas, link_cdf(0.0)) * (samples as f64).sqrt() < KS_CRITICAL);
+        /* far beyond the overflow of exp(2 prefactor) the angle is still drawn with width 1 / sqrt(prefactor) */
+        let thetas: Vec<f64> = (0..samples).map(|_| sample_theta(1.0, 1e12, &mut rng)).collect();
+        let width = (thetas.iter().map(|theta| theta * theta).sum::<f64>() / samples as f64).sqrt();
+        assert!((width * 1e6 - 1.0).abs() < 0.05, "width {}", width);
+    }
+
+    fn phases_are_principal(lattice: &Lattice) -> bool {
+        return lattice.lattice.iter().flat_map(|vector| vector.phases).all(|phase| phase > -PI && phase <= PI);
+    }
+
+    #[test]
+    fn phases_stay_principal_over_a_long_run() {
+        let mut rng = Rng::with_seed(22);
+        let mut lattice = Lattice::new_random(2, &mut rng);
+        assert!(phases_are_principal(&lattice));
+        /* the heatbath draws phases anew, the other updates add to them and would drift */
+        for sweep in 0..100_000 {
+            let update = match sweep % 4 {
+                0 => {
+                    lattice.heatbath_sweep(Couplings::isotropic(1.0), &mut rng);
+                    "heatbath"
+                }
+                1 => {
+                    lattice.overrelaxation_sweep();
+                    "overrelaxation"
+                }
+                2 => {
+                    lattice.metropolis_sweep(1.0, 2.0, &mut rng);
+                    "metropolis"
+                }
+                _ => {
+                    lattice.coarse_update(Couplings::isotropic(1.0), 0.0, 2, 3.0, &mut rng);
+                    "coarse update"
+                }
+            };
+            assert!(phases_are_principal(&lattice), "{} in sweep {}", update, sweep);
+            if sweep % 10_000 == 0 {
+                lattice.fix_landau_gauge(1e-10, 20);
+                assert!(phases_are_principal(&lattice), "gauge fixing in sweep {}", sweep);
+            }
+        }
+    }
+
+    /* the Polyakov loop through every site of the first time slice */
+    fn local_polyakov_loops(lattice: &Lattice) -> Vec<Complex<f64>> {
+        return (0..lattice.lattice.len())
+            .filter(|&site| lattice.site_coordinates(site)[TIME_DIRECTION] == 0)
+            .map(|site| Complex::from_polar(1.0, lattice.line_phase(site, TIME_DIRECTION, lattice.dims[TIME_DIRECTION]).0))
+            .collect();
+    }
+
+    fn thermalized(dims: [usize; 4], seed: u64) -> Lattice {
+        let mut rng = Rng::with_seed(seed);
+        let mut lattice = Lattice::new_random_dims(dims, &mut rng);
+        for _ in 0..20 {
+            lattice.heatbath_sweep(Couplings::isotropic(1.5), &mut rng);
+        }
+        return lattice;
+    }
+
+    #[test]
+    fn coulomb_gauge_converges_on_every_slice_and_keeps_the_polyakov_loops() {
+        let mut lattice = thermalized([4, 4, 4, 3], 26);
+        let (loops, action) = (local_polyakov_loops(&lattice), lattice.average_action());
+
+        let reports = lattice.fix_coulomb_gauge(1e-12, 10_000);
+        assert_eq!(reports.len(), 3);
+        for (t, report) in reports.iter().enumerate() {
+            assert!(report.converged && report.residual < 1e-12, "slice {}: {:?}", t, report);
+            assert!(report.iterations > 1, "slice {}: {:?}", t, report);
+        }
+        /* the spatial divergence vanishes on every site */
+        let spatial: Vec<usize> = (0..4).filter(|&direction| direction != TIME_DIRECTION).collect();
+        for site in lattice.sites() {
+            let [i, j, k, l] = site.coords();
+            assert!(lattice.gauge_functional_gradient(&spatial, i, j, k, l).im.abs() < 1e-5);
+        }
+
+        for (before, after) in loops.iter().zip(local_polyakov_loops(&lattice)) {
+            assert!((before - after).norm() < 1e-10, "{} against {}", before, after);
+        }
+        assert!((lattice.average_action() - action).abs() < 1e-12);
+        assert!(phases_are_principal(&lattice));
+    }
+
+    #[test]
+    fn landau_gauge_converges_and_moves_the_links_of_every_direction() {
+        let mut lattice = thermalized([4, 4, 4, 4], 27);
+        let (before, action) = (lattice.clone(), lattice.average_action());
+
+        let report = lattice.fix_landau_gauge(1e-12, 10_000);
+        assert!(report.converged && report.residual < 1e-12, "{:?}", report);
+        for site in lattice.sites() {
+            let [i, j, k, l] = site.coords();
+            assert!(lattice.gauge_functional_gradient(&[0, 1, 2, 3], i, j, k, l).im.abs() < 1e-5);
+        }
+
+        for direction in 0..4 {
+            let moved = lattice
+                .lattice
+                .iter()
+                .zip(&before.lattice)
+                .filter(|(after, before)| (after.phases[direction] - before.phases[direction]).abs() > 1e-6)
+                .count();
+            assert!(moved > lattice.volume() / 2, "direction {}: {} links moved", direction, moved);
+        }
+        /* the gauge functional only grows */
+        let functional = |lattice: &Lattice| -> f64 {
+            lattice.lattice.iter().flat_map(|links| links.phases).map(f64::cos).sum()
+        };
+        assert!(functional(&lattice) > functional(&before));
+        assert!((lattice.average_action() - action).abs() < 1e-12);
+        assert!(phases_are_principal(&lattice));
+    }
+
+    #[test]
+    fn wrapping_leaves_the_action_unchanged() {
+        let mut rng = Rng::with_seed(23);
+        let mut lattice = Lattice::new_random_dims([2, 3, 2, 4], &mut rng);
+        let wrapped_action = lattice.average_action();
+        for vector in lattice.lattice.iter_mut() {
+            for phase in vector.phases.iter_mut() {
+                *phase += 2.0 * PI * rng.i32(-3..=3) as f64;
+            }
+        }
+        let unwrapped_action = lattice.average_action();
+        lattice.wrap_phases();
+        assert!(phases_are_principal(&lattice));
+        /* shifts of 2 pi k round the phases by a few ulps of 6 pi */
+        assert!((lattice.average_action() - wrapped_action).abs() < 1e-14);
+        assert!((unwrapped_action - wrapped_action).abs() < 1e-14);
+
+        for (phi, expected) in [(PI, PI), (-PI, PI), (3.0 * PI, PI), (0.5 - 4.0 * PI, 0.5), (0.0, 0.0)] {
+            assert!((principal_angle(phi) - expected).abs() < 1e-14, "{} reduces to {}", phi, principal_angle(phi));
+            assert_eq!(principal_angle(principal_angle(phi)), principal_angle(phi));
+        }
+    }
+
+    #[test]
+    fn sample_link_rotates_by_the_staple_phase() {
+        let mut rng = Rng::with_seed(18);
+        let samples = 20_000;
+        let staple = Complex::from_polar(1.5, 0.7);
+        let environment = LinkEnvironment {
+            staple,
+            coupling: 2.0,
+            double_staple: Complex::new(0.0, 0.0),
+            double_coupling: 0.0,
+            plaquette_staple: None,
+        };
+        /* the weight is exp(3 cos(theta + 0.7)) */
+        let shifted: Vec<f64> = (0..samples)
+            .map(|_| principal_angle(sample_link(&environment, &mut rng) + 0.7))
+            .collect();
+        let distance = ks_statistic(shifted, link_cdf(3.0));
+        assert!(distance * (samples as f64).sqrt() < KS_CRITICAL, "KS distance {}", distance);
+    }
+
+    #[test]
+    fn coarse_shift_change_matches_the_total_action() {
+        let mut rng = Rng::with_seed(19);
+        let mut lattice = Lattice::new_random_dims([4, 4, 2, 4], &mut rng);
+        let action = crate::action::ExtendedAction {
+            couplings: Couplings { spatial: 1.1, temporal: 0.7 },
+            gamma: -0.2,
+        };
+        let block_of: Vec<usize> = lattice.sites().map(|site| site.coords()[0] / 2 * 2 + site.coords()[3] / 2).collect();
+        let shifts: Vec<[f64; 4]> = (0..4).map(|_| [0; 4].map(|_| rng.f64() - 0.5)).collect();
+
+        let (action_change, plaquette_change) =
+            lattice.coarse_shift_change(action.couplings, action.gamma, &block_of, &shifts);
+        let (old_action, old_sum) = (action.total_action(&lattice), lattice.total_action());
+        lattice.apply_coarse_shift(&block_of, &shifts);
+        assert!((action.total_action(&lattice) - old_action - action_change).abs() < 1e-10);
+        assert!((lattice.total_action() - old_sum - plaquette_change).abs() < 1e-10);
+    }
+
+    #[test]
+    fn accepted_coarse_updates_keep_the_tracked_action() {
+        let mut rng = Rng::with_seed(20);
+        let mut lattice = Lattice::new_random(4, &mut rng);
+        lattice.set_action_tracking(Some(usize::MAX));
+        let mut accepted = 0;
+        for _ in 0..200 {
+            accepted += lattice.coarse_update(Couplings::isotropic(0.5), 0.0, 2, 0.05, &mut rng) as usize;
+        }
+        assert!(accepted > 0);
+        assert!((lattice.tracked_action().unwrap() - lattice.total_action()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn the_tracked_action_stays_with_the_total_over_a_thousand_sweeps() {
+        let isotropic = WilsonAction { couplings: Couplings::isotropic(1.0) };
+        /* the anisotropic environment does not hold the unweighted staple, the tracker draws its own */
+        let anisotropic = WilsonAction { couplings: Couplings { spatial: 0.8, temporal: 1.3 } };
+        let extended = crate::action::ExtendedAction { couplings: Couplings::isotropic(0.9), gamma: 0.2 };
+        type Sweep = fn(&mut Lattice, &WilsonAction, &crate::action::ExtendedAction, &mut Rng);
+        let sweeps: [(&str, Sweep); 5] = [
+            ("heatbath", |lattice, action, _, rng| lattice.heatbath_sweep_with_action(action, rng)),
+            ("overrelaxation", |lattice, action, _, rng| {
+                lattice.overrelaxation_sweep_with_action(action);
+                lattice.heatbath_sweep_with_action(action, rng);
+            }),
+            ("metropolis", |lattice, action, _, rng| {
+                lattice.metropolis_sweep_with_action(action, 0.8, rng);
+            }),
+            ("checkerboard", |lattice, action, _, rng| {
+                lattice.set_checkerboard(true);
+                lattice.heatbath_sweep_with_action(action, rng);
+            }),
+            ("extended", |lattice, _, extended, rng| lattice.heatbath_sweep_with_action(extended, rng)),
+        ];
+
+        for (name, sweep) in sweeps {
+            for action in [&isotropic, &anisotropic] {
+                let mut rng = Rng::with_seed(21);
+                let mut lattice = Lattice::new_random(4, &mut rng);
+                lattice.set_action_tracking(Some(usize::MAX));
+                for _ in 0..1000 {
+                    sweep(&mut lattice, action, &extended, &mut rng);
+                }
+                let (tracked, total) = (lattice.tracked_action().unwrap(), lattice.total_action());
+                assert!((tracked - total).abs() < 1e-8, "{} {:?}: {} against {}", name, action.couplings, tracked, total);
+
+                /* the tracker only reads the updates, the chain is the one without it */
+                let chains: Vec<Vec<f64>> = [Some(usize::MAX), None]
+                    .into_iter()
+                    .map(|tracking| {
+                        let mut rng = Rng::with_seed(22);
+                        let mut lattice = Lattice::new_random(2, &mut rng);
+                        lattice.set_action_tracking(tracking);
+                        for _ in 0..10 {
+                            sweep(&mut lattice, action, &extended, &mut rng);
+                        }
+                        lattice.to_array()
+                    })
+                    .collect();
+                assert_eq!(chains[0], chains[1], "{}", name);
+                /* the extended sweep brings its own couplings */
+                if name == "extended" {
+                    break;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn the_tracked_action_resynchronizes_on_its_interval_and_on_recompute() {
+        let action = WilsonAction { couplings: Couplings::isotropic(1.0) };
+        let mut rng = Rng::with_seed(22);
+        let mut lattice = Lattice::new_random(3, &mut rng);
+        assert!(lattice.tracked_action().is_none());
+        lattice.set_action_tracking(Some(3));
+        assert_eq!(lattice.tracked_action().unwrap().to_bits(), lattice.total_action().to_bits());
+
+        /* every third sweep ends on the sum over all plaquettes */
+        for sweep in 1..=9usize {
+            lattice.heatbath_sweep_with_action(&action, &mut rng);
+            if sweep.is_multiple_of(3) {
+                assert_eq!(lattice.tracked_action().unwrap().to_bits(), lattice.total_action().to_bits(), "{}", sweep);
+            }
+        }
+
+        /* a drifted total is set right by recompute */
+        lattice.tracked_action.as_mut().unwrap().total += 1.0;
+        lattice.recompute();
+        assert_eq!(lattice.tracked_action().unwrap().to_bits(), lattice.total_action().to_bits());
+
+        lattice.set_action_tracking(None);
+        lattice.heatbath_sweep_with_action(&action, &mut rng);
+        assert!(lattice.tracked_action().is_none());
+    }
+
+    #[test]
+    fn sites_wrap_every_coordinate_around_the_torus() {
+        let dims = [3, 4, 2, 5];
+        let site = Site::new([4, 7, 2, 9], dims);
+        assert_eq!(site.coords(), [1, 3, 0, 4]);
+        assert_eq!(Site::wrapped([-2, -1, 3, -10], dims).coords(), [1, 3, 1, 0]);
+        assert_eq!(site.shift(0, 2).coords(), [0, 3, 0, 4]);
+        assert_eq!(site.shift(3, -9).coords(), [1, 3, 0, 0]);
+        assert_eq!(site.shift(1, 4), site);
+        assert_eq!(site.dims(), dims);
+    }
+
+    #[test]
+    fn staple_predicts_the_action_change_of_a_single_link() {
+        let mut rng = Rng::with_seed(24);
+        let mut lattice = Lattice::new_random_dims([3, 4, 2, 3], &mut rng);
+        let plaquettes = 6.0 * lattice.volume() as f64;
+        for (site, mu) in lattice.links().collect::<Vec<_>>() {
+            let staple = lattice.staple_sum(site, mu);
+            let index = lattice.position(site);
+            let theta = lattice.lattice[index].phases[mu];
+
+            /* e^{i theta} S sums cos(theta_P) over the six plaquettes through the link */
+            let mut through_link = 0.0;
+            for nu in (0..4).filter(|&nu| nu != mu) {
+                through_link += lattice.plaquette_angle(site, mu, nu).cos();
+                through_link += lattice.plaquette_angle(site.shift(nu, -1), mu, nu).cos();
+            }
+            assert!(((Complex::from_polar(1.0, theta) * staple).re - through_link).abs() < 1e-12);
+
+            /* a flip by pi and a random new angle */
+            for new_theta in [principal_angle(theta + PI), PI * (1.0 - 2.0 * rng.f64())] {
+                let predicted = -((Complex::from_polar(1.0, new_theta) - Complex::from_polar(1.0, theta)) * staple).re;
+                let before = lattice.average_action();
+                lattice.lattice[index].phases[mu] = new_theta;
+                let changed = lattice.average_action();
+                /* the staple does not depend on the link */
+                assert_eq!(lattice.staple_sum(site, mu), staple);
+                lattice.lattice[index].phases[mu] = theta;
+                assert!(((changed - before) * plaquettes - predicted).abs() < 1e-11, "link {:?} {}", site, mu);
+            }
+        }
+    }
+
+    #[test]
+    fn integrated_link_is_the_conditional_mean_of_the_link() {
+        const STEPS: usize = 4096;
+        let lattice = Lattice::new_random_dims([4, 2, 2, 4], &mut Rng::with_seed(21));
+        let beta = 1.3;
+        for (site, mu) in lattice.links().step_by(13) {
+            let staple = lattice.staple_sum(site, mu);
+            /* the link enters the action only through -beta Re(e^{i theta} S) */
+            let (mut numerator, mut denominator) = (Complex::new(0.0, 0.0), 0.0);
+            for index in 0..STEPS {
+                let link = Complex::from_polar(1.0, 2.0 * PI * index as f64 / STEPS as f64);
+                let weight = (beta * (link * staple).re).exp();
+                numerator += link * weight;
+                denominator += weight;
+            }
+            assert!((lattice.integrated_link(site, mu, beta) - numerator / denominator).norm() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn integrated_sides_must_not_share_plaquettes() {
+        let lattice = Lattice::new_uniform_dims([4, 6, 4, 4]);
+        assert!(!lattice.integrated_loop_allowed(1, 2, 0, 3));
+        assert!(lattice.integrated_loop_allowed(2, 4, 0, 3));
+        /* three steps along an extent of 4 are one step the other way around */
+        assert!(!lattice.integrated_loop_allowed(3, 2, 0, 3));
+        assert!(lattice.integrated_loop_allowed(3, 2, 1, 3));
+        /* a side longer than its extent would contain a link twice */
+        assert!(!lattice.integrated_loop_allowed(2, 5, 0, 3));
+        assert!(lattice.integrated_loop_allowed(2, 6, 0, 1));
+        /* on a cold lattice every integrated link is real and the loops are equal */
+        let integrated = lattice.integrated_links(1.0);
+        assert!((lattice.integrated_wilson_loop(2, 2, 1, 3, &integrated) - bessel_ratio(6.0).powi(4)).abs() < 1e-12);
+    }
+
+    #[test]
+    fn integrated_and_naive_wilson_loops_agree_with_less_noise() {
+        let mut rng = Rng::with_seed(22);
+        let mut lattice = Lattice::new_random(4, &mut rng);
+        let couplings = Couplings::isotropic(1.1);
+        for _ in 0..50 {
+            lattice.heatbath_sweep(couplings, &mut rng);
+        }
+        let (mut naive, mut integrated) = (Vec::new(), Vec::new());
+        for _ in 0..300 {
+            lattice.heatbath_sweep(couplings, &mut rng);
+            naive.push(lattice.wilson_loops_up_to(2, 2, None)[1][1]);
+            integrated.push(lattice.wilson_loops_up_to(2, 2, Some(1.1))[1][1]);
+        }
+
+        let mean = |series: &[f64]| series.iter().sum::<f64>() / series.len() as f64;
+        let variance = |series: &[f64]| {
+            let average = mean(series);
+            series.iter().map(|value| (value - average).powi(2)).sum::<f64>() / (series.len() - 1) as f64
+        };
+        /* the two estimators are positively correlated, so the naive combined error is generous */
+        let error = ((variance(&naive) + variance(&integrated)) / naive.len() as f64).sqrt();
+        assert!((mean(&naive) - mean(&integrated)).abs() < 4.0 * error, "{} vs {} +- {}", mean(&naive), mean(&integrated), error);
+        assert!(
+            variance(&integrated) < 0.7 * variance(&naive),
+            "variance {} integrated, {} naive",
+            variance(&integrated),
+            variance(&naive)
+        );
+    }
+
+    /* a checkerboard sweep whose second color evaluates the parity at the neighbor up along the
+     * direction instead of at the site, so it picks the first color again */
+    fn faulty_checkerboard_sweep(lattice: &mut Lattice, couplings: Couplings, rng: &mut Rng) {
+        let action = WilsonAction { couplings };
+        for m in 0..4 {
+            for parity in 0..2 {
+                for site in lattice.sites() {
+                    let probe = if parity == 0 { site } else { site.shift(m, 1) };
+                    if probe.coords().iter().sum::<usize>() % 2 != parity {
+                        continue;
+                    }
+                    let environment = action.link_environment(lattice, site, m);
+                    let index = lattice.position(site);
+                    lattice.lattice[index].phases[m] = sample_link(&environment, rng);
+                    let [i, j, k, l] = site.coords();
+                    lattice.mark_updated(i, j, k, l, m);
+                }
+            }
+        }
+        lattice.check_all_updated();
+    }
+
+    #[test]
+    fn paranoid_mode_accepts_every_sweep() {
+        let mut rng = Rng::with_seed(23);
+        let mut lattice = Lattice::new_random_dims([4, 2, 2, 4], &mut rng);
+        let couplings = Couplings::isotropic(1.0);
+        lattice.set_paranoid(true);
+        for _ in 0..2 {
+            lattice.heatbath_sweep(couplings, &mut rng);
+            lattice.overrelaxation_sweep();
+            lattice.metropolis_sweep(1.0, 0.5, &mut rng);
+        }
+        lattice.set_checkerboard(true);
+        lattice.heatbath_sweep(couplings, &mut rng);
+        /* without the bitset the faulty sweep goes unnoticed */
+        lattice.set_paranoid(false);
+        faulty_checkerboard_sweep(&mut lattice, couplings, &mut rng);
+    }
+
+    #[test]
+    #[should_panic(expected = "updated twice in one sweep")]
+    fn paranoid_mode_catches_a_faulty_mask() {
+        let mut rng = Rng::with_seed(24);
+        let mut lattice = Lattice::new_random_dims([4, 2, 2, 4], &mut rng);
+        lattice.set_paranoid(true);
+        faulty_checkerboard_sweep(&mut lattice, Couplings::isotropic(1.0), &mut rng);
+    }
+
+    #[test]
+    #[should_panic(expected = "was not updated in the sweep")]
+    fn paranoid_mode_catches_a_skipped_link() {
+        let mut lattice = Lattice::new_uniform_dims([2, 2, 2, 2]);
+        lattice.set_paranoid(true);
+        for (site, m) in lattice.links().skip(1) {
+            let [i, j, k, l] = site.coords();
+            lattice.mark_updated(i, j, k, l, m);
+        }
+        lattice.check_all_updated();
+    }
+
+    #[test]
+    fn the_flat_array_restores_every_phase_bit_for_bit() {
+        let mut rng = Rng::with_seed(25);
+        for dims in [[3, 3, 3, 3], [2, 4, 3, 5]] {
+            let lattice = Lattice::new_random_dims(dims, &mut rng);
+            let phases = lattice.to_array();
+            assert_eq!(phases.len(), 4 * lattice.volume());
+            let restored = Lattice::from_array_dims(dims, &phases).unwrap();
+            for (site, mu) in lattice.links() {
+                let [i, j, k, l] = site.coords();
+                assert_eq!(restored.link_phase(i, j, k, l, mu).to_bits(), lattice.link_phase(i, j, k, l, mu).to_bits());
+            }
+            assert!(Lattice::from_array_dims(dims, &phases[1..]).is_err());
+        }
+        let lattice = Lattice::new_random(3, &mut rng);
+        assert_eq!(Lattice::from_array(3, &lattice.to_array()).unwrap().to_array(), lattice.to_array());
+        assert!(Lattice::from_array(2, &lattice.to_array()).is_err());
+    }
+}
@@ -0,0 +1,38 @@
+/* the simulation kernel and its bookkeeping, main.rs is the command line around it */
+#![allow(clippy::needless_return)]
+pub mod action;
+pub mod analysis;
+pub mod buildinfo;
+pub mod approx;
+pub mod cli;
+pub mod config;
+pub mod diskspace;
+pub mod equilibration;
+pub mod expression;
+pub mod heartbeat;
+pub mod histogram;
+pub mod json;
+pub mod latency;
+pub mod lattice;
+pub mod lint;
+pub mod manifest;
+pub mod memory;
+pub mod phasevector;
+pub mod portable;
+pub mod presets;
+pub mod progress;
+pub mod publish;
+pub mod rng;
+pub mod scalar;
+pub mod scan;
+pub mod sidecar;
+pub mod simulation;
+pub mod start;
+pub mod tempering;
+
+pub use lattice::{Lattice, Site};
+pub use phasevector::PhaseVector;
+pub use simulation::Simulation;
+
+/* couplings closer than this to the transition make the calibration extrapolation unreliable */
+pub const CRITICAL_BETA: f64 = 1.01;
@@ -0,0 +1,154 @@
+use crate::config::RunConfig;
+use crate::start::StartSpec;
+use crate::CRITICAL_BETA;
+
+/* below this coupling an ordered start is far from the typical configurations */
+const DISORDERED_BETA: f64 = 0.5;
+
+/* a legal but most likely unintended parameter combination */
+pub struct Rule {
+    pub name: &'static str,
+    pub check: fn(&RunConfig) -> Option<String>,
+}
+
+pub const RULES: [Rule; 3] = [
+    Rule {
+        name: "ordered-start-strong-coupling",
+        check: |config| {
+            (config.start == StartSpec::Ordered && config.beta < DISORDERED_BETA).then(|| {
+                format!(
+                    "ordered start at beta {} is deep in the disordered phase and needs a long burn in, a random start equilibrates faster",
+                    config.beta
+                )
+            })
+        },
+    },
+    Rule {
+        name: "no-sweeps-between-measurements",
+        check: |config| {
+            (config.sweeps_between_measurements == 0 && !config.frozen).then(|| {
+                "0 sweeps between measurements measures the same configuration over and over, use --frozen if that is intended"
+                    .to_string()
+            })
+        },
+    },
+    Rule {
+        name: "short-equilibration-weak-coupling",
+        check: |config| {
+            let longest_extent = *config.lattice_dims.iter().max().unwrap();
+            (config.beta > CRITICAL_BETA && config.equilibration_sweeps < longest_extent).then(|| {
+                format!(
+                    "{} equilibration sweeps are fewer than the longest lattice extent {}, at weak coupling the burn in is unlikely to be complete",
+                    config.equilibration_sweeps, longest_extent
+                )
+            })
+        },
+    },
+];
+
+/* every rule that fires for this configuration, as (rule name, explanation) */
+pub fn lint(config: &RunConfig) -> Vec<(&'static str, String)> {
+    return RULES
+        .iter()
+        .filter_map(|rule| (rule.check)(config).map(|message| (rule.name, message)))
+        .collect();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation::Algorithm;
+
+    /* a run none of the rules objects to */
+    fn quiet() -> RunConfig {
+        return RunConfig {
+            name: "run.h5".to_string(),
+            beta: 1.0,
+            beta_spatial: None,
+            beta_temporal: None,
+            lattice_dims: [4; 4],
+            start: StartSpec::Random,
+            measurements: 10,
+            equilibration_sweeps: 4,
+            sweeps_between_measurements: 1,
+            interval: 60,
+            publish: None,
+            region_blocks: None,
+            wilson_loops: None,
+            strict_equilibration: false,
+            kappa: None,
+            gamma: None,
+            topological_charge: false,
+            polyakov: false,
+            monopoles: false,
+            plane_resolved: false,
+            frozen: false,
+            derive: Vec::new(),
+            seed: None,
+            threads: None,
+            algorithm: Algorithm::Heatbath,
+            step_size: 1.0,
+            overrelaxation_per_heatbath: 0,
+            targeted_fraction: None,
+            targeted_hits: 1,
+            targeted_refresh: 10,
+            coarse_update: None,
+            integrated_links: false,
+        };
+    }
+
+    fn fired(config: RunConfig) -> Vec<&'static str> {
+        return lint(&config).into_iter().map(|(rule, _)| rule).collect();
+    }
+
+    #[test]
+    fn an_unremarkable_run_passes() {
+        assert!(fired(quiet()).is_empty());
+    }
+
+    #[test]
+    fn an_ordered_start_is_flagged_only_at_strong_coupling() {
+        let ordered = |beta| RunConfig { beta, start: StartSpec::Ordered, ..quiet() };
+        assert_eq!(fired(ordered(0.3)), ["ordered-start-strong-coupling"]);
+        assert!(fired(ordered(DISORDERED_BETA)).is_empty());
+        assert!(fired(RunConfig { beta: 0.3, ..quiet() }).is_empty());
+    }
+
+    #[test]
+    fn measuring_without_sweeps_is_flagged_unless_frozen() {
+        let unswept = |frozen| RunConfig { sweeps_between_measurements: 0, frozen, ..quiet() };
+        assert_eq!(fired(unswept(false)), ["no-sweeps-between-measurements"]);
+        assert!(fired(unswept(true)).is_empty());
+    }
+
+    #[test]
+    fn a_short_burn_in_is_flagged_only_at_weak_coupling() {
+        let burn_in = |beta, equilibration_sweeps| RunConfig {
+            beta,
+            equilibration_sweeps,
+            lattice_dims: [4, 4, 4, 6],
+            ..quiet()
+        };
+        assert_eq!(fired(burn_in(1.1, 5)), ["short-equilibration-weak-coupling"]);
+        assert!(fired(burn_in(1.1, 6)).is_empty());
+        assert!(fired(burn_in(0.9, 5)).is_empty());
+    }
+
+    #[test]
+    fn every_message_names_the_offending_values() {
+        let config = RunConfig {
+            beta: 0.25,
+            start: StartSpec::Ordered,
+            sweeps_between_measurements: 0,
+            ..quiet()
+        };
+        let findings = lint(&config);
+        assert_eq!(findings.len(), 2);
+        assert!(findings[0].1.contains("0.25"), "{}", findings[0].1);
+        assert!(findings[1].1.contains("--frozen"), "{}", findings[1].1);
+
+        let config = RunConfig { beta: 1.5, equilibration_sweeps: 2, lattice_dims: [3, 3, 3, 7], ..quiet() };
+        let (_, message) = &lint(&config)[0];
+        assert!(message.contains("2 equilibration sweeps") && message.contains("extent 7"), "{}", message);
+    }
+}
@@ -1,11 +1,46 @@
-pub mod lattice;
-pub mod phasevector;
-
-use anyhow::{Context, Result};
-use clap::{Args, Parser, Subcommand};
-use hdf5::File;
-use lattice::Lattice;
+#![allow(clippy::needless_return)]
+use anyhow::{anyhow, bail, Context, Result};
+use clap::{Args, Parser, Subcommand, ValueEnum};
 use fastrand::Rng;
+use hdf5::types::VarLenUnicode;
+use hdf5::{Dataset, File, Group, H5Type};
+use lattice_rust::action::Couplings;
+use lattice_rust::analysis;
+use lattice_rust::buildinfo::BuildInfo;
+use lattice_rust::cli::{format_duration, format_size, parse_coarse_update, parse_dims, parse_duration, parse_seconds, parse_size};
+use lattice_rust::config::{json_string, split_rerun_command, validate_lattice, RunConfig};
+use lattice_rust::diskspace::{report_state, DiskWatchdog, SaveAction};
+use lattice_rust::equilibration::{drift_significance, DRIFT_THRESHOLD};
+use lattice_rust::expression::Derived;
+use lattice_rust::heartbeat::Heartbeat;
+use lattice_rust::histogram::StreamingHistogram;
+use lattice_rust::json::{self, Json};
+use lattice_rust::latency::SweepLatency;
+use lattice_rust::lint::lint;
+use lattice_rust::manifest::{
+    analysis_parameters, compare, count_items, probation_length, recompute_statistic, Mismatch, MANIFEST_VERSION,
+    SUMMARY_STATISTICS,
+};
+use lattice_rust::memory::{check_memory, Footprint};
+use lattice_rust::portable::{read_configuration, xxh64};
+use lattice_rust::presets::{find_preset, print_presets, PRESETS};
+use lattice_rust::progress::{install_panic_report, Aborted, Phase, Progress};
+use lattice_rust::publish::Publisher;
+use lattice_rust::rng::{derive_seed, RngRegistry};
+use lattice_rust::scalar::{Matter, ScalarField};
+use lattice_rust::scan::{beta_range, check_betas, group_name, max_discrepancy, ScanPoint};
+use lattice_rust::sidecar::{read_sidecar, sidecar_path, write_sidecar, SavedSummary};
+use lattice_rust::simulation::{Algorithm, CoarseUpdate, Targeting};
+use lattice_rust::start::{tile_to, StartFlags, StartSpec};
+use lattice_rust::tempering::Ladder;
+use lattice_rust::lattice::{format_extents, spatial_average, temporal_average, LinkScores, PLANES, TIME_DIRECTION};
+use lattice_rust::{Lattice, Simulation, CRITICAL_BETA};
+use std::io::Read;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
 
 #[derive(Parser)]
 #[command(author, version, about, long_about=None)]
@@ -15,53 +50,605 @@ struct Cli {
     command: Commands,
 }
 
+/* parsed once per invocation, the size of New does not matter */
+#[allow(clippy::large_enum_variant)]
 #[derive(Subcommand)]
 enum Commands {
-    /// resume from old config
+    /// continue a run from the configuration stored in its save file
     Resume(Resume),
     /// create new config
     New(New),
 
     /// generate tikz code 
     Visualize(Visualize),
+
+    /// estimate the run length needed for a target error
+    Plan(Plan),
+
+    /// perform a fixed number of sweeps on a run and checkpoint, creating it first if needed
+    Step(Step),
+
+    /// run one coupling after the other in a single file, each starting from the last configuration of the previous
+    Scan(Scan),
+
+    /// run replicas at several couplings in parallel and exchange their configurations (parallel tempering)
+    Tempering(Tempering),
+
+    /// write the latest configuration of a run to a file for other codes
+    Export(Export),
+
+    /// print the mean action of a run with naive and binned jackknife errors
+    Analyze(Analyze),
+
+    /// print the parameters and progress of every segment of a run
+    Info(Info),
+
+    /// close the measurements of a run and continue it in a new segment with new parameters
+    Retarget(Retarget),
+
+    /// describe everything needed to regenerate the data of a run or of a directory of runs
+    Manifest(Manifest),
+
+    /// check run files against a manifest, optionally rerunning small runs
+    VerifyManifest(VerifyManifest),
+
+    /// run miniature demonstrations of the other subcommands in a temporary directory
+    Examples(Examples),
 }
 
 #[derive(Args)]
-struct Resume {}
+struct Retarget {
+    /// name of the save file
+    #[arg(short, long)]
+    name: String,
+
+    /// start the new segment from the last checkpoint of the current one instead of its own start
+    #[arg(long)]
+    carry_over: bool,
+
+    #[command(flatten)]
+    options: RunOptions,
+
+    #[command(flatten)]
+    memory: MemoryCheck,
+
+    /// arguments of the new subcommand, without --name, with the parameters of the new segment
+    #[arg(last = true)]
+    parameters: Vec<String>,
+}
 
 #[derive(Args)]
-struct New {
-    /// name for new save file
+struct Manifest {
+    /// run file, or directory whose run files all go into the manifest
     #[arg(short, long)]
     name: String,
 
-    /// specify value of beta
+    /// write the manifest to this file instead of printing it
     #[arg(short, long)]
-    beta: f64,
+    output: Option<String>,
+}
 
-    /// specify lattice width
+#[derive(Args)]
+struct VerifyManifest {
+    /// manifest written by the manifest subcommand
+    #[arg(short, long)]
+    manifest: String,
+
+    /// directory holding the run files, by default the one recorded in the manifest
+    #[arg(long)]
+    directory: Option<String>,
+
+    /// also rerun every small segment that does not continue an earlier one and compare its measurements bit for bit
+    #[arg(long)]
+    rerun: bool,
+}
+
+#[derive(Args)]
+struct Analyze {
+    /// name of the save file
+    #[arg(short, long)]
+    name: String,
+
+    /// print the results as a single JSON object instead of a table
+    #[arg(long)]
+    json: bool,
+
+    /// also estimate the integrated autocorrelation time with automatic windowing
+    #[arg(long)]
+    autocorr: bool,
+}
+
+#[derive(Args)]
+struct Info {
+    /// name of the save file
+    #[arg(short, long)]
+    name: String,
+
+    /// print only the command line that reruns the current segment
+    #[arg(long)]
+    rerun_command: bool,
+
+    /// read the parameters and progress from the <name>.meta.json sidecar instead of the save file,
+    /// which is faster on network filesystems
+    #[arg(long, conflicts_with = "rerun_command")]
+    from_sidecar: bool,
+}
+
+#[derive(Copy, Clone, ValueEnum)]
+enum ExportFormat {
+    /// self describing format with byte order, precision, metadata and checksum, see src/portable.rs
+    Portable,
+    /// native format of --cache-config, only meant to be read by this program
+    Cache,
+}
+
+#[derive(Args)]
+struct Export {
+    /// name of the save file
+    #[arg(short, long)]
+    name: String,
+
+    /// file to write the configuration to
+    #[arg(short, long)]
+    output: String,
+
+    /// format of the written file
+    #[arg(long, value_enum, default_value_t = ExportFormat::Portable)]
+    format: ExportFormat,
+}
+
+#[derive(Args)]
+struct Step {
+    /// name of the save file, created from the new arguments after -- if it does not exist
+    #[arg(short, long)]
+    name: String,
+
+    /// number of sweeps to perform, burn in sweeps included
+    #[arg(short, long)]
+    sweeps: usize,
+
+    /// sweep count the step starts from, a retried step that already got past it does nothing
+    #[arg(long)]
+    from_sweep: Option<usize>,
+
+    #[command(flatten)]
+    options: RunOptions,
+
+    #[command(flatten)]
+    memory: MemoryCheck,
+
+    /// arguments of the new subcommand, without --name, used when the save file does not exist
+    #[arg(last = true)]
+    create: Vec<String>,
+}
+
+#[derive(Args)]
+struct Scan {
+    /// name of the save file, the measurements of every coupling go into a group beta_<beta>
+    #[arg(short, long)]
+    name: String,
+
+    /// first coupling of the scan, above --beta-end to scan downward
+    #[arg(long, requires_all = ["beta_end", "beta_step"], required_unless_present = "betas")]
+    beta_start: Option<f64>,
+
+    /// last coupling of the scan, included if it is a whole number of steps from --beta-start
+    #[arg(long, requires = "beta_start")]
+    beta_end: Option<f64>,
+
+    /// distance between consecutive couplings
+    #[arg(long, requires = "beta_start")]
+    beta_step: Option<f64>,
+
+    /// couplings in scan order instead of a range, e.g. 1.03,1.01,0.99
+    #[arg(long, value_delimiter = ',', conflicts_with = "beta_start")]
+    betas: Vec<f64>,
+
+    /// scan twice into the groups heating and cooling: down from the largest coupling with an ordered
+    /// start and up from the smallest with a random start, and report where the two differ most
+    #[arg(long)]
+    hysteresis: bool,
+
+    #[command(flatten)]
+    options: RunOptions,
+
+    #[command(flatten)]
+    memory: MemoryCheck,
+
+    /// arguments of the new subcommand without --name and --beta, the same for every coupling. The
+    /// start only applies to the first coupling
+    #[arg(last = true)]
+    parameters: Vec<String>,
+}
+
+#[derive(Args)]
+struct Tempering {
+    /// name of the save file, the measurements at every coupling go into a group beta_<beta>
     #[arg(short, long)]
-    lattice_width: usize,
+    name: String,
+
+    /// couplings of the replicas, e.g. 0.99,1.0,1.01. Exchanges are proposed between neighbors in
+    /// ascending order
+    #[arg(long, value_delimiter = ',', required = true)]
+    betas: Vec<f64>,
+
+    /// sweeps of every replica between two rounds of exchanges
+    #[arg(long, default_value_t = 10)]
+    swap_interval: usize,
+
+    #[command(flatten)]
+    memory: MemoryCheck,
+
+    /// arguments of the new subcommand without --name and --beta, the same for every replica
+    #[arg(last = true)]
+    parameters: Vec<String>,
+}
+
+#[derive(Args)]
+struct Resume {
+    /// name of the save file to continue
+    #[arg(short, long)]
+    name: String,
+
+    #[command(flatten)]
+    options: RunOptions,
+
+    #[command(flatten)]
+    memory: MemoryCheck,
+}
+
+/* the memory check every subcommand runs before it allocates its lattices */
+#[derive(Args, Clone, Default)]
+struct MemoryCheck {
+    /// continue even if the lattice does not seem to fit into the available memory
+    #[arg(long)]
+    ignore_memory_check: bool,
+
+    /// refuse to start if the lattice needs more memory than this, e.g. 512M or 4G
+    #[arg(long, value_parser = parse_size)]
+    memory_limit: Option<u64>,
+}
+
+impl MemoryCheck {
+    fn check(&self, footprint: &Footprint, dims: [usize; 4]) -> Result<()> {
+        return check_memory(footprint, dims, self.ignore_memory_check, self.memory_limit);
+    }
+}
+
+/* options that do not change the Markov chain, shared by New, Resume and Step */
+#[derive(Args, Clone)]
+struct RunOptions {
+    /// keep a <name>.meta.json file with the run parameters and progress next to the output
+    #[arg(long)]
+    sidecar: bool,
+
+    /// keep rewriting a small JSON status file at this path while the run is alive
+    #[arg(long)]
+    heartbeat: Option<String>,
+
+    /// time between heartbeats, e.g. 10s or 1m
+    #[arg(long, default_value = "10s", value_parser = parse_duration)]
+    heartbeat_interval: Duration,
+
+    /// check after every sweep that each link was updated exactly once, slow
+    #[arg(long)]
+    paranoid: bool,
+
+    /// count sweeps taking longer than this multiple of the median sweep as interference events
+    #[arg(long, default_value_t = 3.0)]
+    interference_factor: f64,
+
+    /// store the histogram of sweep wall times of this invocation in the output file
+    #[arg(long)]
+    latency_histogram: bool,
+
+    /// suspend checkpoints when less disk space than this is free next to the output file, and pause
+    /// the run below half of it until space is freed, e.g. 512M, 0 turns the check off
+    #[arg(long, default_value = "256M", value_parser = parse_size)]
+    min_free_disk: u64,
+
+    /// how long a run paused for disk space waits before it stops, e.g. 30m or 2h
+    #[arg(long, default_value = "30m", value_parser = parse_duration)]
+    disk_grace: Duration,
+
+    /// panic in place of the measurement with this index, to exercise the crash report
+    #[arg(long, hide = true)]
+    panic_at_measurement: Option<usize>,
+}
+
+#[derive(Copy, Clone, ValueEnum)]
+enum GaugeFix {
+    Landau,
+    Coulomb,
+}
+
+const GAUGE_FIX_TOLERANCE: f64 = 1e-12;
+const GAUGE_FIX_MAX_ITERS: usize = 10000;
+
+#[derive(Args)]
+struct New {
+    /// name for new save file
+    #[arg(short, long, required_unless_present = "list_presets")]
+    name: Option<String>,
+
+    /// specify value of beta
+    #[arg(short, long, required_unless_present = "list_presets")]
+    beta: Option<f64>,
+
+    /// coupling of the spatial plaquettes, those without direction 3, defaults to --beta
+    #[arg(long)]
+    beta_spatial: Option<f64>,
+
+    /// coupling of the temporal plaquettes, those containing direction 3, defaults to --beta
+    #[arg(long)]
+    beta_temporal: Option<f64>,
+
+    /// start from a curated set of parameters, individual flags override it
+    #[arg(long, value_parser = clap::builder::PossibleValuesParser::new(PRESETS.iter().map(|preset| preset.name)))]
+    preset: Option<String>,
+
+    /// print the available presets and exit
+    #[arg(long)]
+    list_presets: bool,
+
+    /// specify lattice width
+    #[arg(short, long, short_alias = 'l')]
+    width: Option<usize>,
+
+    /// deprecated spelling of --width
+    #[arg(long, hide = true, conflicts_with = "width")]
+    lattice_width: Option<usize>,
+
+    /// specify the extents nx,ny,nz,nt of the four directions instead of one width, e.g. 16,16,16,4
+    #[arg(long, value_parser = parse_dims, conflicts_with_all = ["width", "lattice_width"])]
+    dims: Option<[usize; 4]>,
 
     /// specify if state should start in ordered config
     #[arg(short, long)]
     ordered: bool,
 
+    /// start from an analytic configuration, planewave:k=1,0,0,0;A=0.3[;mu=0] or constant:n01=1,n23=1
+    #[arg(long)]
+    start: Option<String>,
+
+    /// start from a configuration written by export or --cache-config, of the same width
+    #[arg(long)]
+    initial_config: Option<String>,
+
+    /// start from the latest checkpoint of another run, file.h5[:segment], tiled periodically if
+    /// this lattice is a multiple of it. Without --equilibration-sweeps the burn in is a quarter
+    /// of the preset's
+    #[arg(long, value_name = "FILE[:SEGMENT]")]
+    warm_start_from: Option<String>,
+
     /// specify number of measurements
     #[arg(short, long)]
-    measurements: usize,
+    measurements: Option<usize>,
 
     /// specify number of equilibration sweeps
     #[arg(short, long)]
-    equilibration_sweeps: usize,
+    equilibration_sweeps: Option<usize>,
 
     /// specify number of sweeps between measurements
     #[arg(short, long)]
-    sweeps_between_measurements: usize,
+    sweeps_per_measurement: Option<usize>,
 
-    /// specify number of seconds between saves
-    #[arg(short, long)]
-    interval: usize,
+    /// deprecated spelling of --sweeps-per-measurement
+    #[arg(long, hide = true, conflicts_with = "sweeps_per_measurement")]
+    sweeps_between_measurements: Option<usize>,
+
+    /// time between saves in seconds or e.g. 90s, 1h30m
+    #[arg(short, long, short_alias = 'i', value_parser = parse_seconds)]
+    flush_every: Option<usize>,
+
+    /// deprecated spelling of --flush-every
+    #[arg(long, hide = true, conflicts_with = "flush_every", value_parser = parse_seconds)]
+    interval: Option<usize>,
+
+    /// publish every measurement as a JSON line to unix:<path> or tcp:<host>:<port>
+    #[arg(long)]
+    publish: Option<String>,
+
+    /// also record the plaquette average of each of region_blocks^4 sub-lattices
+    #[arg(long)]
+    region_blocks: Option<usize>,
+
+    /// also record the R x T Wilson loops for all 1 <= R, T <= R_MAX
+    #[arg(long, value_name = "R_MAX")]
+    wilson_loops: Option<usize>,
+
+    /// measure the Wilson loops with the links of their temporal sides integrated out, which
+    /// reduces the noise wherever the two sides are at least two sites apart
+    #[arg(long, requires = "wilson_loops")]
+    integrated_links: bool,
+
+    /// write a <name>.rerun.sh script that repeats this run
+    #[arg(long)]
+    rerun_script: bool,
+
+    /// abort instead of warning when the first measurements still drift
+    #[arg(long)]
+    strict_equilibration: bool,
+
+    /// couple a compact scalar field to the links with this hopping parameter
+    #[arg(long, allow_negative_numbers = true)]
+    kappa: Option<f64>,
+
+    /// add the double charge plaquette term gamma * sum_P (1 - cos(2 theta_P)) to the action and
+    /// record its plaquette average
+    #[arg(long, conflicts_with = "kappa", allow_negative_numbers = true)]
+    gamma: Option<f64>,
+
+    /// also record the naive topological charge per measurement
+    #[arg(long)]
+    topological_charge: bool,
+
+    /// also record the Polyakov loop along the time direction, as |P|, Re P and Im P per measurement
+    #[arg(long)]
+    polyakov: bool,
+
+    /// also record the DeGrand-Toussaint monopole density per measurement
+    #[arg(long)]
+    monopoles: bool,
+
+    /// also record the plaquette action of each (mu, nu) plane and of the spatial and temporal
+    /// planes per measurement
+    #[arg(long)]
+    plane_resolved: bool,
+
+    /// debug mode, measure the equilibrated configuration over and over without sweeping in between
+    #[arg(long)]
+    frozen: bool,
+
+    /// record a derived observable name=expression of action, hopping, double_action,
+    /// monopole_density, topological_charge, spatial_action and temporal_action, repeatable
+    #[arg(long)]
+    derive: Vec<String>,
+
+    /// master seed for the random streams, the same seed reproduces the same measurements
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// sweep in parallel checkerboard order on this many threads, needs an even lattice width. The
+    /// chain does not depend on the number of threads but differs from the sequential sweep
+    #[arg(long)]
+    threads: Option<usize>,
+
+    /// link update algorithm
+    #[arg(long, value_enum, default_value_t = Algorithm::Heatbath)]
+    algorithm: Algorithm,
+
+    /// initial half width of the Metropolis proposals in radians, tuned towards 50 % acceptance
+    /// during the burn in
+    #[arg(long, default_value_t = 1.0)]
+    step_size: f64,
+
+    /// follow every link update sweep with this many overrelaxation sweeps, which leave the action
+    /// unchanged but shorten autocorrelations near the transition
+    #[arg(long, default_value_t = 0)]
+    overrelaxation_per_heatbath: usize,
+
+    /// experimental: after every sweep give extra heatbath hits to the links whose plaquettes
+    /// disagree most, about this fraction of all links
+    #[arg(long)]
+    targeted_fraction: Option<f64>,
+
+    /// heatbath hits per targeted link and sweep
+    #[arg(long, default_value_t = 1, requires = "targeted_fraction")]
+    targeted_hits: usize,
+
+    /// sweeps between refreshes of the score threshold of targeted links during the equilibration,
+    /// the threshold stays fixed while measuring
+    #[arg(long, default_value_t = 10, requires = "targeted_fraction")]
+    targeted_refresh: usize,
+
+    /// after every sweep propose shifting all links by random amounts constant on blocks of
+    /// BLOCK^4 sites, at most AMPLITUDE radians, to move the long wavelength modes, e.g. 4,0.1
+    #[arg(long, value_name = "BLOCK,AMPLITUDE", value_parser = parse_coarse_update)]
+    coarse_update: Option<(usize, f64)>,
+
+    #[command(flatten)]
+    options: RunOptions,
+
+    #[command(flatten)]
+    memory: MemoryCheck,
+
+    /// refuse to start on suspicious parameter combinations instead of warning about them
+    #[arg(long)]
+    strict: bool,
+}
+
+impl New {
+    /* old spellings of renamed flags that were given, with their replacements */
+    fn deprecated_flags(&self) -> Vec<(&'static str, &'static str)> {
+        let mut used = Vec::new();
+        if self.lattice_width.is_some() {
+            used.push(("lattice-width", "width"));
+        }
+        if self.sweeps_between_measurements.is_some() {
+            used.push(("sweeps-between-measurements", "sweeps-per-measurement"));
+        }
+        if self.interval.is_some() {
+            used.push(("interval", "flush-every"));
+        }
+        return used;
+    }
+
+    /* fill in every parameter not given on the command line from the chosen preset */
+    fn resolve(self) -> Result<RunConfig> {
+        let preset = self.preset.as_deref().and_then(find_preset);
+        let missing = |flag: &str| anyhow!("--{} must be given when no preset covers it", flag);
+        let start = StartFlags {
+            ordered: self.ordered,
+            start: self.start.as_deref(),
+            file: self.initial_config.as_deref().map(|path| ("initial-config", path)),
+            warm_start: self.warm_start_from.as_deref(),
+        }
+        .resolve()?;
+        /* a warm start is already locally equilibrated, only the long wavelength modes relax */
+        let burn_in_divisor = match start {
+            StartSpec::WarmStart { .. } => WARM_START_BURN_IN_DIVISOR,
+            _ => 1,
+        };
+
+        let config = RunConfig {
+            name: self.name.ok_or_else(|| missing("name"))?,
+            beta: self.beta.ok_or_else(|| missing("beta"))?,
+            beta_spatial: self.beta_spatial,
+            beta_temporal: self.beta_temporal,
+            lattice_dims: match self.dims {
+                Some(dims) => dims,
+                None => [self
+                    .width
+                    .or(self.lattice_width)
+                    .or(preset.map(|preset| preset.lattice_width))
+                    .ok_or_else(|| missing("width"))?; 4],
+            },
+            start,
+            measurements: self
+                .measurements
+                .or(preset.map(|preset| preset.measurements))
+                .ok_or_else(|| missing("measurements"))?,
+            equilibration_sweeps: self
+                .equilibration_sweeps
+                .or(preset.map(|preset| preset.equilibration_sweeps / burn_in_divisor))
+                .ok_or_else(|| missing("equilibration-sweeps"))?,
+            sweeps_between_measurements: self
+                .sweeps_per_measurement
+                .or(self.sweeps_between_measurements)
+                .or(preset.map(|preset| preset.sweeps_between_measurements))
+                .ok_or_else(|| missing("sweeps-per-measurement"))?,
+            interval: self
+                .flush_every
+                .or(self.interval)
+                .or(preset.map(|preset| preset.interval))
+                .ok_or_else(|| missing("flush-every"))?,
+            publish: self.publish,
+            region_blocks: self.region_blocks,
+            wilson_loops: self.wilson_loops,
+            strict_equilibration: self.strict_equilibration,
+            kappa: self.kappa,
+            gamma: self.gamma,
+            topological_charge: self.topological_charge,
+            polyakov: self.polyakov,
+            monopoles: self.monopoles,
+            plane_resolved: self.plane_resolved,
+            frozen: self.frozen,
+            derive: self.derive,
+            seed: self.seed,
+            threads: self.threads,
+            algorithm: self.algorithm,
+            step_size: self.step_size,
+            overrelaxation_per_heatbath: self.overrelaxation_per_heatbath,
+            targeted_fraction: self.targeted_fraction,
+            targeted_hits: self.targeted_hits,
+            targeted_refresh: self.targeted_refresh,
+            coarse_update: self.coarse_update,
+            integrated_links: self.integrated_links,
+        };
+        config.validate()?;
+        Ok(config)
+    }
 }
 
 #[derive(Args)]
@@ -71,163 +658,4397 @@ struct Visualize {
     name: String,
 
     /// specify value of beta
-    #[arg(short, long)]
-    beta: f64,
+    #[arg(short, long, required_unless_present = "from_cache")]
+    beta: Option<f64>,
 
     /// specify lattice width
-    #[arg(short, long)]
-    lattice_width: usize,
+    #[arg(short, long, short_alias = 'l', required_unless_present_any = ["from_cache", "lattice_width", "dims"])]
+    width: Option<usize>,
+
+    /// deprecated spelling of --width
+    #[arg(long, hide = true, conflicts_with = "width")]
+    lattice_width: Option<usize>,
+
+    /// specify the extents nx,ny,nz,nt of the four directions instead of one width, e.g. 16,16,16,4
+    #[arg(long, value_parser = parse_dims, conflicts_with_all = ["width", "lattice_width"])]
+    dims: Option<[usize; 4]>,
 
     /// specify if state should start in ordered config
     #[arg(short, long)]
     ordered: bool,
 
     /// specify number of equilibration sweeps
-    #[arg(short, long)]
-    equilibration_sweeps: usize,
+    #[arg(short, long, required_unless_present = "from_cache")]
+    equilibration_sweeps: Option<usize>,
     
     /// visualize the plaquettes instead of links
     #[arg(short, long)]
     plaquettes: bool,
+
+    /// fix the gauge before drawing the links
+    #[arg(long, value_enum)]
+    gauge_fix: Option<GaugeFix>,
+
+    /// start from an analytic configuration, planewave:k=1,0,0,0;A=0.3[;mu=0] or constant:n01=1,n23=1
+    #[arg(long)]
+    start: Option<String>,
+
+    /// seed for the random number generator, the same seed reproduces the same configuration
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// store the equilibrated configuration, before gauge fixing, in this file
+    #[arg(long)]
+    cache_config: Option<String>,
+
+    /// draw a configuration stored with --cache-config or export instead of generating one
+    #[arg(long, conflicts_with = "seed")]
+    from_cache: Option<String>,
+
+    #[command(flatten)]
+    memory: MemoryCheck,
 }
 
-fn main() -> Result<()> {
-    // initialize the random number generator
-    let mut rng =  Rng::new();
-    // parse the arguments
-    let cli = Cli::parse();
+#[derive(Args)]
+struct Plan {
+    /// specify value of beta
+    #[arg(short, long)]
+    beta: f64,
 
-    match cli.command {
-        Commands::New(settings) => {
-            // print settings to user
-            println!("Starting new simulation");
-            println!("Data will be saved in: {}", settings.name);
-            println!("Beta is set to: {}", settings.beta);
-            println!("Lattice width is set to {}", settings.lattice_width);
-            println!("Ordered start is set to {}", settings.ordered);
-            println!(
-                "Simulation will perform {} measurements",
-                settings.measurements
-            );
-            println!(
-                "Burn in phase is {} sweeps long",
-                settings.equilibration_sweeps
-            );
-            println!(
-                "{} sweeps will be performed in between measurements",
-                settings.sweeps_between_measurements
-            );
-            println!(
-                "Simulation will be saved every {} measurements",
-                settings.interval
-            );
+    /// specify lattice width
+    #[arg(short, long, short_alias = 'l', required_unless_present = "lattice_width")]
+    width: Option<usize>,
 
-            // create the save file, give error if it exists to prevent accidental overwriting of data
-            let file = File::create_excl(&settings.name)
-                .with_context(|| format!("Failed to create file {}", settings.name))?;
+    /// deprecated spelling of --width
+    #[arg(long, hide = true, conflicts_with = "width")]
+    lattice_width: Option<usize>,
 
-            // create dataset
-            let action_dataset = file
-                .new_dataset::<f64>()
-                .chunk((1, settings.interval))
-                .shape((0.., settings.interval))
-                .create("action_measurements")?;
-
-            // write attributes
-            let beta_attribute = action_dataset.new_attr::<f64>().shape([1]).create("beta")?;
-            beta_attribute
-                .write(&[settings.beta])
-                .with_context(|| format!("failed to write beta"))?;
-
-            let lattice_width_attribute = action_dataset
-                .new_attr::<usize>()
-                .shape([1])
-                .create("lattice-width")?;
-            lattice_width_attribute.write(&[settings.lattice_width])?;
-
-            let ordered_attribute = action_dataset
-                .new_attr::<bool>()
-                .shape([1])
-                .create("ordered")?;
-            ordered_attribute.write(&[settings.ordered])?;
-
-            let equilibration_sweeps_atttribute = action_dataset
-                .new_attr::<usize>()
-                .shape([1])
-                .create("equilibration_sweeps")?;
-            equilibration_sweeps_atttribute.write(&[settings.equilibration_sweeps])?;
-
-            let sweeps_between_measurements_attribute = action_dataset
-                .new_attr::<usize>()
-                .shape([1])
-                .create("sweeps-between-measurements")?;
-            sweeps_between_measurements_attribute.write(&[settings.sweeps_between_measurements])?;
-
-            // initialize lattice
-            let mut lattice: Lattice;
-
-            if settings.ordered {
-                lattice = Lattice::new_uniform(settings.lattice_width);
-            } else {
-                lattice = Lattice::new_random(settings.lattice_width, &mut rng);
-            }
+    /// target standard error of the mean action per plaquette
+    #[arg(short, long)]
+    target_error: f64,
 
-            // burn in phase
-            for _ in 0..settings.equilibration_sweeps {
-                lattice.heatbath_sweep(settings.beta, &mut rng);
-            }
+    /// specify number of sweeps used for the calibration
+    #[arg(short, long, default_value_t = 500)]
+    calibration_sweeps: usize,
 
-            let mut measurement_vector = Vec::with_capacity(settings.interval);
-            let mut save_counter = 0;
+    /// specify number of equilibration sweeps before the calibration
+    #[arg(short, long, default_value_t = 200)]
+    equilibration_sweeps: usize,
 
-            // note that if the amount measurements is not divisible by the amount of measurement between saves some data is lost
-            for i in 0..settings.measurements {
-                for _ in 0..settings.sweeps_between_measurements {
-                    lattice.heatbath_sweep(settings.beta, &mut rng);
-                }
-                measurement_vector.push(lattice.average_action());
+    /// name for the save file in the suggested command
+    #[arg(short, long, default_value = "run.h5")]
+    name: String,
 
-                if (i + 1) % settings.interval == 0 {
-                    action_dataset
-                        .resize((save_counter + 1, settings.interval))?;
-                    action_dataset.write_slice(&measurement_vector, (save_counter, ..))?;
-                    measurement_vector.clear();
-                    save_counter += 1;
-                }
-            }
+    /// seed for the random number generator, the same seed reproduces the same calibration
+    #[arg(long)]
+    seed: Option<u64>,
 
-            println!("simulation complete");
-            Ok(())
-        }
-        Commands::Resume(_settings) => {
-            println!("started with resume");
-            todo!();
-        }
-        Commands::Visualize(settings) => {
-            println!("generating visualisation");
-           
-            let mut file = std::fs::File::create(settings.name)?;
-            
-            let mut lattice;
+    #[command(flatten)]
+    memory: MemoryCheck,
+}
 
-            if settings.ordered {
-                lattice = Lattice::new_uniform(settings.lattice_width);
-            } else {
-                lattice = Lattice::new_random(settings.lattice_width, &mut rng);
-            }
+#[derive(Args)]
+struct Examples {
+    /// keep the temporary directory with the demonstration outputs
+    #[arg(long)]
+    keep: bool,
+}
 
-            for _ in 0..settings.equilibration_sweeps {
-                lattice.heatbath_sweep(settings.beta, &mut rng);
-            }
+/* a miniature run of one subcommand, the arguments refer to files in the demonstration directory
+ * through the placeholder {dir} */
+struct Demonstration {
+    name: &'static str,
+    args: &'static [&'static str],
+    /* file the demonstration must leave behind, if it writes one */
+    artifact: Option<&'static str>,
+}
 
-            if settings.plaquettes {
-                lattice.visualize_plaquettes_plane_svg(&mut file)?;
-            } else {
-                lattice.visualize_3d_lattice(&mut file)?;
-            }
+const DEMONSTRATIONS: [Demonstration; 13] = [
+    Demonstration {
+        name: "new run",
+        args: &[
+            "new", "--name", "{dir}/run.h5", "--beta", "1.0", "--width", "2", "--measurements", "20",
+            "--equilibration-sweeps", "10", "--sweeps-per-measurement", "1", "--flush-every", "1s",
+            "--seed", "1", "--sidecar",
+        ],
+        artifact: Some("run.h5"),
+    },
+    Demonstration {
+        name: "resume a finished run",
+        args: &["resume", "--name", "{dir}/run.h5"],
+        artifact: Some("run.h5"),
+    },
+    Demonstration {
+        name: "step",
+        args: &[
+            "step", "--name", "{dir}/step.h5", "--sweeps", "15", "--", "--beta", "1.0", "--width", "2",
+            "--measurements", "10", "--equilibration-sweeps", "5", "--sweeps-per-measurement", "1",
+            "--flush-every", "1s", "--seed", "2",
+        ],
+        artifact: Some("step.h5"),
+    },
+    Demonstration {
+        name: "retarget",
+        args: &[
+            "retarget", "--name", "{dir}/run.h5", "--carry-over", "--", "--beta", "1.1", "--width", "2",
+            "--measurements", "10", "--equilibration-sweeps", "0", "--sweeps-per-measurement", "1",
+            "--flush-every", "1s", "--seed", "5",
+        ],
+        artifact: Some("run.h5"),
+    },
+    Demonstration {
+        name: "scan downward",
+        args: &[
+            "scan", "--name", "{dir}/scan.h5", "--beta-start", "1.1", "--beta-end", "0.9", "--beta-step", "0.1",
+            "--", "--width", "2", "--measurements", "10", "--equilibration-sweeps", "5",
+            "--sweeps-per-measurement", "1", "--flush-every", "1s", "--seed", "6",
+        ],
+        artifact: Some("scan.h5"),
+    },
+    Demonstration {
+        name: "analyze",
+        args: &["analyze", "--name", "{dir}/run.h5", "--autocorr"],
+        artifact: None,
+    },
+    Demonstration {
+        name: "export",
+        args: &["export", "--name", "{dir}/run.h5", "--output", "{dir}/run.u1"],
+        artifact: Some("run.u1"),
+    },
+    Demonstration {
+        name: "manifest",
+        args: &["manifest", "--name", "{dir}/run.h5", "--output", "{dir}/manifest.json"],
+        artifact: Some("manifest.json"),
+    },
+    Demonstration {
+        name: "verify the manifest with a rerun",
+        args: &["verify-manifest", "--manifest", "{dir}/manifest.json", "--rerun"],
+        artifact: None,
+    },
+    Demonstration {
+        name: "new run from an exported configuration",
+        args: &[
+            "new", "--name", "{dir}/imported.h5", "--beta", "1.0", "--width", "2", "--measurements", "5",
+            "--equilibration-sweeps", "0", "--sweeps-per-measurement", "1", "--flush-every", "1s",
+            "--seed", "4", "--initial-config", "{dir}/run.u1",
+        ],
+        artifact: Some("imported.h5"),
+    },
+    Demonstration {
+        name: "plan",
+        args: &[
+            "plan", "--beta", "1.0", "--width", "2", "--target-error", "0.01", "--calibration-sweeps", "50",
+            "--equilibration-sweeps", "10", "--name", "{dir}/planned.h5",
+        ],
+        artifact: None,
+    },
+    Demonstration {
+        name: "visualize links",
+        args: &[
+            "visualize", "--name", "{dir}/links.tex", "--beta", "1.0", "--width", "2", "--equilibration-sweeps",
+            "5", "--seed", "3",
+        ],
+        artifact: Some("links.tex"),
+    },
+    Demonstration {
+        name: "visualize plaquettes",
+        args: &[
+            "visualize", "--name", "{dir}/plaquettes.svg", "--beta", "1.0", "--width", "2",
+            "--equilibration-sweeps", "5", "--seed", "3", "--plaquettes",
+        ],
+        artifact: Some("plaquettes.svg"),
+    },
+];
 
-            Ok(())
+/* run every demonstration through the same code as the command line, stopping at the first one
+ * that fails or leaves its artifact missing */
+fn run_examples(examples: Examples) -> Result<()> {
+    let dir = std::env::temp_dir().join(format!("{}-examples-{}", env!("CARGO_PKG_NAME"), std::process::id()));
+    std::fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    let dir_text = dir.to_string_lossy().to_string();
+
+    let result = (|| -> Result<()> {
+        for (index, demonstration) in DEMONSTRATIONS.iter().enumerate() {
+            let mut args = vec![env!("CARGO_PKG_NAME").to_string()];
+            args.extend(demonstration.args.iter().map(|arg| arg.replace("{dir}", &dir_text)));
+            println!("[{}/{}] {}: {}", index + 1, DEMONSTRATIONS.len(), demonstration.name, args.join(" "));
+
+            let command = Cli::try_parse_from(&args)
+                .with_context(|| format!("demonstration {} has invalid arguments", demonstration.name))?
+                .command;
+            execute(command).with_context(|| format!("demonstration {} failed", demonstration.name))?;
+
+            match demonstration.artifact {
+                Some(artifact) => {
+                    let path = dir.join(artifact);
+                    let metadata = std::fs::metadata(&path).with_context(|| {
+                        format!("demonstration {} did not write {}", demonstration.name, path.display())
+                    })?;
+                    println!("ok: {} wrote {} ({})", demonstration.name, artifact, format_size(metadata.len()));
+                }
+                None => println!("ok: {}", demonstration.name),
+            }
         }
+        Ok(())
+    })();
+
+    if examples.keep {
+        println!("demonstration outputs are kept in {}", dir.display());
+    } else {
+        std::fs::remove_dir_all(&dir).with_context(|| format!("Failed to remove {}", dir.display()))?;
     }
+    result?;
+    println!("all {} demonstrations succeeded", DEMONSTRATIONS.len());
+    return Ok(());
+}
+
+/* half width of the window around CRITICAL_BETA in which Plan warns */
+const CRITICAL_WINDOW: f64 = 0.05;
+/* seconds between saves suggested by Plan */
+const SUGGESTED_SAVE_INTERVAL: usize = 300;
+
+/* sweeps between adjustments of the Metropolis step size during the burn in */
+const STEP_TUNING_SWEEPS: usize = 20;
+
+/* sweeps between resynchronizations of the tracked action of a tempering replica */
+const ACTION_RESYNC_SWEEPS: usize = 100;
+
+/* a warm start burns in for this fraction of the preset's equilibration sweeps */
+const WARM_START_BURN_IN_DIVISOR: usize = 4;
+
+/* datasets of |P|, Re P and Im P */
+const POLYAKOV_DATASETS: [&str; 3] = ["polyakov_abs", "polyakov_re", "polyakov_im"];
+/* counts of the measured topological charge rounded to the nearest integer */
+const SECTOR_DATASET: &str = "topological_sectors";
+/* counts of log2 of the sweep times in microseconds, see SweepLatency */
+const LATENCY_DATASET: &str = "sweep_latency_histogram";
+const HISTOGRAM_CHUNK: usize = 64;
+/* datasets of the means over the spatial and the temporal planes */
+const PLANE_AVERAGE_DATASETS: [&str; 2] = ["spatial_action", "temporal_action"];
+
+/* measurements per HDF5 chunk of the per measurement datasets, independent of the save cadence */
+const MEASUREMENT_CHUNK: usize = 1024;
+
+fn measurement_chunk(settings: &RunConfig) -> usize {
+    return settings.measurements.clamp(1, MEASUREMENT_CHUNK);
+}
+
+/* memory held by New, Resume and Step: at worst every measurement of every recorded column is buffered
+ * until a save, and every checkpoint flattens the configuration into four values per site */
+fn run_footprint(settings: &RunConfig) -> Footprint {
+    let columns = 1
+        + settings.kappa.is_some() as usize
+        + settings.gamma.is_some() as usize
+        + settings.topological_charge as usize
+        + 3 * settings.polyakov as usize
+        + settings.monopoles as usize
+        + (PLANES.len() + PLANE_AVERAGE_DATASETS.len()) * settings.plane_resolved as usize
+        + settings.region_blocks.map_or(0, |blocks| blocks.pow(4))
+        + settings.wilson_loops.map_or(0, |r_max| r_max * r_max);
+    return Footprint {
+        lattices: 1,
+        scalar_fields: settings.kappa.is_some() as u64,
+        site_buffers: 4 + settings.topological_charge as u64,
+        values: (settings.measurements * columns) as u64,
+    };
 }
 
+/* a dataset of the run with one row per measurement, the shape after its first axis, and the rows
+ * measured since the last save */
+struct BufferedDataset {
+    dataset: Dataset,
+    row_length: usize,
+    rows: Vec<f64>,
+}
+
+/* the measurements of every observable since the last save, so that a save resizes and writes
+ * each dataset once however many measurements it covers */
+#[derive(Default)]
+struct MeasurementBuffers {
+    datasets: Vec<BufferedDataset>,
+}
+
+impl MeasurementBuffers {
+    /* buffer the measurement dataset `name` of the segment, returns its column for push and rows */
+    fn open(&mut self, segment: &Group, name: &str) -> Result<usize> {
+        let dataset = segment.dataset(name)?;
+        let row_length = dataset.shape()[1..].iter().product();
+        self.datasets.push(BufferedDataset { dataset, row_length, rows: Vec::new() });
+        return Ok(self.datasets.len() - 1);
+    }
+
+    /* the row of one measurement */
+    fn push(&mut self, column: usize, row: &[f64]) {
+        let buffered = &mut self.datasets[column];
+        assert_eq!(row.len(), buffered.row_length, "a row of {} values for rows of {}", row.len(), buffered.row_length);
+        buffered.rows.extend_from_slice(row);
+    }
+
+    /* the values buffered for a column, row after row */
+    fn rows(&self, column: usize) -> &[f64] {
+        return &self.datasets[column].rows;
+    }
+
+    /* append the buffered rows of every dataset after the first `saved` rows and empty the
+     * buffers. Rows of several values are written one by one after a single resize */
+    fn flush(&mut self, saved: usize) -> hdf5::Result<()> {
+        for buffered in &mut self.datasets {
+            if buffered.rows.is_empty() {
+                continue;
+            }
+            let count = buffered.rows.len() / buffered.row_length;
+            let mut shape = buffered.dataset.shape();
+            shape[0] = saved + count;
+            buffered.dataset.resize(shape.clone())?;
+            match shape.len() {
+                1 => buffered.dataset.write_slice(&buffered.rows, saved..saved + count)?,
+                2 => {
+                    for (index, row) in buffered.rows.chunks_exact(buffered.row_length).enumerate() {
+                        buffered.dataset.write_slice(row, (saved + index, ..))?;
+                    }
+                }
+                _ => {
+                    for (index, row) in buffered.rows.chunks_exact(buffered.row_length).enumerate() {
+                        buffered.dataset.write_slice(row, (saved + index, .., ..))?;
+                    }
+                }
+            }
+            buffered.rows.clear();
+        }
+        return Ok(());
+    }
+}
+
+/* the measurement loop of New, Resume and Step, continuing the chain from its sweep and measurement
+ * counters along the schedule of the run. All datasets of the run must already exist in `segment` and
+ * hold exactly as many entries as there are measurements so far. Without a sweep budget the loop
+ * runs until every measurement is stored, with one it stops after that many sweeps. Returns the
+ * chain where it stopped */
+fn run_measurements(
+    segment: &Group,
+    settings: &RunConfig,
+    derived: &[Derived],
+    options: &RunOptions,
+    mut simulation: Simulation,
+    sweep_budget: Option<usize>,
+) -> Result<Simulation> {
+    let first_measurement = simulation.measurements;
+    /* a frozen run keeps measuring the configuration left by the burn in phase */
+    let sweeps_per_measurement = if settings.frozen { 0 } else { settings.sweeps_between_measurements };
+    let last_sweep = sweep_budget.map(|budget| simulation.sweeps + budget);
+    let action_dataset = segment.dataset("action_measurements")?;
+    /* every observable is buffered until the next save, the columns index the buffers */
+    let mut buffers = MeasurementBuffers::default();
+    let action_column = buffers.open(segment, "action_measurements")?;
+    let hopping_column = settings.kappa.map(|_| buffers.open(segment, "hopping_measurements")).transpose()?;
+    let double_action_column = settings.gamma.map(|_| buffers.open(segment, "double_action_measurements")).transpose()?;
+    let polyakov_columns = if settings.polyakov {
+        Some([
+            buffers.open(segment, POLYAKOV_DATASETS[0])?,
+            buffers.open(segment, POLYAKOV_DATASETS[1])?,
+            buffers.open(segment, POLYAKOV_DATASETS[2])?,
+        ])
+    } else {
+        None
+    };
+    let monopole_column = settings.monopoles.then(|| buffers.open(segment, "monopole_density")).transpose()?;
+    let charge_column = settings.topological_charge.then(|| buffers.open(segment, "topological_charge")).transpose()?;
+    let region_column = match settings.region_blocks {
+        Some(blocks) => Some((buffers.open(segment, "region_plaquette_averages")?, blocks)),
+        None => None,
+    };
+    let plane_columns = if settings.plane_resolved {
+        Some([
+            buffers.open(segment, "plane_action")?,
+            buffers.open(segment, PLANE_AVERAGE_DATASETS[0])?,
+            buffers.open(segment, PLANE_AVERAGE_DATASETS[1])?,
+        ])
+    } else {
+        None
+    };
+    let wilson_loop_column = match settings.wilson_loops {
+        Some(r_max) => Some((buffers.open(segment, "wilson_loops")?, r_max)),
+        None => None,
+    };
+    let mut derived_columns = Vec::with_capacity(derived.len());
+    for definition in derived {
+        derived_columns.push(buffers.open(segment, &format!("derived_{}", definition.name))?);
+    }
+
+    // open the live measurement stream, if requested
+    let mut publisher = match &settings.publish {
+        Some(spec) => Some(Publisher::bind(spec)?),
+        None => None,
+    };
+
+    let stored = action_dataset.read_raw::<f64>()?;
+    let mut saved = first_measurement;
+    let save_interval = Duration::from_secs(settings.interval as u64);
+    let mut last_save = Instant::now();
+    let probation_length = probation_length(settings.measurements);
+    let mut probation_window = Vec::with_capacity(probation_length);
+    probation_window.extend_from_slice(&stored[..first_measurement.min(probation_length)]);
+    /* integer bins, restored from the charges stored before this invocation */
+    let mut sectors = if settings.topological_charge {
+        let mut sectors = StreamingHistogram::new(1.0, -0.5);
+        for charge in &segment.dataset("topological_charge")?.read_raw::<f64>()?[..first_measurement] {
+            sectors.insert(*charge);
+        }
+        Some(sectors)
+    } else {
+        None
+    };
+    let mut summary = SavedSummary::default();
+    summary.add_saved(&stored);
+    summary.completed_measurements = first_measurement;
+    if options.sidecar {
+        write_sidecar(settings, &summary)?;
+    }
+    simulation.lattice.set_paranoid(options.paranoid);
+    if let Some(threads) = settings.threads {
+        build_thread_pool(threads)?;
+        simulation.lattice.set_checkerboard(true);
+    }
+
+    // report progress if anything panics during the run
+    let progress = Arc::new(Progress::default());
+    progress.sweeps.store(simulation.sweeps, Ordering::Relaxed);
+    progress.measurements.store(first_measurement, Ordering::Relaxed);
+    progress.saved_measurements.store(first_measurement, Ordering::Relaxed);
+    if first_measurement > 0 {
+        progress.set_phase(Phase::Measurement);
+    }
+    /* a chain that already swept was restored from a checkpoint */
+    if simulation.sweeps > 0 {
+        progress.record_checkpoint(first_measurement);
+    }
+    let _panic_report = install_panic_report(progress.clone(), settings.name.clone());
+    let heartbeat = options.heartbeat.clone().map(|path| {
+        Heartbeat::start(path, options.heartbeat_interval, progress.clone())
+    });
+    let mut watchdog = (options.min_free_disk > 0)
+        .then(|| DiskWatchdog::new(&settings.name, options.min_free_disk, options.disk_grace));
+    let mut recorded_transitions = 0;
+
+    let run = panic::catch_unwind(AssertUnwindSafe(|| -> Result<()> {
+        loop {
+            // sweep up to the next measurement of the schedule, a step stops early once its sweeps are used up
+            let i = simulation.measurements;
+            let measurement_sweep = settings.equilibration_sweeps + (i + 1) * sweeps_per_measurement;
+            let target = last_sweep.map_or(measurement_sweep, |last| last.min(measurement_sweep));
+            let mut sweep_start = Instant::now();
+            while simulation.sweeps < target {
+                simulation.sweep();
+                if simulation.sweeps <= settings.equilibration_sweeps {
+                    if simulation.sweeps.is_multiple_of(STEP_TUNING_SWEEPS) {
+                        simulation.tune_step_size();
+                    }
+                    simulation.refresh_targeting();
+                }
+                let sweep_end = Instant::now();
+                progress.latency.record(sweep_end - sweep_start, options.interference_factor);
+                sweep_start = sweep_end;
+                progress.sweeps.fetch_add(1, Ordering::Relaxed);
+            }
+
+            if simulation.sweeps >= measurement_sweep && i < settings.measurements {
+                progress.set_phase(Phase::Measurement);
+                if options.panic_at_measurement == Some(i) {
+                    panic!("observable failed at measurement {} as requested by --panic-at-measurement", i);
+                }
+                let action = simulation.measure_action();
+                buffers.push(action_column, &[action]);
+                progress.measurements.fetch_add(1, Ordering::Relaxed);
+
+                // check that the burn in phase was long enough
+                if i < probation_length {
+                    probation_window.push(action);
+                }
+                if i + 1 == probation_length {
+                    if let Some(significance) = drift_significance(&probation_window) {
+                        let unequilibrated = significance > DRIFT_THRESHOLD;
+                        write_attribute(&action_dataset, "possibly-unequilibrated", unequilibrated)?;
+
+                        if unequilibrated {
+                            let message = format!(
+                                "the first {} measurements drift by {:.1} standard errors, the burn in phase of {} sweeps is probably too short",
+                                probation_length, significance, settings.equilibration_sweeps
+                            );
+                            if settings.strict_equilibration {
+                                bail!(message);
+                            }
+                            println!("WARNING: {}", message);
+                        }
+                    }
+                }
+
+                let lattice = &simulation.lattice;
+                let hopping = simulation.matter.as_ref().map(|matter| matter.field.average_hopping(lattice));
+                if let (Some(hopping), Some(column)) = (hopping, hopping_column) {
+                    buffers.push(column, &[hopping]);
+                }
+
+                let double_action = double_action_column.map(|_| lattice.average_double_action());
+                if let (Some(double_action), Some(column)) = (double_action, double_action_column) {
+                    buffers.push(column, &[double_action]);
+                }
+
+                if let Some(columns) = polyakov_columns {
+                    let polyakov = lattice.polyakov_loop(TIME_DIRECTION);
+                    for (value, column) in [polyakov.norm(), polyakov.re, polyakov.im].into_iter().zip(columns) {
+                        buffers.push(column, &[value]);
+                    }
+                }
+
+                let monopole_density = monopole_column.map(|_| lattice.monopole_density());
+                if let (Some(density), Some(column)) = (monopole_density, monopole_column) {
+                    buffers.push(column, &[density]);
+                }
+
+                let charge = charge_column.map(|_| lattice.topological_charge());
+                if let (Some(charge), Some(column)) = (charge, charge_column) {
+                    buffers.push(column, &[charge]);
+                }
+                if let (Some(charge), Some(sectors)) = (charge, sectors.as_mut()) {
+                    sectors.insert(charge);
+                }
+
+                let by_plane = settings.plane_resolved.then(|| lattice.average_action_by_plane());
+                if let (Some(by_plane), Some([plane_column, spatial_column, temporal_column])) = (by_plane, plane_columns) {
+                    let planes: Vec<f64> = PLANES.iter().map(|&(m, n)| by_plane[m][n]).collect();
+                    buffers.push(plane_column, &planes);
+                    buffers.push(spatial_column, &[spatial_average(&by_plane)]);
+                    buffers.push(temporal_column, &[temporal_average(&by_plane)]);
+                }
+
+                /* the columns the derived observables can refer to, published together with them */
+                let mut values = vec![("action", action)];
+                if let Some(hopping) = hopping {
+                    values.push(("hopping", hopping));
+                }
+                if let Some(double_action) = double_action {
+                    values.push(("double_action", double_action));
+                }
+                if let Some(density) = monopole_density {
+                    values.push(("monopole_density", density));
+                }
+                if let Some(charge) = charge {
+                    values.push(("topological_charge", charge));
+                }
+                if let Some(by_plane) = by_plane {
+                    values.push(("spatial_action", spatial_average(&by_plane)));
+                    values.push(("temporal_action", temporal_average(&by_plane)));
+                }
+                let columns = values.len();
+                for (definition, column) in derived.iter().zip(&derived_columns) {
+                    let value = definition.expression.evaluate(&values[..columns]);
+                    buffers.push(*column, &[value]);
+                    values.push((definition.name.as_str(), value));
+                }
+
+                if let Some((column, blocks)) = region_column {
+                    buffers.push(column, &lattice.region_plaquette_averages(blocks));
+                }
+
+                if let Some((column, r_max)) = wilson_loop_column {
+                    let loops: Vec<f64> = lattice
+                        .wilson_loops_up_to(r_max, r_max, settings.integrated_links.then_some(settings.beta))
+                        .concat();
+                    buffers.push(column, &loops);
+                }
+
+                if let Some(publisher) = publisher.as_mut() {
+                    let sweep = progress.sweeps.load(Ordering::Relaxed);
+                    publisher.publish(i, sweep, &values);
+                }
+            }
+
+            // save once the interval has passed, after every measurement if one takes longer
+            let finished = simulation.measurements == settings.measurements || last_sweep == Some(simulation.sweeps);
+            if finished || last_save.elapsed() >= save_interval {
+                let save = watchdog.as_mut().map_or(SaveAction::Everything, |watchdog| watchdog.before_save());
+                let done = simulation.measurements;
+                summary.add_saved(buffers.rows(action_column));
+                buffers.flush(saved)?;
+                summary.completed_measurements = done;
+                if options.latency_histogram {
+                    write_latency_histogram(segment, &progress.latency)?;
+                }
+                if let Some(sectors) = &sectors {
+                    write_histogram(segment, SECTOR_DATASET, sectors)?;
+                }
+                if options.sidecar {
+                    write_sidecar(settings, &summary)?;
+                }
+                saved = done;
+                progress.saved_measurements.store(saved, Ordering::Relaxed);
+
+                // the checkpoint is the bulk of a save, it waits when the disk fills up
+                if let Some(watchdog) = watchdog.as_mut() {
+                    report_state(watchdog, &progress);
+                    let waited = if save == SaveAction::Pause {
+                        segment.file()?.flush()?;
+                        watchdog.wait_for_space(&progress)
+                    } else {
+                        Ok(())
+                    };
+                    report_state(watchdog, &progress);
+                    if watchdog.transitions().len() > recorded_transitions {
+                        record_disk_watchdog(&action_dataset, watchdog);
+                        recorded_transitions = watchdog.transitions().len();
+                    }
+                    waited?;
+                }
+                if save != SaveAction::MeasurementsOnly {
+                    write_checkpoint(segment, &simulation)?;
+                    progress.record_checkpoint(simulation.measurements);
+                }
+                last_save = Instant::now();
+            }
+            if finished {
+                break;
+            }
+        }
+
+        Ok(())
+    }));
+
+    match run {
+        Ok(result) => result?,
+        Err(_) => {
+            // keep the measurements that were still buffered, if the file is still usable
+            let buffered = buffers.rows(action_column).len();
+            if buffered > 0 {
+                let valid_measurements = saved + buffered;
+                match buffers.flush(saved) {
+                    Ok(_) => {
+                        progress
+                            .saved_measurements
+                            .store(valid_measurements, Ordering::Relaxed);
+                        eprintln!(
+                            "flushed buffered measurements, {} are stored",
+                            valid_measurements
+                        );
+                    }
+                    Err(error) => {
+                        eprintln!("could not flush buffered measurements: {}", error)
+                    }
+                }
+            }
+            progress.set_phase(Phase::Aborted);
+            if let Some(heartbeat) = heartbeat {
+                heartbeat.stop();
+            }
+            return Err(Aborted { progress }.into());
+        }
+    }
+
+    report_latency(&progress.latency, options.interference_factor);
+    if simulation.algorithm == Algorithm::Metropolis {
+        println!(
+            "Metropolis acceptance rate {:.1} % at step size {:.3}",
+            100.0 * simulation.metropolis_stats.acceptance_rate(),
+            simulation.step_size
+        );
+    }
+    if let Some(coarse) = &simulation.coarse {
+        println!(
+            "coarse update acceptance rate {:.1} % of {} proposals",
+            100.0 * coarse.stats.acceptance_rate(),
+            coarse.stats.proposals
+        );
+        write_attribute(&action_dataset, "coarse-proposals", coarse.stats.proposals)?;
+        write_attribute(&action_dataset, "coarse-accepted", coarse.stats.accepted)?;
+    }
+    if simulation.measurements < settings.measurements {
+        if let Some(heartbeat) = heartbeat {
+            heartbeat.stop();
+        }
+        println!(
+            "step finished after {} sweeps, {} of {} measurements are stored",
+            simulation.sweeps, simulation.measurements, settings.measurements
+        );
+        return Ok(simulation);
+    }
+
+    progress.set_phase(Phase::Complete);
+    if let Some(heartbeat) = heartbeat {
+        heartbeat.stop();
+    }
+
+    if options.sidecar {
+        summary.completed_measurements = settings.measurements;
+        summary.complete = true;
+        write_sidecar(settings, &summary)?;
+    }
+
+    if settings.polyakov {
+        let magnitudes = segment.dataset(POLYAKOV_DATASETS[0])?.read_raw::<f64>()?;
+        let spatial_volume = (settings.volume() / settings.lattice_dims[TIME_DIRECTION]) as f64;
+        println!(
+            "Polyakov loop susceptibility: {}",
+            analysis::susceptibility(&magnitudes, spatial_volume)
+        );
+    }
+
+    println!("simulation complete");
+    Ok(simulation)
+}
+
+/* the global thread pool can only be built once per process, while a scan runs the measurement loop
+ * once per coupling */
+fn build_thread_pool(threads: usize) -> Result<()> {
+    static BUILT: OnceLock<usize> = OnceLock::new();
+    match BUILT.get() {
+        Some(&built) if built == threads => {}
+        Some(&built) => bail!("the thread pool already has {} threads and can not be rebuilt with {}", built, threads),
+        None => {
+            rayon::ThreadPoolBuilder::new().num_threads(threads).build_global()?;
+            BUILT.get_or_init(|| threads);
+        }
+    }
+    return Ok(());
+}
+
+/* keep the states the disk space watchdog went through with the run, best effort since the disk
+ * may be full */
+fn record_disk_watchdog(action_dataset: &Dataset, watchdog: &DiskWatchdog) {
+    let recorded = watchdog
+        .to_json()
+        .parse::<VarLenUnicode>()
+        .map_err(anyhow::Error::from)
+        .and_then(|json| Ok(write_attribute(action_dataset, "disk-watchdog", json)?));
+    if let Err(error) = recorded {
+        eprintln!("could not record the disk space watchdog in the output file: {}", error);
+    }
+}
+
+/* one warning per old flag spelling given on the command line */
+fn warn_deprecated(flags: &[(&str, &str)]) {
+    for (old, new) in flags {
+        println!("Warning: --{} is deprecated and will be removed, use --{} instead", old, new);
+    }
+}
+
+fn report_latency(latency: &SweepLatency, interference_factor: f64) {
+    let milliseconds = |q: f64| latency.percentile(q).map_or(0.0, |latency| latency.as_secs_f64() * 1e3);
+    if latency.count() == 0 {
+        return;
+    }
+    println!(
+        "sweep latency: p50 {:.3} ms, p90 {:.3} ms, p99 {:.3} ms",
+        milliseconds(0.5),
+        milliseconds(0.9),
+        milliseconds(0.99)
+    );
+    println!(
+        "{} of {} sweeps took more than {} times the median, likely interference from other processes",
+        latency.interference_events(),
+        latency.count(),
+        interference_factor
+    );
+}
+
+/* the sweep latency histogram of this invocation, binned in log2 of the sweep time in microseconds */
+fn write_latency_histogram(segment: &Group, latency: &SweepLatency) -> Result<()> {
+    write_histogram(segment, LATENCY_DATASET, &latency.histogram())?;
+    write_attribute(&segment.dataset(LATENCY_DATASET)?, "interference-events", latency.interference_events())?;
+    return Ok(());
+}
+
+/* a streaming histogram as a dataset of its counts, overwriting an earlier version. The grid and
+ * the lower edge of the first bin are attributes, so the bin edges are first-edge + b * bin-width */
+fn write_histogram(segment: &Group, name: &str, histogram: &StreamingHistogram) -> Result<()> {
+    let dataset = if segment.link_exists(name) {
+        segment.dataset(name)?
+    } else {
+        segment.new_dataset::<u64>().chunk(HISTOGRAM_CHUNK).shape(0..).create(name)?
+    };
+    dataset.resize(histogram.counts().len())?;
+    dataset.write_raw(histogram.counts())?;
+    write_attribute(&dataset, "bin-width", histogram.bin_width())?;
+    write_attribute(&dataset, "origin", histogram.origin())?;
+    write_attribute(&dataset, "first-bin", histogram.first_bin())?;
+    write_attribute(&dataset, "first-edge", histogram.lower_edge(0))?;
+    write_attribute(&dataset, "non-finite", histogram.non_finite())?;
+    return Ok(());
+}
+
+fn read_histogram(dataset: &Dataset) -> Result<StreamingHistogram> {
+    return StreamingHistogram::from_parts(
+        read_attribute(dataset, "bin-width")?,
+        read_attribute(dataset, "origin")?,
+        read_attribute(dataset, "first-bin")?,
+        dataset.read_raw::<u64>()?,
+        read_attribute(dataset, "non-finite")?,
+    );
+}
+
+/* write a single valued attribute, creating it unless an earlier part of the run already did */
+fn write_attribute<T: H5Type>(dataset: &Dataset, name: &str, value: T) -> hdf5::Result<()> {
+    let attribute = if dataset.attr_names()?.iter().any(|existing| existing == name) {
+        dataset.attr(name)?
+    } else {
+        dataset.new_attr::<T>().shape([1]).create(name)?
+    };
+    return attribute.write(&[value]);
+}
+
+fn read_attribute<T: H5Type + Copy>(dataset: &Dataset, name: &str) -> Result<T> {
+    return dataset
+        .attr(name)?
+        .read_raw::<T>()?
+        .first()
+        .copied()
+        .with_context(|| format!("attribute {} is empty", name));
+}
+
+fn read_string_attribute(dataset: &Dataset, name: &str) -> Result<String> {
+    let value = dataset.attr(name)?.read_raw::<VarLenUnicode>()?;
+    return Ok(value
+        .first()
+        .with_context(|| format!("attribute {} is empty", name))?
+        .as_str()
+        .to_string());
+}
+
+/* a run switched to new parameters by Retarget keeps each earlier set of measurements in its own
+ * segment. Segment 0 is the root group, so that files from before segments existed are runs with a
+ * single segment, the later ones are the groups segment-1, segment-2, ... */
+const SEGMENT_PREFIX: &str = "segment-";
+
+fn segment_path(index: usize) -> String {
+    if index == 0 {
+        return "/".to_string();
+    }
+    return format!("{}{}", SEGMENT_PREFIX, index);
+}
+
+/* every segment of a run in order, the last one is the current segment */
+fn run_segments(file: &File) -> Result<Vec<Group>> {
+    let mut segments = vec![file.group("/")?];
+    while file.link_exists(&segment_path(segments.len())) {
+        segments.push(file.group(&segment_path(segments.len()))?);
+    }
+    return Ok(segments);
+}
+
+/* finalize the summary attributes of a segment that Retarget replaces or a scan has finished */
+fn close_segment(segment: &Group) -> Result<()> {
+    let series = read_action_series(segment)?;
+    let dataset = segment.dataset("action_measurements")?;
+    write_attribute(&dataset, "summary-measurements", series.len())?;
+    if !series.is_empty() {
+        write_attribute(&dataset, "summary-mean-action", analysis::mean(&series))?;
+    }
+    if let Some(error) = analysis::naive_error(&series) {
+        write_attribute(&dataset, "summary-naive-error", error)?;
+    }
+    write_attribute(&dataset, "closed", true)?;
+    Ok(())
+}
+
+/* checkpoints alternate between two datasets, so that a crash while one is written still leaves
+ * the previous checkpoint intact. A slot only counts once its complete attribute is set */
+const CHECKPOINT_SLOTS: [&str; 2] = ["configuration-0", "configuration-1"];
+
+/* the scalar phases stored next to the links of a checkpoint slot, for runs with --kappa */
+fn scalar_slot(slot: &str) -> String {
+    return format!("scalar-{}", slot);
+}
+
+/* the complete checkpoint furthest along the chain, if there is one */
+fn latest_checkpoint(segment: &Group) -> Result<Option<(&'static str, Dataset)>> {
+    let mut latest: Option<((usize, usize), &'static str, Dataset)> = None;
+
+    for slot in CHECKPOINT_SLOTS {
+        if !segment.link_exists(slot) {
+            continue;
+        }
+        let dataset = segment.dataset(slot)?;
+        if !read_attribute::<bool>(&dataset, "complete")? {
+            continue;
+        }
+        /* a step may checkpoint during the burn in, and a frozen run measures without sweeping */
+        let progress = (
+            read_attribute::<usize>(&dataset, "sweeps")?,
+            read_attribute::<usize>(&dataset, "measurements")?,
+        );
+        if latest.as_ref().is_none_or(|(newest, _, _)| progress > *newest) {
+            latest = Some((progress, slot, dataset));
+        }
+    }
+
+    return Ok(latest.map(|(_, slot, dataset)| (slot, dataset)));
+}
+
+/* the latest checkpoint of a segment of the run `path`, the last segment if none is given, tiled up
+ * to `dims`. Returns the configuration with the segment, the sweep of the checkpoint and the tiling
+ * factor */
+fn warm_start_lattice(path: &str, segment: Option<usize>, dims: [usize; 4]) -> Result<(Lattice, usize, usize, usize)> {
+    let file = File::open(path).with_context(|| format!("Failed to open warm start file {}", path))?;
+    let mut segments = run_segments(&file)?;
+    let index = segment.unwrap_or(segments.len() - 1);
+    if index >= segments.len() {
+        bail!("{} has {} segments, there is no segment {}", path, segments.len(), index);
+    }
+    let (_, configuration) = latest_checkpoint(&segments.swap_remove(index))?
+        .with_context(|| format!("segment {} of {} has no stored configuration", index, path))?;
+    let source_dims = match configuration.shape().as_slice() {
+        &[nx, ny, nz, nt, 4] => [nx, ny, nz, nt],
+        shape => bail!("the checkpoint in {} has the unexpected shape {:?}", path, shape),
+    };
+    let sweep = read_attribute::<usize>(&configuration, "sweeps")?;
+    let lattice = Lattice::from_array_dims(source_dims, &configuration.read_raw::<f64>()?)?;
+    let (lattice, factor) = tile_to(&lattice, dims)?;
+    return Ok((lattice, index, sweep, factor));
+}
+
+/* store the configuration together with the number of measurements and sweeps it follows and the
+ * state of the random number generator, so that Resume continues the same Markov chain. A scalar
+ * field goes into the matching scalar slot with the state of its own generator. The slot holding
+ * the latest checkpoint is left alone */
+fn write_checkpoint(segment: &Group, simulation: &Simulation) -> Result<()> {
+    let slot = match latest_checkpoint(segment)? {
+        Some((latest, _)) if latest == CHECKPOINT_SLOTS[0] => CHECKPOINT_SLOTS[1],
+        _ => CHECKPOINT_SLOTS[0],
+    };
+    let [nx, ny, nz, nt] = simulation.lattice.dims();
+    let dataset = if segment.link_exists(slot) {
+        segment.dataset(slot)?
+    } else {
+        segment.new_dataset::<f64>()
+            .shape([nx, ny, nz, nt, 4])
+            .create(slot)?
+    };
+
+    write_attribute(&dataset, "complete", false)?;
+    segment.file()?.flush()?;
+    dataset.write_raw(&simulation.lattice.to_array())?;
+    if let Some(matter) = &simulation.matter {
+        let scalar_slot = scalar_slot(slot);
+        let scalar_dataset = if segment.link_exists(&scalar_slot) {
+            segment.dataset(&scalar_slot)?
+        } else {
+            segment.new_dataset::<f64>()
+                .shape([nx, ny, nz, nt])
+                .create(scalar_slot.as_str())?
+        };
+        scalar_dataset.write_raw(matter.field.phases())?;
+        write_attribute(&dataset, "scalar-rng-state", matter.rng.get_seed())?;
+    }
+    write_attribute(&dataset, "measurements", simulation.measurements)?;
+    write_attribute(&dataset, "sweeps", simulation.sweeps)?;
+    write_attribute(&dataset, "rng-state", simulation.rng.get_seed())?;
+    write_attribute(&dataset, "step-size", simulation.step_size)?;
+    if let Some(scores) = simulation.targeting.as_ref().and_then(|targeting| targeting.scores.as_ref()) {
+        write_attribute(&dataset, "targeted-threshold", scores.threshold())?;
+    }
+    write_attribute(&dataset, "complete", true)?;
+    segment.file()?.flush()?;
+    Ok(())
+}
+
+/* targeted updates of a run, the scores are computed at the first sweep. A resumed run takes the
+ * threshold frozen in its checkpoint, see open_run */
+fn targeting(settings: &RunConfig) -> Option<Targeting> {
+    return settings.targeted_fraction.map(|fraction| Targeting {
+        fraction,
+        hits: settings.targeted_hits,
+        refresh: settings.targeted_refresh,
+        scores: None,
+    });
+}
+
+/* derived observables of a run, checked against the observables it records */
+fn parse_derived(settings: &RunConfig) -> Result<Vec<Derived>> {
+    let mut available = vec!["action"];
+    if settings.kappa.is_some() {
+        available.push("hopping");
+    }
+    if settings.gamma.is_some() {
+        available.push("double_action");
+    }
+    if settings.monopoles {
+        available.push("monopole_density");
+    }
+    if settings.topological_charge {
+        available.push("topological_charge");
+    }
+    if settings.plane_resolved {
+        available.extend(PLANE_AVERAGE_DATASETS);
+    }
+
+    let mut derived = Vec::with_capacity(settings.derive.len());
+    for definition in &settings.derive {
+        let parsed = Derived::parse(definition, &available)?;
+        if derived.iter().any(|other: &Derived| other.name == parsed.name) {
+            bail!("derived observable {} is defined twice", parsed.name);
+        }
+        derived.push(parsed);
+    }
+
+    return Ok(derived);
+}
+
+/* parameters of a run given as the arguments of the new subcommand without --name */
+fn new_run_settings(name: &str, new_args: &[String]) -> Result<RunConfig> {
+    let mut args = vec![
+        env!("CARGO_PKG_NAME").to_string(),
+        "new".to_string(),
+        "--name".to_string(),
+        name.to_string(),
+    ];
+    args.extend(new_args.iter().cloned());
+    match Cli::try_parse_from(args)?.command {
+        Commands::New(new) => {
+            warn_deprecated(&new.deprecated_flags());
+            new.resolve()
+        }
+        _ => bail!("the arguments after -- do not describe a new run"),
+    }
+}
+
+/* the command line stored with a segment that reruns it */
+fn stored_rerun_command(segment: &Group) -> Result<String> {
+    return read_string_attribute(&segment.dataset("action_measurements")?, "rerun-command");
+}
+
+/* parameters of the run stored in a save file, recovered from its rerun-command attribute */
+fn stored_settings(segment: &Group) -> Result<RunConfig> {
+    let args = split_rerun_command(&stored_rerun_command(segment)?)?;
+
+    match Cli::try_parse_from(args)?.command {
+        Commands::New(new) => new.resolve(),
+        _ => bail!("rerun-command does not describe a new run"),
+    }
+}
+
+/* write the latest checkpoint of the current segment of a run, in the portable format with the run parameters as metadata */
+fn export_run(export: Export) -> Result<()> {
+    let file = File::open(&export.name).with_context(|| format!("Failed to open file {}", export.name))?;
+    let segment = run_segments(&file)?.pop().unwrap();
+    let settings = stored_settings(&segment)
+        .with_context(|| format!("Failed to read the run parameters from {}", export.name))?;
+    let (_, configuration) = latest_checkpoint(&segment)?
+        .with_context(|| format!("{} has no stored configuration to export", export.name))?;
+    let sweeps = read_attribute::<usize>(&configuration, "sweeps")?;
+    let measurements = read_attribute::<usize>(&configuration, "measurements")?;
+    let lattice = Lattice::from_array_dims(settings.lattice_dims, &configuration.read_raw::<f64>()?)?;
+
+    let mut output = std::fs::File::create(&export.output)
+        .with_context(|| format!("Failed to create {}", export.output))?;
+    match export.format {
+        ExportFormat::Portable => {
+            let metadata = format!(
+                "{{\"source\":{},\"sweeps\":{},\"measurements\":{},\"settings\":{}}}",
+                json_string(&export.name),
+                sweeps,
+                measurements,
+                settings.to_json()
+            );
+            lattice.write_portable(&mut output, &metadata)?;
+        }
+        ExportFormat::Cache => lattice.write_config(&mut output)?,
+    }
+
+    println!(
+        "Exported the configuration with {} of {} after {} sweeps to {}",
+        format_extents(lattice.dims()),
+        export.name,
+        sweeps,
+        export.output
+    );
+    return Ok(());
+}
+
+/* the rerun command of the current segment, or a summary of every segment */
+fn info_run(info: Info) -> Result<()> {
+    if info.from_sidecar {
+        return print_sidecar(&info.name);
+    }
+    let file = File::open(&info.name).with_context(|| format!("Failed to open file {}", info.name))?;
+    let segments = run_segments(&file)?;
+    if info.rerun_command {
+        println!("{}", stored_rerun_command(segments.last().unwrap())?);
+        return Ok(());
+    }
+
+    println!("{} segments in {}", segments.len(), info.name);
+    for (index, segment) in segments.iter().enumerate() {
+        let action_dataset = segment.dataset("action_measurements")?;
+        let progress = match latest_checkpoint(segment)? {
+            Some((_, configuration)) => format!(
+                "checkpoint after {} sweeps and {} measurements",
+                read_attribute::<usize>(&configuration, "sweeps")?,
+                read_attribute::<usize>(&configuration, "measurements")?
+            ),
+            None => "no checkpoint".to_string(),
+        };
+        println!(
+            "segment {}: {} measurements stored, {}, seed {}",
+            index,
+            read_action_series(segment)?.len(),
+            progress,
+            read_attribute::<u64>(&action_dataset, "seed")?
+        );
+        println!("  {}", stored_rerun_command(segment)?);
+        if segment.link_exists(SECTOR_DATASET) {
+            let sectors = read_histogram(&segment.dataset(SECTOR_DATASET)?)?;
+            let counts: Vec<String> = sectors
+                .counts()
+                .iter()
+                .enumerate()
+                .filter(|(_, count)| **count > 0)
+                .map(|(index, count)| format!("{}: {}", sectors.first_bin() + index as i64, count))
+                .collect();
+            println!("  topological sectors {}", counts.join(", "));
+        }
+    }
+    return Ok(());
+}
+
+/* the progress kept in the sidecar of a run, the save file is not opened */
+fn print_sidecar(name: &str) -> Result<()> {
+    let path = sidecar_path(name);
+    let sidecar = read_sidecar(name)?;
+    let field = |key: &str| -> Result<String> {
+        return Ok(sidecar.get(key).with_context(|| format!("{} has no {}", path, key))?.to_json());
+    };
+    let config = sidecar.get("config").with_context(|| format!("{} has no config", path))?;
+    let measurements = config.get("measurements").map_or("?".to_string(), Json::to_json);
+
+    println!("{} from {}", name, path);
+    println!(
+        "{} of {} measurements completed, {} saved{}",
+        field("completed_measurements")?,
+        measurements,
+        field("saved_measurements")?,
+        if field("complete")? == "true" { ", complete" } else { "" }
+    );
+    println!(
+        "mean action {}, standard deviation {}",
+        field("mean_action")?,
+        field("action_standard_deviation")?
+    );
+    println!("  {}", config.to_json());
+    return Ok(());
+}
+
+/* the action measurements of a save file in order. Files written before the measurements were
+ * stored one by one hold them in rows of one save interval each, a trailing row left at the fill
+ * value by an interrupted save is dropped */
+fn read_action_series(segment: &Group) -> Result<Vec<f64>> {
+    let dataset = segment.dataset("action_measurements")?;
+    let mut series = dataset.read_raw::<f64>()?;
+
+    match dataset.shape().as_slice() {
+        [_] => {}
+        [_, row_length] => {
+            while series.len() >= *row_length
+                && *row_length > 0
+                && series[series.len() - row_length..].iter().all(|x| *x == 0.0)
+            {
+                series.truncate(series.len() - row_length);
+            }
+        }
+        shape => bail!("action_measurements has unexpected shape {:?}", shape),
+    }
+
+    return Ok(series);
+}
+
+/* statistics of one action series, see analyze_run */
+struct SeriesAnalysis {
+    measurements: usize,
+    mean: f64,
+    naive_error: Option<f64>,
+    /* bin size, number of bins and jackknife error */
+    jackknife: Vec<(usize, usize, f64)>,
+    /* None if not requested */
+    autocorrelation: Option<Option<analysis::Autocorrelation>>,
+    /* bin size, number of bins and two-way jackknife over the region plaquette averages, if recorded */
+    two_way: Vec<(usize, usize, analysis::TwoWayJackknife)>,
+}
+
+impl SeriesAnalysis {
+    fn new(series: &[f64], autocorr: bool) -> Self {
+        return Self {
+            measurements: series.len(),
+            mean: analysis::mean(series),
+            naive_error: analysis::naive_error(series),
+            jackknife: analysis::bin_sizes(series.len())
+                .into_iter()
+                .filter_map(|size| {
+                    analysis::jackknife_error(series, size).map(|error| (size, series.len() / size, error))
+                })
+                .collect(),
+            autocorrelation: autocorr.then(|| analysis::autocorrelation(series)),
+            two_way: Vec::new(),
+        };
+    }
+
+    /* add the two-way (time x region) jackknife of a measurements x regions table */
+    fn with_regions(mut self, table: &[f64], num_regions: usize) -> Self {
+        let measurements = table.len() / num_regions.max(1);
+        self.two_way = analysis::bin_sizes(measurements)
+            .into_iter()
+            .filter_map(|size| {
+                analysis::two_way_jackknife(table, num_regions, size).map(|result| (size, measurements / size, result))
+            })
+            .collect();
+        return self;
+    }
+
+    /* the members of a JSON object, without the braces */
+    fn json_fields(&self) -> String {
+        let optional = |value: Option<f64>| value.map_or("null".to_string(), |value| value.to_string());
+        let bins = self
+            .jackknife
+            .iter()
+            .map(|(size, bins, error)| format!("{{\"bin_size\":{},\"bins\":{},\"error\":{}}}", size, bins, error))
+            .collect::<Vec<_>>()
+            .join(",");
+        let autocorrelation = match &self.autocorrelation {
+            Some(Some(result)) => format!(
+                ",\"autocorrelation\":{{\"tau_int\":{},\"error\":{},\"window\":{},\"effective_samples\":{},\"rho\":[{}]}}",
+                result.tau,
+                result.error,
+                result.window,
+                result.effective_samples(self.measurements),
+                result.rho.iter().map(|rho| rho.to_string()).collect::<Vec<_>>().join(",")
+            ),
+            Some(None) => ",\"autocorrelation\":null".to_string(),
+            None => String::new(),
+        };
+        let two_way = if self.two_way.is_empty() {
+            String::new()
+        } else {
+            let bins = self
+                .two_way
+                .iter()
+                .map(|(size, bins, result)| {
+                    format!(
+                        "{{\"bin_size\":{},\"bins\":{},\"time\":{},\"region\":{},\"error\":{}}}",
+                        size,
+                        bins,
+                        result.time,
+                        result.region,
+                        result.error()
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join(",");
+            format!(",\"two_way_jackknife\":[{}]", bins)
+        };
+        return format!(
+            "\"measurements\":{},\"mean\":{},\"naive_error\":{},\"jackknife\":[{}]{}{}",
+            self.measurements,
+            optional((self.measurements > 0).then_some(self.mean)),
+            optional(self.naive_error),
+            bins,
+            autocorrelation,
+            two_way
+        );
+    }
+
+    /* the table of jackknife errors and the autocorrelation, with tau_int also in sweeps if the
+     * spacing of the measurements is known */
+    fn print(&self, sweeps_between_measurements: Option<usize>) {
+        match self.naive_error {
+            _ if self.measurements == 0 => {
+                println!("no measurements");
+                return;
+            }
+            Some(error) => println!("mean action: {} +- {} (naive)", self.mean, error),
+            None => println!("mean action: {}, a single measurement has no error", self.mean),
+        }
+        if !self.jackknife.is_empty() {
+            println!("{:>10} {:>8} {:>14}", "bin size", "bins", "jackknife");
+            for (size, bins, error) in &self.jackknife {
+                println!("{:>10} {:>8} {:>14.6e}", size, bins, error);
+            }
+            if self.measurements / self.jackknife.last().unwrap().0 < analysis::MIN_JACKKNIFE_BINS {
+                println!("Warning: too few measurements to see whether the binned error levels off");
+            }
+        }
+        if !self.two_way.is_empty() {
+            println!("{:>10} {:>8} {:>14} {:>14} {:>14}", "bin size", "bins", "time", "region", "two-way");
+            for (size, bins, result) in &self.two_way {
+                println!(
+                    "{:>10} {:>8} {:>14.6e} {:>14.6e} {:>14.6e}",
+                    size,
+                    bins,
+                    result.time,
+                    result.region,
+                    result.error()
+                );
+            }
+        }
+
+        match &self.autocorrelation {
+            Some(Some(result)) => {
+                println!("{:>10} {:>14}", "lag", "rho");
+                for (lag, rho) in result.rho.iter().enumerate() {
+                    println!("{:>10} {:>14.6}", lag, rho);
+                }
+                println!(
+                    "integrated autocorrelation time: {:.2} +- {:.2} measurements, window {}",
+                    result.tau, result.error, result.window
+                );
+                println!("effective independent samples: {:.1}", result.effective_samples(self.measurements));
+                if let Some(spacing) = sweeps_between_measurements {
+                    println!(
+                        "measurements are {} sweeps apart, tau_int is about {:.1} sweeps",
+                        spacing,
+                        result.tau * spacing as f64
+                    );
+                }
+            }
+            Some(None) => println!("integrated autocorrelation time: undefined, the action does not fluctuate"),
+            None => {}
+        }
+    }
+}
+
+/* the region plaquette averages of a segment and the number of regions, if they were recorded */
+fn read_region_table(segment: &Group) -> Result<Option<(Vec<f64>, usize)>> {
+    if !segment.link_exists("region_plaquette_averages") {
+        return Ok(None);
+    }
+    let dataset = segment.dataset("region_plaquette_averages")?;
+    match dataset.shape().as_slice() {
+        &[_, num_regions] => Ok(Some((dataset.read_raw::<f64>()?, num_regions))),
+        shape => bail!("region_plaquette_averages has unexpected shape {:?}", shape),
+    }
+}
+
+/* segments whose measurements sample the same distribution and can be analyzed as one series */
+fn same_physics(a: &RunConfig, b: &RunConfig) -> bool {
+    return a.couplings() == b.couplings()
+        && a.lattice_dims == b.lattice_dims
+        && a.gamma == b.gamma
+        && a.kappa == b.kappa;
+}
+
+/* the analysis of every segment of a run with its stored parameters, None for files from before the
+ * rerun command was stored, and of all segments together when their parameters match */
+struct RunAnalysis {
+    segments: Vec<(SeriesAnalysis, Option<RunConfig>)>,
+    combined: Option<SeriesAnalysis>,
+}
+
+impl RunAnalysis {
+    fn new(file: &File, autocorr: bool) -> Result<Self> {
+        let mut segments = Vec::new();
+        let mut concatenated = Vec::new();
+        for (index, segment) in run_segments(file)?.iter().enumerate() {
+            let series = read_action_series(segment)
+                .with_context(|| format!("Failed to read the action measurements of segment {}", index))?;
+            /* the region plaquette averages are only analyzed segment by segment */
+            let result = match read_region_table(segment)? {
+                Some((table, num_regions)) => SeriesAnalysis::new(&series, autocorr).with_regions(&table, num_regions),
+                None => SeriesAnalysis::new(&series, autocorr),
+            };
+            concatenated.extend(series);
+            segments.push((result, stored_settings(segment).ok()));
+        }
+
+        let first = segments[0].1.as_ref();
+        let combinable = segments.len() > 1
+            && segments
+                .iter()
+                .all(|(_, settings)| matches!((first, settings), (Some(first), Some(settings)) if same_physics(first, settings)));
+        return Ok(Self {
+            segments,
+            combined: combinable.then(|| SeriesAnalysis::new(&concatenated, autocorr)),
+        });
+    }
+}
+
+/* mean action with its naive error and the jackknife error at increasing bin sizes, which levels
+ * off once the bins are longer than the autocorrelation time. Every segment of the run is
+ * analyzed separately, and all of them together when their parameters match */
+fn analyze_run(analyze: Analyze) -> Result<()> {
+    let file = File::open(&analyze.name).with_context(|| format!("Failed to open file {}", analyze.name))?;
+    let RunAnalysis { segments, combined } =
+        RunAnalysis::new(&file, analyze.autocorr).with_context(|| format!("Failed to analyze {}", analyze.name))?;
+
+    if let [(result, settings)] = segments.as_slice() {
+        if result.measurements == 0 {
+            bail!("{} contains no action measurements", analyze.name);
+        }
+        if analyze.json {
+            println!("{{\"name\":{},{}}}", json_string(&analyze.name), result.json_fields());
+        } else {
+            println!("{} measurements in {}", result.measurements, analyze.name);
+            result.print(settings.as_ref().map(|settings| settings.sweeps_between_measurements));
+        }
+        return Ok(());
+    }
+
+    if analyze.json {
+        let entries = segments
+            .iter()
+            .enumerate()
+            .map(|(index, (result, settings))| {
+                format!(
+                    "{{\"segment\":{},\"settings\":{},{}}}",
+                    index,
+                    settings.as_ref().map_or("null".to_string(), |settings| settings.to_json()),
+                    result.json_fields()
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(",");
+        println!(
+            "{{\"name\":{},\"segments\":[{}],\"combined\":{}}}",
+            json_string(&analyze.name),
+            entries,
+            combined.map_or("null".to_string(), |combined| format!("{{{}}}", combined.json_fields()))
+        );
+        return Ok(());
+    }
+
+    println!("{} segments in {}", segments.len(), analyze.name);
+    for (index, (result, settings)) in segments.iter().enumerate() {
+        match settings {
+            Some(settings) => println!(
+                "segment {}: beta {}, {}, {} measurements",
+                index,
+                settings.beta,
+                format_extents(settings.lattice_dims),
+                result.measurements
+            ),
+            None => println!("segment {}: unknown parameters, {} measurements", index, result.measurements),
+        }
+        result.print(settings.as_ref().map(|settings| settings.sweeps_between_measurements));
+    }
+    match combined {
+        Some(combined) => {
+            println!("all segments combined:");
+            /* the spacing may differ between segments, tau_int is only given in measurements */
+            combined.print(None);
+        }
+        None => println!("the segments differ in beta, width, gamma or kappa and are not combined"),
+    }
+    return Ok(());
+}
+
+/* add a segment with new parameters to a run and measure it. The new segment is complete before
+ * the current one is closed, so an interrupted retarget leaves the run as it was or with the new
+ * segment already in place */
+fn retarget_run(retarget: Retarget) -> Result<()> {
+    let file = File::open_rw(&retarget.name).with_context(|| format!("Failed to open file {}", retarget.name))?;
+    let segments = run_segments(&file)?;
+    let index = segments.len();
+    let previous = &segments[index - 1];
+
+    let mut settings = new_run_settings(&retarget.name, &retarget.parameters)?;
+    let carried = if retarget.carry_over {
+        if settings.start != StartSpec::Random {
+            bail!("conflicting start options --carry-over and the start {} of the new segment", settings.start);
+        }
+        let previous_settings = stored_settings(previous)
+            .with_context(|| format!("Failed to read the parameters of segment {}", index - 1))?;
+        let (_, configuration) = latest_checkpoint(previous)?
+            .with_context(|| format!("segment {} has no stored configuration to carry over", index - 1))?;
+        let lattice = Lattice::from_array_dims(previous_settings.lattice_dims, &configuration.read_raw::<f64>()?)?;
+        if lattice.dims() != settings.lattice_dims {
+            bail!(
+                "segment {} has {}, which can not be carried over to {}",
+                index - 1,
+                format_extents(lattice.dims()),
+                format_extents(settings.lattice_dims)
+            );
+        }
+        Some(lattice)
+    } else {
+        None
+    };
+
+    let registry = settings.seed.map_or_else(RngRegistry::from_entropy, RngRegistry::new);
+    settings.seed = Some(registry.master_seed());
+    let derived = parse_derived(&settings)?;
+    retarget.memory.check(&run_footprint(&settings), settings.lattice_dims)?;
+
+    let segment = file.create_group(&segment_path(index))?;
+    let simulation = create_segment(&segment, &settings, &derived, registry, false, carried)?;
+    let action_dataset = segment.dataset("action_measurements")?;
+    write_attribute(&action_dataset, "segment", index)?;
+    write_attribute(&action_dataset, "previous-segment", segment_path(index - 1).parse::<VarLenUnicode>()?)?;
+    write_attribute(&action_dataset, "carried-over", retarget.carry_over)?;
+    close_segment(previous)?;
+    file.flush()?;
+
+    println!(
+        "Retargeted {} to segment {} at beta {} with random seed {}",
+        retarget.name,
+        index,
+        settings.beta,
+        settings.seed.unwrap()
+    );
+    run_measurements(&segment, &settings, &derived, &retarget.options, simulation, None)?;
+    Ok(())
+}
+
+/* run the couplings of a scan one after the other, each in its own group of one save file. The
+ * first coupling starts from the start given in the parameters, every later one from the last
+ * configuration of the one before, so that scanning up and down shows hysteresis. With --hysteresis
+ * both directions run as the branches heating and cooling */
+fn scan_run(scan: Scan) -> Result<()> {
+    let betas = match (scan.beta_start, scan.beta_end, scan.beta_step) {
+        (Some(start), Some(end), Some(step)) => beta_range(start, end, step)?,
+        _ => scan.betas,
+    };
+    check_betas(&betas)?;
+    if scan.parameters.iter().any(|arg| arg == "-b" || arg == "--beta" || arg.starts_with("--beta=")) {
+        bail!("the couplings of a scan are given by --beta-start, --beta-end and --beta-step or by --betas, not by --beta after --");
+    }
+
+    let mut parameters = scan.parameters.clone();
+    parameters.push(format!("--beta={}", betas[0]));
+    let template = new_run_settings(&scan.name, &parameters)?;
+    if template.kappa.is_some() {
+        bail!("a scan only carries the links from one coupling to the next, runs with --kappa can not be scanned");
+    }
+    if template.beta_spatial.is_some() && template.beta_temporal.is_some() {
+        bail!("--beta-spatial and --beta-temporal together fix both couplings, which leaves nothing to scan");
+    }
+
+    /* heating starts cold at the largest coupling and lowers it, cooling starts hot at the smallest
+     * and raises it */
+    let branches = if scan.hysteresis {
+        if template.start != StartSpec::Random {
+            bail!("--hysteresis starts the heating branch ordered and the cooling branch random, the parameters can not give a start");
+        }
+        let mut ascending = betas.clone();
+        ascending.sort_by(f64::total_cmp);
+        let descending = ascending.iter().rev().copied().collect();
+        vec![
+            (Some("heating"), descending, StartSpec::Ordered),
+            (Some("cooling"), ascending, StartSpec::Random),
+        ]
+    } else {
+        vec![(None, betas.clone(), template.start.clone())]
+    };
+    let mut branch_points = Vec::with_capacity(branches.len());
+    for (branch, betas, start) in branches {
+        let mut points = Vec::with_capacity(betas.len());
+        for (index, &beta) in betas.iter().enumerate() {
+            let mut settings = template.clone();
+            settings.beta = beta;
+            settings.start = if index == 0 { start.clone() } else { StartSpec::Random };
+            settings.validate()?;
+            points.push(settings);
+        }
+        branch_points.push((branch, points));
+    }
+
+    print_findings(branch_points.iter().flat_map(|(_, points)| points));
+    let derived = parse_derived(&template)?;
+    scan.memory.check(&run_footprint(&template), template.lattice_dims)?;
+
+    /* every coupling gets its own random streams, derived from the master seed and its group */
+    let master_seed = template.seed.map_or_else(RngRegistry::from_entropy, RngRegistry::new).master_seed();
+    let file = File::create_excl(&scan.name).with_context(|| format!("Failed to create file {}", scan.name))?;
+    let root = file.group("/")?;
+    root.new_attr::<f64>().shape([betas.len()]).create("scanned-betas")?.write(&betas)?;
+    root.new_attr::<u64>().shape([1]).create("seed")?.write(&[master_seed])?;
+    println!(
+        "Scanning {} couplings from {} to {} into {} with random seed {}",
+        betas.len(),
+        betas[0],
+        betas[betas.len() - 1],
+        scan.name,
+        master_seed
+    );
+
+    let mut results = Vec::with_capacity(branch_points.len());
+    for (branch, points) in branch_points {
+        let parent = match branch {
+            Some(branch) => {
+                let group = file.create_group(branch)?;
+                let betas: Vec<f64> = points.iter().map(|settings| settings.beta).collect();
+                group.new_attr::<f64>().shape([betas.len()]).create("scanned-betas")?.write(&betas)?;
+                group
+                    .new_attr::<VarLenUnicode>()
+                    .shape([1])
+                    .create("start")?
+                    .write(&[points[0].start.to_string().parse::<VarLenUnicode>()?])?;
+                println!("{} branch from beta {}, start {}", branch, betas[0], points[0].start);
+                group
+            }
+            None => root.clone(),
+        };
+        let scanned = scan_branch(&parent, branch, points, master_seed, &derived, &scan.options)?;
+        results.push((branch, scanned));
+    }
+
+    for (branch, scanned) in &results {
+        if let Some(branch) = branch {
+            println!("{}:", branch);
+        }
+        println!("beta          mean action     error");
+        for point in scanned {
+            println!(
+                "{:<12}  {:<14.8}  {}",
+                point.beta,
+                point.mean,
+                point.error.map_or("-".to_string(), |error| format!("{:.2e}", error))
+            );
+        }
+    }
+    if let [(_, heating), (_, cooling)] = results.as_slice() {
+        if let Some(discrepancy) = max_discrepancy(heating, cooling) {
+            root.new_attr::<f64>().shape([1]).create("max-discrepancy")?.write(&[discrepancy.difference])?;
+            root.new_attr::<f64>().shape([1]).create("max-discrepancy-beta")?.write(&[discrepancy.beta])?;
+            println!(
+                "largest discrepancy between heating and cooling: {:.6} at beta {}{}",
+                discrepancy.difference,
+                discrepancy.beta,
+                discrepancy
+                    .significance
+                    .map_or(String::new(), |significance| format!(", {:.1} standard errors", significance))
+            );
+        }
+    }
+    return Ok(());
+}
+
+/* the lint findings of several runs sharing their parameters but the coupling, every rule once */
+fn print_findings<'a>(runs: impl Iterator<Item = &'a RunConfig>) {
+    let mut findings: Vec<(&str, String)> = Vec::new();
+    for finding in runs.flat_map(lint) {
+        if !findings.iter().any(|(rule, _)| *rule == finding.0) {
+            findings.push(finding);
+        }
+    }
+    for (rule, message) in &findings {
+        println!("Warning [{}]: {}", rule, message);
+    }
+}
+
+/* the couplings of one branch of a scan in order, each continuing from the last configuration of
+ * the one before. Returns the mean action of every coupling */
+fn scan_branch(
+    parent: &Group,
+    branch: Option<&str>,
+    points: Vec<RunConfig>,
+    master_seed: u64,
+    derived: &[Derived],
+    options: &RunOptions,
+) -> Result<Vec<ScanPoint>> {
+    let count = points.len();
+    let mut carried = None;
+    let mut scanned = Vec::with_capacity(count);
+    for (index, mut settings) in points.into_iter().enumerate() {
+        let name = group_name(settings.beta);
+        /* the branches draw independent streams at the same coupling */
+        let stream_name = branch.map_or(name.clone(), |branch| format!("{}/{}", branch, name));
+        let registry = RngRegistry::new(derive_seed(master_seed, &stream_name));
+        settings.seed = Some(registry.master_seed());
+
+        let group = parent.create_group(&name)?;
+        let simulation = create_segment(&group, &settings, derived, registry, false, carried.take())?;
+        let action_dataset = group.dataset("action_measurements")?;
+        write_attribute(&action_dataset, "scan-index", index)?;
+        write_attribute(&action_dataset, "carried-over", index > 0)?;
+        parent.file()?.flush()?;
+
+        println!("[{}/{}] beta {} in group {}", index + 1, count, settings.beta, stream_name);
+        let simulation = run_measurements(&group, &settings, derived, options, simulation, None)?;
+        close_segment(&group)?;
+        scanned.push(ScanPoint::new(settings.beta, &read_action_series(&group)?));
+        carried = Some(simulation.lattice);
+    }
+    return Ok(scanned);
+}
+
+/* the estimates of the plan subcommand and the run they suggest */
+struct RunPlan {
+    mean: f64,
+    variance: f64,
+    /* integrated autocorrelation time and its error, None if the action does not fluctuate */
+    tau: Option<(f64, f64)>,
+    sweeps_per_second: f64,
+    spacing: usize,
+    measurements: usize,
+    measurements_uncertainty: f64,
+    total_sweeps: usize,
+    /* seconds for the equilibration and all measurements at the calibrated sweep rate */
+    wall_time: f64,
+    config: RunConfig,
+}
+
+/* calibrate the autocorrelation time and the variance of the action and size a run that reaches the
+ * target error */
+fn plan_run(settings: Plan) -> Result<RunPlan> {
+    if settings.calibration_sweeps < 2 {
+        bail!("--calibration-sweeps must be at least 2");
+    }
+    if settings.lattice_width.is_some() {
+        warn_deprecated(&[("lattice-width", "width")]);
+    }
+    let lattice_width = settings.width.or(settings.lattice_width).context("--width is required")?;
+    validate_lattice([lattice_width; 4], settings.beta)?;
+    let footprint = Footprint {
+        lattices: 1,
+        values: settings.calibration_sweeps as u64,
+        ..Default::default()
+    };
+    settings.memory.check(&footprint, [lattice_width; 4])?;
+
+    println!(
+        "Calibrating with {} sweeps on a {}^4 lattice at beta {}",
+        settings.calibration_sweeps, lattice_width, settings.beta
+    );
+
+    let mut registry = settings.seed.map_or_else(RngRegistry::from_entropy, RngRegistry::new);
+    println!("Random seed is {}", registry.master_seed());
+    let lattice = Lattice::new_random(lattice_width, &mut registry.stream("start"));
+    let mut simulation = Simulation::new(lattice, Couplings::isotropic(settings.beta), registry.stream("sweep"));
+    simulation.thermalize(settings.equilibration_sweeps);
+
+    let start = Instant::now();
+    let mut series = Vec::with_capacity(settings.calibration_sweeps);
+    for _ in 0..settings.calibration_sweeps {
+        simulation.sweep();
+        series.push(simulation.measure_action());
+    }
+    let sweeps_per_second =
+        settings.calibration_sweeps as f64 / start.elapsed().as_secs_f64();
+
+    let variance = analysis::variance(&series);
+    let autocorrelation = analysis::integrated_autocorrelation(&series);
+    /* a constant action has no autocorrelation, every sweep is as good as independent */
+    let (tau, tau_error) = autocorrelation.unwrap_or((0.5, 0.0));
+
+    // measuring every 2 tau_int sweeps makes consecutive measurements nearly independent
+    let spacing = ((2.0 * tau).round() as usize).max(1);
+    let tau_per_measurement = (tau / spacing as f64).max(0.5);
+    let required = (2.0 * tau_per_measurement * variance / settings.target_error.powi(2)).ceil();
+    let measurements = (required as usize).max(1);
+    let measurements_uncertainty = measurements as f64 * tau_error / tau;
+    let total_sweeps = measurements * spacing;
+    let wall_time =
+        (total_sweeps + settings.equilibration_sweeps) as f64 / sweeps_per_second;
+    // short runs are only saved at the end
+    let interval = (wall_time.ceil() as usize).clamp(1, SUGGESTED_SAVE_INTERVAL);
+
+    let config = RunConfig {
+        name: settings.name,
+        beta: settings.beta,
+        beta_spatial: None,
+        beta_temporal: None,
+        lattice_dims: [lattice_width; 4],
+        start: StartSpec::Random,
+        measurements,
+        equilibration_sweeps: settings.equilibration_sweeps,
+        sweeps_between_measurements: spacing,
+        interval,
+        publish: None,
+        region_blocks: None,
+        wilson_loops: None,
+        strict_equilibration: false,
+        kappa: None,
+        gamma: None,
+        topological_charge: false,
+        polyakov: false,
+        monopoles: false,
+        plane_resolved: false,
+        frozen: false,
+        derive: Vec::new(),
+        seed: None,
+        threads: None,
+        algorithm: Algorithm::Heatbath,
+        step_size: 1.0,
+        overrelaxation_per_heatbath: 0,
+        targeted_fraction: None,
+        targeted_hits: 1,
+        targeted_refresh: 10,
+        coarse_update: None,
+        integrated_links: false,
+    };
+
+    return Ok(RunPlan {
+        mean: analysis::mean(&series),
+        variance,
+        tau: autocorrelation,
+        sweeps_per_second,
+        spacing,
+        measurements,
+        measurements_uncertainty,
+        total_sweeps,
+        wall_time,
+        config,
+    });
+}
+
+/* parallel tempering: one replica per coupling, each sweeping on its own thread, and every
+ * --swap-interval sweeps a round of exchanges between neighboring couplings. An exchange swaps the
+ * couplings of two replicas instead of their lattices, so the replica at a coupling changes while
+ * its group keeps collecting the measurements at that coupling */
+fn tempering_run(tempering: Tempering) -> Result<()> {
+    let mut betas = tempering.betas.clone();
+    betas.sort_by(f64::total_cmp);
+    check_betas(&betas)?;
+    if betas.len() < 2 {
+        bail!("parallel tempering needs at least two couplings to exchange configurations between, got {}", betas[0]);
+    }
+    if tempering.swap_interval == 0 {
+        bail!("--swap-interval must be at least 1");
+    }
+    if tempering.parameters.iter().any(|arg| arg == "-b" || arg == "--beta" || arg.starts_with("--beta=")) {
+        bail!("the couplings of the replicas are given by --betas, not by --beta after --");
+    }
+
+    let mut parameters = tempering.parameters.clone();
+    parameters.push(format!("--beta={}", betas[0]));
+    let template = new_run_settings(&tempering.name, &parameters)?;
+    /* the exchange probability only holds for the isotropic Wilson action, and only the action is
+     * measured */
+    let unsupported = [
+        ("beta-spatial", template.beta_spatial.is_some()),
+        ("beta-temporal", template.beta_temporal.is_some()),
+        ("kappa", template.kappa.is_some()),
+        ("gamma", template.gamma.is_some()),
+        ("threads", template.threads.is_some()),
+        ("targeted-fraction", template.targeted_fraction.is_some()),
+        ("frozen", template.frozen),
+        ("strict-equilibration", template.strict_equilibration),
+        ("region-blocks", template.region_blocks.is_some()),
+        ("wilson-loops", template.wilson_loops.is_some()),
+        ("topological-charge", template.topological_charge),
+        ("polyakov", template.polyakov),
+        ("monopoles", template.monopoles),
+        ("plane-resolved", template.plane_resolved),
+        ("derive", !template.derive.is_empty()),
+        ("publish", template.publish.is_some()),
+    ];
+    if let Some((flag, _)) = unsupported.iter().find(|(_, given)| *given) {
+        bail!(
+            "--{} is not supported by tempering, which only measures the plaquette action of the Wilson action and sweeps every replica on one thread",
+            flag
+        );
+    }
+    if template.sweeps_between_measurements == 0 {
+        bail!("tempering needs at least one sweep between measurements");
+    }
+    let mut points = Vec::with_capacity(betas.len());
+    for &beta in &betas {
+        let mut settings = template.clone();
+        settings.beta = beta;
+        settings.validate()?;
+        points.push(settings);
+    }
+    print_findings(points.iter());
+    let replica_footprint = run_footprint(&template);
+    let footprint = Footprint {
+        lattices: betas.len() as u64,
+        values: replica_footprint.values * betas.len() as u64,
+        ..replica_footprint
+    };
+    tempering.memory.check(&footprint, template.lattice_dims)?;
+
+    /* every replica gets its own random streams, derived from the master seed and the group of its
+     * first coupling, and the exchanges one more */
+    let mut registry = template.seed.map_or_else(RngRegistry::from_entropy, RngRegistry::new);
+    let master_seed = registry.master_seed();
+    let mut swap_rng = registry.stream("swap");
+    let file = File::create_excl(&tempering.name)
+        .with_context(|| format!("Failed to create file {}", tempering.name))?;
+    let root = file.group("/")?;
+    root.new_attr::<f64>().shape([betas.len()]).create("tempering-betas")?.write(&betas)?;
+    root.new_attr::<u64>().shape([1]).create("seed")?.write(&[master_seed])?;
+    root.new_attr::<usize>().shape([1]).create("swap-interval")?.write(&[tempering.swap_interval])?;
+    let pairs = betas.len() - 1;
+    let proposal_dataset = root.new_dataset::<usize>().shape([pairs]).create("swap_proposals")?;
+    let acceptance_dataset = root.new_dataset::<usize>().shape([pairs]).create("swap_acceptances")?;
+    println!(
+        "Tempering {} replicas from beta {} to {} into {} with random seed {}",
+        betas.len(),
+        betas[0],
+        betas[pairs],
+        tempering.name,
+        master_seed
+    );
+
+    let mut groups = Vec::with_capacity(points.len());
+    let mut replicas = Vec::with_capacity(points.len());
+    for mut settings in points {
+        let name = group_name(settings.beta);
+        let registry = RngRegistry::new(derive_seed(master_seed, &name));
+        settings.seed = Some(registry.master_seed());
+        let group = root.create_group(&name)?;
+        let mut replica = create_segment(&group, &settings, &[], registry, false, None)?;
+        replica.lattice.set_action_tracking(Some(ACTION_RESYNC_SWEEPS));
+        replicas.push(replica);
+        /* the replica whose configuration every measurement was taken from */
+        let replica_dataset = group
+            .new_dataset::<usize>()
+            .chunk(measurement_chunk(&settings))
+            .shape(0..)
+            .create("replica")?;
+        groups.push((group.dataset("action_measurements")?, replica_dataset, group));
+    }
+    file.flush()?;
+
+    let mut ladder = Ladder::new(betas);
+    let equilibration_sweeps = template.equilibration_sweeps;
+    let save_interval = Duration::from_secs(template.interval as u64);
+    let mut last_save = Instant::now();
+    let mut sweeps = 0;
+    let mut measured = 0;
+    while measured < template.measurements {
+        let measurement_sweep = equilibration_sweeps + (measured + 1) * template.sweeps_between_measurements;
+        let swap_sweep = (sweeps / tempering.swap_interval + 1) * tempering.swap_interval;
+        let target = measurement_sweep.min(swap_sweep);
+        sweep_replicas(&mut replicas, target - sweeps, equilibration_sweeps);
+        sweeps = target;
+
+        if sweeps == swap_sweep {
+            let actions: Vec<f64> = replicas
+                .iter()
+                .map(|replica| replica.lattice.tracked_action().unwrap_or_else(|| replica.lattice.total_action()))
+                .collect();
+            for pair in ladder.propose_swaps(&actions, &mut swap_rng) {
+                let (lower, upper) = (ladder.replica_at[pair], ladder.replica_at[pair + 1]);
+                replicas[lower].couplings = Couplings::isotropic(ladder.betas[pair]);
+                replicas[upper].couplings = Couplings::isotropic(ladder.betas[pair + 1]);
+                /* the tuned Metropolis step belongs to the coupling */
+                let step_size = replicas[lower].step_size;
+                replicas[lower].step_size = replicas[upper].step_size;
+                replicas[upper].step_size = step_size;
+            }
+        }
+
+        if sweeps == measurement_sweep {
+            for (position, (action_dataset, replica_dataset, _)) in groups.iter().enumerate() {
+                let replica = ladder.replica_at[position];
+                let action = replicas[replica].measure_action();
+                action_dataset.resize(measured + 1)?;
+                action_dataset.write_slice(&[action], measured..measured + 1)?;
+                replica_dataset.resize(measured + 1)?;
+                replica_dataset.write_slice(&[replica], measured..measured + 1)?;
+            }
+            measured += 1;
+        }
+
+        let finished = measured == template.measurements;
+        if finished || last_save.elapsed() >= save_interval {
+            proposal_dataset.write(&ladder.proposals)?;
+            acceptance_dataset.write(&ladder.acceptances)?;
+            file.flush()?;
+            last_save = Instant::now();
+            let rates: Vec<String> = (0..pairs)
+                .map(|pair| ladder.acceptance_rate(pair).map_or("-".to_string(), |rate| format!("{:.2}", rate)))
+                .collect();
+            println!(
+                "[{}/{}] sweep {}, swap acceptance {}",
+                measured,
+                template.measurements,
+                sweeps,
+                rates.join(" ")
+            );
+        }
+    }
+
+    println!("beta          mean action     error");
+    for ((_, _, group), &beta) in groups.iter().zip(&ladder.betas) {
+        close_segment(group)?;
+        let point = ScanPoint::new(beta, &read_action_series(group)?);
+        println!(
+            "{:<12}  {:<14.8}  {}",
+            point.beta,
+            point.mean,
+            point.error.map_or("-".to_string(), |error| format!("{:.2e}", error))
+        );
+    }
+    println!("pair                swap acceptance");
+    for pair in 0..pairs {
+        println!(
+            "{:<8} - {:<8}  {}",
+            ladder.betas[pair],
+            ladder.betas[pair + 1],
+            ladder.acceptance_rate(pair).map_or("-".to_string(), |rate| format!("{:.3}", rate))
+        );
+    }
+    return Ok(());
+}
+
+/* the given number of sweeps on every replica, each on its own thread. The replicas only share
+ * their schedule, so the result does not depend on how the threads interleave */
+fn sweep_replicas(replicas: &mut [Simulation], sweeps: usize, equilibration_sweeps: usize) {
+    std::thread::scope(|scope| {
+        for replica in replicas.iter_mut() {
+            scope.spawn(move || {
+                for _ in 0..sweeps {
+                    replica.sweep();
+                    if replica.sweeps <= equilibration_sweeps {
+                        if replica.sweeps.is_multiple_of(STEP_TUNING_SWEEPS) {
+                            replica.tune_step_size();
+                        }
+                        replica.refresh_targeting();
+                    }
+                }
+            });
+        }
+    });
+}
+
+/* HDF5 files start with this signature, other files in a campaign directory are left out */
+const HDF5_SIGNATURE: [u8; 8] = *b"\x89HDF\r\n\x1a\n";
+
+/* link updates above which verify-manifest does not rerun a segment */
+const RERUN_LINK_UPDATES: usize = 100_000_000;
+
+/* the HDF5 files directly inside a directory, by name */
+fn hdf5_files(directory: &Path) -> Result<Vec<String>> {
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(directory).with_context(|| format!("Failed to read {}", directory.display()))? {
+        let path = entry?.path();
+        let mut signature = [0u8; 8];
+        let is_hdf5 = path.is_file()
+            && std::fs::File::open(&path).and_then(|mut file| file.read_exact(&mut signature)).is_ok()
+            && signature == HDF5_SIGNATURE;
+        if is_hdf5 {
+            files.push(path.file_name().unwrap().to_string_lossy().to_string());
+        }
+    }
+    files.sort();
+    return Ok(files);
+}
+
+/* checksum, element type and shape of a dataset, and the position in the chain of a checkpoint */
+fn dataset_manifest(dataset: &Dataset, name: &str) -> Result<String> {
+    let (element_type, bytes) = if dataset.dtype()?.is::<u64>() {
+        ("u64", dataset.read_raw::<u64>()?.iter().flat_map(|value| value.to_le_bytes()).collect::<Vec<u8>>())
+    } else {
+        ("f64", dataset.read_raw::<f64>()?.iter().flat_map(|value| value.to_le_bytes()).collect())
+    };
+    let shape: Vec<String> = dataset.shape().iter().map(|extent| extent.to_string()).collect();
+    let mut fields = vec![
+        format!("\"type\":{}", json_string(element_type)),
+        format!("\"shape\":[{}]", shape.join(",")),
+        format!("\"xxh64\":\"{:016x}\"", xxh64(&bytes, 0)),
+    ];
+    if CHECKPOINT_SLOTS.contains(&name) {
+        fields.push(format!("\"complete\":{}", read_attribute::<bool>(dataset, "complete")?));
+        fields.push(format!("\"sweeps\":{}", read_attribute::<usize>(dataset, "sweeps")?));
+        fields.push(format!("\"measurements\":{}", read_attribute::<usize>(dataset, "measurements")?));
+        fields.push(format!("\"rng_state\":{}", read_attribute::<u64>(dataset, "rng-state")?));
+    }
+    return Ok(format!("{{{}}}", fields.join(",")));
+}
+
+/* value of a summary attribute as JSON text */
+fn statistic_value(action_dataset: &Dataset, statistic: &str) -> Result<String> {
+    return Ok(match statistic {
+        "possibly-unequilibrated" => read_attribute::<bool>(action_dataset, statistic)?.to_string(),
+        "summary-measurements" => read_attribute::<usize>(action_dataset, statistic)?.to_string(),
+        _ => read_attribute::<f64>(action_dataset, statistic)?.to_string(),
+    });
+}
+
+/* everything needed to regenerate a segment: seeds, parameters and build, with a checksum of every
+ * dataset and the analysis behind every summary attribute. Attributes that a file predates are null */
+fn segment_manifest(segment: &Group) -> Result<String> {
+    let action_dataset = segment.dataset("action_measurements")?;
+    let attributes = action_dataset.attr_names()?;
+    let has = |name: &str| attributes.iter().any(|attribute| attribute == name);
+    let embedded = |name: &str| -> Result<String> {
+        if !has(name) {
+            return Ok("null".to_string());
+        }
+        let text = read_string_attribute(&action_dataset, name)?;
+        return Ok(json::parse(&text)
+            .with_context(|| format!("attribute {} is not valid JSON", name))?
+            .to_json());
+    };
+
+    let settings = if has("rerun-command") { Some(stored_settings(segment)?) } else { None };
+    let buildinfo = embedded("buildinfo")?;
+    let version = json::parse(&buildinfo)?.get("version").map_or("null".to_string(), Json::to_json);
+
+    let mut datasets = Vec::new();
+    for name in segment.member_names()? {
+        if name.starts_with(SEGMENT_PREFIX) {
+            continue;
+        }
+        let dataset = segment.dataset(&name)?;
+        let manifest = dataset_manifest(&dataset, &name).with_context(|| format!("Failed to read dataset {}", name))?;
+        datasets.push(format!("{}:{}", json_string(&name), manifest));
+    }
+
+    let probation = settings.as_ref().map(|settings| probation_length(settings.measurements));
+    let mut statistics = Vec::new();
+    for statistic in SUMMARY_STATISTICS.into_iter().filter(|statistic| has(statistic)) {
+        statistics.push(format!(
+            "{}:{{\"dataset\":\"action_measurements\",\"value\":{},\"analysis\":{}}}",
+            json_string(statistic),
+            statistic_value(&action_dataset, statistic)?,
+            analysis_parameters(statistic, probation).unwrap()
+        ));
+    }
+
+    return Ok(format!(
+        "{{\"master_seed\":{},\"rng_registry\":{},\"config\":{},\"rerun_command\":{},\"carried_over\":{},\"crate_version\":{},\"buildinfo\":{},\"datasets\":{{{}}},\"statistics\":{{{}}}}}",
+        if has("seed") { read_attribute::<u64>(&action_dataset, "seed")?.to_string() } else { "null".to_string() },
+        embedded("rng-registry")?,
+        settings.as_ref().map_or("null".to_string(), RunConfig::to_json),
+        if has("rerun-command") { json_string(&read_string_attribute(&action_dataset, "rerun-command")?) } else { "null".to_string() },
+        has("carried-over") && read_attribute::<bool>(&action_dataset, "carried-over")?,
+        version,
+        buildinfo,
+        datasets.join(","),
+        statistics.join(",")
+    ));
+}
+
+/* the manifest entry of a run file, one entry per segment */
+fn run_manifest(path: &Path) -> Result<String> {
+    let file = File::open(path).with_context(|| format!("Failed to open file {}", path.display()))?;
+    let mut segments = Vec::new();
+    for (index, segment) in run_segments(&file)?.iter().enumerate() {
+        let manifest = segment_manifest(segment).with_context(|| format!("Failed to describe segment {}", index))?;
+        segments.push(format!("{}:{}", json_string(&segment_path(index)), manifest));
+    }
+    return Ok(format!("{{\"segments\":{{{}}}}}", segments.join(",")));
+}
+
+/* write the manifest of a run file, or of every run file in a directory. The runs are named
+ * relative to the directory, so that verify-manifest can check a copy of it */
+fn write_manifest(manifest: Manifest) -> Result<()> {
+    let metadata = std::fs::metadata(&manifest.name).with_context(|| format!("Failed to open {}", manifest.name))?;
+    let (scope, directory, files) = if metadata.is_dir() {
+        ("directory", manifest.name.clone(), hdf5_files(Path::new(&manifest.name))?)
+    } else {
+        let path = Path::new(&manifest.name);
+        let directory = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent.to_string_lossy().to_string(),
+            _ => ".".to_string(),
+        };
+        ("file", directory, vec![path.file_name().unwrap().to_string_lossy().to_string()])
+    };
+
+    let mut runs = Vec::new();
+    for file_name in &files {
+        let path = Path::new(&directory).join(file_name);
+        match run_manifest(&path) {
+            Ok(run) => runs.push(format!("{}:{}", json_string(file_name), run)),
+            Err(error) if scope == "directory" => {
+                eprintln!("Skipping {}, it is not a readable run: {:#}", path.display(), error)
+            }
+            Err(error) => return Err(error.context(format!("Failed to describe the run {}", path.display()))),
+        }
+    }
+    if runs.is_empty() {
+        bail!("there are no run files in {}", directory);
+    }
+
+    let text = format!(
+        "{{\"manifest_version\":{},\"generator\":{{\"crate\":{},\"version\":{}}},\"scope\":{},\"directory\":{},\"runs\":{{{}}}}}",
+        MANIFEST_VERSION,
+        json_string(env!("CARGO_PKG_NAME")),
+        json_string(env!("CARGO_PKG_VERSION")),
+        json_string(scope),
+        json_string(&directory),
+        runs.join(",")
+    );
+    let pretty = json::parse(&text)?.to_pretty_json();
+    match manifest.output {
+        Some(output) => {
+            std::fs::write(&output, pretty).with_context(|| format!("Failed to write {}", output))?;
+            println!("Wrote the manifest of {} runs to {}", runs.len(), output);
+        }
+        None => print!("{}", pretty),
+    }
+    return Ok(());
+}
+
+/* the summary attributes of every segment recomputed from its measurements with the analysis the
+ * manifest lists, returns the number compared */
+fn recheck_statistics(file: &File, path: &[String], mismatches: &mut Vec<Mismatch>) -> Result<usize> {
+    let mut compared = 0;
+    for (index, segment) in run_segments(file)?.iter().enumerate() {
+        let action_dataset = segment.dataset("action_measurements")?;
+        let attributes = action_dataset.attr_names()?;
+        let probation = if attributes.iter().any(|attribute| attribute == "rerun-command") {
+            Some(probation_length(stored_settings(segment)?.measurements))
+        } else {
+            None
+        };
+        let series = read_action_series(segment)?;
+        for statistic in SUMMARY_STATISTICS {
+            if !attributes.iter().any(|attribute| attribute == statistic) {
+                continue;
+            }
+            let stored = statistic_value(&action_dataset, statistic)?;
+            let recomputed = recompute_statistic(statistic, &series, probation);
+            compared += 1;
+            if recomputed.as_ref() != Some(&stored) {
+                let mut item = path.to_vec();
+                item.extend(["segments", &segment_path(index), "statistics", statistic, "recomputed"].map(String::from));
+                mismatches.push(Mismatch {
+                    path: item,
+                    expected: Some(stored),
+                    found: Some(recomputed.unwrap_or_else(|| "nothing computable from the measurements".to_string())),
+                });
+            }
+        }
+    }
+    return Ok(compared);
+}
+
+/* outcome of rerunning a segment from its stored parameters */
+enum Rerun {
+    Skipped(String),
+    Compared { datasets: usize, measurements: usize },
+}
+
+/* run the stored parameters and seed of a segment again, for as many measurements as it holds, in
+ * a separate process so that the thread pool of every rerun is set up fresh, and compare every
+ * measurement dataset bit for bit */
+fn rerun_segment(segment: &Group, scratch: &Path, path: &[String], mismatches: &mut Vec<Mismatch>) -> Result<Rerun> {
+    let action_dataset = segment.dataset("action_measurements")?;
+    let attributes = action_dataset.attr_names()?;
+    let has = |name: &str| attributes.iter().any(|attribute| attribute == name);
+    if !has("rerun-command") || !has("seed") {
+        return Ok(Rerun::Skipped("the file predates stored parameters and seeds".to_string()));
+    }
+    if has("carried-over") && read_attribute::<bool>(&action_dataset, "carried-over")? {
+        return Ok(Rerun::Skipped("the segment starts from the configuration of the previous one".to_string()));
+    }
+
+    let mut settings = stored_settings(segment)?;
+    let measurements = read_action_series(segment)?.len();
+    if measurements == 0 {
+        return Ok(Rerun::Skipped("there are no measurements yet".to_string()));
+    }
+    let sweeps = if settings.frozen {
+        0
+    } else {
+        settings.equilibration_sweeps + measurements * settings.sweeps_between_measurements
+    };
+    let link_updates = 4 * settings.volume() * sweeps;
+    if link_updates > RERUN_LINK_UPDATES {
+        return Ok(Rerun::Skipped(format!(
+            "it takes {} link updates, reruns are limited to {}",
+            link_updates, RERUN_LINK_UPDATES
+        )));
+    }
+
+    settings.name = scratch.join("rerun.h5").to_string_lossy().to_string();
+    settings.seed = Some(read_attribute::<u64>(&action_dataset, "seed")?);
+    settings.measurements = measurements;
+    if Path::new(&settings.name).exists() {
+        std::fs::remove_file(&settings.name)?;
+    }
+    let output = std::process::Command::new(std::env::current_exe()?)
+        .args(settings.to_cli_args())
+        .output()
+        .context("Failed to start the rerun")?;
+    if !output.status.success() {
+        bail!("the rerun failed: {}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+
+    let rerun_file = File::open(&settings.name)?;
+    let datasets = compare_rerun(segment, &rerun_file.group("/")?, path, mismatches)?;
+    return Ok(Rerun::Compared { datasets, measurements });
+}
+
+/* compare every measurement dataset of a segment bit for bit with the one of its rerun, returns the
+ * number of datasets of the segment */
+fn compare_rerun(segment: &Group, rerun: &Group, path: &[String], mismatches: &mut Vec<Mismatch>) -> Result<usize> {
+    let is_measurement = |name: &String| {
+        !name.starts_with(SEGMENT_PREFIX)
+            && !CHECKPOINT_SLOTS.iter().any(|slot| name == slot || *name == scalar_slot(slot))
+            && name != LATENCY_DATASET
+    };
+    let original_names: Vec<String> = segment.member_names()?.into_iter().filter(is_measurement).collect();
+    let rerun_names: Vec<String> = rerun.member_names()?.into_iter().filter(is_measurement).collect();
+    let item = |name: &str| {
+        let mut item = path.to_vec();
+        item.extend(["rerun", name].map(String::from));
+        item
+    };
+
+    for name in &original_names {
+        if !rerun_names.contains(name) {
+            mismatches.push(Mismatch { path: item(name), expected: Some("a dataset".to_string()), found: None });
+            continue;
+        }
+        let (original, rerun) = (segment.dataset(name)?, rerun.dataset(name)?);
+        if original.shape() != rerun.shape() {
+            mismatches.push(Mismatch {
+                path: item(name),
+                expected: Some(format!("shape {:?}", original.shape())),
+                found: Some(format!("shape {:?}", rerun.shape())),
+            });
+            continue;
+        }
+        let (original, rerun) = (original.read_raw::<f64>()?, rerun.read_raw::<f64>()?);
+        if let Some(index) = (0..original.len()).find(|&index| original[index].to_bits() != rerun[index].to_bits()) {
+            mismatches.push(Mismatch {
+                path: item(name),
+                expected: Some(format!("element {} = {}", index, original[index])),
+                found: Some(rerun[index].to_string()),
+            });
+        }
+    }
+    for name in rerun_names.iter().filter(|name| !original_names.contains(name)) {
+        mismatches.push(Mismatch { path: item(name), expected: None, found: Some("a dataset".to_string()) });
+    }
+    return Ok(original_names.len());
+}
+
+/* regenerate the manifest entry of every run it lists and report each item that differs, together
+ * with summary attributes that their measurements do not reproduce and, with --rerun, measurements
+ * that a rerun does not reproduce */
+fn verify_manifest(verify: VerifyManifest) -> Result<()> {
+    let text = std::fs::read_to_string(&verify.manifest).with_context(|| format!("Failed to read {}", verify.manifest))?;
+    let manifest = json::parse(&text).with_context(|| format!("Failed to parse {}", verify.manifest))?;
+    if manifest.get("manifest_version").map(Json::to_json) != Some(MANIFEST_VERSION.to_string()) {
+        bail!("{} is not a manifest of version {}", verify.manifest, MANIFEST_VERSION);
+    }
+    let directory = match verify.directory {
+        Some(directory) => directory,
+        None => manifest
+            .get("directory")
+            .and_then(Json::as_str)
+            .context("the manifest records no directory, pass --directory")?
+            .to_string(),
+    };
+    let runs = match manifest.get("runs") {
+        Some(Json::Object(runs)) => runs,
+        _ => bail!("{} lists no runs", verify.manifest),
+    };
+    if !Path::new(&directory).is_dir() {
+        bail!("the run directory {} does not exist, pass --directory", directory);
+    }
+
+    let scratch = std::env::temp_dir().join(format!("{}-verify-{}", env!("CARGO_PKG_NAME"), std::process::id()));
+    if verify.rerun {
+        std::fs::create_dir_all(&scratch).with_context(|| format!("Failed to create {}", scratch.display()))?;
+    }
+
+    let mut mismatches = Vec::new();
+    let mut items = 0;
+    let result = (|| -> Result<()> {
+        for (file_name, expected) in runs {
+            let path = Path::new(&directory).join(file_name);
+            let prefix = vec!["runs".to_string(), file_name.clone()];
+            items += count_items(expected);
+            let found = match run_manifest(&path).and_then(|run| json::parse(&run)) {
+                Ok(found) => found,
+                Err(error) => {
+                    mismatches.push(Mismatch {
+                        path: prefix,
+                        expected: Some("a readable run file".to_string()),
+                        found: Some(format!("{:#}", error)),
+                    });
+                    continue;
+                }
+            };
+            mismatches.extend(compare(&prefix, expected, &found));
+
+            let file = File::open(&path)?;
+            match recheck_statistics(&file, &prefix, &mut mismatches) {
+                Ok(compared) => items += compared,
+                Err(error) => {
+                    let mut item = prefix.clone();
+                    item.push("statistics".to_string());
+                    mismatches.push(Mismatch {
+                        path: item,
+                        expected: Some("recomputable summary attributes".to_string()),
+                        found: Some(format!("{:#}", error)),
+                    });
+                }
+            }
+            if !verify.rerun {
+                continue;
+            }
+            for (index, segment) in run_segments(&file)?.iter().enumerate() {
+                let mut segment_prefix = prefix.clone();
+                segment_prefix.extend(["segments".to_string(), segment_path(index)]);
+                match rerun_segment(segment, &scratch, &segment_prefix, &mut mismatches) {
+                    Ok(Rerun::Skipped(reason)) => println!("{} segment {}: not rerun, {}", file_name, index, reason),
+                    Ok(Rerun::Compared { datasets, measurements }) => {
+                        items += datasets;
+                        println!("{} segment {}: reran {} measurements of {} datasets", file_name, index, measurements, datasets);
+                    }
+                    Err(error) => {
+                        let mut item = segment_prefix;
+                        item.push("rerun".to_string());
+                        mismatches.push(Mismatch {
+                            path: item,
+                            expected: Some("a rerun".to_string()),
+                            found: Some(format!("{:#}", error)),
+                        });
+                    }
+                }
+            }
+        }
+
+        if manifest.get("scope").and_then(Json::as_str) == Some("directory") {
+            for file_name in hdf5_files(Path::new(&directory))? {
+                if runs.iter().all(|(listed, _)| *listed != file_name)
+                    && run_manifest(&Path::new(&directory).join(&file_name)).is_ok()
+                {
+                    mismatches.push(Mismatch {
+                        path: vec!["runs".to_string(), file_name],
+                        expected: None,
+                        found: Some("a run file".to_string()),
+                    });
+                }
+            }
+        }
+        Ok(())
+    })();
+
+    if verify.rerun {
+        std::fs::remove_dir_all(&scratch).with_context(|| format!("Failed to remove {}", scratch.display()))?;
+    }
+    result?;
+
+    for mismatch in &mismatches {
+        println!("MISMATCH {}", mismatch);
+    }
+    if !mismatches.is_empty() {
+        bail!("{} of {} items of {} do not match", mismatches.len(), items, verify.manifest);
+    }
+    println!("all {} items of the {} runs in {} match", items, runs.len(), verify.manifest);
+    return Ok(());
+}
+
+/* open the current segment of an existing run at its latest checkpoint, dropping whatever was
+ * written after it */
+fn open_run(name: &str, memory: &MemoryCheck) -> Result<(File, Group, RunConfig, Vec<Derived>, Simulation)> {
+    let file = File::open_rw(name).with_context(|| format!("Failed to open file {}", name))?;
+    let segment = run_segments(&file)?.pop().unwrap();
+    let mut settings = stored_settings(&segment)
+        .with_context(|| format!("Failed to read the run parameters from {}", name))?;
+    settings.name = name.to_string();
+    let (slot, configuration) = latest_checkpoint(&segment)?.with_context(|| {
+        format!(
+            "{} has no stored configuration, the run stopped before its first save",
+            settings.name
+        )
+    })?;
+    let derived = parse_derived(&settings)?;
+    memory.check(&run_footprint(&settings), settings.lattice_dims)?;
+
+    let completed = read_attribute::<usize>(&configuration, "measurements")?;
+    let sweeps = read_attribute::<usize>(&configuration, "sweeps")?;
+    let rng_state = read_attribute::<u64>(&configuration, "rng-state")?;
+    let lattice = Lattice::from_array_dims(settings.lattice_dims, &configuration.read_raw::<f64>()?)?;
+
+    segment.dataset("action_measurements")?.resize(completed)?;
+    if settings.kappa.is_some() {
+        segment.dataset("hopping_measurements")?.resize(completed)?;
+    }
+    if settings.gamma.is_some() {
+        segment.dataset("double_action_measurements")?.resize(completed)?;
+    }
+    if settings.topological_charge {
+        segment.dataset("topological_charge")?.resize(completed)?;
+    }
+    if settings.monopoles {
+        segment.dataset("monopole_density")?.resize(completed)?;
+    }
+    if settings.polyakov {
+        for name in POLYAKOV_DATASETS {
+            segment.dataset(name)?.resize(completed)?;
+        }
+    }
+    for definition in &derived {
+        segment.dataset(format!("derived_{}", definition.name).as_str())?
+            .resize(completed)?;
+    }
+    if let Some(blocks) = settings.region_blocks {
+        segment.dataset("region_plaquette_averages")?
+            .resize((completed, blocks.pow(4)))?;
+    }
+    if settings.plane_resolved {
+        segment.dataset("plane_action")?.resize((completed, PLANES.len()))?;
+        for name in PLANE_AVERAGE_DATASETS {
+            segment.dataset(name)?.resize(completed)?;
+        }
+    }
+    if let Some(r_max) = settings.wilson_loops {
+        segment.dataset("wilson_loops")?.resize((completed, r_max, r_max))?;
+    }
+
+    let matter = match settings.kappa {
+        Some(kappa) => {
+            let scalar_slot = scalar_slot(slot);
+            if !segment.link_exists(&scalar_slot) {
+                bail!("the checkpoint of {} carries no scalar field, the run can not be resumed", settings.name);
+            }
+            let phases = segment.dataset(&scalar_slot)?.read_raw::<f64>()?;
+            Some(Matter {
+                field: ScalarField::from_phases(settings.lattice_dims, &phases)?,
+                kappa,
+                rng: Rng::with_seed(read_attribute::<u64>(&configuration, "scalar-rng-state")?),
+            })
+        }
+        None => None,
+    };
+
+    let mut simulation = Simulation::new(lattice, settings.couplings(), Rng::with_seed(rng_state));
+    simulation.matter = matter;
+    simulation.sweeps = sweeps;
+    simulation.measurements = completed;
+    simulation.algorithm = settings.algorithm;
+    simulation.gamma = settings.gamma.unwrap_or(0.0);
+    simulation.overrelaxation = settings.overrelaxation_per_heatbath;
+    simulation.targeting = targeting(&settings);
+    /* without a stored threshold the first sweep computes one, and outside of the burn in keeps it */
+    if let Some(targeting) = &mut simulation.targeting {
+        if configuration.attr_names()?.iter().any(|name| name == "targeted-threshold") {
+            let threshold = read_attribute::<f64>(&configuration, "targeted-threshold")?;
+            targeting.scores = Some(LinkScores::frozen(Lattice::staple_deficit, threshold));
+        }
+    }
+    simulation.coarse = settings.coarse_update.map(|(block, amplitude)| CoarseUpdate::new(block, amplitude));
+    /* checkpoints from before the Metropolis update carry no step size */
+    simulation.step_size = settings.step_size;
+    if configuration.attr_names()?.iter().any(|name| name == "step-size") {
+        simulation.step_size = read_attribute::<f64>(&configuration, "step-size")?;
+    }
+    return Ok((file, segment, settings, derived, simulation));
+}
+
+/* create the save file of a new run with all its datasets and the initial configuration, refusing
+ * to overwrite an existing file. Every random stream is drawn from `registry` */
+fn create_run(
+    settings: &RunConfig,
+    derived: &[Derived],
+    registry: RngRegistry,
+    rerun_script: bool,
+) -> Result<(File, Group, Simulation)> {
+    // create the save file, give error if it exists to prevent accidental overwriting of data
+    let file = File::create_excl(&settings.name)
+        .with_context(|| format!("Failed to create file {}", settings.name))?;
+    let segment = file.group("/")?;
+    let simulation = create_segment(&segment, settings, derived, registry, rerun_script, None)?;
+    return Ok((file, segment, simulation));
+}
+
+/* create the datasets of a run in `segment`, the root group of a new save file or a group added by
+ * Retarget. The initial configuration is `carried` if given and built from the start of the run
+ * otherwise */
+fn create_segment(
+    segment: &Group,
+    settings: &RunConfig,
+    derived: &[Derived],
+    mut registry: RngRegistry,
+    rerun_script: bool,
+    carried: Option<Lattice>,
+) -> Result<Simulation> {
+
+    // create dataset
+    let action_dataset = segment
+        .new_dataset::<f64>()
+        .chunk(measurement_chunk(settings))
+        .shape(0..)
+        .create("action_measurements")?;
+
+    // write attributes
+    let beta_attribute = action_dataset.new_attr::<f64>().shape([1]).create("beta")?;
+    beta_attribute
+        .write(&[settings.beta])
+        .context("failed to write beta")?;
+    let couplings = settings.couplings();
+    write_attribute(&action_dataset, "beta-spatial", couplings.spatial)?;
+    write_attribute(&action_dataset, "beta-temporal", couplings.temporal)?;
+
+    /* lattice-width is only written for hypercubic lattices, which older readers expect */
+    if let Some(width) = settings.width() {
+        let lattice_width_attribute = action_dataset
+            .new_attr::<usize>()
+            .shape([1])
+            .create("lattice-width")?;
+        lattice_width_attribute.write(&[width])?;
+    }
+    let lattice_dims_attribute = action_dataset
+        .new_attr::<usize>()
+        .shape([4])
+        .create("lattice-dims")?;
+    lattice_dims_attribute.write(&settings.lattice_dims)?;
+
+    let ordered_attribute = action_dataset
+        .new_attr::<bool>()
+        .shape([1])
+        .create("ordered")?;
+    ordered_attribute.write(&[settings.start == StartSpec::Ordered])?;
+
+    let start_attribute = action_dataset
+        .new_attr::<VarLenUnicode>()
+        .shape([1])
+        .create("start")?;
+    start_attribute.write(&[settings.start.to_string().parse::<VarLenUnicode>()?])?;
+
+    let equilibration_sweeps_atttribute = action_dataset
+        .new_attr::<usize>()
+        .shape([1])
+        .create("equilibration_sweeps")?;
+    equilibration_sweeps_atttribute.write(&[settings.equilibration_sweeps])?;
+
+    let sweeps_between_measurements_attribute = action_dataset
+        .new_attr::<usize>()
+        .shape([1])
+        .create("sweeps-between-measurements")?;
+    sweeps_between_measurements_attribute.write(&[settings.sweeps_between_measurements])?;
+
+    let frozen_attribute = action_dataset
+        .new_attr::<bool>()
+        .shape([1])
+        .create("frozen")?;
+    frozen_attribute.write(&[settings.frozen])?;
+
+    let seed_attribute = action_dataset
+        .new_attr::<u64>()
+        .shape([1])
+        .create("seed")?;
+    seed_attribute.write(&[registry.master_seed()])?;
+
+    let rerun_command = settings.rerun_command();
+    let rerun_command_attribute = action_dataset
+        .new_attr::<VarLenUnicode>()
+        .shape([1])
+        .create("rerun-command")?;
+    rerun_command_attribute.write(&[rerun_command.parse::<VarLenUnicode>()?])?;
+
+    let buildinfo_attribute = action_dataset
+        .new_attr::<VarLenUnicode>()
+        .shape([1])
+        .create("buildinfo")?;
+    buildinfo_attribute.write(&[BuildInfo::collect(settings.threads).to_json().parse::<VarLenUnicode>()?])?;
+
+    if rerun_script {
+        let script_name = format!("{}.rerun.sh", settings.name);
+        std::fs::write(
+            &script_name,
+            format!(
+                "#!/bin/sh\n# {} {}\n{}\n",
+                env!("CARGO_PKG_NAME"),
+                env!("CARGO_PKG_VERSION"),
+                rerun_command
+            ),
+        )
+        .with_context(|| format!("failed to write {}", script_name))?;
+    }
+
+    // create the region resolved dataset, if requested
+    if let Some(blocks) = settings.region_blocks {
+        if blocks == 0 || settings.lattice_dims.iter().any(|extent| extent % blocks != 0) {
+            bail!(
+                "--region-blocks {} does not divide the lattice {}",
+                blocks,
+                format_extents(settings.lattice_dims)
+            );
+        }
+        let num_regions = blocks.pow(4);
+        let dataset = segment
+            .new_dataset::<f64>()
+            .chunk((1, num_regions))
+            .shape((0.., num_regions))
+            .create("region_plaquette_averages")?;
+        let blocks_attribute = dataset
+            .new_attr::<usize>()
+            .shape([1])
+            .create("blocks-per-dimension")?;
+        blocks_attribute.write(&[blocks])?;
+    }
+
+    // create the Wilson loop dataset, indexed by measurement, R - 1 and T - 1
+    if let Some(r_max) = settings.wilson_loops {
+        let wilson_loops = segment.new_dataset::<f64>()
+            .chunk((1, r_max, r_max))
+            .shape((0.., r_max, r_max))
+            .create("wilson_loops")?;
+        /* the loops whose sides are too close to integrate are measured as they are */
+        write_attribute(&wilson_loops, "integrated-temporal-links", settings.integrated_links)?;
+    }
+
+    let rng = registry.stream("sweep");
+
+    // initialize lattice
+    let lattice = match (carried, &settings.start) {
+        (Some(lattice), _) => lattice,
+        (None, StartSpec::WarmStart { path, segment }) => {
+            let (lattice, segment, sweep, factor) = warm_start_lattice(path, *segment, settings.lattice_dims)?;
+            /* provenance of the start configuration */
+            write_attribute(&action_dataset, "warm-start-file", path.parse::<VarLenUnicode>()?)?;
+            write_attribute(&action_dataset, "warm-start-segment", segment)?;
+            write_attribute(&action_dataset, "warm-start-sweep", sweep)?;
+            write_attribute(&action_dataset, "warm-start-tiling", factor)?;
+            lattice
+        }
+        (None, start) => start.build(settings.lattice_dims, &mut registry)?,
+    };
+
+    /* initialize the scalar field and its dataset, if a hopping parameter is given */
+    let matter = settings.kappa.map(|kappa| {
+        let mut scalar_rng = registry.stream("scalar");
+        let field = if settings.start == StartSpec::Ordered {
+            ScalarField::new_uniform(settings.lattice_dims)
+        } else {
+            ScalarField::new_random(settings.lattice_dims, &mut scalar_rng)
+        };
+        Matter {
+            field,
+            kappa,
+            rng: scalar_rng,
+        }
+    });
+    if let Some(kappa) = settings.kappa {
+        let dataset = segment
+            .new_dataset::<f64>()
+            .chunk(measurement_chunk(settings))
+            .shape(0..)
+            .create("hopping_measurements")?;
+        let kappa_attribute = dataset.new_attr::<f64>().shape([1]).create("kappa")?;
+        kappa_attribute.write(&[kappa])?;
+    }
+
+    if let Some(gamma) = settings.gamma {
+        let dataset = segment
+            .new_dataset::<f64>()
+            .chunk(measurement_chunk(settings))
+            .shape(0..)
+            .create("double_action_measurements")?;
+        let gamma_attribute = dataset.new_attr::<f64>().shape([1]).create("gamma")?;
+        gamma_attribute.write(&[gamma])?;
+    }
+
+    if settings.topological_charge {
+        segment.new_dataset::<f64>()
+            .chunk(measurement_chunk(settings))
+            .shape(0..)
+            .create("topological_charge")?;
+    }
+
+    if settings.monopoles {
+        segment.new_dataset::<f64>()
+            .chunk(measurement_chunk(settings))
+            .shape(0..)
+            .create("monopole_density")?;
+    }
+
+    // the plane resolved action, one column per plane in the order of the planes attribute
+    if settings.plane_resolved {
+        let dataset = segment
+            .new_dataset::<f64>()
+            .chunk((measurement_chunk(settings), PLANES.len()))
+            .shape((0.., PLANES.len()))
+            .create("plane_action")?;
+        let planes: Vec<usize> = PLANES.iter().flat_map(|&(m, n)| [m, n]).collect();
+        dataset
+            .new_attr::<usize>()
+            .shape([PLANES.len(), 2])
+            .create("planes")?
+            .write(&planes)?;
+        for name in PLANE_AVERAGE_DATASETS {
+            let dataset = segment
+                .new_dataset::<f64>()
+                .chunk(measurement_chunk(settings))
+                .shape(0..)
+                .create(name)?;
+            write_attribute(&dataset, "time-direction", TIME_DIRECTION)?;
+        }
+    }
+
+    if settings.polyakov {
+        for name in POLYAKOV_DATASETS {
+            let dataset = segment
+                .new_dataset::<f64>()
+                .chunk(measurement_chunk(settings))
+                .shape(0..)
+                .create(name)?;
+            write_attribute(&dataset, "direction", TIME_DIRECTION)?;
+        }
+    }
+
+    for definition in derived {
+        let dataset = segment
+            .new_dataset::<f64>()
+            .chunk(measurement_chunk(settings))
+            .shape(0..)
+            .create(format!("derived_{}", definition.name).as_str())?;
+        let expression_attribute = dataset
+            .new_attr::<VarLenUnicode>()
+            .shape([1])
+            .create("expression")?;
+        expression_attribute.write(&[definition.source.parse::<VarLenUnicode>()?])?;
+    }
+
+    let registry_attribute = action_dataset
+        .new_attr::<VarLenUnicode>()
+        .shape([1])
+        .create("rng-registry")?;
+    registry_attribute.write(&[registry.to_json().parse::<VarLenUnicode>()?])?;
+
+    let mut simulation = Simulation::new(lattice, settings.couplings(), rng);
+    simulation.matter = matter;
+    simulation.algorithm = settings.algorithm;
+    simulation.gamma = settings.gamma.unwrap_or(0.0);
+    simulation.step_size = settings.step_size;
+    simulation.overrelaxation = settings.overrelaxation_per_heatbath;
+    simulation.targeting = targeting(settings);
+    simulation.coarse = settings.coarse_update.map(|(block, amplitude)| CoarseUpdate::new(block, amplitude));
+    return Ok(simulation);
+}
+
+fn main() -> Result<()> {
+    /* clap answers --version on its own, the build details are only printed together with --verbose */
+    let args: Vec<String> = std::env::args().collect();
+    if args.iter().any(|arg| arg == "--version" || arg == "-V") && args.iter().any(|arg| arg == "--verbose") {
+        println!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+        println!("{}", BuildInfo::collect(None));
+        return Ok(());
+    }
+
+    // parse the arguments
+    let cli = Cli::parse();
+
+    let result = execute(cli.command);
+    /* the panic report is already printed, only the exit code tells whether data was saved */
+    if let Some(aborted) = result.as_ref().err().and_then(|error| error.downcast_ref::<Aborted>()) {
+        std::process::exit(aborted.progress.exit_code());
+    }
+    return result;
+}
+
+fn execute(command: Commands) -> Result<()> {
+    match command {
+        Commands::Examples(examples) => run_examples(examples),
+        Commands::Export(export) => export_run(export),
+        Commands::Analyze(analyze) => analyze_run(analyze),
+        Commands::Info(info) => info_run(info),
+        Commands::Retarget(retarget) => retarget_run(retarget),
+        Commands::Scan(scan) => scan_run(scan),
+        Commands::Tempering(tempering) => tempering_run(tempering),
+        Commands::Manifest(manifest) => write_manifest(manifest),
+        Commands::VerifyManifest(verify) => verify_manifest(verify),
+        Commands::New(settings) => {
+            if settings.list_presets {
+                print_presets();
+                return Ok(());
+            }
+            if let Some(preset) = &settings.preset {
+                println!("Using preset {}", preset);
+            }
+            warn_deprecated(&settings.deprecated_flags());
+            let rerun_script = settings.rerun_script;
+            let options = settings.options.clone();
+            let memory = settings.memory.clone();
+            let strict = settings.strict;
+            let mut settings = settings.resolve()?;
+
+            // every random stream of the run is derived from one master seed, which is kept in
+            // the rerun command so that rerunning reproduces the measurements exactly
+            let registry = settings.seed.map_or_else(RngRegistry::from_entropy, RngRegistry::new);
+            settings.seed = Some(registry.master_seed());
+
+            let findings = lint(&settings);
+            for (rule, message) in &findings {
+                println!("Warning [{}]: {}", rule, message);
+            }
+            if strict && !findings.is_empty() {
+                bail!("{} suspicious parameter combination(s) found, refusing to start in --strict mode", findings.len());
+            }
+
+            // catch mistakes in derived observables before anything is simulated
+            let derived = parse_derived(&settings)?;
+
+            // print settings to user
+            println!("Starting new simulation");
+            println!("Data will be saved in: {}", settings.name);
+            println!("Beta is set to: {}", settings.beta);
+            if let Some(gamma) = settings.gamma {
+                println!("Gamma is set to: {}", gamma);
+            }
+            match settings.width() {
+                Some(width) => println!("Lattice width is set to {}", width),
+                None => println!("Lattice extents are set to {:?}", settings.lattice_dims),
+            }
+            println!("Start configuration is {}", settings.start);
+            println!(
+                "Simulation will perform {} measurements",
+                settings.measurements
+            );
+            println!(
+                "Burn in phase is {} sweeps long",
+                settings.equilibration_sweeps
+            );
+            println!(
+                "{} sweeps will be performed in between measurements",
+                settings.sweeps_between_measurements
+            );
+            println!(
+                "Simulation will be saved every {}",
+                format_duration(Duration::from_secs(settings.interval as u64))
+            );
+            println!("Random seed is {}", registry.master_seed());
+
+            memory.check(&run_footprint(&settings), settings.lattice_dims)?;
+
+            let (_file, segment, simulation) = create_run(&settings, &derived, registry, rerun_script)?;
+            run_measurements(&segment, &settings, &derived, &options, simulation, None)?;
+            Ok(())
+        }
+        Commands::Plan(settings) => {
+            let plan = plan_run(settings)?;
+
+            println!("mean action: {}", plan.mean);
+            println!("variance of the action: {:e}", plan.variance);
+            match plan.tau {
+                Some((tau, tau_error)) => {
+                    println!("integrated autocorrelation time: {:.2} +- {:.2} sweeps", tau, tau_error)
+                }
+                None => println!("integrated autocorrelation time: undefined, the action does not fluctuate"),
+            }
+            println!("sweep rate: {:.1} sweeps per second", plan.sweeps_per_second);
+            let buildinfo = BuildInfo::collect(None);
+            println!(
+                "measured with {} ({}, opt-level {}) on {} cores of {}",
+                buildinfo.target,
+                buildinfo.profile,
+                buildinfo.opt_level,
+                buildinfo.cores,
+                buildinfo.cpu_model.as_deref().unwrap_or("an unknown cpu")
+            );
+
+            println!("recommended sweeps between measurements: {}", plan.spacing);
+            println!(
+                "required measurements: {} +- {:.0}",
+                plan.measurements, plan.measurements_uncertainty
+            );
+            println!(
+                "total sweeps: {} ({} measurements x {} sweeps)",
+                plan.total_sweeps, plan.measurements, plan.spacing
+            );
+            let wall_time_text = Duration::try_from_secs_f64(plan.wall_time)
+                .map_or_else(|_| "unknown".to_string(), format_duration);
+            println!("estimated wall time: {}", wall_time_text);
+            println!(
+                "estimated output size: {}",
+                format_size((plan.measurements * std::mem::size_of::<f64>()) as u64)
+            );
+            if (plan.config.beta - CRITICAL_BETA).abs() < CRITICAL_WINDOW {
+                println!("WARNING: beta is close to the transition, the autocorrelation time grows with the run length and these estimates are unreliable");
+            }
+
+            for (rule, message) in lint(&plan.config) {
+                println!("Warning [{}]: {}", rule, message);
+            }
+            println!("suggested command:");
+            println!("{}", plan.config.rerun_command());
+
+            Ok(())
+        }
+        Commands::Resume(resume) => {
+            let (_file, segment, settings, derived, simulation) = open_run(&resume.name, &resume.memory)?;
+            let completed = simulation.measurements;
+            if completed >= settings.measurements {
+                println!("All {} measurements are already stored in {}", settings.measurements, settings.name);
+                return Ok(());
+            }
+            println!(
+                "Resuming {} after {} measurements, {} remain",
+                settings.name,
+                completed,
+                settings.measurements - completed
+            );
+
+            run_measurements(&segment, &settings, &derived, &resume.options, simulation, None)?;
+            Ok(())
+        }
+        Commands::Step(step) => {
+            let (_file, segment, settings, derived, simulation) = if Path::new(&step.name).exists() {
+                open_run(&step.name, &step.memory)?
+            } else {
+                if let Some(from_sweep) = step.from_sweep.filter(|&from_sweep| from_sweep > 0) {
+                    bail!("{} does not exist, so the step can not start from sweep {}", step.name, from_sweep);
+                }
+                let mut settings = new_run_settings(&step.name, &step.create)?;
+                let registry = settings.seed.map_or_else(RngRegistry::from_entropy, RngRegistry::new);
+                settings.seed = Some(registry.master_seed());
+                let derived = parse_derived(&settings)?;
+                step.memory.check(&run_footprint(&settings), settings.lattice_dims)?;
+                println!("Creating {} with random seed {}", settings.name, registry.master_seed());
+                let (file, segment, simulation) = create_run(&settings, &derived, registry, false)?;
+                (file, segment, settings, derived, simulation)
+            };
+
+            if simulation.measurements >= settings.measurements {
+                println!("All {} measurements are already stored in {}", settings.measurements, settings.name);
+                return Ok(());
+            }
+            // a retried step finds the checkpoint of its first attempt
+            if let Some(from_sweep) = step.from_sweep {
+                if simulation.sweeps == from_sweep + step.sweeps {
+                    println!("{} is already at sweep {}, the step was done before", settings.name, simulation.sweeps);
+                    return Ok(());
+                }
+                if simulation.sweeps != from_sweep {
+                    bail!(
+                        "{} is at sweep {}, but the step should start from sweep {}",
+                        settings.name,
+                        simulation.sweeps,
+                        from_sweep
+                    );
+                }
+            }
+            println!("Stepping {} from sweep {} by {} sweeps", settings.name, simulation.sweeps, step.sweeps);
+
+            run_measurements(&segment, &settings, &derived, &step.options, simulation, Some(step.sweeps))?;
+            Ok(())
+        }
+        Commands::Visualize(settings) => {
+            println!("generating visualisation");
+
+            let mut lattice;
+            let start = StartFlags {
+                ordered: settings.ordered,
+                start: settings.start.as_deref(),
+                file: settings.from_cache.as_deref().map(|path| ("from-cache", path)),
+                warm_start: None,
+            }
+            .resolve()?;
+
+            if let StartSpec::File(cache) = &start {
+                lattice = read_configuration(cache)?.0;
+                println!("Loaded configuration with {} from {}", format_extents(lattice.dims()), cache);
+            } else {
+                if settings.lattice_width.is_some() {
+                    warn_deprecated(&[("lattice-width", "width")]);
+                }
+                let dims = match settings.dims {
+                    Some(dims) => dims,
+                    None => [settings.width.or(settings.lattice_width).context("--width is required")?; 4],
+                };
+                let beta = settings.beta.context("--beta is required")?;
+                let equilibration_sweeps = settings
+                    .equilibration_sweeps
+                    .context("--equilibration-sweeps is required")?;
+                validate_lattice(dims, beta)?;
+                let mut registry = settings.seed.map_or_else(RngRegistry::from_entropy, RngRegistry::new);
+                println!("Random seed is {}", registry.master_seed());
+                let mut rng = registry.stream("sweep");
+
+                let footprint = Footprint {
+                    lattices: 1,
+                    ..Default::default()
+                };
+                settings.memory.check(&footprint, dims)?;
+
+                lattice = start.build(dims, &mut registry)?;
+                if matches!(start, StartSpec::PlaneWave { .. } | StartSpec::Constant { .. }) {
+                    println!("Start configuration has average action {}", lattice.average_action());
+                }
+                if let Some((action, charge)) = start.reference_values(dims[0]) {
+                    println!("Closed form average action {}, topological charge {}", action, charge);
+                }
+
+                for _ in 0..equilibration_sweeps {
+                    lattice.heatbath_sweep(Couplings::isotropic(beta), &mut rng);
+                }
+            }
+
+            if let Some(cache) = &settings.cache_config {
+                let mut cache_file = std::fs::File::create(cache)
+                    .with_context(|| format!("Failed to create configuration cache {}", cache))?;
+                lattice.write_config(&mut cache_file)?;
+            }
+
+            match settings.gauge_fix {
+                Some(GaugeFix::Landau) => {
+                    let report = lattice.fix_landau_gauge(GAUGE_FIX_TOLERANCE, GAUGE_FIX_MAX_ITERS);
+                    println!(
+                        "Landau gauge: {} iterations, residual {:e}, converged: {}",
+                        report.iterations, report.residual, report.converged
+                    );
+                }
+                Some(GaugeFix::Coulomb) => {
+                    let reports = lattice.fix_coulomb_gauge(GAUGE_FIX_TOLERANCE, GAUGE_FIX_MAX_ITERS);
+                    for (t, report) in reports.iter().enumerate() {
+                        println!(
+                            "Coulomb gauge on slice {}: {} iterations, residual {:e}, converged: {}",
+                            t, report.iterations, report.residual, report.converged
+                        );
+                    }
+                }
+                None => {}
+            }
+
+            let mut file = std::fs::File::create(&settings.name)?;
+            if settings.plaquettes {
+                lattice.visualize_plaquettes_plane_svg(&mut file)?;
+            } else {
+                lattice.visualize_3d_lattice(&mut file)?;
+            }
+
+            Ok(())
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lattice_rust::progress::{EXIT_PANIC_WITHOUT_DATA, EXIT_PANIC_WITH_DATA};
+
+    /* a save file name in the temporary directory that no other test uses */
+    fn temp_run(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!("lattice-rust-{}-{}.h5", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        return path.display().to_string();
+    }
+
+    /* run `new` in process */
+    fn run_new(name: &str, args: &[&str]) -> Result<()> {
+        let mut command = vec![env!("CARGO_PKG_NAME"), "new", "--name", name];
+        command.extend_from_slice(args);
+        return execute(Cli::try_parse_from(command)?.command);
+    }
+
+    /* the resolved parameters of `new --name <name> <args>` */
+    fn new_settings(args: &[&str]) -> Result<RunConfig> {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        return new_run_settings("test.h5", &args);
+    }
+
+    #[test]
+    fn the_legacy_ordered_flag_clashes_with_every_newer_start() {
+        let base = ["--beta", "1.0", "--preset", "quick-test"];
+        let with = |extra: &[&str]| new_settings(&[&base[..], extra].concat());
+
+        assert_eq!(with(&[]).unwrap().start, StartSpec::Random);
+        assert_eq!(with(&["--ordered"]).unwrap().start, StartSpec::Ordered);
+        for (flag, value) in [
+            ("--start", "constant:n01=1"),
+            ("--initial-config", "start.u1l"),
+            ("--warm-start-from", "run.h5"),
+        ] {
+            assert!(with(&[flag, value]).is_ok(), "{}", flag);
+            let error = with(&["--ordered", flag, value]).unwrap_err().to_string();
+            assert!(error.contains("--ordered") && error.contains(flag), "{}", error);
+        }
+        let error = with(&["--start", "constant:n01=1", "--warm-start-from", "run.h5"]).unwrap_err();
+        assert!(error.to_string().contains("--start, --warm-start-from"), "{}", error);
+    }
+
+    #[test]
+    fn invalid_parameters_are_refused_before_any_file_is_written() {
+        let name = temp_run("refused");
+        let schedule = ["--measurements", "2", "--equilibration-sweeps", "1", "--sweeps-per-measurement", "1", "--flush-every", "60"];
+        for (args, flag) in [
+            (&["--beta", "1.0", "--width", "0"][..], "--width"),
+            (&["--beta", "1.0", "--width", "1"], "--width"),
+            (&["--beta=-3", "--width", "2"], "--beta"),
+            (&["--beta", "0", "--kappa", "0.5", "--width", "2"], "--kappa"),
+            (&["--beta", "1.0", "--width", "64", "--memory-limit", "1M"], "--memory-limit"),
+        ] {
+            let error = run_new(&name, &[args, &schedule].concat()).unwrap_err().to_string();
+            assert!(error.contains(flag), "{:?}: {}", args, error);
+            assert!(!Path::new(&name).exists(), "{:?}", args);
+        }
+        for (args, flag) in [
+            (&["--width", "1", "--beta", "1.0"][..], "--width"),
+            (&["--dims", "4,4,1,4", "--beta", "1.0"], "--dims"),
+            (&["--width", "4", "--beta=-0.5"], "--beta"),
+            (&["--width", "64", "--beta", "1.0", "--memory-limit", "1M"], "--memory-limit"),
+        ] {
+            let error = run_command(&[&["visualize", "--name", &name, "--equilibration-sweeps", "1"][..], args].concat())
+                .unwrap_err()
+                .to_string();
+            assert!(error.contains(flag), "{:?}: {}", args, error);
+        }
+    }
+
+    #[test]
+    fn every_subcommand_that_builds_a_lattice_takes_the_memory_limit() {
+        let name = temp_run("memory_limit");
+        let lattice = ["--width", "64", "--measurements", "2", "--equilibration-sweeps", "1", "--sweeps-per-measurement", "1", "--flush-every", "60"];
+        for args in [
+            &["plan", "--beta", "1.0", "--width", "64", "--target-error", "0.01", "--memory-limit", "1M"][..],
+            &[&["scan", "--name", &name, "--betas", "1.0,1.1", "--memory-limit", "1M", "--"][..], &lattice].concat(),
+            &[&["tempering", "--name", &name, "--betas", "1.0,1.1", "--memory-limit", "1M", "--"][..], &lattice].concat(),
+        ] {
+            let error = run_command(args).unwrap_err().to_string();
+            assert!(error.contains("--memory-limit"), "{:?}: {}", args, error);
+            assert!(!Path::new(&name).exists(), "{:?}", args);
+        }
+    }
+
+    #[test]
+    fn visualizing_a_cached_configuration_reproduces_the_drawing() {
+        let cache = temp_run("visualize_cache");
+        let [generated, first, second] =
+            ["generated", "first", "second"].map(|name| temp_run(&format!("visualize_{}", name)).replace(".h5", ".svg"));
+        for drawing in [&["--plaquettes"][..], &["--gauge-fix", "landau"]] {
+            let generate = ["visualize", "--name", &generated, "--width", "3", "--beta", "1.0", "--equilibration-sweeps", "2"];
+            let seeded = ["--seed", "11", "--cache-config", &cache];
+            run_command(&[&generate[..], &seeded, drawing].concat()).unwrap();
+            for svg in [&first, &second] {
+                run_command(&[&["visualize", "--name", svg, "--from-cache", &cache][..], drawing].concat()).unwrap();
+            }
+
+            let drawings = [&generated, &first, &second].map(|svg| std::fs::read(svg).unwrap());
+            assert!(!drawings[0].is_empty());
+            assert!(drawings[1] == drawings[2], "{:?}", drawing);
+            assert!(drawings[0] == drawings[1], "{:?}", drawing);
+        }
+        for path in [&cache, &generated, &first, &second] {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+
+    #[test]
+    fn presets_and_plans_lint_cleanly() {
+        for preset in PRESETS.iter() {
+            for beta in ["0.3", "1.0", "1.5"] {
+                let settings = new_settings(&["--beta", beta, "--preset", preset.name]).unwrap();
+                assert!(lint(&settings).is_empty(), "{} at beta {}: {:?}", preset.name, beta, lint(&settings));
+            }
+        }
+        for beta in ["0.3", "1.0", "1.5"] {
+            let args = ["--beta", beta, "--width", "3", "--target-error", "0.002", "--calibration-sweeps", "60", "--seed", "2"];
+            let plan = plan(&args).unwrap();
+            assert!(lint(&plan.config).is_empty(), "plan at beta {}: {:?}", beta, lint(&plan.config));
+        }
+    }
+
+    #[test]
+    fn strict_mode_refuses_a_suspicious_run() {
+        let name = temp_run("strict");
+        let schedule = ["--width", "2", "--measurements", "2", "--equilibration-sweeps", "2", "--flush-every", "60"];
+        let suspicious = ["--beta", "0.3", "--ordered", "--sweeps-per-measurement", "1"];
+
+        let error = run_new(&name, &[&schedule[..], &suspicious, &["--strict"]].concat()).unwrap_err().to_string();
+        assert!(error.contains("--strict"), "{}", error);
+        assert!(!Path::new(&name).exists());
+
+        run_new(&name, &[&schedule[..], &suspicious].concat()).unwrap();
+        assert!(Path::new(&name).exists());
+        let _ = std::fs::remove_file(&name);
+        run_new(&name, &[&schedule[..], &["--beta", "0.3", "--sweeps-per-measurement", "1", "--strict"]].concat()).unwrap();
+        let _ = std::fs::remove_file(&name);
+    }
+
+    #[test]
+    fn every_preset_passes_validation() {
+        for preset in PRESETS.iter() {
+            let settings = new_settings(&["--beta", "1.0", "--preset", preset.name]).unwrap();
+            settings.validate().unwrap();
+            assert_eq!(settings.lattice_dims, [preset.lattice_width; 4]);
+            assert_eq!(settings.measurements, preset.measurements);
+            assert_eq!(settings.equilibration_sweeps, preset.equilibration_sweeps);
+            assert_eq!(settings.sweeps_between_measurements, preset.sweeps_between_measurements);
+            assert_eq!(settings.interval, preset.interval);
+        }
+    }
+
+    #[test]
+    fn flags_take_precedence_over_the_preset() {
+        let settings = new_settings(&[
+            "--beta",
+            "1.0",
+            "--preset",
+            "production-16",
+            "--width",
+            "6",
+            "--measurements",
+            "7",
+            "--equilibration-sweeps",
+            "8",
+            "--sweeps-per-measurement",
+            "9",
+            "--flush-every",
+            "10",
+        ])
+        .unwrap();
+        assert_eq!(settings.lattice_dims, [6; 4]);
+        assert_eq!(settings.measurements, 7);
+        assert_eq!(settings.equilibration_sweeps, 8);
+        assert_eq!(settings.sweeps_between_measurements, 9);
+        assert_eq!(settings.interval, 10);
+
+        let settings = new_settings(&["--beta", "1.0", "--preset", "quick-test", "--dims", "4,4,4,2"]).unwrap();
+        assert_eq!(settings.lattice_dims, [4, 4, 4, 2]);
+    }
+
+    #[test]
+    fn a_finite_temperature_run_keeps_its_extents() {
+        let name = temp_run("dims");
+        run_new(&name, &["--beta", "1.0", "--dims", "4,4,4,2", "--measurements", "4", "--equilibration-sweeps", "2",
+            "--sweeps-per-measurement", "1", "--flush-every", "60", "--seed", "3", "--polyakov"])
+        .unwrap();
+        {
+            let segment = File::open(&name).unwrap().group("/").unwrap();
+            let dataset = segment.dataset("action_measurements").unwrap();
+            assert_eq!(dataset.attr("lattice-dims").unwrap().read_raw::<usize>().unwrap(), vec![4, 4, 4, 2]);
+            /* lattice-width only describes hypercubic runs */
+            assert!(dataset.attr("lattice-width").is_err());
+            let (_, configuration) = latest_checkpoint(&segment).unwrap().unwrap();
+            assert_eq!(configuration.shape(), vec![4, 4, 4, 2, 4]);
+            let series = read_action_series(&segment).unwrap();
+            assert!(series.iter().all(|action| (0.0..2.0).contains(action)), "{:?}", series);
+            assert_eq!(stored_settings(&segment).unwrap().lattice_dims, [4, 4, 4, 2]);
+        }
+        let (_file, _, settings, _, simulation) = open_run(&name, &MemoryCheck::default()).unwrap();
+        assert_eq!((settings.lattice_dims, simulation.lattice.dims()), ([4, 4, 4, 2], [4, 4, 4, 2]));
+        let _ = std::fs::remove_file(&name);
+    }
+
+    #[test]
+    fn equal_spatial_and_temporal_couplings_reproduce_the_isotropic_run() {
+        let common = ["--measurements", "6", "--equilibration-sweeps", "3", "--sweeps-per-measurement", "2",
+            "--flush-every", "60", "--seed", "17", "--lattice-width", "4"];
+        let isotropic = temp_run("isotropic");
+        run_new(&isotropic, &[&["--beta", "1.05"][..], &common].concat()).unwrap();
+        let anisotropic = temp_run("anisotropic");
+        run_new(&anisotropic, &[&["--beta", "1.05", "--beta-spatial", "1.05", "--beta-temporal", "1.05"][..], &common].concat())
+            .unwrap();
+
+        let series = |name: &str| {
+            let segment = File::open(name).unwrap().group("/").unwrap();
+            let dataset = segment.dataset("action_measurements").unwrap();
+            let couplings: (f64, f64) = (
+                read_attribute(&dataset, "beta-spatial").unwrap(),
+                read_attribute(&dataset, "beta-temporal").unwrap(),
+            );
+            let bits: Vec<u64> = read_action_series(&segment).unwrap().iter().map(|action| action.to_bits()).collect();
+            return (couplings, bits);
+        };
+        let (isotropic_couplings, isotropic_bits) = series(&isotropic);
+        let (anisotropic_couplings, anisotropic_bits) = series(&anisotropic);
+        assert_eq!(isotropic_couplings, (1.05, 1.05));
+        assert_eq!(anisotropic_couplings, (1.05, 1.05));
+        assert_eq!(isotropic_bits.len(), 6);
+        assert_eq!(isotropic_bits, anisotropic_bits);
+
+        /* a differing temporal coupling changes the chain and is stored as given */
+        let weighted = temp_run("weighted");
+        run_new(&weighted, &[&["--beta", "1.05", "--beta-temporal", "1.4"][..], &common].concat()).unwrap();
+        let (weighted_couplings, weighted_bits) = series(&weighted);
+        assert_eq!(weighted_couplings, (1.05, 1.4));
+        assert_ne!(weighted_bits, isotropic_bits);
+        for name in [isotropic, anisotropic, weighted] {
+            let _ = std::fs::remove_file(&name);
+        }
+    }
+
+    #[test]
+    fn the_coarse_update_needs_blocks_that_divide_the_lattice() {
+        let settings = new_settings(&["--beta", "1.0", "--preset", "quick-test", "--coarse-update", "2,0.1"]).unwrap();
+        assert_eq!(settings.coarse_update, Some((2, 0.1)));
+        assert!(new_settings(&["--beta", "1.0", "--preset", "quick-test", "--coarse-update", "3,0.1"]).is_err());
+        assert!(new_settings(&["--beta", "1.0", "--preset", "quick-test", "--coarse-update", "2,0"]).is_err());
+        assert!(new_settings(&["--beta", "1.0", "--preset", "quick-test", "--coarse-update", "2"]).is_err());
+    }
+
+    #[test]
+    fn matter_needs_a_positive_beta() {
+        let error = new_settings(&["--beta", "0", "--kappa", "0.5", "--preset", "quick-test"]).unwrap_err();
+        assert!(error.to_string().contains("--kappa"), "{}", error);
+        new_settings(&["--beta", "0", "--preset", "quick-test"]).unwrap();
+        new_settings(&["--beta", "0.1", "--kappa", "0.5", "--preset", "quick-test"]).unwrap();
+    }
+
+    #[test]
+    fn integrated_links_need_wilson_loops_of_the_isotropic_wilson_action() {
+        assert!(new_settings(&["--beta", "1.0", "--preset", "quick-test", "--integrated-links"]).is_err());
+        let error = new_settings(&["--beta", "1.0", "--preset", "quick-test", "--wilson-loops", "2", "--integrated-links",
+            "--gamma", "0.1"])
+        .unwrap_err();
+        assert!(error.to_string().contains("--integrated-links"), "{}", error);
+
+        let name = temp_run("integrated-links");
+        run_new(&name, &["--beta", "1.0", "--preset", "quick-test", "--measurements", "5", "--wilson-loops", "2",
+            "--integrated-links", "--seed", "3"])
+        .unwrap();
+        let file = File::open(&name).unwrap();
+        let loops = file.dataset("wilson_loops").unwrap();
+        assert!(read_attribute::<bool>(&loops, "integrated-temporal-links").unwrap());
+        assert_eq!(loops.shape(), vec![5, 2, 2]);
+        let _ = std::fs::remove_file(&name);
+    }
+
+    #[test]
+    fn the_stored_unit_wilson_loop_is_one_minus_the_action() {
+        let name = temp_run("wilson-loops");
+        /* on width 2 every loop but the plaquette is larger than half the lattice */
+        run_new(&name, &["--beta", "1.0", "--width", "2", "--measurements", "6", "--equilibration-sweeps", "3",
+            "--sweeps-per-measurement", "1", "--flush-every", "60", "--wilson-loops", "3"])
+        .unwrap();
+        let file = File::open(&name).unwrap();
+        let loops = file.dataset("wilson_loops").unwrap();
+        assert_eq!(loops.shape(), vec![6, 3, 3]);
+        let loops = loops.read_raw::<f64>().unwrap();
+        let action = file.dataset("action_measurements").unwrap().read_raw::<f64>().unwrap();
+        for (i, action) in action.iter().enumerate() {
+            assert!((loops[9 * i] - (1.0 - action)).abs() < 1e-14);
+            /* the 2 x 2 loop covers the whole plane and retraces its sides */
+            assert!((loops[9 * i + 4] - 1.0).abs() < 1e-14);
+        }
+        let _ = std::fs::remove_file(&name);
+    }
+
+    #[test]
+    fn the_stored_polyakov_loop_is_consistent() {
+        let name = temp_run("polyakov");
+        run_new(&name, &["--beta", "1.5", "--width", "2", "--measurements", "7", "--equilibration-sweeps", "3",
+            "--sweeps-per-measurement", "1", "--flush-every", "60", "--polyakov"])
+        .unwrap();
+        let file = File::open(&name).unwrap();
+        let [magnitude, real, imaginary] =
+            POLYAKOV_DATASETS.map(|dataset| file.dataset(dataset).unwrap().read_raw::<f64>().unwrap());
+        assert_eq!(magnitude.len(), 7);
+        for i in 0..7 {
+            assert!((magnitude[i] - real[i].hypot(imaginary[i])).abs() < 1e-14);
+            assert!(magnitude[i] <= 1.0 + 1e-14);
+        }
+        let _ = std::fs::remove_file(&name);
+    }
+
+    #[test]
+    fn exported_configurations_start_new_runs() {
+        let source = temp_run("export-source");
+        run_new(&source, &["--beta", "1.0", "--width", "3", "--measurements", "4", "--equilibration-sweeps", "2",
+            "--sweeps-per-measurement", "1", "--flush-every", "60"])
+        .unwrap();
+        let (_, checkpoint) = latest_checkpoint(&File::open(&source).unwrap().group("/").unwrap()).unwrap().unwrap();
+        let stored = checkpoint.read_raw::<f64>().unwrap();
+
+        for format in ["portable", "cache"] {
+            let output = std::env::temp_dir().join(format!("lattice-rust-export-{}-{}", format, std::process::id()));
+            let output = output.display().to_string();
+            run_command(&["export", "--name", &source, "--output", &output, "--format", format]).unwrap();
+            let (lattice, metadata) = read_configuration(&output).unwrap();
+            assert_eq!(lattice.to_array(), stored);
+            assert_eq!(metadata.is_some(), format == "portable");
+            if let Some(metadata) = metadata {
+                assert!(metadata.contains("\"sweeps\":6,\"measurements\":4"), "{}", metadata);
+            }
+
+            let started = temp_run(&format!("export-started-{}", format));
+            let args = ["--beta", "1.0", "--measurements", "2", "--equilibration-sweeps", "0", "--sweeps-per-measurement", "1",
+                "--flush-every", "60", "--initial-config", &output];
+            assert!(run_new(&started, &[&["--width", "4"], &args[..]].concat()).is_err());
+            let _ = std::fs::remove_file(&started);
+            run_new(&started, &[&["--width", "3"], &args[..]].concat()).unwrap();
+            let _ = std::fs::remove_file(&started);
+            let _ = std::fs::remove_file(&output);
+        }
+        let _ = std::fs::remove_file(&source);
+    }
+
+    #[test]
+    fn derived_observables_are_recorded_and_checked_before_the_run() {
+        let name = temp_run("derive-unknown");
+        let error = run_new(&name, &["--beta", "1.0", "--preset", "quick-test", "--derive", "x=topological_charge"])
+            .unwrap_err();
+        assert!(error.to_string().contains("topological_charge"), "{}", error);
+        assert!(!std::path::Path::new(&name).exists());
+
+        let name = temp_run("derive");
+        run_new(&name, &["--beta", "1.0", "--preset", "quick-test", "--measurements", "6", "--topological-charge",
+            "--derive", "chi=topological_charge^2", "--derive", "ratio=topological_charge / (1 + action)"])
+        .unwrap();
+        let file = File::open(&name).unwrap();
+        let action = file.dataset("action_measurements").unwrap().read_raw::<f64>().unwrap();
+        let charge = file.dataset("topological_charge").unwrap().read_raw::<f64>().unwrap();
+        let chi = file.dataset("derived_chi").unwrap();
+        assert_eq!(read_string_attribute(&chi, "expression").unwrap(), "topological_charge^2");
+        let chi = chi.read_raw::<f64>().unwrap();
+        let ratio = file.dataset("derived_ratio").unwrap().read_raw::<f64>().unwrap();
+        assert_eq!(chi.len(), 6);
+        for i in 0..6 {
+            assert_eq!(chi[i], charge[i].powi(2));
+            assert_eq!(ratio[i], charge[i] / (1.0 + action[i]));
+        }
+        let _ = std::fs::remove_file(&name);
+    }
+
+    /* run any subcommand in process */
+    fn run_command(args: &[&str]) -> Result<()> {
+        let mut command = vec![env!("CARGO_PKG_NAME")];
+        command.extend_from_slice(args);
+        return execute(Cli::try_parse_from(command)?.command);
+    }
+
+    /* run the calibration of `plan` in process */
+    fn plan(args: &[&str]) -> Result<RunPlan> {
+        let command = Cli::try_parse_from([&[env!("CARGO_PKG_NAME"), "plan"][..], args].concat())?.command;
+        let Commands::Plan(settings) = command else {
+            unreachable!();
+        };
+        return plan_run(settings);
+    }
+
+    #[test]
+    fn a_seeded_calibration_plans_the_same_run() {
+        let calibration = ["--beta", "1.0", "--width", "3", "--target-error", "0.002", "--calibration-sweeps", "60"];
+        let [first, second, other] =
+            ["5", "5", "6"].map(|seed| plan(&[&calibration[..], &["--equilibration-sweeps", "20", "--seed", seed]].concat()).unwrap());
+
+        for plan in [&first, &second, &other] {
+            assert_eq!(plan.measurements * plan.spacing, plan.total_sweeps);
+            assert_eq!(plan.config.measurements, plan.measurements);
+            assert_eq!(plan.config.sweeps_between_measurements, plan.spacing);
+            assert!(plan.tau.is_some());
+        }
+        assert_eq!(first.mean.to_bits(), second.mean.to_bits());
+        assert_eq!(first.variance.to_bits(), second.variance.to_bits());
+        let bits = |plan: &RunPlan| plan.tau.map(|(tau, error)| (tau.to_bits(), error.to_bits()));
+        assert_eq!(bits(&first), bits(&second));
+        assert_eq!((first.spacing, first.measurements), (second.spacing, second.measurements));
+        assert_eq!(first.measurements_uncertainty.to_bits(), second.measurements_uncertainty.to_bits());
+        assert_ne!(first.mean, other.mean);
+    }
+
+    #[test]
+    fn a_hysteresis_scan_far_from_the_transition_agrees_between_branches() {
+        let name = temp_run("hysteresis");
+        /* deep in the confined phase both starts reach equilibrium within the equilibration */
+        run_command(&["scan", "--name", &name, "--betas", "0.2,0.3", "--hysteresis", "--", "--width", "4",
+            "--measurements", "200", "--equilibration-sweeps", "30", "--sweeps-per-measurement", "1",
+            "--flush-every", "60", "--seed", "23"])
+        .unwrap();
+
+        let file = File::open(&name).unwrap();
+        let root = file.group("/").unwrap();
+        let discrepancy_beta = root.attr("max-discrepancy-beta").unwrap().read_raw::<f64>().unwrap()[0];
+        assert!(discrepancy_beta == 0.2 || discrepancy_beta == 0.3, "{}", discrepancy_beta);
+        let mut branches = Vec::new();
+        let mut seeds = Vec::new();
+        for (branch, start, first) in [("heating", "ordered", "beta_0.300"), ("cooling", "random", "beta_0.200")] {
+            let group = file.group(branch).unwrap();
+            let stored_start = group.attr("start").unwrap().read_raw::<VarLenUnicode>().unwrap();
+            assert_eq!(stored_start[0].as_str(), start);
+            let settings = stored_settings(&group.group(first).unwrap()).unwrap();
+            assert_eq!(settings.start.to_string(), start);
+            let mut points = Vec::new();
+            for beta in [0.2, 0.3] {
+                let segment = group.group(&group_name(beta)).unwrap();
+                seeds.push(stored_settings(&segment).unwrap().seed.unwrap());
+                points.push(ScanPoint::new(beta, &read_action_series(&segment).unwrap()));
+            }
+            branches.push(points);
+        }
+        /* every coupling of every branch draws its own stream */
+        let mut distinct = seeds.clone();
+        distinct.sort_unstable();
+        distinct.dedup();
+        assert_eq!(distinct.len(), seeds.len(), "{:?}", seeds);
+
+        for (heated, cooled) in branches[0].iter().zip(&branches[1]) {
+            let combined = heated.error.unwrap().hypot(cooled.error.unwrap());
+            assert!((heated.mean - cooled.mean).abs() < 4.0 * combined, "{:?} {:?}", heated, cooled);
+        }
+        let discrepancy = max_discrepancy(&branches[0], &branches[1]).unwrap();
+        assert_eq!(discrepancy.beta, discrepancy_beta);
+        let stored = root.attr("max-discrepancy").unwrap().read_raw::<f64>().unwrap()[0];
+        assert_eq!(stored.to_bits(), discrepancy.difference.to_bits());
+        assert!(discrepancy.significance.unwrap() < 4.0);
+
+        /* the branches fix their own starts */
+        let refused = temp_run("hysteresis-start");
+        assert!(run_command(&["scan", "--name", &refused, "--betas", "0.2,0.3", "--hysteresis", "--", "--width", "2",
+            "--measurements", "2", "--ordered"])
+        .is_err());
+        assert!(!std::path::Path::new(&refused).exists());
+        let _ = std::fs::remove_file(&name);
+    }
+
+    #[test]
+    fn the_sidecar_agrees_with_the_completed_run() {
+        let name = temp_run("sidecar");
+        run_new(&name, &["--beta", "0.95", "--width", "2", "--measurements", "9", "--equilibration-sweeps", "3",
+            "--sweeps-per-measurement", "2", "--flush-every", "60", "--seed", "8", "--polyakov", "--sidecar"])
+        .unwrap();
+
+        let sidecar = read_sidecar(&name).unwrap();
+        let number = |key: &str| sidecar.get(key).unwrap().to_json().parse::<f64>().unwrap();
+        let segment = File::open(&name).unwrap().group("/").unwrap();
+        let settings = stored_settings(&segment).unwrap();
+        let series = read_action_series(&segment).unwrap();
+
+        assert_eq!(sidecar.get("config").unwrap().to_json(), settings.to_json());
+        assert_eq!(sidecar.get("complete"), Some(&Json::Bool(true)));
+        assert_eq!(number("completed_measurements") as usize, settings.measurements);
+        assert_eq!(number("saved_measurements") as usize, series.len());
+        assert_eq!(series.len(), 9);
+        assert!((number("mean_action") - analysis::mean(&series)).abs() < 1e-14);
+        let variance = series.iter().map(|action| (action - analysis::mean(&series)).powi(2)).sum::<f64>() / 9.0;
+        assert!((number("action_standard_deviation") - variance.sqrt()).abs() < 1e-9);
+
+        /* the summary attributes of the closed segment tell the same */
+        close_segment(&segment).unwrap();
+        let dataset = segment.dataset("action_measurements").unwrap();
+        assert_eq!(read_attribute::<usize>(&dataset, "summary-measurements").unwrap(), 9);
+        let mean_action = read_attribute::<f64>(&dataset, "summary-mean-action").unwrap();
+        assert!((number("mean_action") - mean_action).abs() < 1e-14);
+
+        run_command(&["info", "--name", &name, "--from-sidecar"]).unwrap();
+        assert!(run_command(&["info", "--name", &name, "--from-sidecar", "--rerun-command"]).is_err());
+        std::fs::remove_file(sidecar_path(&name)).unwrap();
+        assert!(run_command(&["info", "--name", &name, "--from-sidecar"]).is_err());
+        let _ = std::fs::remove_file(&name);
+    }
+
+    #[test]
+    fn a_panicking_observable_reports_how_far_the_run_got() {
+        let args = ["--beta", "1.0", "--width", "2", "--measurements", "10", "--equilibration-sweeps", "4",
+            "--sweeps-per-measurement", "2", "--flush-every", "60", "--seed", "5"];
+        let aborted = |result: Result<()>| result.unwrap_err().downcast::<Aborted>().unwrap().progress;
+        let counters = |progress: &Progress| {
+            return (
+                progress.sweeps.load(Ordering::Relaxed),
+                progress.measurements.load(Ordering::Relaxed),
+                progress.saved_measurements.load(Ordering::Relaxed),
+            );
+        };
+        let reference = temp_run("panic-reference");
+        run_new(&reference, &args).unwrap();
+        let reference_series = read_action_series(&File::open(&reference).unwrap().group("/").unwrap()).unwrap();
+
+        /* a panic in the first measurement leaves nothing on disk */
+        let empty = temp_run("panic-empty");
+        let progress = aborted(run_new(&empty, &[&args[..], &["--panic-at-measurement", "0"]].concat()));
+        assert_eq!(counters(&progress), (6, 0, 0));
+        assert_eq!((progress.checkpoint(), progress.exit_code()), (None, EXIT_PANIC_WITHOUT_DATA));
+        assert!(read_action_series(&File::open(&empty).unwrap().group("/").unwrap()).unwrap().is_empty());
+
+        /* the measurements buffered since the last save are flushed and counted */
+        let buffered = temp_run("panic-buffered");
+        let progress = aborted(run_new(&buffered, &[&args[..], &["--panic-at-measurement", "6"]].concat()));
+        assert_eq!(counters(&progress), (18, 6, 6));
+        assert_eq!((progress.checkpoint(), progress.exit_code()), (None, EXIT_PANIC_WITH_DATA));
+        let series = read_action_series(&File::open(&buffered).unwrap().group("/").unwrap()).unwrap();
+        assert_eq!(series, reference_series[..6]);
+        assert_eq!(
+            progress.panic_report(&buffered)[4..],
+            ["6 measurements were saved before the panic", "no configuration checkpoint is stored, the run cannot be resumed"]
+        );
+
+        /* a resumed run names the checkpoint it started from */
+        let resumed = temp_run("panic-resumed");
+        let mut step = vec!["step", "--name", resumed.as_str(), "--sweeps", "9", "--"];
+        step.extend_from_slice(&args);
+        run_command(&step).unwrap();
+        let progress = aborted(run_command(&["resume", "--name", &resumed, "--panic-at-measurement", "5"]));
+        assert_eq!(counters(&progress), (16, 5, 5));
+        assert_eq!((progress.checkpoint(), progress.exit_code()), (Some(2), EXIT_PANIC_WITH_DATA));
+        assert_eq!(
+            progress.panic_report(&resumed)[5],
+            "the configuration after 2 measurements is stored, resume continues from there"
+        );
+        for name in [reference, empty, buffered, resumed] {
+            let _ = std::fs::remove_file(&name);
+        }
+    }
+
+    #[test]
+    fn a_resumed_run_matches_the_uninterrupted_one() {
+        let base = ["--beta", "1.0", "--width", "3", "--measurements", "12", "--equilibration-sweeps", "5",
+            "--sweeps-per-measurement", "2", "--flush-every", "60", "--seed", "11", "--polyakov", "--monopoles"];
+        for matter in [&[][..], &["--kappa", "0.4"]] {
+            resume_matches_the_uninterrupted_run(&[&base[..], matter].concat());
+        }
+        assert!(run_command(&["resume", "--name", &temp_run("resume-missing")]).is_err());
+    }
+
+    fn resume_matches_the_uninterrupted_run(args: &[&str]) {
+        let kappa = args.contains(&"--kappa");
+        let uninterrupted = temp_run("resume-reference");
+        run_new(&uninterrupted, args).unwrap();
+
+        /* stop after the burn in and half of the measurements, then continue from the checkpoint */
+        let interrupted = temp_run("resume-interrupted");
+        let mut step = vec!["step", "--name", interrupted.as_str(), "--sweeps", "17", "--"];
+        step.extend_from_slice(args);
+        run_command(&step).unwrap();
+        assert_eq!(File::open(&interrupted).unwrap().dataset("action_measurements").unwrap().size(), 6);
+        run_command(&["resume", "--name", &interrupted]).unwrap();
+        /* a finished run has nothing left to resume */
+        run_command(&["resume", "--name", &interrupted]).unwrap();
+
+        let (reference, resumed) = (File::open(&uninterrupted).unwrap(), File::open(&interrupted).unwrap());
+        let mut names = vec!["action_measurements", "monopole_density"];
+        names.extend(POLYAKOV_DATASETS);
+        if kappa {
+            names.push("hopping_measurements");
+        }
+        for name in names {
+            let expected = reference.dataset(name).unwrap().read_raw::<f64>().unwrap();
+            assert_eq!(expected.len(), 12);
+            assert_eq!(resumed.dataset(name).unwrap().read_raw::<f64>().unwrap(), expected, "{}", name);
+        }
+        let configuration = |file: &File| {
+            let segment = file.group("/").unwrap();
+            let (slot, checkpoint) = latest_checkpoint(&segment).unwrap().unwrap();
+            let scalar = segment.link_exists(&scalar_slot(slot)).then(|| {
+                let phases = segment.dataset(&scalar_slot(slot)).unwrap().read_raw::<f64>().unwrap();
+                (phases, read_attribute::<u64>(&checkpoint, "scalar-rng-state").unwrap())
+            });
+            (read_attribute::<usize>(&checkpoint, "sweeps").unwrap(), checkpoint.read_raw::<f64>().unwrap(), scalar)
+        };
+        assert_eq!(configuration(&resumed), configuration(&reference));
+        assert_eq!(configuration(&reference).0, 29);
+        assert_eq!(configuration(&reference).2.is_some(), kappa);
+
+        let _ = std::fs::remove_file(&uninterrupted);
+        let _ = std::fs::remove_file(&interrupted);
+    }
+
+    #[test]
+    fn a_resumed_targeted_run_keeps_its_frozen_threshold() {
+        let args = ["--beta", "0.95", "--width", "3", "--measurements", "10", "--equilibration-sweeps", "6",
+            "--sweeps-per-measurement", "2", "--flush-every", "60", "--seed", "5", "--monopoles",
+            "--targeted-fraction", "0.3", "--targeted-refresh", "2"];
+        let uninterrupted = temp_run("targeted-reference");
+        run_new(&uninterrupted, &args).unwrap();
+        let interrupted = temp_run("targeted-interrupted");
+        let mut step = vec!["step", "--name", interrupted.as_str(), "--sweeps", "13", "--"];
+        step.extend_from_slice(&args);
+        run_command(&step).unwrap();
+
+        /* the threshold of the last refresh of the burn in, sweep 6, is stored with the checkpoint */
+        let threshold = |file: &File| {
+            let (_, checkpoint) = latest_checkpoint(&file.group("/").unwrap()).unwrap().unwrap();
+            read_attribute::<f64>(&checkpoint, "targeted-threshold").unwrap()
+        };
+        let stopped = threshold(&File::open(&interrupted).unwrap());
+        assert!(stopped.is_finite() && stopped > 0.0);
+        run_command(&["resume", "--name", &interrupted]).unwrap();
+
+        let (reference, resumed) = (File::open(&uninterrupted).unwrap(), File::open(&interrupted).unwrap());
+        assert_eq!(threshold(&reference), stopped);
+        assert_eq!(threshold(&resumed), stopped);
+        for name in ["action_measurements", "monopole_density"] {
+            let expected = reference.dataset(name).unwrap().read_raw::<f64>().unwrap();
+            assert_eq!(resumed.dataset(name).unwrap().read_raw::<f64>().unwrap(), expected, "{}", name);
+        }
+        let _ = std::fs::remove_file(&uninterrupted);
+        let _ = std::fs::remove_file(&interrupted);
+    }
+
+    #[test]
+    fn topological_sectors_count_every_stored_charge() {
+        let name = temp_run("sectors");
+        let step = ["step", "--name", name.as_str(), "--sweeps", "30", "--", "--beta", "0.8", "--width", "3",
+            "--measurements", "40", "--equilibration-sweeps", "10", "--sweeps-per-measurement", "1", "--flush-every", "60",
+            "--seed", "4", "--topological-charge"];
+        run_command(&step).unwrap();
+        run_command(&["resume", "--name", &name]).unwrap();
+
+        let file = File::open(&name).unwrap();
+        let charges = file.dataset("topological_charge").unwrap().read_raw::<f64>().unwrap();
+        let sectors = read_histogram(&file.dataset(SECTOR_DATASET).unwrap()).unwrap();
+        assert_eq!(sectors.total(), 40);
+        let mut expected = StreamingHistogram::new(1.0, -0.5);
+        for charge in &charges {
+            expected.insert(*charge);
+        }
+        assert_eq!(sectors, expected);
+        for (index, count) in sectors.counts().iter().enumerate() {
+            let sector = (sectors.first_bin() + index as i64) as f64;
+            assert_eq!(*count, charges.iter().filter(|charge| charge.round() == sector).count() as u64);
+        }
+        let _ = std::fs::remove_file(&name);
+    }
+
+    #[test]
+    fn the_latency_histogram_counts_every_sweep() {
+        let name = temp_run("latency");
+        run_new(&name, &["--beta", "0.8", "--width", "3", "--measurements", "8", "--equilibration-sweeps", "10",
+            "--sweeps-per-measurement", "1", "--flush-every", "60", "--latency-histogram"]).unwrap();
+
+        let dataset = File::open(&name).unwrap().dataset(LATENCY_DATASET).unwrap();
+        let latency = read_histogram(&dataset).unwrap();
+        assert_eq!((latency.bin_width(), latency.origin()), (0.25, 0.0));
+        assert_eq!(latency.total(), 18);
+        assert_eq!(latency.non_finite(), 0);
+        assert!(read_attribute::<u64>(&dataset, "interference-events").unwrap() <= 18);
+        let _ = std::fs::remove_file(&name);
+    }
+
+    #[test]
+    fn checkpoints_restore_the_configuration_bit_for_bit() {
+        let name = temp_run("checkpoint");
+        let file = File::create_excl(&name).unwrap();
+        let segment = file.group("/").unwrap();
+        let mut rng = Rng::with_seed(6);
+        let lattice = Lattice::new_random_dims([3, 2, 2, 4], &mut rng);
+        let mut simulation = Simulation::new(lattice, Couplings::isotropic(1.2), rng);
+        let stored = |segment: &Group| {
+            let (slot, checkpoint) = latest_checkpoint(segment).unwrap().unwrap();
+            let lattice = Lattice::from_array_dims([3, 2, 2, 4], &checkpoint.read_raw::<f64>().unwrap()).unwrap();
+            (slot, read_attribute::<usize>(&checkpoint, "sweeps").unwrap(), lattice.to_array())
+        };
+        let bits = |phases: &[f64]| phases.iter().map(|phase| phase.to_bits()).collect::<Vec<u64>>();
+
+        write_checkpoint(&segment, &simulation).unwrap();
+        let first = simulation.lattice.to_array();
+        let (first_slot, sweeps, restored) = stored(&segment);
+        assert_eq!((first_slot, sweeps), (CHECKPOINT_SLOTS[0], 0));
+        assert_eq!(bits(&restored), bits(&first));
+
+        /* the next checkpoint goes to the other slot and leaves the first one intact */
+        simulation.sweep();
+        write_checkpoint(&segment, &simulation).unwrap();
+        let (second_slot, sweeps, restored) = stored(&segment);
+        assert_eq!((second_slot, sweeps), (CHECKPOINT_SLOTS[1], 1));
+        assert_eq!(bits(&restored), bits(&simulation.lattice.to_array()));
+
+        /* a write interrupted before it was marked complete falls back to the older checkpoint */
+        write_attribute(&segment.dataset(second_slot).unwrap(), "complete", false).unwrap();
+        let (slot, sweeps, restored) = stored(&segment);
+        assert_eq!((slot, sweeps), (first_slot, 0));
+        assert_eq!(bits(&restored), bits(&first));
+
+        drop(file);
+        let _ = std::fs::remove_file(&name);
+    }
+
+    #[test]
+    fn every_measurement_is_stored_when_the_run_ends_between_saves() {
+        for (measurements, flush_every) in [("7", "60"), ("13", "1")] {
+            let name = temp_run("stored-count");
+            run_new(&name, &["--beta", "1.0", "--width", "2", "--measurements", measurements, "--equilibration-sweeps", "3",
+                "--sweeps-per-measurement", "1", "--flush-every", flush_every, "--seed", "8"])
+            .unwrap();
+            let file = File::open(&name).unwrap();
+            let stored = read_action_series(&file.group("/").unwrap()).unwrap();
+            assert_eq!(stored.len().to_string(), measurements);
+            assert!(stored.iter().all(|action| *action > 0.0));
+            let (_, checkpoint) = latest_checkpoint(&file.group("/").unwrap()).unwrap().unwrap();
+            assert_eq!(read_attribute::<usize>(&checkpoint, "measurements").unwrap().to_string(), measurements);
+            let _ = std::fs::remove_file(&name);
+        }
+    }
+
+    #[test]
+    fn buffered_measurements_reach_every_dataset_only_at_a_flush() {
+        let name = temp_run("buffers");
+        let file = File::create_excl(&name).unwrap();
+        let segment = file.group("/").unwrap();
+        segment.new_dataset::<f64>().chunk(4).shape(0..).create("scalar").unwrap();
+        segment.new_dataset::<f64>().chunk((1, 2)).shape((0.., 2)).create("row").unwrap();
+        segment.new_dataset::<f64>().chunk((1, 2, 2)).shape((0.., 2, 2)).create("square").unwrap();
+
+        let mut buffers = MeasurementBuffers::default();
+        let columns = ["scalar", "row", "square"].map(|name| buffers.open(&segment, name).unwrap());
+        for measurement in 0..3 {
+            let value = measurement as f64;
+            buffers.push(columns[0], &[value]);
+            buffers.push(columns[1], &[value, -value]);
+            buffers.push(columns[2], &[value, 1.0, 2.0, 3.0]);
+        }
+        assert_eq!(buffers.rows(columns[1]), &[0.0, -0.0, 1.0, -1.0, 2.0, -2.0]);
+        assert_eq!(segment.dataset("square").unwrap().shape(), vec![0, 2, 2]);
+
+        buffers.flush(0).unwrap();
+        buffers.push(columns[0], &[3.0]);
+        buffers.push(columns[1], &[3.0, -3.0]);
+        buffers.push(columns[2], &[3.0, 1.0, 2.0, 3.0]);
+        buffers.flush(3).unwrap();
+        assert!(buffers.rows(columns[0]).is_empty());
+        /* an empty buffer leaves its dataset alone */
+        buffers.flush(4).unwrap();
+
+        assert_eq!(segment.dataset("scalar").unwrap().read_raw::<f64>().unwrap(), vec![0.0, 1.0, 2.0, 3.0]);
+        assert_eq!(segment.dataset("row").unwrap().shape(), vec![4, 2]);
+        assert_eq!(segment.dataset("row").unwrap().read_raw::<f64>().unwrap()[4..], [2.0, -2.0, 3.0, -3.0]);
+        let square = segment.dataset("square").unwrap();
+        assert_eq!(square.shape(), vec![4, 2, 2]);
+        assert_eq!(square.read_raw::<f64>().unwrap()[12..], [3.0, 1.0, 2.0, 3.0]);
+
+        drop(file);
+        let _ = std::fs::remove_file(&name);
+    }
+
+    #[test]
+    fn every_observable_has_a_row_per_measurement() {
+        let name = temp_run("all-observables");
+        run_new(&name, &["--beta", "1.0", "--width", "2", "--measurements", "9", "--equilibration-sweeps", "2",
+            "--sweeps-per-measurement", "1", "--flush-every", "60", "--seed", "12", "--polyakov", "--monopoles",
+            "--topological-charge", "--plane-resolved", "--region-blocks", "2", "--wilson-loops", "2",
+            "--gamma", "0.1", "--derive", "sum=action + double_action"])
+        .unwrap();
+        let file = File::open(&name).unwrap();
+        let mut names = vec!["action_measurements", "double_action_measurements", "monopole_density",
+            "topological_charge", "plane_action", "region_plaquette_averages", "wilson_loops", "derived_sum"];
+        names.extend(POLYAKOV_DATASETS);
+        names.extend(PLANE_AVERAGE_DATASETS);
+        for dataset in names {
+            assert_eq!(file.dataset(dataset).unwrap().shape()[0], 9, "{}", dataset);
+        }
+        let action = file.dataset("action_measurements").unwrap().read_raw::<f64>().unwrap();
+        let double_action = file.dataset("double_action_measurements").unwrap().read_raw::<f64>().unwrap();
+        let sum = file.dataset("derived_sum").unwrap().read_raw::<f64>().unwrap();
+        for i in 0..9 {
+            assert_eq!(sum[i], action[i] + double_action[i]);
+        }
+        let _ = std::fs::remove_file(&name);
+    }
+
+    #[test]
+    fn a_frozen_run_has_no_fluctuations_and_analyzes_cleanly() {
+        let name = temp_run("frozen");
+        run_new(&name, &["--beta", "1.0", "--width", "2", "--measurements", "16", "--equilibration-sweeps", "3",
+            "--sweeps-per-measurement", "1", "--flush-every", "60", "--seed", "4", "--frozen", "--polyakov",
+            "--monopoles", "--topological-charge", "--plane-resolved", "--region-blocks", "2", "--wilson-loops", "2"])
+        .unwrap();
+        let file = File::open(&name).unwrap();
+        let mut names = vec!["action_measurements", "monopole_density", "topological_charge", "plane_action",
+            "region_plaquette_averages", "wilson_loops"];
+        names.extend(POLYAKOV_DATASETS);
+        for dataset in names {
+            let dataset = file.dataset(dataset).unwrap();
+            let row_length: usize = dataset.shape()[1..].iter().product();
+            let values = dataset.read_raw::<f64>().unwrap();
+            assert_eq!(values.len(), 16 * row_length);
+            assert!(values.chunks(row_length).all(|row| row == &values[..row_length]), "{}", dataset.name());
+        }
+
+        let RunAnalysis { segments, .. } = RunAnalysis::new(&file, true).unwrap();
+        let [(result, _)] = segments.as_slice() else { panic!("{} segments", segments.len()) };
+        assert_eq!(analysis::variance(&read_action_series(&file.group("/").unwrap()).unwrap()), 0.0);
+        assert_eq!(result.naive_error, Some(0.0));
+        assert!(!result.jackknife.is_empty() && result.jackknife.iter().all(|&(_, _, error)| error == 0.0));
+        assert!(result.two_way.iter().all(|(_, _, jackknife)| jackknife.time == 0.0 && jackknife.region.is_finite()));
+        assert!(matches!(result.autocorrelation, Some(None)));
+        let json = result.json_fields();
+        assert!(json.contains("\"autocorrelation\":null") && !json.contains("NaN") && !json.contains("inf"), "{}", json);
+
+        drop(file);
+        run_command(&["analyze", "--name", &name, "--autocorr"]).unwrap();
+        run_command(&["analyze", "--name", &name, "--autocorr", "--json"]).unwrap();
+        let _ = std::fs::remove_file(&name);
+    }
+
+    #[test]
+    fn the_stored_plane_actions_average_to_the_action() {
+        let name = temp_run("plane-resolved");
+        run_new(&name, &["--beta", "1.0", "--dims", "4,2,2,2", "--beta-temporal", "1.4", "--measurements", "5",
+            "--equilibration-sweeps", "2", "--sweeps-per-measurement", "1", "--flush-every", "60", "--plane-resolved"])
+        .unwrap();
+        let file = File::open(&name).unwrap();
+        let planes = file.dataset("plane_action").unwrap();
+        assert_eq!(planes.shape(), vec![5, PLANES.len()]);
+        let order = planes.attr("planes").unwrap().read_raw::<usize>().unwrap();
+        assert_eq!(order, PLANES.iter().flat_map(|&(m, n)| [m, n]).collect::<Vec<_>>());
+
+        let rows = planes.read_raw::<f64>().unwrap();
+        let action = file.dataset("action_measurements").unwrap().read_raw::<f64>().unwrap();
+        let spatial = file.dataset("spatial_action").unwrap().read_raw::<f64>().unwrap();
+        let temporal = file.dataset("temporal_action").unwrap().read_raw::<f64>().unwrap();
+        for (i, row) in rows.chunks(PLANES.len()).enumerate() {
+            let mut by_plane = [[0.0; 4]; 4];
+            for (&(m, n), &value) in PLANES.iter().zip(row) {
+                by_plane[m][n] = value;
+            }
+            assert!((row.iter().sum::<f64>() / 6.0 - action[i]).abs() < 1e-14);
+            assert!((spatial_average(&by_plane) - spatial[i]).abs() < 1e-15);
+            assert!((temporal_average(&by_plane) - temporal[i]).abs() < 1e-15);
+        }
+        let _ = std::fs::remove_file(&name);
+    }
+
+    #[test]
+    fn the_same_seed_gives_identical_measurements() {
+        let run = |name: &str, seed: Option<&str>| {
+            let mut args = vec!["--beta", "0.9", "--width", "3", "--measurements", "8", "--equilibration-sweeps", "4",
+                "--sweeps-per-measurement", "1", "--flush-every", "60"];
+            if let Some(seed) = seed {
+                args.extend(["--seed", seed]);
+            }
+            let path = temp_run(name);
+            run_new(&path, &args).unwrap();
+            let dataset = File::open(&path).unwrap().dataset("action_measurements").unwrap();
+            let bits: Vec<u64> = dataset.read_raw::<f64>().unwrap().iter().map(|action| action.to_bits()).collect();
+            let seed = read_attribute::<u64>(&dataset, "seed").unwrap();
+            let _ = std::fs::remove_file(&path);
+            (bits, seed)
+        };
+
+        let (first, first_seed) = run("seed-first", Some("18446744073709551557"));
+        let (second, second_seed) = run("seed-second", Some("18446744073709551557"));
+        assert_eq!(first_seed, 18446744073709551557);
+        assert_eq!(second_seed, first_seed);
+        assert_eq!(first.len(), 8);
+        assert_eq!(first, second);
+        let (other, _) = run("seed-other", Some("7"));
+        assert_ne!(other, first);
+
+        /* a drawn seed is stored too, and reproduces the run */
+        let (drawn, drawn_seed) = run("seed-drawn", None);
+        let (repeated, _) = run("seed-repeated", Some(&drawn_seed.to_string()));
+        assert_eq!(repeated, drawn);
+    }
+
+    #[test]
+    fn without_a_preset_every_parameter_is_required() {
+        let error = new_settings(&["--beta", "1.0", "--width", "4"]).unwrap_err();
+        assert!(error.to_string().contains("--measurements"), "{}", error);
+        assert!(new_settings(&["--beta", "1.0", "--preset", "no-such-preset"]).is_err());
+    }
+
+    #[test]
+    fn the_rerun_command_expands_the_preset() {
+        let settings = new_settings(&["--beta", "1.0", "--preset", "quick-test", "--seed", "1"]).unwrap();
+        let command = settings.rerun_command();
+        assert!(!command.contains("--preset"), "{}", command);
+        let args = split_rerun_command(&command).unwrap();
+        let rerun = match Cli::try_parse_from(args).unwrap().command {
+            Commands::New(new) => new.resolve().unwrap(),
+            _ => panic!("the rerun command is not a new run"),
+        };
+        assert_eq!(rerun.measurements, settings.measurements);
+        assert_eq!(rerun.lattice_dims, settings.lattice_dims);
+    }
+
+    #[test]
+    fn warm_start_tiles_the_latest_checkpoint_and_records_its_source() {
+        let source = temp_run("warm-source");
+        run_new(&source, &["--beta", "1.1", "--width", "2", "--measurements", "3", "--equilibration-sweeps", "4",
+            "--sweeps-per-measurement", "2", "--flush-every", "1", "--seed", "5"])
+        .unwrap();
+        let (checkpoint, sweep) = {
+            let file = File::open(&source).unwrap();
+            let (_, configuration) = latest_checkpoint(&file.group("/").unwrap()).unwrap().unwrap();
+            let lattice = Lattice::from_array_dims([2; 4], &configuration.read_raw::<f64>().unwrap()).unwrap();
+            (lattice, read_attribute::<usize>(&configuration, "sweeps").unwrap())
+        };
+
+        let target = temp_run("warm-target");
+        let settings = new_run_settings(&target, &["--beta".to_string(), "1.1".to_string(), "--preset".to_string(),
+            "quick-test".to_string(), "--width".to_string(), "4".to_string(), "--warm-start-from".to_string(),
+            format!("{}:0", source)])
+        .unwrap();
+        let preset = find_preset("quick-test").unwrap();
+        assert_eq!(settings.equilibration_sweeps, preset.equilibration_sweeps / WARM_START_BURN_IN_DIVISOR);
+        assert!(settings.rerun_command().contains("--warm-start-from"));
+
+        let (_file, segment, simulation) = create_run(&settings, &[], RngRegistry::new(5), false).unwrap();
+        assert_eq!(simulation.lattice.to_array(), Lattice::tile_from(&checkpoint, 2).unwrap().to_array());
+        let action_dataset = segment.dataset("action_measurements").unwrap();
+        assert_eq!(read_string_attribute(&action_dataset, "warm-start-file").unwrap(), source);
+        assert_eq!(read_attribute::<usize>(&action_dataset, "warm-start-segment").unwrap(), 0);
+        assert_eq!(read_attribute::<usize>(&action_dataset, "warm-start-sweep").unwrap(), sweep);
+        assert_eq!(read_attribute::<usize>(&action_dataset, "warm-start-tiling").unwrap(), 2);
+
+        let missing = new_run_settings(&temp_run("warm-missing"), &["--beta".to_string(), "1.1".to_string(),
+            "--preset".to_string(), "quick-test".to_string(), "--warm-start-from".to_string(), format!("{}:3", source)])
+        .unwrap();
+        assert!(create_run(&missing, &[], RngRegistry::new(5), false).is_err());
+
+        let _ = std::fs::remove_file(&source);
+        let _ = std::fs::remove_file(&target);
+    }
+
+    #[test]
+    fn analyze_jackknifes_the_region_averages_over_time_and_regions() {
+        let name = temp_run("regions");
+        run_new(&name, &["--beta", "1.0", "--width", "4", "--measurements", "16", "--equilibration-sweeps", "2",
+            "--sweeps-per-measurement", "1", "--flush-every", "60", "--region-blocks", "2", "--seed", "7"])
+        .unwrap();
+
+        let file = File::open(&name).unwrap();
+        let segment = file.group("/").unwrap();
+        let series = read_action_series(&segment).unwrap();
+        let (table, num_regions) = read_region_table(&segment).unwrap().unwrap();
+        assert_eq!((table.len(), num_regions), (16 * 16, 16));
+        for (action, row) in series.iter().zip(table.chunks_exact(num_regions)) {
+            assert!((row.iter().sum::<f64>() / num_regions as f64 - action).abs() < 1e-12);
+        }
+
+        let result = SeriesAnalysis::new(&series, false).with_regions(&table, num_regions);
+        assert_eq!(result.two_way.iter().map(|(size, _, _)| *size).collect::<Vec<_>>(), vec![1, 2]);
+        assert!(result.json_fields().contains("\"two_way_jackknife\":[{\"bin_size\":1,\"bins\":16,"));
+        execute(Cli::try_parse_from(["lattice-rust", "analyze", "--name", &name, "--json"]).unwrap().command).unwrap();
+        let _ = std::fs::remove_file(&name);
+    }
+
+    #[test]
+    fn analyze_reads_rows_of_save_intervals_and_short_series() {
+        /* the layout of older files, three saves of four with the last one never written */
+        let name = temp_run("analyze-rows");
+        let file = File::create_excl(&name).unwrap();
+        let segment = file.group("/").unwrap();
+        let rows = [0.5, 0.25, 0.75, 0.5, 1.0, 0.0, 0.5, 0.5, 0.0, 0.0, 0.0, 0.0];
+        segment.new_dataset::<f64>().shape([3, 4]).create("action_measurements").unwrap().write_raw(&rows).unwrap();
+        /* a stored zero inside a written row is a measurement */
+        assert_eq!(read_action_series(&segment).unwrap(), rows[..8].to_vec());
+        let result = SeriesAnalysis::new(&read_action_series(&segment).unwrap(), true);
+        assert_eq!((result.measurements, result.mean), (8, 0.5));
+        assert_eq!(result.jackknife.iter().map(|(size, bins, _)| (*size, *bins)).collect::<Vec<_>>(), vec![(1, 8)]);
+        drop(file);
+        run_command(&["analyze", "--name", &name, "--json", "--autocorr"]).unwrap();
+        let _ = std::fs::remove_file(&name);
+
+        /* one measurement has a mean but no error, none is an error */
+        for measurements in ["1", "2"] {
+            let name = temp_run(&format!("analyze-{}", measurements));
+            run_new(&name, &["--beta", "1.0", "--width", "2", "--measurements", measurements, "--equilibration-sweeps", "1",
+                "--sweeps-per-measurement", "1", "--flush-every", "60"])
+            .unwrap();
+            run_command(&["analyze", "--name", &name]).unwrap();
+            run_command(&["analyze", "--name", &name, "--json", "--autocorr"]).unwrap();
+            let series = read_action_series(&File::open(&name).unwrap().group("/").unwrap()).unwrap();
+            let result = SeriesAnalysis::new(&series, true);
+            assert_eq!(result.naive_error.is_some(), measurements == "2");
+            let _ = std::fs::remove_file(&name);
+        }
+        let name = temp_run("analyze-empty");
+        File::create_excl(&name).unwrap().new_dataset::<f64>().shape(0..).create("action_measurements").unwrap();
+        assert!(run_command(&["analyze", "--name", &name]).is_err());
+        let _ = std::fs::remove_file(&name);
+    }
+
+    #[test]
+    fn a_three_segment_run_is_analyzed_per_segment_and_combined() {
+        let name = temp_run("three-segments");
+        let schedule = ["--width", "2", "--equilibration-sweeps", "2", "--sweeps-per-measurement", "1", "--flush-every", "60"];
+        let parameters = |beta: &'static str, measurements: &'static str| [&["--", "--beta", beta, "--measurements", measurements][..], &schedule].concat();
+        run_new(&name, &[&["--beta", "1.0", "--measurements", "6", "--seed", "5"][..], &schedule].concat()).unwrap();
+        run_command(&[&["retarget", "--name", &name, "--carry-over"][..], &parameters("1.0", "4")].concat()).unwrap();
+
+        /* two segments at the same coupling are combined in order */
+        let file = File::open(&name).unwrap();
+        let series: Vec<Vec<f64>> = run_segments(&file).unwrap().iter().map(|segment| read_action_series(segment).unwrap()).collect();
+        assert_eq!(series.iter().map(Vec::len).collect::<Vec<_>>(), vec![6, 4]);
+        let analysis = RunAnalysis::new(&file, true).unwrap();
+        let combined = analysis.combined.unwrap();
+        assert_eq!(combined.measurements, 10);
+        assert!((combined.mean - analysis::mean(&series.concat())).abs() < 1e-15);
+        drop(file);
+
+        /* a failed carry over leaves the run as it was */
+        assert!(run_command(&["retarget", "--name", &name, "--carry-over", "--", "--beta", "1.2", "--measurements", "3",
+            "--width", "3", "--equilibration-sweeps", "2", "--sweeps-per-measurement", "1", "--flush-every", "60"])
+        .is_err());
+        run_command(&[&["retarget", "--name", &name][..], &parameters("1.2", "3")].concat()).unwrap();
+
+        let file = File::open(&name).unwrap();
+        let segments = run_segments(&file).unwrap();
+        assert_eq!(segments.len(), 3);
+        let analysis = RunAnalysis::new(&file, false).unwrap();
+        assert!(analysis.combined.is_none());
+        for (index, (segment, (result, settings))) in segments.iter().zip(&analysis.segments).enumerate() {
+            let series = read_action_series(segment).unwrap();
+            assert_eq!(result.measurements, [6, 4, 3][index]);
+            assert_eq!(result.mean, analysis::mean(&series));
+            assert_eq!(settings.as_ref().unwrap().beta, [1.0, 1.0, 1.2][index]);
+
+            let dataset = segment.dataset("action_measurements").unwrap();
+            assert_eq!(dataset.attr("closed").is_ok(), index < 2, "segment {}", index);
+            if index < 2 {
+                assert_eq!(read_attribute::<usize>(&dataset, "summary-measurements").unwrap(), series.len());
+                assert_eq!(read_attribute::<f64>(&dataset, "summary-mean-action").unwrap(), analysis::mean(&series));
+            }
+            if index > 0 {
+                assert_eq!(read_attribute::<usize>(&dataset, "segment").unwrap(), index);
+                assert_eq!(read_attribute::<bool>(&dataset, "carried-over").unwrap(), index == 1);
+                assert_eq!(read_string_attribute(&dataset, "previous-segment").unwrap(), segment_path(index - 1));
+            }
+        }
+        drop(file);
+
+        run_command(&["analyze", "--name", &name, "--json"]).unwrap();
+        run_command(&["analyze", "--name", &name]).unwrap();
+        let _ = std::fs::remove_file(&name);
+    }
+
+    #[test]
+    fn a_manifest_verifies_and_names_every_changed_item() {
+        let name = temp_run("manifest");
+        let manifest = format!("{}.json", name);
+        run_new(&name, &["--beta", "1.0", "--width", "2", "--measurements", "20", "--equilibration-sweeps", "2",
+            "--sweeps-per-measurement", "1", "--flush-every", "60", "--seed", "13", "--polyakov"])
+        .unwrap();
+        /* the summary attributes a retarget leaves on the closed segment */
+        close_segment(&File::open_rw(&name).unwrap().group("/").unwrap()).unwrap();
+        run_command(&["manifest", "--name", &name, "--output", &manifest]).unwrap();
+        run_command(&["verify-manifest", "--manifest", &manifest]).unwrap();
+
+        let file_name = Path::new(&name).file_name().unwrap().to_string_lossy().to_string();
+        let item = |keys: &[&str]| -> Vec<String> {
+            ["runs", &file_name, "segments", "/"].iter().chain(keys).map(|key| key.to_string()).collect()
+        };
+        let expected = json::parse(&std::fs::read_to_string(&manifest).unwrap()).unwrap();
+        let listed = expected.get("runs").unwrap().get(&file_name).unwrap().get("segments").unwrap().get("/").unwrap();
+        let mismatched_items = || {
+            let run = json::parse(&run_manifest(Path::new(&name)).unwrap()).unwrap();
+            let mismatches = compare(&item(&[]), listed, run.get("segments").unwrap().get("/").unwrap());
+            mismatches.into_iter().map(|mismatch| mismatch.path).collect::<Vec<_>>()
+        };
+        /* the file and its manifest agree, the master seed is stored exactly */
+        assert!(mismatched_items().is_empty());
+        assert_eq!(listed.get("master_seed"), Some(&Json::Number("13".to_string())));
+
+        /* one changed measurement shows up as the checksum of its dataset and in the statistics */
+        let file = File::open_rw(&name).unwrap();
+        let segment = file.group("/").unwrap();
+        let mut series = read_action_series(&segment).unwrap();
+        let original = series.clone();
+        series[3] += 1e-3;
+        segment.dataset("action_measurements").unwrap().write_raw(&series).unwrap();
+        assert_eq!(mismatched_items(), vec![item(&["datasets", "action_measurements", "xxh64"])]);
+        let mut mismatches = Vec::new();
+        recheck_statistics(&file, &item(&[])[..2], &mut mismatches).unwrap();
+        let paths: Vec<String> = mismatches.iter().map(|mismatch| mismatch.path.join(" > ")).collect();
+        assert!(paths.iter().any(|path| path.ends_with("summary-mean-action > recomputed")), "{:?}", paths);
+        drop(file);
+        let error = run_command(&["verify-manifest", "--manifest", &manifest]).unwrap_err();
+        assert!(error.to_string().contains("do not match"), "{}", error);
+
+        /* a rerun with the stored parameters and seed reproduces every measurement bit for bit */
+        let rerun = temp_run("manifest-rerun");
+        let file = File::open_rw(&name).unwrap();
+        let segment = file.group("/").unwrap();
+        segment.dataset("action_measurements").unwrap().write_raw(&original).unwrap();
+        let mut settings = stored_settings(&segment).unwrap();
+        settings.name = rerun.clone();
+        let args = std::iter::once(env!("CARGO_PKG_NAME").to_string()).chain(settings.to_cli_args());
+        execute(Cli::try_parse_from(args).unwrap().command).unwrap();
+        let rerun_file = File::open(&rerun).unwrap();
+        let mut mismatches = Vec::new();
+        let datasets = compare_rerun(&segment, &rerun_file.group("/").unwrap(), &item(&[]), &mut mismatches).unwrap();
+        assert!(datasets >= 2 && mismatches.is_empty(), "{}", mismatches.iter().map(|m| m.to_string()).collect::<Vec<_>>().join("; "));
+
+        let polyakov = rerun_file.dataset("polyakov_re").unwrap();
+        let mut values = polyakov.read_raw::<f64>().unwrap();
+        values[5] = -values[5] - 1.0;
+        polyakov.write_raw(&values).unwrap();
+        compare_rerun(&segment, &rerun_file.group("/").unwrap(), &item(&[]), &mut mismatches).unwrap();
+        assert_eq!(mismatches.len(), 1);
+        assert_eq!(mismatches[0].path, item(&["rerun", "polyakov_re"]));
+        assert!(mismatches[0].to_string().contains("expected element 5 = "), "{}", mismatches[0]);
+        drop((file, rerun_file));
+        run_command(&["verify-manifest", "--manifest", &manifest]).unwrap();
+
+        for path in [&name, &manifest, &rerun] {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+
+    /* Some(value(rng)) for about half the calls */
+    fn maybe<T>(rng: &mut Rng, value: impl FnOnce(&mut Rng) -> T) -> Option<T> {
+        return rng.bool().then(|| value(rng));
+    }
+
+    #[test]
+    fn resolved_settings_survive_the_rerun_command() {
+        let mut rng = Rng::with_seed(11);
+        let mut checked = 0;
+        for case in 0..500 {
+            let width = rng.usize(2..7);
+            let algorithm = if rng.bool() { Algorithm::Metropolis } else { Algorithm::Heatbath };
+            let targeted_fraction = maybe(&mut rng, |rng| rng.f64().max(0.01));
+            let kappa = maybe(&mut rng, |rng| rng.f64() - 0.5);
+            let start = match rng.usize(0..6) {
+                0 => StartSpec::Ordered,
+                1 => StartSpec::Random,
+                2 => StartSpec::File(format!("cached config {}.bin", case)),
+                3 => StartSpec::WarmStart { path: "runs/source.h5".to_string(), segment: maybe(&mut rng, |rng| rng.usize(0..3)) },
+                4 => StartSpec::PlaneWave {
+                    momentum: [rng.usize(0..3), 0, rng.usize(0..3), 1],
+                    amplitude: rng.f64(),
+                    direction: rng.usize(0..4),
+                },
+                _ => {
+                    let mut quanta = [[0i64; 4]; 4];
+                    quanta[0][1] = rng.i64(-2..3);
+                    quanta[1][0] = -quanta[0][1];
+                    quanta[2][3] = rng.i64(-2..3);
+                    quanta[3][2] = -quanta[2][3];
+                    StartSpec::Constant { quanta }
+                }
+            };
+            let settings = RunConfig {
+                name: format!("run {}.h5", case),
+                beta: 2.0 * rng.f64(),
+                beta_spatial: maybe(&mut rng, |rng| rng.f64()),
+                beta_temporal: maybe(&mut rng, |rng| rng.f64()),
+                lattice_dims: if rng.bool() { [width; 4] } else { [width, width, width, 2] },
+                start,
+                measurements: rng.usize(1..1000),
+                equilibration_sweeps: rng.usize(0..1000),
+                sweeps_between_measurements: rng.usize(1..10),
+                interval: rng.usize(1..3600),
+                publish: None,
+                region_blocks: maybe(&mut rng, |_| 1),
+                wilson_loops: maybe(&mut rng, |rng| rng.usize(1..4)),
+                strict_equilibration: rng.bool(),
+                kappa,
+                /* the flags conflict, which only the command line checks */
+                gamma: maybe(&mut rng, |rng| rng.f64() - 0.5).filter(|_| kappa.is_none()),
+                topological_charge: rng.bool(),
+                polyakov: rng.bool(),
+                monopoles: rng.bool(),
+                plane_resolved: rng.bool(),
+                frozen: rng.bool(),
+                derive: (0..rng.usize(0..3)).map(|index| format!("d{} = action * (1 + {})", index, index)).collect(),
+                seed: Some(rng.u64(..)),
+                threads: maybe(&mut rng, |_| 2),
+                algorithm,
+                step_size: if algorithm == Algorithm::Metropolis { rng.f64() + 0.1 } else { 1.0 },
+                overrelaxation_per_heatbath: rng.usize(0..2),
+                targeted_fraction,
+                targeted_hits: if targeted_fraction.is_some() { rng.usize(1..4) } else { 1 },
+                targeted_refresh: if targeted_fraction.is_some() { rng.usize(1..20) } else { 10 },
+                coarse_update: maybe(&mut rng, |rng| (rng.usize(1..3), rng.f64() + 0.01)),
+                integrated_links: rng.bool(),
+            };
+            if settings.validate().is_err() {
+                continue;
+            }
+
+            let args = split_rerun_command(&settings.rerun_command()).unwrap();
+            let parsed = match Cli::try_parse_from(&args).unwrap().command {
+                Commands::New(new) => new.resolve().unwrap(),
+                _ => panic!("the rerun command is not a new run"),
+            };
+            assert_eq!(format!("{:?}", parsed), format!("{:?}", settings), "{}", settings.rerun_command());
+            checked += 1;
+        }
+        assert!(checked > 50, "only {} valid configurations", checked);
+    }
+
+    #[test]
+    fn the_stored_rerun_command_reproduces_the_run() {
+        let name = temp_run("rerun-original");
+        run_new(&name, &["--beta", "0.9", "--width", "3", "--measurements", "5", "--equilibration-sweeps", "3",
+            "--sweeps-per-measurement", "2", "--flush-every", "60", "--monopoles"])
+        .unwrap();
+        execute(Cli::try_parse_from(["lattice-rust", "info", "--name", &name, "--rerun-command"]).unwrap().command)
+            .unwrap();
+
+        let original = File::open(&name).unwrap().group("/").unwrap();
+        let command = stored_rerun_command(&original).unwrap();
+        assert!(command.contains("--seed"), "the seed is pinned: {}", command);
+        let mut args = split_rerun_command(&command).unwrap();
+        let rerun = temp_run("rerun-copy");
+        let position = args.iter().position(|arg| arg == "--name").unwrap();
+        args[position + 1] = rerun.clone();
+        execute(Cli::try_parse_from(&args).unwrap().command).unwrap();
+
+        let copy = File::open(&rerun).unwrap().group("/").unwrap();
+        assert_eq!(read_action_series(&copy).unwrap(), read_action_series(&original).unwrap());
+        assert_eq!(
+            copy.dataset("monopole_density").unwrap().read_raw::<f64>().unwrap(),
+            original.dataset("monopole_density").unwrap().read_raw::<f64>().unwrap()
+        );
+        let _ = std::fs::remove_file(&name);
+        let _ = std::fs::remove_file(&rerun);
+    }
+}
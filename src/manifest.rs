@@ -0,0 +1,170 @@
+use crate::analysis;
+use crate::equilibration::{drift_significance, BLOCKS_PER_HALF, DRIFT_THRESHOLD, PROBATION_WINDOW};
+use crate::json::Json;
+use std::fmt;
+
+/* version of the manifest layout, bumped whenever fields change meaning */
+pub const MANIFEST_VERSION: u64 = 1;
+
+/* summary attributes a run stores with its action measurements, in manifest order */
+pub const SUMMARY_STATISTICS: [&str; 4] = [
+    "possibly-unequilibrated",
+    "summary-measurements",
+    "summary-mean-action",
+    "summary-naive-error",
+];
+
+/* measurements checked for drift after the burn in, for a run of the given length */
+pub fn probation_length(measurements: usize) -> usize {
+    return PROBATION_WINDOW.min(measurements);
+}
+
+/* how a summary attribute was computed from the action measurements, as JSON. The probation
+ * window depends on the configured run length, None where that is unknown */
+pub fn analysis_parameters(statistic: &str, probation_length: Option<usize>) -> Option<String> {
+    let parameters = match statistic {
+        "possibly-unequilibrated" => format!(
+            "{{\"method\":\"equilibration::drift_significance\",\"series\":\"first measurements\",\"window\":{},\"blocks_per_half\":{},\"threshold\":{},\"flagged\":\"significance > threshold\"}}",
+            probation_length.map_or("null".to_string(), |length| length.to_string()),
+            BLOCKS_PER_HALF,
+            DRIFT_THRESHOLD
+        ),
+        "summary-measurements" => "{\"method\":\"count\",\"series\":\"all measurements\"}".to_string(),
+        "summary-mean-action" => "{\"method\":\"analysis::mean\",\"series\":\"all measurements\"}".to_string(),
+        "summary-naive-error" => {
+            "{\"method\":\"analysis::naive_error\",\"series\":\"all measurements\",\"formula\":\"sqrt(variance / n), variance with n - 1\",\"binning\":null}".to_string()
+        }
+        _ => return None,
+    };
+    return Some(parameters);
+}
+
+/* the value of a summary attribute recomputed from the action measurements, as JSON text in the
+ * form the manifest stores it. None where the measurements do not determine it */
+pub fn recompute_statistic(statistic: &str, series: &[f64], probation_length: Option<usize>) -> Option<String> {
+    return match statistic {
+        "possibly-unequilibrated" => {
+            let window = series.get(..probation_length?)?;
+            drift_significance(window).map(|significance| (significance > DRIFT_THRESHOLD).to_string())
+        }
+        "summary-measurements" => Some(series.len().to_string()),
+        "summary-mean-action" if !series.is_empty() => Some(analysis::mean(series).to_string()),
+        "summary-naive-error" => analysis::naive_error(series).map(|error| error.to_string()),
+        _ => None,
+    };
+}
+
+/* an item that a verification did not reproduce, the path names the keys leading to it. Expected
+ * is what the manifest or the file records, None for an item it does not have */
+pub struct Mismatch {
+    pub path: Vec<String>,
+    pub expected: Option<String>,
+    pub found: Option<String>,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: ", self.path.join(" > "))?;
+        match (&self.expected, &self.found) {
+            (Some(expected), Some(found)) => write!(f, "expected {}, found {}", expected, found),
+            (Some(expected), None) => write!(f, "missing, expected {}", expected),
+            (None, Some(found)) => write!(f, "unexpected {}", found),
+            (None, None) => write!(f, "missing"),
+        }
+    }
+}
+
+/* every value of the manifest that differs from the regenerated one, objects are compared key by
+ * key so that a mismatch names the innermost item */
+pub fn compare(path: &[String], expected: &Json, found: &Json) -> Vec<Mismatch> {
+    let (expected_members, found_members) = match (expected, found) {
+        (Json::Object(expected_members), Json::Object(found_members)) => (expected_members, found_members),
+        _ if expected == found => return Vec::new(),
+        _ => {
+            return vec![Mismatch {
+                path: path.to_vec(),
+                expected: Some(expected.to_json()),
+                found: Some(found.to_json()),
+            }]
+        }
+    };
+
+    let child = |name: &str| {
+        let mut child = path.to_vec();
+        child.push(name.to_string());
+        child
+    };
+    let mut mismatches = Vec::new();
+    for (name, expected_value) in expected_members {
+        match found.get(name) {
+            Some(found_value) => mismatches.extend(compare(&child(name), expected_value, found_value)),
+            None => mismatches.push(Mismatch {
+                path: child(name),
+                expected: Some(expected_value.to_json()),
+                found: None,
+            }),
+        }
+    }
+    for (name, found_value) in found_members {
+        if expected.get(name).is_none() {
+            mismatches.push(Mismatch {
+                path: child(name),
+                expected: None,
+                found: Some(found_value.to_json()),
+            });
+        }
+    }
+    return mismatches;
+}
+
+/* number of values below the objects of a manifest, the items a verification checks */
+pub fn count_items(value: &Json) -> usize {
+    return match value {
+        Json::Object(members) => members.iter().map(|(_, member)| count_items(member)).sum(),
+        _ => 1,
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::json::parse;
+
+    #[test]
+    fn compare_names_the_innermost_changed_missing_and_unexpected_items() {
+        let expected = parse(r#"{"seed":1,"datasets":{"a":{"xxh64":"aa","shape":[2]},"b":{"xxh64":"bb"}},"list":[1,2]}"#).unwrap();
+        let found = parse(r#"{"seed":1,"datasets":{"a":{"xxh64":"ab","shape":[2]},"c":{"xxh64":"cc"}},"list":[1,3]}"#).unwrap();
+        assert!(compare(&["run".to_string()], &expected, &expected).is_empty());
+
+        let mismatches: Vec<String> = compare(&["run".to_string()], &expected, &found).iter().map(Mismatch::to_string).collect();
+        assert_eq!(
+            mismatches,
+            [
+                "run > datasets > a > xxh64: expected \"aa\", found \"ab\"",
+                "run > datasets > b: missing, expected {\"xxh64\":\"bb\"}",
+                "run > datasets > c: unexpected {\"xxh64\":\"cc\"}",
+                "run > list: expected [1,2], found [1,3]",
+            ]
+        );
+        assert_eq!(count_items(&expected), 5);
+    }
+
+    #[test]
+    fn statistics_are_recomputed_as_they_are_stored() {
+        let series = [1.0, 2.0, 3.0, 4.0];
+        assert_eq!(recompute_statistic("summary-measurements", &series, None).as_deref(), Some("4"));
+        assert_eq!(recompute_statistic("summary-mean-action", &series, None).as_deref(), Some("2.5"));
+        let error = (5.0f64 / 3.0 / 4.0).sqrt().to_string();
+        assert_eq!(recompute_statistic("summary-naive-error", &series, None), Some(error));
+        assert_eq!(recompute_statistic("summary-mean-action", &[], None), None);
+        /* the drift test needs the probation window of the configured run */
+        assert_eq!(recompute_statistic("possibly-unequilibrated", &series, None), None);
+        assert_eq!(recompute_statistic("unknown", &series, None), None);
+
+        for statistic in SUMMARY_STATISTICS {
+            let parameters = parse(&analysis_parameters(statistic, Some(probation_length(1000))).unwrap()).unwrap();
+            assert!(parameters.get("method").and_then(Json::as_str).is_some(), "{}", statistic);
+        }
+        assert_eq!(probation_length(3), 3);
+    }
+}
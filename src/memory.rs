@@ -0,0 +1,143 @@
+use crate::cli::format_size;
+use crate::lattice::format_extents;
+use crate::phasevector::PhaseVector;
+use anyhow::{bail, Result};
+use std::mem::size_of;
+
+/* assumed available memory when /proc/meminfo can not be read */
+const FALLBACK_AVAILABLE_BYTES: u64 = 1 << 30;
+
+/* what a command keeps in memory at the same time, link configurations are stored flat while scalar
+ * fields are nested Vec<Vec<Vec<Vec<_>>>> so every row carries a Vec header besides its elements */
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Footprint {
+    /* link configurations alive at once, e.g. 2 while gauge fixing keeps the old lattice */
+    pub lattices: u64,
+    /* scalar fields on the sites */
+    pub scalar_fields: u64,
+    /* flat per-site f64 buffers, e.g. the topological charge density */
+    pub site_buffers: u64,
+    /* f64 values buffered independently of the width, e.g. measurements before a save */
+    pub values: u64,
+}
+
+impl Footprint {
+    pub fn required_bytes(&self, dims: [usize; 4]) -> u64 {
+        let [nx, ny, nz, nt] = dims.map(|extent| extent as u64);
+        let sites = nx.saturating_mul(ny).saturating_mul(nz).saturating_mul(nt);
+        let rows = nx
+            .saturating_add(1)
+            .saturating_add(nx.saturating_mul(ny))
+            .saturating_add(nx.saturating_mul(ny).saturating_mul(nz));
+        let vec_header = size_of::<Vec<f64>>() as u64;
+
+        /* the links and the forward and backward neighbor tables */
+        let lattice = (sites.saturating_mul((size_of::<PhaseVector>() + 2 * size_of::<[usize; 4]>()) as u64))
+            .saturating_add(3 * vec_header);
+        let scalar_field = (sites.saturating_mul(size_of::<f64>() as u64))
+            .saturating_add(rows.saturating_mul(vec_header));
+        let site_buffer = sites.saturating_mul(size_of::<f64>() as u64);
+
+        return self
+            .lattices
+            .saturating_mul(lattice)
+            .saturating_add(self.scalar_fields.saturating_mul(scalar_field))
+            .saturating_add(self.site_buffers.saturating_mul(site_buffer))
+            .saturating_add(self.values.saturating_mul(size_of::<f64>() as u64));
+    }
+
+    /* largest width whose footprint fits into the given number of bytes */
+    pub fn largest_width(&self, available: u64) -> usize {
+        let mut width = 0;
+        while self.required_bytes([width + 1; 4]) <= available {
+            width += 1;
+        }
+        return width;
+    }
+}
+
+/* MemAvailable from /proc/meminfo, None where it does not exist */
+pub fn available_bytes() -> Option<u64> {
+    let meminfo = std::fs::read_to_string("/proc/meminfo").ok()?;
+    let line = meminfo
+        .lines()
+        .find(|line| line.starts_with("MemAvailable:"))?;
+    let kilobytes = line.split_whitespace().nth(1)?.parse::<u64>().ok()?;
+    return Some(kilobytes * 1024);
+}
+
+/* fail before allocating anything if the configuration can not fit into memory, or into the
+ * limit given by the user if that is lower */
+pub fn check_memory(footprint: &Footprint, dims: [usize; 4], ignore: bool, limit: Option<u64>) -> Result<()> {
+    let required = footprint.required_bytes(dims);
+    let (available, source) = match (available_bytes(), limit) {
+        (Some(available), Some(limit)) if limit < available => (limit, "allowed by --memory-limit"),
+        (None, Some(limit)) => (limit, "allowed by --memory-limit"),
+        (Some(available), _) => (available, "available"),
+        (None, None) => (FALLBACK_AVAILABLE_BYTES, "assumed available"),
+    };
+
+    if required <= available {
+        return Ok(());
+    }
+    if ignore {
+        println!(
+            "Warning: lattice {} needs about {} but only {} is {}, continuing anyway",
+            format_extents(dims),
+            format_size(required),
+            format_size(available),
+            source
+        );
+        return Ok(());
+    }
+
+    bail!(
+        "lattice {} needs about {} but only {} is {}; use a width of at most {} or pass --ignore-memory-check",
+        format_extents(dims),
+        format_size(required),
+        format_size(available),
+        source,
+        footprint.largest_width(available)
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ONE_LATTICE: Footprint = Footprint { lattices: 1, scalar_fields: 0, site_buffers: 0, values: 0 };
+
+    #[test]
+    fn the_footprint_counts_links_and_neighbor_tables() {
+        /* per site four phases and two tables of four neighbors */
+        let per_site = (size_of::<PhaseVector>() + 2 * size_of::<[usize; 4]>()) as u64;
+        let headers = 3 * size_of::<Vec<f64>>() as u64;
+        assert_eq!(ONE_LATTICE.required_bytes([2, 3, 4, 5]), 120 * per_site + headers);
+        assert_eq!(Footprint::default().required_bytes([8; 4]), 0);
+        /* widths beyond the address space saturate instead of wrapping */
+        assert_eq!(ONE_LATTICE.required_bytes([usize::MAX; 4]), u64::MAX);
+    }
+
+    #[test]
+    fn lattices_above_the_limit_are_refused_with_their_size() {
+        let required = ONE_LATTICE.required_bytes([8; 4]);
+        check_memory(&ONE_LATTICE, [8; 4], false, Some(required)).unwrap();
+
+        let error = check_memory(&ONE_LATTICE, [8; 4], false, Some(required - 1)).unwrap_err().to_string();
+        assert!(error.contains(&format_size(required)), "{}", error);
+        assert!(error.contains("allowed by --memory-limit"), "{}", error);
+        assert!(error.contains("--ignore-memory-check"), "{}", error);
+        assert!(error.contains("a width of at most 7"), "{}", error);
+
+        check_memory(&ONE_LATTICE, [8; 4], true, Some(required - 1)).unwrap();
+    }
+
+    #[test]
+    fn the_largest_width_is_the_last_one_that_fits() {
+        for available in [0, 1 << 20, 1 << 30] {
+            let width = ONE_LATTICE.largest_width(available);
+            assert!(width == 0 || ONE_LATTICE.required_bytes([width; 4]) <= available);
+            assert!(ONE_LATTICE.required_bytes([width + 1; 4]) > available);
+        }
+    }
+}
@@ -1,3 +1,4 @@
+use crate::lattice::principal_angle;
 use fastrand::Rng;
 use std::f64::consts::PI;
 
@@ -17,7 +18,15 @@ impl PhaseVector {
         for phase in new_phase_vector.phases.iter_mut() {
             *phase = rng.f64() * 2.0 * PI;
         }
+        new_phase_vector.wrap();
 
         return new_phase_vector;
     }
+
+    /* reduce every phase into (-pi, pi], the canonical interval all stored link phases live in */
+    pub fn wrap(&mut self) {
+        for phase in self.phases.iter_mut() {
+            *phase = principal_angle(*phase);
+        }
+    }
 }
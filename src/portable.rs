@@ -0,0 +1,396 @@
+use crate::lattice::Lattice;
+use anyhow::{bail, Context, Result};
+use std::fs::File;
+use std::io::{Read, Seek, Write};
+
+/* self describing configuration format for exchange with other codes. All multi byte fields use
+ * the byte order given in the header:
+ *
+ *   offset  size  field
+ *        0     8  magic "U1LPORTB"
+ *        8     1  format version, 1
+ *        9     1  byte order of everything below, b'L' little or b'B' big endian
+ *       10     1  precision, bytes per phase: 4 (f32) or 8 (f64)
+ *       11     1  gauge group tag, 1 for U(1)
+ *       12     1  number of dimensions d, 4
+ *       13     1  flags, bit 0 set if the file ends with an XXH64 checksum
+ *       14     2  reserved, zero
+ *       16  8 d   extent of every dimension as u64
+ *   16 + 8 d   4  length n of the metadata blob as u32
+ *   20 + 8 d   n  metadata, UTF-8 JSON
+ *
+ * followed by the link phases in radians in (x_0, ..., x_{d-1}, mu) order with the last index
+ * fastest, and, if flagged, the XXH64 (seed 0) of every byte before it as u64 */
+pub const PORTABLE_MAGIC: &[u8; 8] = b"U1LPORTB";
+const PORTABLE_VERSION: u8 = 1;
+const GAUGE_GROUP_U1: u8 = 1;
+const DIMENSIONS: usize = 4;
+const FLAG_CHECKSUM: u8 = 1;
+const FIXED_HEADER_LENGTH: usize = 16;
+
+#[derive(Copy, Clone)]
+enum ByteOrder {
+    Little,
+    Big,
+}
+
+impl ByteOrder {
+    fn u32(&self, bytes: &[u8]) -> u32 {
+        let bytes = bytes.try_into().unwrap();
+        return match self {
+            ByteOrder::Little => u32::from_le_bytes(bytes),
+            ByteOrder::Big => u32::from_be_bytes(bytes),
+        };
+    }
+
+    fn u64(&self, bytes: &[u8]) -> u64 {
+        let bytes = bytes.try_into().unwrap();
+        return match self {
+            ByteOrder::Little => u64::from_le_bytes(bytes),
+            ByteOrder::Big => u64::from_be_bytes(bytes),
+        };
+    }
+}
+
+impl Lattice {
+    /* write the configuration in the portable format, little endian f64 with a checksum */
+    pub fn write_portable<W: Write>(&self, writer: &mut W, metadata: &str) -> Result<()> {
+        let phases = self.to_array();
+        let mut buffer = Vec::with_capacity(FIXED_HEADER_LENGTH + 8 * DIMENSIONS + 4 + metadata.len() + 8 * phases.len() + 8);
+        buffer.extend_from_slice(PORTABLE_MAGIC);
+        buffer.extend_from_slice(&[PORTABLE_VERSION, b'L', 8, GAUGE_GROUP_U1, DIMENSIONS as u8, FLAG_CHECKSUM, 0, 0]);
+        for extent in self.dims() {
+            buffer.extend_from_slice(&(extent as u64).to_le_bytes());
+        }
+        let metadata_length = u32::try_from(metadata.len()).context("metadata is longer than 4 GiB")?;
+        buffer.extend_from_slice(&metadata_length.to_le_bytes());
+        buffer.extend_from_slice(metadata.as_bytes());
+        for phase in phases {
+            buffer.extend_from_slice(&phase.to_le_bytes());
+        }
+        let checksum = xxh64(&buffer, 0);
+        buffer.extend_from_slice(&checksum.to_le_bytes());
+
+        writer.write_all(&buffer)?;
+        Ok(())
+    }
+
+    /* read a configuration in the portable format in either byte order and precision, together
+     * with its metadata */
+    pub fn read_portable<R: Read>(reader: &mut R) -> Result<(Self, String)> {
+        let mut buffer = Vec::new();
+        reader.read_to_end(&mut buffer)?;
+
+        if buffer.len() < FIXED_HEADER_LENGTH {
+            bail!("portable configuration is truncated within its {} byte header", FIXED_HEADER_LENGTH);
+        }
+        if &buffer[..PORTABLE_MAGIC.len()] != PORTABLE_MAGIC {
+            bail!("not a portable configuration, the magic string is missing");
+        }
+        let [version, order, precision, group, dimensions, flags, reserved_0, reserved_1] =
+            buffer[8..FIXED_HEADER_LENGTH].try_into().unwrap();
+        if version != PORTABLE_VERSION {
+            bail!("unsupported portable format version {}, expected {}", version, PORTABLE_VERSION);
+        }
+        let order = match order {
+            b'L' => ByteOrder::Little,
+            b'B' => ByteOrder::Big,
+            other => bail!("invalid byte order marker {:#04x}, expected 'L' or 'B'", other),
+        };
+        if precision != 4 && precision != 8 {
+            bail!("invalid precision of {} bytes per phase, expected 4 or 8", precision);
+        }
+        if group != GAUGE_GROUP_U1 {
+            bail!("gauge group tag {} is not U(1), which has tag {}", group, GAUGE_GROUP_U1);
+        }
+        if dimensions as usize != DIMENSIONS {
+            bail!("{} dimensional configurations are not supported, expected {}", dimensions, DIMENSIONS);
+        }
+        if flags & !FLAG_CHECKSUM != 0 {
+            bail!("unknown flags {:#04x}", flags & !FLAG_CHECKSUM);
+        }
+        if reserved_0 != 0 || reserved_1 != 0 {
+            bail!("reserved header bytes are not zero");
+        }
+
+        let extents_end = FIXED_HEADER_LENGTH + 8 * DIMENSIONS;
+        if buffer.len() < extents_end + 4 {
+            bail!("portable configuration is truncated within its extents");
+        }
+        let extents: Vec<u64> = (0..DIMENSIONS)
+            .map(|d| order.u64(&buffer[FIXED_HEADER_LENGTH + 8 * d..FIXED_HEADER_LENGTH + 8 * (d + 1)]))
+            .collect();
+        if extents.contains(&0) {
+            bail!("lattice extents {:?} contain a zero", extents);
+        }
+        let mut dims = [0usize; DIMENSIONS];
+        for (dim, extent) in dims.iter_mut().zip(&extents) {
+            *dim = usize::try_from(*extent).context("lattice extent does not fit into memory")?;
+        }
+
+        let metadata_length = order.u32(&buffer[extents_end..extents_end + 4]) as usize;
+        let metadata_start = extents_end + 4;
+        let data_start = metadata_start
+            .checked_add(metadata_length)
+            .filter(|end| *end <= buffer.len())
+            .context("portable configuration is truncated within its metadata")?;
+        let metadata = std::str::from_utf8(&buffer[metadata_start..data_start])
+            .context("metadata is not valid UTF-8")?
+            .to_string();
+
+        let num_phases = dims
+            .iter()
+            .try_fold(DIMENSIONS, |phases, &extent| phases.checked_mul(extent))
+            .context("lattice extents are too large")?;
+        let data_length = num_phases
+            .checked_mul(precision as usize)
+            .context("lattice extents are too large")?;
+        let checksum_length = if flags & FLAG_CHECKSUM != 0 { 8 } else { 0 };
+        let expected_length = data_start + data_length + checksum_length;
+        if buffer.len() < expected_length {
+            bail!(
+                "portable configuration is truncated, {} bytes instead of {} for extents {:?}",
+                buffer.len(),
+                expected_length,
+                dims
+            );
+        }
+        if buffer.len() > expected_length {
+            bail!("portable configuration has {} bytes of trailing data", buffer.len() - expected_length);
+        }
+
+        let data_end = data_start + data_length;
+        if checksum_length > 0 {
+            let stored = order.u64(&buffer[data_end..]);
+            let computed = xxh64(&buffer[..data_end], 0);
+            if stored != computed {
+                bail!("checksum mismatch, stored {:016x} but the contents hash to {:016x}", stored, computed);
+            }
+        }
+
+        let phases: Vec<f64> = buffer[data_start..data_end]
+            .chunks_exact(precision as usize)
+            .map(|bytes| match precision {
+                4 => f32::from_bits(order.u32(bytes)) as f64,
+                _ => f64::from_bits(order.u64(bytes)),
+            })
+            .collect();
+
+        return Ok((Lattice::from_array_dims(dims, &phases)?, metadata));
+    }
+}
+
+/* read a configuration from either the portable format or the native cache format of
+ * Lattice::write_config, telling them apart by the magic string. The metadata is None for the
+ * native format */
+pub fn read_configuration(path: &str) -> Result<(Lattice, Option<String>)> {
+    let mut file = File::open(path).with_context(|| format!("Failed to open configuration {}", path))?;
+    let mut magic = [0u8; 8];
+    let portable = file.read_exact(&mut magic).is_ok() && &magic == PORTABLE_MAGIC;
+    file.rewind()?;
+
+    if portable {
+        let (lattice, metadata) = Lattice::read_portable(&mut file)
+            .with_context(|| format!("Failed to read portable configuration {}", path))?;
+        return Ok((lattice, Some(metadata)));
+    }
+    let lattice = Lattice::read_config(&mut file).with_context(|| format!("Failed to read configuration {}", path))?;
+    return Ok((lattice, None));
+}
+
+const PRIME_1: u64 = 0x9E3779B185EBCA87;
+const PRIME_2: u64 = 0xC2B2AE3D27D4EB4F;
+const PRIME_3: u64 = 0x165667B19E3779F9;
+const PRIME_4: u64 = 0x85EBCA77C2B2AE63;
+const PRIME_5: u64 = 0x27D4EB2F165667C5;
+
+fn xxh64_round(accumulator: u64, input: u64) -> u64 {
+    return accumulator
+        .wrapping_add(input.wrapping_mul(PRIME_2))
+        .rotate_left(31)
+        .wrapping_mul(PRIME_1);
+}
+
+fn xxh64_merge(hash: u64, accumulator: u64) -> u64 {
+    return (hash ^ xxh64_round(0, accumulator))
+        .wrapping_mul(PRIME_1)
+        .wrapping_add(PRIME_4);
+}
+
+/* the XXH64 hash, so that other codes can check the file with the reference implementation */
+pub fn xxh64(data: &[u8], seed: u64) -> u64 {
+    let word = |offset: usize| u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap());
+    let half_word = |offset: usize| u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap()) as u64;
+    let mut offset = 0;
+
+    let mut hash = if data.len() >= 32 {
+        let mut accumulators = [
+            seed.wrapping_add(PRIME_1).wrapping_add(PRIME_2),
+            seed.wrapping_add(PRIME_2),
+            seed,
+            seed.wrapping_sub(PRIME_1),
+        ];
+        while offset + 32 <= data.len() {
+            for (lane, accumulator) in accumulators.iter_mut().enumerate() {
+                *accumulator = xxh64_round(*accumulator, word(offset + 8 * lane));
+            }
+            offset += 32;
+        }
+
+        let [a, b, c, d] = accumulators;
+        let mut hash = a
+            .rotate_left(1)
+            .wrapping_add(b.rotate_left(7))
+            .wrapping_add(c.rotate_left(12))
+            .wrapping_add(d.rotate_left(18));
+        for accumulator in accumulators {
+            hash = xxh64_merge(hash, accumulator);
+        }
+        hash
+    } else {
+        seed.wrapping_add(PRIME_5)
+    };
+
+    hash = hash.wrapping_add(data.len() as u64);
+    while offset + 8 <= data.len() {
+        hash ^= xxh64_round(0, word(offset));
+        hash = hash.rotate_left(27).wrapping_mul(PRIME_1).wrapping_add(PRIME_4);
+        offset += 8;
+    }
+    if offset + 4 <= data.len() {
+        hash ^= half_word(offset).wrapping_mul(PRIME_1);
+        hash = hash.rotate_left(23).wrapping_mul(PRIME_2).wrapping_add(PRIME_3);
+        offset += 4;
+    }
+    for byte in &data[offset..] {
+        hash ^= (*byte as u64).wrapping_mul(PRIME_5);
+        hash = hash.rotate_left(11).wrapping_mul(PRIME_1);
+    }
+
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(PRIME_2);
+    hash ^= hash >> 29;
+    hash = hash.wrapping_mul(PRIME_3);
+    hash ^= hash >> 32;
+    return hash;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fastrand::Rng;
+
+    /* checked into the repository, written by version 1 of the format */
+    const FIXTURE: &[u8] = include_bytes!("../tests/fixtures/portable-v1.u1l");
+    const FIXTURE_METADATA: &str = "{\"fixture\":\"portable format version 1\"}";
+
+    fn fixture_lattice() -> Lattice {
+        let phases: Vec<f64> = (0..4 * 6).map(|index| 0.25 * index as f64 - 3.0).collect();
+        return Lattice::from_array_dims([2, 1, 3, 1], &phases).unwrap();
+    }
+
+    fn portable_bytes(lattice: &Lattice, metadata: &str) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        lattice.write_portable(&mut bytes, metadata).unwrap();
+        return bytes;
+    }
+
+    fn read_error(bytes: &[u8]) -> String {
+        return format!("{:#}", Lattice::read_portable(&mut &bytes[..]).expect_err("malformed file was accepted"));
+    }
+
+    #[test]
+    fn xxh64_matches_the_reference_vectors() {
+        assert_eq!(xxh64(b"", 0), 0xef46db3751d8e999);
+        assert_eq!(xxh64(b"a", 0), 0xd24ec4f1a98c6e5b);
+        assert_eq!(xxh64(b"abc", 0), 0x44bc2cf5ad770999);
+        /* 39 bytes, through the four lane loop and every tail case */
+        assert_eq!(xxh64(b"Nobody inspects the spammish repetition", 0), 0xfbcea83c8a378bf1);
+    }
+
+    #[test]
+    fn configurations_round_trip_bit_for_bit() {
+        let mut rng = Rng::with_seed(19);
+        for dims in [[2, 2, 2, 2], [3, 1, 4, 2]] {
+            let lattice = Lattice::new_random_dims(dims, &mut rng);
+            let bytes = portable_bytes(&lattice, "{\"beta\":1.0}");
+            let (read, metadata) = Lattice::read_portable(&mut &bytes[..]).unwrap();
+            assert_eq!(read.dims(), dims);
+            assert_eq!(metadata, "{\"beta\":1.0}");
+            let bits = |lattice: &Lattice| lattice.to_array().iter().map(|phase| phase.to_bits()).collect::<Vec<u64>>();
+            assert_eq!(bits(&read), bits(&lattice));
+        }
+    }
+
+    #[test]
+    fn the_fixture_still_reads_and_writes_the_same() {
+        let (lattice, metadata) = Lattice::read_portable(&mut &FIXTURE[..]).unwrap();
+        assert_eq!(metadata, FIXTURE_METADATA);
+        assert_eq!(lattice.dims(), [2, 1, 3, 1]);
+        assert_eq!(lattice.to_array(), fixture_lattice().to_array());
+        assert_eq!(u64::from_le_bytes(FIXTURE[FIXTURE.len() - 8..].try_into().unwrap()), 0xb39db2e1f8b1ceae);
+        assert_eq!(portable_bytes(&fixture_lattice(), FIXTURE_METADATA), FIXTURE);
+    }
+
+    #[test]
+    fn big_endian_single_precision_files_are_read() {
+        let mut bytes = PORTABLE_MAGIC.to_vec();
+        bytes.extend_from_slice(&[PORTABLE_VERSION, b'B', 4, GAUGE_GROUP_U1, 4, 0, 0, 0]);
+        for extent in [1u64, 2, 1, 1] {
+            bytes.extend_from_slice(&extent.to_be_bytes());
+        }
+        bytes.extend_from_slice(&2u32.to_be_bytes());
+        bytes.extend_from_slice(b"{}");
+        let phases = [0.5f32, -1.25, 2.0, 0.0, 3.0, -0.75, 1.5, -2.5];
+        for phase in phases {
+            bytes.extend_from_slice(&phase.to_be_bytes());
+        }
+
+        let (lattice, metadata) = Lattice::read_portable(&mut &bytes[..]).unwrap();
+        assert_eq!((lattice.dims(), metadata.as_str()), ([1, 2, 1, 1], "{}"));
+        assert_eq!(lattice.to_array(), phases.map(|phase| phase as f64).to_vec());
+    }
+
+    #[test]
+    fn every_truncation_is_rejected() {
+        for length in 0..FIXTURE.len() {
+            let error = read_error(&FIXTURE[..length]);
+            assert!(error.contains("truncated"), "{} bytes: {}", length, error);
+        }
+        let mut trailing = FIXTURE.to_vec();
+        trailing.push(0);
+        assert!(read_error(&trailing).contains("1 bytes of trailing data"));
+    }
+
+    #[test]
+    fn every_malformed_header_field_is_reported() {
+        let cases: [(usize, u8, &str); 10] = [
+            (0, b'X', "magic string"),
+            (8, 2, "version 2"),
+            (9, b'M', "byte order"),
+            (10, 2, "precision of 2 bytes"),
+            (11, 2, "gauge group tag 2"),
+            (12, 3, "3 dimensional"),
+            (13, 3, "unknown flags"),
+            (15, 1, "reserved header bytes"),
+            /* the lowest byte of the second extent */
+            (24, 0, "contain a zero"),
+            /* a phase, caught by the checksum */
+            (0x70, 0x12, "checksum mismatch"),
+        ];
+        for (offset, value, message) in cases {
+            let mut bytes = FIXTURE.to_vec();
+            bytes[offset] = value;
+            let error = read_error(&bytes);
+            assert!(error.contains(message), "byte {} set to {}: {}", offset, value, error);
+        }
+
+        /* a metadata blob running past the end of the file, and one that is not UTF-8 */
+        let mut bytes = FIXTURE.to_vec();
+        bytes[48..52].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(read_error(&bytes).contains("truncated within its metadata"));
+        let mut bytes = FIXTURE.to_vec();
+        bytes[52] = 0xff;
+        assert!(read_error(&bytes).contains("UTF-8"));
+    }
+}
@@ -0,0 +1,73 @@
+/* curated parameter sets for common run types, individual flags still take precedence */
+pub struct Preset {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub lattice_width: usize,
+    pub measurements: usize,
+    pub equilibration_sweeps: usize,
+    pub sweeps_between_measurements: usize,
+    /* seconds between saves */
+    pub interval: usize,
+}
+
+pub const PRESETS: [Preset; 4] = [
+    Preset {
+        name: "quick-test",
+        description: "tiny lattice that finishes in seconds, for checking a setup works",
+        lattice_width: 4,
+        measurements: 100,
+        equilibration_sweeps: 100,
+        sweeps_between_measurements: 1,
+        interval: 10,
+    },
+    Preset {
+        name: "transition-scan",
+        description: "moderate statistics on 8^4, for locating the transition near beta = 1.01",
+        lattice_width: 8,
+        measurements: 2000,
+        equilibration_sweeps: 2000,
+        sweeps_between_measurements: 5,
+        interval: 60,
+    },
+    Preset {
+        name: "production-8",
+        description: "long equilibration and high statistics on 8^4",
+        lattice_width: 8,
+        measurements: 20000,
+        equilibration_sweeps: 5000,
+        sweeps_between_measurements: 10,
+        interval: 300,
+    },
+    Preset {
+        name: "production-16",
+        description: "long equilibration and high statistics on 16^4",
+        lattice_width: 16,
+        measurements: 20000,
+        equilibration_sweeps: 10000,
+        sweeps_between_measurements: 10,
+        interval: 300,
+    },
+];
+
+pub fn find_preset(name: &str) -> Option<&'static Preset> {
+    return PRESETS.iter().find(|preset| preset.name == name);
+}
+
+pub fn print_presets() {
+    println!(
+        "{:<16} {:>6} {:>13} {:>13} {:>8} {:>9}  description",
+        "name", "width", "measurements", "equilibration", "between", "save (s)"
+    );
+    for preset in PRESETS.iter() {
+        println!(
+            "{:<16} {:>6} {:>13} {:>13} {:>8} {:>9}  {}",
+            preset.name,
+            preset.lattice_width,
+            preset.measurements,
+            preset.equilibration_sweeps,
+            preset.sweeps_between_measurements,
+            preset.interval,
+            preset.description
+        );
+    }
+}
@@ -0,0 +1,202 @@
+use crate::latency::SweepLatency;
+use std::fmt;
+use std::panic::{self, PanicHookInfo};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/* exit code when a run panics after some measurements reached the output file */
+pub const EXIT_PANIC_WITH_DATA: i32 = 3;
+/* exit code when a run panics before anything was written */
+pub const EXIT_PANIC_WITHOUT_DATA: i32 = 4;
+
+/* stage a run is in, stored as its index in Progress::phase */
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Phase {
+    Equilibration,
+    Measurement,
+    Complete,
+    Aborted,
+    /* waiting for free disk space */
+    Paused,
+}
+
+impl Phase {
+    const ALL: [Phase; 5] =
+        [Phase::Equilibration, Phase::Measurement, Phase::Complete, Phase::Aborted, Phase::Paused];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Phase::Equilibration => "equilibration",
+            Phase::Measurement => "measurement",
+            Phase::Complete => "complete",
+            Phase::Aborted => "aborted",
+            Phase::Paused => "paused-disk-full",
+        }
+    }
+}
+
+/* counters of a running simulation, shared with the panic hook and the heartbeat */
+#[derive(Default)]
+pub struct Progress {
+    pub sweeps: AtomicUsize,
+    pub measurements: AtomicUsize,
+    pub saved_measurements: AtomicUsize,
+    pub latency: SweepLatency,
+    /* the disk space watchdog holds back checkpoints */
+    pub checkpoints_suspended: AtomicBool,
+    /* measurements covered by the last stored configuration, once there is one */
+    checkpoint_measurements: AtomicUsize,
+    checkpointed: AtomicBool,
+    phase: AtomicUsize,
+}
+
+impl Progress {
+    pub fn phase(&self) -> Phase {
+        return Phase::ALL[self.phase.load(Ordering::Relaxed)];
+    }
+
+    pub fn set_phase(&self, phase: Phase) {
+        self.phase.store(phase as usize, Ordering::Relaxed);
+    }
+
+    pub fn record_checkpoint(&self, measurements: usize) {
+        self.checkpoint_measurements.store(measurements, Ordering::Relaxed);
+        self.checkpointed.store(true, Ordering::Relaxed);
+    }
+
+    /* the measurements of the last stored configuration, None before the first checkpoint */
+    pub fn checkpoint(&self) -> Option<usize> {
+        if !self.checkpointed.load(Ordering::Relaxed) {
+            return None;
+        }
+        return Some(self.checkpoint_measurements.load(Ordering::Relaxed));
+    }
+
+    /* what the panic hook prints after the default panic message */
+    pub fn panic_report(&self, output: &str) -> Vec<String> {
+        let saved = self.saved_measurements.load(Ordering::Relaxed);
+        let mut lines = vec![
+            "simulation aborted".to_string(),
+            format!("last completed sweep: {}", self.sweeps.load(Ordering::Relaxed)),
+            format!("completed measurements: {}", self.measurements.load(Ordering::Relaxed)),
+            format!("output file: {}", output),
+        ];
+        if saved > 0 {
+            lines.push(format!("{} measurements were saved before the panic", saved));
+        } else {
+            lines.push("no measurements were saved before the panic".to_string());
+        }
+        match self.checkpoint() {
+            Some(measurements) if measurements < saved => {
+                lines.push(format!(
+                    "the configuration after {} measurements is stored, resume continues from there",
+                    measurements
+                ));
+                if self.checkpoints_suspended.load(Ordering::Relaxed) {
+                    lines.push("later checkpoints were held back for lack of disk space".to_string());
+                }
+            }
+            Some(_) => lines.push("the configuration of the last save is stored, resume continues from there".to_string()),
+            None => lines.push("no configuration checkpoint is stored, the run cannot be resumed".to_string()),
+        }
+        return lines;
+    }
+
+    pub fn exit_code(&self) -> i32 {
+        if self.saved_measurements.load(Ordering::Relaxed) > 0 {
+            return EXIT_PANIC_WITH_DATA;
+        }
+        return EXIT_PANIC_WITHOUT_DATA;
+    }
+}
+
+/* the error of a run stopped by a panic, the process exits with the exit code of its progress */
+pub struct Aborted {
+    pub progress: Arc<Progress>,
+}
+
+impl fmt::Display for Aborted {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return write!(
+            f,
+            "the simulation panicked after {} sweeps and {} measurements",
+            self.progress.sweeps.load(Ordering::Relaxed),
+            self.progress.measurements.load(Ordering::Relaxed)
+        );
+    }
+}
+
+impl fmt::Debug for Aborted {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return fmt::Display::fmt(self, f);
+    }
+}
+
+impl std::error::Error for Aborted {}
+
+type Hook = Box<dyn Fn(&PanicHookInfo) + Sync + Send + 'static>;
+
+/* the installed panic report, dropping it restores the hook from before */
+pub struct PanicReport {
+    previous: Arc<Hook>,
+}
+
+impl Drop for PanicReport {
+    fn drop(&mut self) {
+        /* the hook can not be changed while unwinding */
+        if !std::thread::panicking() {
+            let previous = self.previous.clone();
+            panic::set_hook(Box::new(move |info| previous(info)));
+        }
+    }
+}
+
+/* after the default panic message, report how far the run got and what is on disk */
+pub fn install_panic_report(progress: Arc<Progress>, output: String) -> PanicReport {
+    let previous = Arc::new(panic::take_hook());
+    let default_hook = previous.clone();
+
+    panic::set_hook(Box::new(move |info| {
+        default_hook(info);
+        for line in progress.panic_report(&output) {
+            eprintln!("{}", line);
+        }
+    }));
+    return PanicReport { previous };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_report_names_the_counters_and_what_resume_can_use() {
+        let progress = Progress::default();
+        progress.sweeps.store(130, Ordering::Relaxed);
+        progress.measurements.store(12, Ordering::Relaxed);
+        let report = progress.panic_report("run.h5");
+        assert_eq!(&report[..4], ["simulation aborted", "last completed sweep: 130", "completed measurements: 12", "output file: run.h5"]);
+        assert_eq!(report[4], "no measurements were saved before the panic");
+        assert_eq!(report[5], "no configuration checkpoint is stored, the run cannot be resumed");
+        assert_eq!(progress.exit_code(), EXIT_PANIC_WITHOUT_DATA);
+
+        /* measurements saved while the watchdog suspends checkpoints do not make the run resumable */
+        progress.saved_measurements.store(10, Ordering::Relaxed);
+        progress.checkpoints_suspended.store(true, Ordering::Relaxed);
+        let report = progress.panic_report("run.h5");
+        assert_eq!(report[4], "10 measurements were saved before the panic");
+        assert_eq!(report[5], "no configuration checkpoint is stored, the run cannot be resumed");
+        assert_eq!(progress.exit_code(), EXIT_PANIC_WITH_DATA);
+
+        progress.record_checkpoint(4);
+        let report = progress.panic_report("run.h5");
+        assert_eq!(report[5], "the configuration after 4 measurements is stored, resume continues from there");
+        assert_eq!(report[6], "later checkpoints were held back for lack of disk space");
+
+        progress.record_checkpoint(10);
+        progress.checkpoints_suspended.store(false, Ordering::Relaxed);
+        let report = progress.panic_report("run.h5");
+        assert_eq!(report.len(), 6);
+        assert_eq!(report[5], "the configuration of the last save is stored, resume continues from there");
+    }
+}
@@ -0,0 +1,178 @@
+use anyhow::{bail, Context, Result};
+use std::io::{ErrorKind, Write};
+use std::net::{TcpListener, TcpStream};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+
+/* where measurement records are published, parsed from `unix:<path>` or `tcp:<addr>` */
+enum Endpoint {
+    Unix(UnixListener, PathBuf),
+    Tcp(TcpListener),
+}
+
+enum Client {
+    Unix(UnixStream),
+    Tcp(TcpStream),
+}
+
+impl Client {
+    fn write(&mut self, buffer: &[u8]) -> std::io::Result<usize> {
+        match self {
+            Client::Unix(stream) => stream.write(buffer),
+            Client::Tcp(stream) => stream.write(buffer),
+        }
+    }
+}
+
+/* pushes measurement records as line-delimited JSON to every connected client,
+ * without ever blocking the simulation: slow clients simply miss records */
+pub struct Publisher {
+    endpoint: Endpoint,
+    clients: Vec<Client>,
+}
+
+impl Publisher {
+    pub fn bind(spec: &str) -> Result<Self> {
+        let endpoint = if let Some(path) = spec.strip_prefix("unix:") {
+            let listener = UnixListener::bind(path)
+                .with_context(|| format!("failed to bind unix socket {}", path))?;
+            listener.set_nonblocking(true)?;
+            Endpoint::Unix(listener, PathBuf::from(path))
+        } else if let Some(address) = spec.strip_prefix("tcp:") {
+            let listener = TcpListener::bind(address)
+                .with_context(|| format!("failed to bind tcp address {}", address))?;
+            listener.set_nonblocking(true)?;
+            Endpoint::Tcp(listener)
+        } else {
+            bail!("publish target must look like unix:<path> or tcp:<host>:<port>, got {}", spec);
+        };
+
+        Ok(Self {
+            endpoint,
+            clients: Vec::new(),
+        })
+    }
+
+    /* pick up any clients that connected since the last record */
+    fn accept_pending(&mut self) {
+        loop {
+            let client = match &self.endpoint {
+                Endpoint::Unix(listener, _) => listener
+                    .accept()
+                    .and_then(|(stream, _)| stream.set_nonblocking(true).map(|_| Client::Unix(stream))),
+                Endpoint::Tcp(listener) => listener
+                    .accept()
+                    .and_then(|(stream, _)| stream.set_nonblocking(true).map(|_| Client::Tcp(stream))),
+            };
+
+            match client {
+                Ok(client) => self.clients.push(client),
+                Err(_) => break,
+            }
+        }
+    }
+
+    pub fn publish(&mut self, index: usize, sweep: usize, observables: &[(&str, f64)]) {
+        self.accept_pending();
+        if self.clients.is_empty() {
+            return;
+        }
+
+        let mut line = format!("{{\"index\":{},\"sweep\":{}", index, sweep);
+        for (name, value) in observables {
+            if value.is_finite() {
+                line.push_str(&format!(",\"{}\":{}", name, value));
+            } else {
+                line.push_str(&format!(",\"{}\":null", name));
+            }
+        }
+        line.push_str("}\n");
+
+        /* a client whose buffer is full misses this record; a client that only took
+         * part of the line is dropped, since it would otherwise receive a torn record */
+        self.clients.retain_mut(|client| match client.write(line.as_bytes()) {
+            Ok(written) => written == line.len(),
+            Err(error) => error.kind() == ErrorKind::WouldBlock,
+        });
+    }
+}
+
+impl Drop for Publisher {
+    fn drop(&mut self) {
+        if let Endpoint::Unix(_, path) = &self.endpoint {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::json::{self, Json};
+    use std::io::{BufRead, BufReader};
+
+    fn socket_path(name: &str) -> PathBuf {
+        return std::env::temp_dir().join(format!("lattice-rust-{}-{}.sock", name, std::process::id()));
+    }
+
+    fn number(record: &Json, key: &str) -> f64 {
+        match record.get(key) {
+            Some(Json::Number(text)) => text.parse().unwrap(),
+            other => panic!("{} is {:?}", key, other),
+        }
+    }
+
+    #[test]
+    fn records_arrive_as_json_lines() {
+        let path = socket_path("records");
+        let mut publisher = Publisher::bind(&format!("unix:{}", path.display())).unwrap();
+        let client = UnixStream::connect(&path).unwrap();
+
+        for index in 0..3 {
+            let observables = [("action", 0.25 * index as f64), ("monopole_density", 0.5), ("ratio", f64::NAN)];
+            publisher.publish(index, 10 * index, &observables);
+        }
+        drop(publisher);
+
+        let records: Vec<Json> = BufReader::new(client)
+            .lines()
+            .map(|line| json::parse(&line.unwrap()).unwrap())
+            .collect();
+        assert_eq!(records.len(), 3);
+        for (index, record) in records.iter().enumerate() {
+            assert_eq!(number(record, "index"), index as f64);
+            assert_eq!(number(record, "sweep"), 10.0 * index as f64);
+            assert_eq!(number(record, "action"), 0.25 * index as f64);
+            assert_eq!(number(record, "monopole_density"), 0.5);
+            assert_eq!(record.get("ratio"), Some(&Json::Null));
+        }
+        assert!(!path.exists(), "the socket file is removed with the publisher");
+    }
+
+    #[test]
+    fn a_client_that_does_not_read_never_blocks() {
+        let path = socket_path("stalled");
+        let mut publisher = Publisher::bind(&format!("unix:{}", path.display())).unwrap();
+        let client = UnixStream::connect(&path).unwrap();
+
+        /* far more than the socket buffer holds */
+        for index in 0..100_000 {
+            publisher.publish(index, index, &[("action", 0.5)]);
+        }
+        drop(publisher);
+
+        /* whatever arrived consists of whole records in order */
+        let mut last = None;
+        for line in BufReader::new(client).lines() {
+            let index = number(&json::parse(&line.unwrap()).unwrap(), "index");
+            assert!(last.is_none_or(|last| index > last));
+            last = Some(index);
+        }
+        assert!(last.is_some());
+    }
+
+    #[test]
+    fn rejects_unknown_targets() {
+        assert!(Publisher::bind("pipe:/tmp/x").is_err());
+    }
+}
@@ -0,0 +1,75 @@
+use crate::config::json_string;
+use fastrand::Rng;
+
+/* hands out named random streams derived from a single master seed, so that every consumer of
+ * randomness can be reproduced from (master seed, name) and no two consumers share a stream */
+pub struct RngRegistry {
+    master_seed: u64,
+    streams: Vec<String>,
+}
+
+impl RngRegistry {
+    pub fn new(master_seed: u64) -> Self {
+        return Self {
+            master_seed,
+            streams: Vec::new(),
+        };
+    }
+
+    pub fn from_entropy() -> Self {
+        return Self::new(Rng::new().u64(..));
+    }
+
+    pub fn master_seed(&self) -> u64 {
+        return self.master_seed;
+    }
+
+    /* the derivation index of a stream is the order in which it was requested */
+    pub fn stream(&mut self, name: &str) -> Rng {
+        debug_assert!(
+            !self.streams.iter().any(|stream| stream == name),
+            "random stream {} is requested twice",
+            name
+        );
+        self.streams.push(name.to_string());
+        return Rng::with_seed(derive_seed(self.master_seed, name));
+    }
+
+    pub fn streams(&self) -> &[String] {
+        return &self.streams;
+    }
+
+    pub fn to_json(&self) -> String {
+        let streams: Vec<String> = self
+            .streams
+            .iter()
+            .enumerate()
+            .map(|(index, name)| format!("{{\"index\":{},\"name\":{}}}", index, json_string(name)))
+            .collect();
+        return format!(
+            "{{\"master_seed\":{},\"streams\":[{}]}}",
+            self.master_seed,
+            streams.join(",")
+        );
+    }
+}
+
+/* FNV-1a hash of the name mixed into the master seed, so that similar names still give unrelated
+ * streams */
+pub fn derive_seed(master_seed: u64, name: &str) -> u64 {
+    let mut hash = 0xcbf29ce484222325u64;
+    for byte in name.bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+
+    return mix_seed(master_seed, hash);
+}
+
+/* seed of the index-th substream of a seed, through the splitmix64 finalizer */
+pub fn mix_seed(seed: u64, index: u64) -> u64 {
+    let mut z = seed.wrapping_add(index.wrapping_mul(0x9e3779b97f4a7c15));
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    return z ^ (z >> 31);
+}
@@ -0,0 +1,254 @@
+use crate::action::HiggsAction;
+use crate::lattice::{sample_theta, Lattice};
+use fastrand::Rng;
+use num_complex::{Complex, ComplexFloat};
+use std::f64::consts::PI;
+
+/* compact scalar (Higgs) phase field living on the sites, coupled to the links through the
+ * hopping term kappa * sum_{n, mu} cos(phi(n) + theta_mu(n) - phi(n + mu)). The phases are stored
+ * in the order of the flat storage of the lattice they couple to, so its site positions and
+ * neighbor tables index the field as well */
+#[derive(Clone, Debug)]
+pub struct ScalarField {
+    phases: Vec<f64>,
+    dims: [usize; 4],
+}
+
+impl ScalarField {
+    pub fn new_uniform(dims: [usize; 4]) -> Self {
+        Self {
+            phases: vec![0.0; dims.iter().product()],
+            dims,
+        }
+    }
+
+    pub fn new_random(dims: [usize; 4], rng: &mut Rng) -> Self {
+        let mut new_field = ScalarField::new_uniform(dims);
+
+        for phase in new_field.phases.iter_mut() {
+            *phase = rng.f64() * 2.0 * PI;
+        }
+
+        return new_field;
+    }
+
+    /* a field from the phases of a checkpoint, in the order of phases() */
+    pub fn from_phases(dims: [usize; 4], phases: &[f64]) -> anyhow::Result<Self> {
+        if phases.len() != dims.iter().product::<usize>() {
+            anyhow::bail!("{} scalar phases do not make up a field with extents {:?}", phases.len(), dims);
+        }
+        return Ok(Self {
+            phases: phases.to_vec(),
+            dims,
+        });
+    }
+
+    /* every phase in the flat site order of the lattice, for checkpoints */
+    pub fn phases(&self) -> &[f64] {
+        return &self.phases;
+    }
+
+    /* phase phi(n) at a site position of the lattice */
+    pub fn phase(&self, site: usize) -> f64 {
+        return self.phases[site];
+    }
+
+    /* phase of the hopping term cos(phi(n) + theta_mu(n) - phi(n + mu)) without the link itself */
+    pub fn link_term_phase(&self, lattice: &Lattice, site: usize, m: usize) -> f64 {
+        return self.phases[site] - self.phases[lattice.neighbor_up(site, m)];
+    }
+
+    /* sum over the eight hopping terms containing phi(n), such that they add up to
+     * Re(e^{i phi(n)} h) */
+    fn site_environment(&self, lattice: &Lattice, site: usize) -> Complex<f64> {
+        let mut h = Complex::from_polar(0.0, 0.0);
+
+        for m in 0..4 {
+            let forward = self.phases[lattice.neighbor_up(site, m)]; /* phi(n + \hat{\mu}) */
+            h += Complex::from_polar(1.0, lattice.link_phase_at(site, m) - forward);
+
+            let back = lattice.neighbor_down(site, m);
+            let backward = self.phases[back]; /* phi(n - \hat{\mu}) */
+            h += Complex::from_polar(1.0, -backward - lattice.link_phase_at(back, m));
+        }
+
+        return h;
+    }
+
+    /* heatbath for every scalar phase given its link environment */
+    pub fn heatbath_sweep(&mut self, lattice: &Lattice, kappa: f64, rng: &mut Rng) {
+        assert_eq!(self.dims, lattice.dims(), "scalar field and lattice differ in their extents");
+        for site in 0..self.phases.len() {
+            let environment = self.site_environment(lattice, site);
+            let new_phi = sample_theta(environment.abs(), kappa, rng);
+
+            self.phases[site] = new_phi - environment.arg();
+        }
+    }
+
+    /* average of the gauge invariant link cos(phi(n) + theta_mu(n) - phi(n + mu)) */
+    pub fn average_hopping(&self, lattice: &Lattice) -> f64 {
+        let mut sum = 0f64;
+
+        for site in 0..self.phases.len() {
+            for m in 0..4 {
+                sum += (self.link_term_phase(lattice, site, m) + lattice.link_phase_at(site, m)).cos();
+            }
+        }
+
+        return sum / (4 * self.phases.len()) as f64;
+    }
+}
+
+/* a scalar field with its hopping parameter, updated from its own random stream so that the
+ * sequence of link updates is the same as without matter when kappa = 0 */
+pub struct Matter {
+    pub field: ScalarField,
+    pub kappa: f64,
+    pub rng: Rng,
+}
+
+impl Matter {
+    /* one heatbath sweep over the links followed by one over the scalar field */
+    pub fn sweep(&mut self, lattice: &mut Lattice, beta: f64, rng: &mut Rng) {
+        let action = HiggsAction {
+            beta,
+            kappa: self.kappa,
+            field: &self.field,
+        };
+        lattice.heatbath_sweep_with_action(&action, rng);
+        self.field.heatbath_sweep(lattice, self.kappa, &mut self.rng);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::action::Couplings;
+    use crate::analysis;
+    use crate::simulation::Simulation;
+
+    #[test]
+    fn vanishing_kappa_reproduces_the_pure_gauge_chain() {
+        let mut rng = Rng::with_seed(21);
+        let lattice = Lattice::new_random(4, &mut rng);
+        let mut pure = Simulation::new(lattice.clone(), Couplings::isotropic(1.1), Rng::with_seed(22));
+        let mut coupled = Simulation::new(lattice, Couplings::isotropic(1.1), Rng::with_seed(22));
+        coupled.matter = Some(Matter {
+            field: ScalarField::new_random([4; 4], &mut rng),
+            kappa: 0.0,
+            rng: Rng::with_seed(23),
+        });
+
+        pure.thermalize(20);
+        coupled.thermalize(20);
+        assert_eq!(pure.lattice.to_array(), coupled.lattice.to_array());
+    }
+
+    #[test]
+    fn the_field_shares_the_site_layout_of_the_lattice() {
+        let mut rng = Rng::with_seed(27);
+        let lattice = Lattice::new_random_dims([3, 2, 4, 2], &mut rng);
+        let field = ScalarField::new_random(lattice.dims(), &mut rng);
+        for site in lattice.sites() {
+            let position = lattice.position(site);
+            for m in 0..4 {
+                let forward = field.phase(lattice.position(site.shift(m, 1)));
+                assert_eq!(field.link_term_phase(&lattice, position, m), field.phase(position) - forward);
+            }
+        }
+    }
+
+    #[test]
+    fn a_field_survives_the_round_trip_through_its_phases() {
+        let field = ScalarField::new_random([3, 2, 4, 2], &mut Rng::with_seed(8));
+        let restored = ScalarField::from_phases([3, 2, 4, 2], field.phases()).unwrap();
+        assert_eq!(restored.phases(), field.phases());
+        assert!(ScalarField::from_phases([3, 2, 4, 3], field.phases()).is_err());
+    }
+
+    #[test]
+    fn the_flat_field_reproduces_the_nested_one() {
+        /* bits of the chain when the field was stored as nested vectors */
+        let mut rng = Rng::with_seed(51);
+        let lattice = Lattice::new_random_dims([4, 2, 3, 2], &mut rng);
+        let mut simulation = Simulation::new(lattice, Couplings::isotropic(1.2), Rng::with_seed(52));
+        simulation.matter = Some(Matter {
+            field: ScalarField::new_random([4, 2, 3, 2], &mut rng),
+            kappa: 0.7,
+            rng: Rng::with_seed(53),
+        });
+        simulation.thermalize(6);
+
+        let matter = simulation.matter.as_ref().unwrap();
+        let bytes: Vec<u8> = simulation.lattice.to_array().iter().flat_map(|x| x.to_le_bytes()).collect();
+        assert_eq!(simulation.lattice.average_action().to_bits(), 0x3fcb917a3d26d0a5);
+        assert_eq!(matter.field.average_hopping(&simulation.lattice).to_bits(), 0x3fe24f31e6a17fa5);
+        assert_eq!(crate::portable::xxh64(&bytes, 0), 0x0c73f2ff0e7dd713);
+    }
+
+    /* independent Metropolis simulation of the 4d XY model with weight
+     * exp(kappa sum_{n, mu} cos(phi(n) - phi(n + mu))) on a periodic width^4 lattice, returning the
+     * average of the bond energy cos(phi(n) - phi(n + mu)) per sweep */
+    fn xy_bond_energies(width: usize, kappa: f64, sweeps: usize, rng: &mut Rng) -> Vec<f64> {
+        let volume = width.pow(4);
+        let neighbor = |site: usize, mu: usize, forward: bool| {
+            let stride = width.pow(mu as u32);
+            let coordinate = site / stride % width;
+            let moved = if forward { (coordinate + 1) % width } else { (coordinate + width - 1) % width };
+            site - coordinate * stride + moved * stride
+        };
+        let mut phi: Vec<f64> = (0..volume).map(|_| 2.0 * PI * rng.f64()).collect();
+        let mut energies = Vec::with_capacity(sweeps);
+
+        for _ in 0..sweeps {
+            for site in 0..volume {
+                let proposal = phi[site] + 2.0 * (rng.f64() - 0.5);
+                let mut change = 0.0;
+                for mu in 0..4 {
+                    for other in [neighbor(site, mu, true), neighbor(site, mu, false)] {
+                        change += (proposal - phi[other]).cos() - (phi[site] - phi[other]).cos();
+                    }
+                }
+                if rng.f64() < (kappa * change).exp() {
+                    phi[site] = proposal;
+                }
+            }
+            let bonds: f64 = (0..volume)
+                .map(|site| (0..4).map(|mu| (phi[site] - phi[neighbor(site, mu, true)]).cos()).sum::<f64>())
+                .sum();
+            energies.push(bonds / (4 * volume) as f64);
+        }
+        return energies;
+    }
+
+    #[test]
+    fn infinite_beta_reduces_to_the_xy_model() {
+        let kappa = 0.25;
+        let (burn_in, sweeps) = (200, 1200);
+        let mut rng = Rng::with_seed(24);
+
+        let mut higgs = Simulation::new(Lattice::new_uniform(4), Couplings::isotropic(1e8), Rng::with_seed(25));
+        higgs.matter = Some(Matter {
+            field: ScalarField::new_random([4; 4], &mut rng),
+            kappa,
+            rng: Rng::with_seed(26),
+        });
+        higgs.thermalize(burn_in);
+        let hopping: Vec<f64> = (0..sweeps)
+            .map(|_| {
+                higgs.sweep();
+                let matter = higgs.matter.as_ref().unwrap();
+                matter.field.average_hopping(&higgs.lattice)
+            })
+            .collect();
+
+        let xy = xy_bond_energies(4, kappa, burn_in + sweeps, &mut rng);
+        let xy = &xy[burn_in..];
+
+        let error = |series: &[f64]| analysis::jackknife_error(series, 40).unwrap();
+        let difference = (analysis::mean(&hopping) - analysis::mean(xy)).abs();
+        let combined = error(&hopping).hypot(error(xy));
+        assert!(difference < 4.0 * combined, "difference {} with error {}", difference, combined);
+    }
+}
@@ -0,0 +1,145 @@
+use crate::analysis;
+use anyhow::{bail, Result};
+
+/* decimals of the coupling in the group names, e.g. beta_1.010 */
+const GROUP_DECIMALS: i32 = 3;
+/* the couplings of a range are rounded to this many decimals, so that start + i * step is stored as
+ * it would be typed instead of e.g. 1.0100000000000002 */
+const RANGE_DECIMALS: i32 = 12;
+
+/* name of the group holding the measurements at beta */
+pub fn group_name(beta: f64) -> String {
+    return format!("beta_{:.*}", GROUP_DECIMALS as usize, beta);
+}
+
+/* the couplings from start to end in steps of step, downward if end is below start. End is
+ * included when it is a whole number of steps away */
+pub fn beta_range(start: f64, end: f64, step: f64) -> Result<Vec<f64>> {
+    if !start.is_finite() || !end.is_finite() {
+        bail!("--beta-start and --beta-end must be finite, got {} and {}", start, end);
+    }
+    let resolution = 10f64.powi(-GROUP_DECIMALS);
+    if !step.is_finite() || step < resolution {
+        bail!(
+            "--beta-step must be at least {}, the resolution of the group names, got {}. Scan downward by giving --beta-end below --beta-start",
+            resolution,
+            step
+        );
+    }
+
+    let direction = if end < start { -1.0 } else { 1.0 };
+    /* the tolerance keeps the end of e.g. 0.9 to 1.1 in steps of 0.1 despite rounding */
+    let steps = ((end - start).abs() / step + 1e-9).floor() as usize;
+    let scale = 10f64.powi(RANGE_DECIMALS);
+    return Ok((0..=steps)
+        .map(|i| ((start + direction * i as f64 * step) * scale).round() / scale)
+        .collect());
+}
+
+/* reject scans that store two couplings in the same group */
+pub fn check_betas(betas: &[f64]) -> Result<()> {
+    if betas.is_empty() {
+        bail!("a scan needs at least one coupling");
+    }
+    for (index, &beta) in betas.iter().enumerate() {
+        if let Some(&earlier) = betas[..index].iter().find(|&&earlier| group_name(earlier) == group_name(beta)) {
+            bail!(
+                "the couplings {} and {} would both be stored in the group {}",
+                earlier,
+                beta,
+                group_name(beta)
+            );
+        }
+    }
+    return Ok(());
+}
+
+/* mean action of one coupling of a scan. The error is the jackknife error at the largest bin size,
+ * which keeps the long autocorrelations near the transition out of it as far as the series allows */
+#[derive(Copy, Clone, Debug)]
+pub struct ScanPoint {
+    pub beta: f64,
+    pub mean: f64,
+    pub error: Option<f64>,
+}
+
+impl ScanPoint {
+    pub fn new(beta: f64, series: &[f64]) -> Self {
+        let error = analysis::bin_sizes(series.len())
+            .last()
+            .and_then(|&bin_size| analysis::jackknife_error(series, bin_size));
+        return Self { beta, mean: analysis::mean(series), error };
+    }
+}
+
+/* the largest difference in the mean action between the two branches of a hysteresis scan, at
+ * couplings both branches measured */
+#[derive(Copy, Clone, Debug)]
+pub struct Discrepancy {
+    pub beta: f64,
+    /* heating minus cooling */
+    pub difference: f64,
+    /* the difference in combined errors, None where a branch has no error */
+    pub significance: Option<f64>,
+}
+
+pub fn max_discrepancy(heating: &[ScanPoint], cooling: &[ScanPoint]) -> Option<Discrepancy> {
+    let mut largest: Option<Discrepancy> = None;
+    for heated in heating {
+        let Some(cooled) = cooling.iter().find(|cooled| cooled.beta == heated.beta) else {
+            continue;
+        };
+        let difference = heated.mean - cooled.mean;
+        if largest.is_some_and(|largest| largest.difference.abs() >= difference.abs()) {
+            continue;
+        }
+        let significance = match (heated.error, cooled.error) {
+            (Some(heated_error), Some(cooled_error)) if heated_error > 0.0 || cooled_error > 0.0 => {
+                Some(difference.abs() / (heated_error * heated_error + cooled_error * cooled_error).sqrt())
+            }
+            _ => None,
+        };
+        largest = Some(Discrepancy { beta: heated.beta, difference, significance });
+    }
+    return largest;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(beta: f64, mean: f64, error: Option<f64>) -> ScanPoint {
+        return ScanPoint { beta, mean, error };
+    }
+
+    #[test]
+    fn the_largest_discrepancy_is_taken_at_couplings_of_both_branches() {
+        let heating = [point(1.1, 0.30, Some(0.01)), point(1.0, 0.36, Some(0.03)), point(0.9, 0.50, Some(0.01))];
+        /* 0.9 is only in the heating branch, its large difference does not count */
+        let cooling = [point(0.95, 0.0, None), point(1.0, 0.40, Some(0.04)), point(1.1, 0.31, Some(0.01))];
+
+        let discrepancy = max_discrepancy(&heating, &cooling).unwrap();
+        assert_eq!(discrepancy.beta, 1.0);
+        assert!((discrepancy.difference + 0.04).abs() < 1e-12);
+        assert!((discrepancy.significance.unwrap() - 0.8).abs() < 1e-12);
+
+        /* without errors there is no significance, and without shared couplings no discrepancy */
+        let unmeasured = [point(1.0, 0.40, None)];
+        assert!(max_discrepancy(&heating, &unmeasured).unwrap().significance.is_none());
+        assert!(max_discrepancy(&heating[2..], &unmeasured).is_none());
+        assert!(max_discrepancy(&[], &cooling).is_none());
+    }
+
+    #[test]
+    fn a_scan_point_carries_the_mean_and_its_binned_error() {
+        let series: Vec<f64> = (0..64).map(|index| ((index * 37) % 11) as f64 / 10.0).collect();
+        let point = ScanPoint::new(1.0, &series);
+        assert_eq!(point.mean, analysis::mean(&series));
+        /* 64 measurements bin down to the largest bin size that leaves enough bins */
+        let bin_size = *analysis::bin_sizes(series.len()).last().unwrap();
+        assert!(bin_size > 1);
+        assert_eq!(point.error, analysis::jackknife_error(&series, bin_size));
+        assert!(point.error.unwrap() > 0.0);
+        assert!(ScanPoint::new(1.0, &[0.5]).error.is_none());
+    }
+}
@@ -0,0 +1,73 @@
+use crate::buildinfo::BuildInfo;
+use crate::config::{json_string, RunConfig};
+use crate::json::{self, Json};
+use anyhow::{Context, Result};
+
+/* version of the sidecar layout, bumped whenever fields change meaning */
+const SIDECAR_FORMAT_VERSION: usize = 1;
+
+/* summary of the measurements that are already stored in the HDF5 file */
+#[derive(Default)]
+pub struct SavedSummary {
+    pub completed_measurements: usize,
+    pub saved_measurements: usize,
+    pub action_sum: f64,
+    pub action_sum_squares: f64,
+    pub complete: bool,
+}
+
+impl SavedSummary {
+    pub fn add_saved(&mut self, actions: &[f64]) {
+        self.saved_measurements += actions.len();
+        self.action_sum += actions.iter().sum::<f64>();
+        self.action_sum_squares += actions.iter().map(|action| action * action).sum::<f64>();
+    }
+}
+
+pub fn sidecar_path(output: &str) -> String {
+    return format!("{}.meta.json", output);
+}
+
+/* the sidecar next to the output file */
+pub fn read_sidecar(output: &str) -> Result<Json> {
+    let path = sidecar_path(output);
+    let text = std::fs::read_to_string(&path).with_context(|| format!("failed to read {}", path))?;
+    return json::parse(&text).with_context(|| format!("failed to parse {}", path));
+}
+
+/* rewrite the sidecar next to the output file, going through a temporary file and a rename so
+ * that readers never see a half written sidecar */
+pub fn write_sidecar(config: &RunConfig, summary: &SavedSummary) -> Result<()> {
+    let path = sidecar_path(&config.name);
+    let temporary_path = format!("{}.tmp", path);
+
+    let (mean, standard_deviation) = if summary.saved_measurements > 0 {
+        let n = summary.saved_measurements as f64;
+        let mean = summary.action_sum / n;
+        let variance = (summary.action_sum_squares / n - mean * mean).max(0.0);
+        (mean.to_string(), variance.sqrt().to_string())
+    } else {
+        ("null".to_string(), "null".to_string())
+    };
+
+    let contents = format!(
+        "{{\"format_version\":{},\"crate\":{},\"crate_version\":{},\"config\":{},\"buildinfo\":{},\"completed_measurements\":{},\"saved_measurements\":{},\"mean_action\":{},\"action_standard_deviation\":{},\"complete\":{}}}\n",
+        SIDECAR_FORMAT_VERSION,
+        json_string(env!("CARGO_PKG_NAME")),
+        json_string(env!("CARGO_PKG_VERSION")),
+        config.to_json(),
+        BuildInfo::collect(config.threads).to_json(),
+        summary.completed_measurements,
+        summary.saved_measurements,
+        mean,
+        standard_deviation,
+        summary.complete
+    );
+
+    std::fs::write(&temporary_path, contents)
+        .with_context(|| format!("failed to write {}", temporary_path))?;
+    std::fs::rename(&temporary_path, &path)
+        .with_context(|| format!("failed to move {} into place", temporary_path))?;
+
+    Ok(())
+}
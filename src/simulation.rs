@@ -0,0 +1,379 @@
+use crate::action::{Couplings, ExtendedAction, WilsonAction};
+use crate::lattice::{Lattice, LinkScores, MetropolisStats};
+use crate::scalar::Matter;
+use clap::ValueEnum;
+use fastrand::Rng;
+use std::f64::consts::PI;
+
+/* acceptance rate the Metropolis step size is tuned towards during the burn in */
+const TARGET_ACCEPTANCE: f64 = 0.5;
+
+/* extra heatbath hits on the highest scoring links after every sweep. The score threshold is
+ * refreshed every `refresh` sweeps of the burn in and frozen afterwards, see refresh_targeting */
+#[derive(Clone, Debug)]
+pub struct Targeting {
+    pub fraction: f64,
+    pub hits: usize,
+    pub refresh: usize,
+    /* computed from the current configuration when missing, e.g. at the first sweep */
+    pub scores: Option<LinkScores>,
+}
+
+/* a coarse update after every sweep, see Lattice::coarse_update, with its acceptance over the
+ * whole chain */
+#[derive(Copy, Clone, Debug)]
+pub struct CoarseUpdate {
+    pub block: usize,
+    pub amplitude: f64,
+    pub stats: MetropolisStats,
+}
+
+impl CoarseUpdate {
+    pub fn new(block: usize, amplitude: f64) -> Self {
+        return Self {
+            block,
+            amplitude,
+            stats: MetropolisStats::default(),
+        };
+    }
+}
+
+/* how the links are updated */
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Algorithm {
+    Heatbath,
+    Metropolis,
+}
+
+impl Algorithm {
+    pub fn name(&self) -> &'static str {
+        return match self {
+            Algorithm::Heatbath => "heatbath",
+            Algorithm::Metropolis => "metropolis",
+        };
+    }
+}
+
+/* a Markov chain without any I/O: the configuration, the random stream of its updates and how
+ * far the chain has got */
+pub struct Simulation {
+    pub lattice: Lattice,
+    pub matter: Option<Matter>,
+    /* beta of the spatial and the temporal plaquettes, a run with matter is isotropic */
+    pub couplings: Couplings,
+    /* coupling of the double charge plaquette term of a pure gauge run */
+    pub gamma: f64,
+    pub rng: Rng,
+    pub sweeps: usize,
+    pub measurements: usize,
+    pub algorithm: Algorithm,
+    /* half width of the Metropolis proposals */
+    pub step_size: f64,
+    /* Metropolis proposals and acceptances since the last tuning */
+    pub metropolis_stats: MetropolisStats,
+    /* overrelaxation sweeps following every link update sweep of a pure gauge run */
+    pub overrelaxation: usize,
+    pub targeting: Option<Targeting>,
+    /* block shift move of a pure gauge run after every sweep */
+    pub coarse: Option<CoarseUpdate>,
+}
+
+impl Simulation {
+    pub fn new(lattice: Lattice, couplings: Couplings, rng: Rng) -> Self {
+        return Self {
+            lattice,
+            matter: None,
+            couplings,
+            gamma: 0.0,
+            rng,
+            sweeps: 0,
+            measurements: 0,
+            algorithm: Algorithm::Heatbath,
+            step_size: 1.0,
+            metropolis_stats: MetropolisStats::default(),
+            overrelaxation: 0,
+            targeting: None,
+            coarse: None,
+        };
+    }
+
+    /* one sweep over the links with the chosen algorithm followed by the overrelaxation sweeps, or
+     * a heatbath sweep over the links and the scalar field if there is one */
+    pub fn sweep(&mut self) {
+        let action = ExtendedAction {
+            couplings: self.couplings,
+            gamma: self.gamma,
+        };
+        match (&mut self.matter, self.algorithm) {
+            (Some(matter), _) => matter.sweep(&mut self.lattice, self.couplings.spatial, &mut self.rng),
+            (None, Algorithm::Heatbath) => self.lattice.heatbath_sweep_with_action(&action, &mut self.rng),
+            (None, Algorithm::Metropolis) => {
+                let stats = self.lattice.metropolis_sweep_with_action(&action, self.step_size, &mut self.rng);
+                self.metropolis_stats.add(stats);
+            }
+        }
+        if self.matter.is_none() {
+            /* the reflection only depends on the ratio of the couplings */
+            let reflection = WilsonAction { couplings: self.couplings };
+            for _ in 0..self.overrelaxation {
+                self.lattice.overrelaxation_sweep_with_action(&reflection);
+            }
+            if let Some(coarse) = &mut self.coarse {
+                let accepted =
+                    self.lattice
+                        .coarse_update(self.couplings, self.gamma, coarse.block, coarse.amplitude, &mut self.rng);
+                coarse.stats.add(MetropolisStats {
+                    proposals: 1,
+                    accepted: accepted as usize,
+                });
+            }
+        }
+        if let Some(targeting) = &mut self.targeting {
+            let scores = targeting
+                .scores
+                .get_or_insert_with(|| LinkScores::compute(&self.lattice, Lattice::staple_deficit, targeting.fraction));
+            self.lattice.targeted_hits(self.couplings, scores, targeting.hits, &mut self.rng);
+        }
+        self.sweeps += 1;
+    }
+
+    pub fn thermalize(&mut self, sweeps: usize) {
+        for _ in 0..sweeps {
+            self.sweep();
+        }
+    }
+
+    /* rescale the Metropolis step size by the ratio of the acceptance since the last tuning to
+     * TARGET_ACCEPTANCE, by at most a factor 2 either way and to at most pi, where the proposals
+     * cover the whole circle */
+    pub fn tune_step_size(&mut self) {
+        if self.algorithm != Algorithm::Metropolis || self.metropolis_stats.proposals == 0 {
+            return;
+        }
+        let ratio = self.metropolis_stats.acceptance_rate() / TARGET_ACCEPTANCE;
+        self.step_size = (self.step_size * ratio.clamp(0.5, 2.0)).min(PI);
+        self.metropolis_stats = MetropolisStats::default();
+    }
+
+    /* recompute the score threshold of targeted hits every `refresh` sweeps. Only for the burn in,
+     * like tune_step_size: the threshold depends on the links it selects, and the hits only leave
+     * the Boltzmann weight invariant while it is frozen */
+    pub fn refresh_targeting(&mut self) {
+        if let Some(targeting) = &mut self.targeting {
+            if self.sweeps.is_multiple_of(targeting.refresh) {
+                targeting.scores = Some(LinkScores::compute(&self.lattice, Lattice::staple_deficit, targeting.fraction));
+            }
+        }
+    }
+
+    pub fn measure_action(&mut self) -> f64 {
+        self.measurements += 1;
+        return self.lattice.average_action();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis;
+
+    /* average actions of `measurements` sweeps after a burn in, on 4^4 at beta 0.9 */
+    fn average_actions(coarse: Option<CoarseUpdate>, seed: u64, measurements: usize) -> (Vec<f64>, Option<CoarseUpdate>) {
+        let mut rng = Rng::with_seed(seed);
+        let lattice = Lattice::new_random(4, &mut rng);
+        let mut simulation = Simulation::new(lattice, Couplings::isotropic(0.9), rng);
+        simulation.coarse = coarse;
+        simulation.thermalize(50);
+        let series = (0..measurements)
+            .map(|_| {
+                simulation.sweep();
+                simulation.measure_action()
+            })
+            .collect();
+        return (series, simulation.coarse);
+    }
+
+    #[test]
+    fn coarse_updates_leave_the_equilibrium_unchanged() {
+        let (plain, _) = average_actions(None, 1, 1500);
+        let (coarse, stats) = average_actions(Some(CoarseUpdate::new(2, 0.1)), 2, 1500);
+
+        let stats = stats.unwrap().stats;
+        assert_eq!(stats.proposals, 1550);
+        assert!(stats.accepted > 0 && stats.accepted < stats.proposals, "{:?}", stats);
+
+        let error = |series: &[f64]| analysis::jackknife_error(series, 50).unwrap();
+        let difference = (analysis::mean(&plain) - analysis::mean(&coarse)).abs();
+        let combined = error(&plain).hypot(error(&coarse));
+        assert!(difference < 4.0 * combined, "difference {} with error {}", difference, combined);
+    }
+
+    #[test]
+    fn isotropic_couplings_reproduce_the_single_beta_chains() {
+        /* bits of the average action and the double action, and the checksum of the configuration,
+         * after five sweeps of the chains run with a single beta before the couplings were split */
+        let cases = [
+            ("heatbath", [4, 4, 4, 4], 41, 1.1, 0.0, [0x3fdc9c9a020112e4, 0x3feada59ac01e9ad, 0xbf1d888cf6dad2f5]),
+            ("double", [4, 2, 4, 2], 42, 0.9, 0.2, [0x3fd9d57875aa6bdb, 0x3fe816fb2ac9ba13, 0x60abaf5707dbb890]),
+            ("metropolis", [3, 3, 3, 3], 43, 1.0, 0.0, [0x3fe6b6df167e201c, 0x3fee0b69d98f9093, 0xcea47abad2c829f3]),
+            ("checkerboard", [4, 4, 4, 4], 44, 1.0, 0.0, [0x3fde46da69c4e5b4, 0x3fea13517924ac30, 0x0820d8b48706407f]),
+        ];
+        for (name, dims, seed, beta, gamma, golden) in cases {
+            let mut rng = Rng::with_seed(seed);
+            let mut lattice = Lattice::new_random_dims(dims, &mut rng);
+            lattice.set_checkerboard(name == "checkerboard");
+            let mut simulation = Simulation::new(lattice, Couplings { spatial: beta, temporal: beta }, rng);
+            simulation.gamma = gamma;
+            if name == "metropolis" {
+                simulation.algorithm = Algorithm::Metropolis;
+                simulation.step_size = 0.7;
+                simulation.overrelaxation = 1;
+            }
+            simulation.thermalize(5);
+            let bytes: Vec<u8> = simulation.lattice.to_array().iter().flat_map(|phase| phase.to_le_bytes()).collect();
+            let found = [
+                simulation.lattice.average_action().to_bits(),
+                simulation.lattice.average_double_action().to_bits(),
+                crate::portable::xxh64(&bytes, 0),
+            ];
+            assert_eq!(found, golden, "{}", name);
+        }
+    }
+
+    /* average actions on 6^4 at beta 1.0, with the Metropolis step tuned during the burn in */
+    fn beta_one_actions(algorithm: Algorithm, seed: u64) -> (Vec<f64>, Simulation) {
+        let mut rng = Rng::with_seed(seed);
+        let lattice = Lattice::new_random(6, &mut rng);
+        let mut simulation = Simulation::new(lattice, Couplings::isotropic(1.0), rng);
+        simulation.algorithm = algorithm;
+        for sweep in 1..=100 {
+            simulation.sweep();
+            if sweep % 10 == 0 {
+                simulation.tune_step_size();
+            }
+        }
+        let series = (0..400)
+            .map(|_| {
+                simulation.sweep();
+                simulation.measure_action()
+            })
+            .collect();
+        return (series, simulation);
+    }
+
+    #[test]
+    fn metropolis_and_heatbath_agree_at_beta_one() {
+        let (heatbath, _) = beta_one_actions(Algorithm::Heatbath, 3);
+        let (metropolis, simulation) = beta_one_actions(Algorithm::Metropolis, 4);
+        /* the acceptance of the tuned step since the last tuning */
+        let acceptance = simulation.metropolis_stats.acceptance_rate();
+        assert!((acceptance - TARGET_ACCEPTANCE).abs() < 0.1, "acceptance {} with step {}", acceptance, simulation.step_size);
+
+        let error = |series: &[f64]| analysis::jackknife_error(series, 25).unwrap();
+        let difference = (analysis::mean(&heatbath) - analysis::mean(&metropolis)).abs();
+        let combined = error(&heatbath).hypot(error(&metropolis));
+        assert!(difference < 4.0 * combined, "difference {} with error {}", difference, combined);
+    }
+
+    #[test]
+    fn overrelaxation_shortens_the_autocorrelation_near_the_transition() {
+        /* tau_int of the action on 4^4 at beta 1.01 averaged over two cold started chains, a single
+         * chain is dominated by the tunneling between the phases */
+        let tau = |overrelaxation: usize| {
+            let taus: Vec<f64> = [8, 9]
+                .into_iter()
+                .map(|seed| {
+                    let lattice = Lattice::new_uniform(4);
+                    let mut simulation = Simulation::new(lattice, Couplings::isotropic(1.01), Rng::with_seed(seed));
+                    simulation.overrelaxation = overrelaxation;
+                    simulation.thermalize(500);
+                    let series: Vec<f64> = (0..3000)
+                        .map(|_| {
+                            simulation.sweep();
+                            simulation.measure_action()
+                        })
+                        .collect();
+                    analysis::integrated_autocorrelation(&series).unwrap().0
+                })
+                .collect();
+            return analysis::mean(&taus);
+        };
+
+        let (plain, relaxed) = (tau(0), tau(3));
+        assert!(relaxed < 0.7 * plain, "tau_int {} with overrelaxation, {} without", relaxed, plain);
+    }
+
+    /* actions and monopole densities on 4^4 at beta 0.95 with and without targeted hits, the
+     * threshold refreshed during the burn in only */
+    fn targeting_series(targeting: Option<Targeting>, seed: u64, measurements: usize) -> (Vec<f64>, Vec<f64>, usize) {
+        let mut rng = Rng::with_seed(seed);
+        let lattice = Lattice::new_random(4, &mut rng);
+        let mut simulation = Simulation::new(lattice, Couplings::isotropic(0.95), rng);
+        simulation.targeting = targeting;
+        for _ in 0..100 {
+            simulation.sweep();
+            simulation.refresh_targeting();
+        }
+        let mut actions = Vec::with_capacity(measurements);
+        let mut monopoles = Vec::with_capacity(measurements);
+        for _ in 0..measurements {
+            simulation.sweep();
+            actions.push(simulation.measure_action());
+            monopoles.push(simulation.lattice.monopole_density());
+        }
+        return (actions, monopoles, simulation.sweeps);
+    }
+
+    #[test]
+    fn the_targeting_threshold_only_changes_at_a_refresh() {
+        let mut rng = Rng::with_seed(6);
+        let lattice = Lattice::new_random(3, &mut rng);
+        let mut simulation = Simulation::new(lattice, Couplings::isotropic(1.0), rng);
+        simulation.targeting = Some(Targeting { fraction: 0.25, hits: 1, refresh: 3, scores: None });
+        let threshold = |simulation: &Simulation| simulation.targeting.as_ref().unwrap().scores.as_ref().unwrap().threshold();
+
+        simulation.sweep();
+        let first = threshold(&simulation);
+        /* refreshes off the schedule and every sweep without one keep the threshold */
+        for _ in 0..2 {
+            simulation.refresh_targeting();
+            simulation.sweep();
+            assert_eq!(threshold(&simulation), first);
+        }
+        assert_eq!(simulation.sweeps, 3);
+        simulation.refresh_targeting();
+        let refreshed = LinkScores::compute(&simulation.lattice, Lattice::staple_deficit, 0.25).threshold();
+        assert_eq!(threshold(&simulation), refreshed);
+        simulation.sweep();
+        assert_eq!(threshold(&simulation), refreshed);
+        assert_ne!(refreshed, first);
+    }
+
+    #[test]
+    fn targeted_hits_keep_the_equilibrium_and_measure_tau_of_the_monopoles() {
+        let targeting = Targeting { fraction: 0.2, hits: 2, refresh: 10, scores: None };
+        let (plain_actions, plain_monopoles, _) = targeting_series(None, 1, 2000);
+        let (actions, monopoles, sweeps) = targeting_series(Some(targeting), 11, 2000);
+        assert_eq!(sweeps, 2100);
+
+        let error = |series: &[f64]| analysis::jackknife_error(series, 100).unwrap();
+        for (plain, targeted) in [(&plain_actions, &actions), (&plain_monopoles, &monopoles)] {
+            let difference = (analysis::mean(plain) - analysis::mean(targeted)).abs();
+            let combined = error(plain).hypot(error(targeted));
+            assert!(difference < 4.0 * combined, "difference {} with error {}", difference, combined);
+        }
+
+        /* here tau_int drops from about 19 sweeps to 3, more than the 40 % extra link updates cost.
+         * Only checked not to get worse, tau_int is noisy */
+        let (plain_tau, plain_tau_error) = analysis::integrated_autocorrelation(&plain_monopoles).unwrap();
+        let (tau, tau_error) = analysis::integrated_autocorrelation(&monopoles).unwrap();
+        assert!(
+            tau < plain_tau + 2.0 * tau_error.hypot(plain_tau_error),
+            "tau_int {} +- {} with targeted hits, {} +- {} without",
+            tau,
+            tau_error,
+            plain_tau,
+            plain_tau_error
+        );
+    }
+}
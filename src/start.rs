@@ -0,0 +1,477 @@
+use crate::lattice::{format_extents, Lattice};
+use crate::portable::read_configuration;
+use crate::rng::RngRegistry;
+use anyhow::{anyhow, bail, Context, Result};
+use std::f64::consts::PI;
+use std::fmt;
+
+/* the initial configuration of a run, resolved once from the start flags by StartFlags::resolve.
+ * The analytically known ones are parsed from
+ *   planewave:k=<k0>,<k1>,<k2>,<k3>;A=<amplitude>[;mu=<direction>]
+ *   constant:n01=<quanta>,n23=<quanta>,...
+ * where the constant field carries n_{mu nu} flux quanta of 2 pi through every (mu, nu) plane */
+#[derive(Clone, Debug, PartialEq)]
+pub enum StartSpec {
+    Ordered,
+    Random,
+    /* a configuration stored by export or --cache-config */
+    File(String),
+    /* the latest checkpoint of a segment of another run, the last segment if none is given,
+     * tiled periodically up to the extents of the run */
+    WarmStart {
+        path: String,
+        segment: Option<usize>,
+    },
+    PlaneWave {
+        momentum: [usize; 4],
+        amplitude: f64,
+        direction: usize,
+    },
+    Constant {
+        quanta: [[i64; 4]; 4],
+    },
+}
+
+impl StartSpec {
+    pub fn parse(spec: &str) -> Result<Self> {
+        let (kind, parameters) = spec.split_once(':').ok_or_else(|| {
+            anyhow!(
+                "start must look like planewave:... or constant:..., got {}",
+                spec
+            )
+        })?;
+
+        match kind {
+            "planewave" => {
+                let mut momentum = None;
+                let mut amplitude = None;
+                let mut direction = 0;
+
+                for parameter in parameters.split(';') {
+                    let (key, value) = split_parameter(parameter)?;
+                    match key {
+                        "k" => {
+                            let components = value
+                                .split(',')
+                                .map(|component| component.trim().parse::<usize>())
+                                .collect::<Result<Vec<_>, _>>()
+                                .with_context(|| format!("invalid momentum {}", value))?;
+                            if components.len() != 4 {
+                                bail!("momentum needs 4 components, got {}", value);
+                            }
+                            momentum =
+                                Some([components[0], components[1], components[2], components[3]]);
+                        }
+                        "A" => {
+                            amplitude = Some(
+                                value
+                                    .parse::<f64>()
+                                    .with_context(|| format!("invalid amplitude {}", value))?,
+                            );
+                        }
+                        "mu" => {
+                            direction = value
+                                .parse::<usize>()
+                                .with_context(|| format!("invalid direction {}", value))?;
+                            if direction > 3 {
+                                bail!("direction must be between 0 and 3, got {}", direction);
+                            }
+                        }
+                        _ => bail!("unknown plane wave parameter {}, expected k, A or mu", key),
+                    }
+                }
+
+                Ok(StartSpec::PlaneWave {
+                    momentum: momentum
+                        .ok_or_else(|| anyhow!("plane wave start needs a momentum k"))?,
+                    amplitude: amplitude
+                        .ok_or_else(|| anyhow!("plane wave start needs an amplitude A"))?,
+                    direction,
+                })
+            }
+            "constant" => {
+                let mut quanta = [[0i64; 4]; 4];
+
+                for parameter in parameters.split(',') {
+                    let (key, value) = split_parameter(parameter)?;
+                    let plane = key
+                        .strip_prefix('n')
+                        .map(|indices| {
+                            indices
+                                .chars()
+                                .filter_map(|c| c.to_digit(10))
+                                .collect::<Vec<_>>()
+                        })
+                        .filter(|indices| key.len() == 3 && indices.len() == 2)
+                        .ok_or_else(|| {
+                            anyhow!(
+                                "flux must be given as n<mu><nu>=<quanta>, got {}",
+                                parameter
+                            )
+                        })?;
+                    let (m, n) = (plane[0] as usize, plane[1] as usize);
+                    if m > 3 || n > 3 || m == n {
+                        bail!(
+                            "flux plane needs two different directions between 0 and 3, got {}",
+                            key
+                        );
+                    }
+                    let value = value
+                        .parse::<i64>()
+                        .with_context(|| format!("invalid flux quanta {}", value))?;
+                    quanta[m][n] = value;
+                    quanta[n][m] = -value;
+                }
+
+                Ok(StartSpec::Constant { quanta })
+            }
+            _ => bail!("unknown start {}, expected planewave or constant", kind),
+        }
+    }
+
+    /* the start configuration with the given extents, a random one draws the stream "start" from
+     * the registry, the others leave it untouched. The analytic starts are only defined on
+     * hypercubic lattices */
+    pub fn build(&self, dims: [usize; 4], registry: &mut RngRegistry) -> Result<Lattice> {
+        let width = dims[0];
+        if matches!(self, StartSpec::PlaneWave { .. } | StartSpec::Constant { .. })
+            && dims.iter().any(|&extent| extent != width)
+        {
+            bail!("the {} start needs the same extent in every direction, got {}", self, format_extents(dims));
+        }
+        match self {
+            StartSpec::Ordered => Ok(Lattice::new_uniform_dims(dims)),
+            StartSpec::Random => Ok(Lattice::new_random_dims(dims, &mut registry.stream("start"))),
+            StartSpec::WarmStart { path, .. } => {
+                bail!("the warm start from {} is read from its run file by the command line", path)
+            }
+            StartSpec::File(path) => {
+                let (lattice, _) = read_configuration(path)?;
+                if lattice.dims() != dims {
+                    bail!(
+                        "start configuration {} has {}, but the run has {}",
+                        path,
+                        format_extents(lattice.dims()),
+                        format_extents(dims)
+                    );
+                }
+                Ok(lattice)
+            }
+            StartSpec::PlaneWave {
+                momentum,
+                amplitude,
+                direction,
+            } => Ok(Lattice::plane_wave(
+                width, *momentum, *amplitude, *direction,
+            )),
+            StartSpec::Constant { quanta } => {
+                let field = quanta.map(|row| row.map(|quanta| flux_angle(quanta, width)));
+                Lattice::constant_field(width, field)
+            }
+        }
+    }
+
+    /* closed form average action per plaquette and topological charge of the start configuration,
+     * only known for the plane wave and the constant field */
+    pub fn reference_values(&self, width: usize) -> Option<(f64, f64)> {
+        match self {
+            StartSpec::Ordered | StartSpec::Random | StartSpec::File(_) | StartSpec::WarmStart { .. } => None,
+            StartSpec::PlaneWave {
+                momentum,
+                amplitude,
+                direction,
+            } => {
+                /* with phi = 2 pi k.n / width the plaquettes of the planes (direction, nu) have angle
+                 * -+2 A sin(pi k_nu / width) sin(phi + pi k_nu / width), the other planes are flat.
+                 * k.n takes the multiples of the gcd g of the momentum and the width equally often */
+                let step = momentum.iter().fold(width, |g, &k| gcd(g, k % width));
+                let residues = width / step;
+                let mut action = 0f64;
+                for nu in (0..4).filter(|nu| nu != direction) {
+                    let half_shift = PI * (momentum[nu] % width) as f64 / width as f64;
+                    let amplitude = 2.0 * amplitude * half_shift.sin();
+                    let mean_cos = (0..residues)
+                        .map(|r| (amplitude * (2.0 * PI * (r * step) as f64 / width as f64 + half_shift).sin()).cos())
+                        .sum::<f64>()
+                        / residues as f64;
+                    action += 1.0 - mean_cos;
+                }
+
+                /* every plaquette with a field strength contains direction, so no two of them span
+                 * the four directions */
+                Some((action / 6.0, 0.0))
+            }
+            StartSpec::Constant { quanta } => {
+                let mut action = 0f64;
+                for (m, row) in quanta.iter().enumerate() {
+                    for &quanta in row.iter().skip(m + 1) {
+                        action += 1.0 - flux_angle(quanta, width).cos();
+                    }
+                }
+                let charge = quanta[0][1] * quanta[2][3] - quanta[0][2] * quanta[1][3]
+                    + quanta[0][3] * quanta[1][2];
+
+                Some((action / 6.0, charge as f64))
+            }
+        }
+    }
+}
+
+/* the spec in the syntax of --start, and ordered, random, file:<path> or warm:<path>[:<segment>]
+ * for the others. Used as
+ * the provenance of a run, parse only reads the analytic forms back */
+impl fmt::Display for StartSpec {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StartSpec::Ordered => write!(f, "ordered"),
+            StartSpec::Random => write!(f, "random"),
+            StartSpec::File(path) => write!(f, "file:{}", path),
+            StartSpec::WarmStart { path, segment: None } => write!(f, "warm:{}", path),
+            StartSpec::WarmStart {
+                path,
+                segment: Some(segment),
+            } => write!(f, "warm:{}:{}", path, segment),
+            StartSpec::PlaneWave {
+                momentum,
+                amplitude,
+                direction,
+            } => write!(
+                f,
+                "planewave:k={},{},{},{};A={};mu={}",
+                momentum[0], momentum[1], momentum[2], momentum[3], amplitude, direction
+            ),
+            StartSpec::Constant { quanta } => {
+                let mut planes = Vec::new();
+                for (m, row) in quanta.iter().enumerate() {
+                    for (n, quanta) in row.iter().enumerate().skip(m + 1) {
+                        if *quanta != 0 {
+                            planes.push(format!("n{}{}={}", m, n, quanta));
+                        }
+                    }
+                }
+                if planes.is_empty() {
+                    planes.push("n01=0".to_string());
+                }
+                write!(f, "constant:{}", planes.join(","))
+            }
+        }
+    }
+}
+
+/* the start flags given on a command line. The stored configuration is named by a different flag
+ * depending on the subcommand, which is kept for the error messages */
+#[derive(Default)]
+pub struct StartFlags<'a> {
+    pub ordered: bool,
+    pub start: Option<&'a str>,
+    pub file: Option<(&'static str, &'a str)>,
+    pub warm_start: Option<&'a str>,
+}
+
+impl StartFlags<'_> {
+    /* the single start the flags ask for, random if none is given. Giving more than one is an
+     * error naming all of them rather than a silent preference */
+    pub fn resolve(&self) -> Result<StartSpec> {
+        let mut given = Vec::new();
+        if self.ordered {
+            given.push("--ordered".to_string());
+        }
+        if self.start.is_some() {
+            given.push("--start".to_string());
+        }
+        if let Some((flag, _)) = self.file {
+            given.push(format!("--{}", flag));
+        }
+        if self.warm_start.is_some() {
+            given.push("--warm-start-from".to_string());
+        }
+        if given.len() > 1 {
+            bail!("conflicting start options {}, give at most one of them", given.join(", "));
+        }
+
+        if self.ordered {
+            return Ok(StartSpec::Ordered);
+        }
+        if let Some(start) = self.start {
+            return StartSpec::parse(start);
+        }
+        if let Some((_, path)) = self.file {
+            return Ok(StartSpec::File(path.to_string()));
+        }
+        if let Some(source) = self.warm_start {
+            return parse_warm_start(source);
+        }
+        return Ok(StartSpec::Random);
+    }
+}
+
+/* file.h5[:segment], a trailing number after the last colon selects the segment */
+fn parse_warm_start(source: &str) -> Result<StartSpec> {
+    let (path, segment) = match source.rsplit_once(':') {
+        Some((path, segment)) if !segment.is_empty() && segment.chars().all(|c| c.is_ascii_digit()) => {
+            (path, Some(segment.parse::<usize>().with_context(|| format!("invalid segment {}", segment))?))
+        }
+        _ => (source, None),
+    };
+    if path.is_empty() {
+        bail!("warm start needs a run file, got {}", source);
+    }
+    return Ok(StartSpec::WarmStart {
+        path: path.to_string(),
+        segment,
+    });
+}
+
+/* replicate a configuration periodically up to the extents `dims`, which must be the same multiple
+ * of the extents of the source in every direction */
+pub fn tile_to(source: &Lattice, dims: [usize; 4]) -> Result<(Lattice, usize)> {
+    let source_dims = source.dims();
+    let factor = dims[0] / source_dims[0];
+    if factor == 0 || source_dims.iter().zip(dims).any(|(&extent, target)| extent * factor != target) {
+        bail!(
+            "a configuration with {} can not be tiled up to {}, every extent needs the same integer factor",
+            format_extents(source_dims),
+            format_extents(dims)
+        );
+    }
+    return Ok((Lattice::tile_from(source, factor)?, factor));
+}
+
+/* plaquette angle of n flux quanta spread evenly over a width x width plane */
+fn flux_angle(quanta: i64, width: usize) -> f64 {
+    return 2.0 * PI * quanta as f64 / (width * width) as f64;
+}
+
+fn gcd(a: usize, b: usize) -> usize {
+    return if b == 0 { a } else { gcd(b, a % b) };
+}
+
+fn split_parameter(parameter: &str) -> Result<(&str, &str)> {
+    let (key, value) = parameter.split_once('=').ok_or_else(|| {
+        anyhow!(
+            "start parameter must look like key=value, got {}",
+            parameter
+        )
+    })?;
+    return Ok((key.trim(), value.trim()));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fastrand::Rng;
+
+    #[test]
+    fn warm_start_names_a_file_and_optionally_a_segment() {
+        let resolve = |source| StartFlags { warm_start: Some(source), ..Default::default() }.resolve();
+        assert_eq!(
+            resolve("run.h5").unwrap(),
+            StartSpec::WarmStart { path: "run.h5".to_string(), segment: None }
+        );
+        assert_eq!(
+            resolve("runs/a:b.h5:2").unwrap(),
+            StartSpec::WarmStart { path: "runs/a:b.h5".to_string(), segment: Some(2) }
+        );
+        assert_eq!(resolve("run.h5:2").unwrap().to_string(), "warm:run.h5:2");
+        assert!(resolve(":1").is_err());
+
+        let error = StartFlags { ordered: true, warm_start: Some("run.h5"), ..Default::default() }
+            .resolve()
+            .unwrap_err();
+        assert!(error.to_string().contains("--warm-start-from"), "{}", error);
+    }
+
+    #[test]
+    fn every_combination_of_start_flags_resolves_or_names_its_clash() {
+        let constant = "constant:n01=1";
+        for mask in 0..16u32 {
+            let flags = StartFlags {
+                ordered: mask & 1 != 0,
+                start: (mask & 2 != 0).then_some(constant),
+                file: (mask & 4 != 0).then_some(("from-cache", "cache.u1l")),
+                warm_start: (mask & 8 != 0).then_some("run.h5:1"),
+            };
+            let resolved = flags.resolve();
+
+            match mask {
+                0 => assert_eq!(resolved.unwrap(), StartSpec::Random),
+                1 => assert_eq!(resolved.unwrap(), StartSpec::Ordered),
+                2 => assert_eq!(resolved.unwrap(), StartSpec::parse(constant).unwrap()),
+                4 => assert_eq!(resolved.unwrap(), StartSpec::File("cache.u1l".to_string())),
+                8 => assert_eq!(
+                    resolved.unwrap(),
+                    StartSpec::WarmStart { path: "run.h5".to_string(), segment: Some(1) }
+                ),
+                _ => {
+                    let message = resolved.unwrap_err().to_string();
+                    for (bit, flag) in [
+                        (1, "--ordered"),
+                        (2, "--start"),
+                        (4, "--from-cache"),
+                        (8, "--warm-start-from"),
+                    ] {
+                        assert_eq!(message.contains(flag), mask & bit != 0, "{:04b}: {}", mask, message);
+                    }
+                }
+            }
+        }
+
+        /* a clash is reported before the values are parsed */
+        let error = StartFlags { ordered: true, start: Some("planewave:"), ..Default::default() }
+            .resolve()
+            .unwrap_err();
+        assert!(error.to_string().contains("conflicting"), "{}", error);
+        let error = StartFlags { start: Some("planewave:"), ..Default::default() }.resolve().unwrap_err();
+        assert!(!error.to_string().contains("conflicting"), "{}", error);
+    }
+
+    #[test]
+    fn resolved_starts_round_trip_through_their_provenance() {
+        for spec in [
+            "planewave:k=1,0,2,0;A=0.5;mu=3",
+            "constant:n01=1,n23=-2",
+        ] {
+            let resolved = StartFlags { start: Some(spec), ..Default::default() }.resolve().unwrap();
+            assert_eq!(StartSpec::parse(&resolved.to_string()).unwrap(), resolved);
+        }
+        assert_eq!(StartSpec::Ordered.to_string(), "ordered");
+        assert_eq!(StartSpec::Random.to_string(), "random");
+        assert_eq!(StartSpec::File("cache.u1l".to_string()).to_string(), "file:cache.u1l");
+    }
+
+    #[test]
+    fn the_reference_values_are_those_of_the_built_lattice() {
+        for spec in [
+            "planewave:k=1,0,0,0;A=0.3",
+            "planewave:k=1,0,2,0;A=0.5;mu=3",
+            "planewave:k=2,2,0,4;A=1.2;mu=1",
+            "planewave:k=0,3,1,1;A=2.9;mu=2",
+            "planewave:k=0,0,0,0;A=3.0",
+            "constant:n01=1",
+            "constant:n01=1,n23=-2",
+            "constant:n02=1,n13=1,n03=2,n12=3",
+        ] {
+            let start = StartSpec::parse(spec).unwrap();
+            for width in [4, 5, 6] {
+                let lattice = start.build([width; 4], &mut RngRegistry::new(1)).unwrap();
+                let (action, charge) = start.reference_values(width).unwrap();
+                assert!((lattice.average_action() - action).abs() < 1e-12, "{} on {}^4: {} against {}", spec, width, lattice.average_action(), action);
+                assert!((lattice.topological_charge() - charge).abs() < 1e-9, "{} on {}^4: {} against {}", spec, width, lattice.topological_charge(), charge);
+            }
+        }
+        for start in [StartSpec::Ordered, StartSpec::Random, StartSpec::File("cache.u1l".to_string())] {
+            assert_eq!(start.reference_values(4), None);
+        }
+    }
+
+    #[test]
+    fn tiling_needs_the_same_factor_in_every_direction() {
+        let smaller = Lattice::new_random_dims([2, 2, 2, 1], &mut Rng::with_seed(2));
+        let (tiled, factor) = tile_to(&smaller, [4, 4, 4, 2]).unwrap();
+        assert_eq!((tiled.dims(), factor), ([4, 4, 4, 2], 2));
+        assert_eq!(tile_to(&smaller, [2, 2, 2, 1]).unwrap().1, 1);
+        assert!(tile_to(&smaller, [4, 4, 4, 4]).is_err());
+        assert!(tile_to(&smaller, [3, 3, 3, 1]).is_err());
+        assert!(tile_to(&smaller, [1, 1, 1, 1]).is_err());
+    }
+}
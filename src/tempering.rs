@@ -0,0 +1,64 @@
+use fastrand::Rng;
+
+/* probability to exchange the configurations of the couplings beta_i and beta_j, with total actions
+ * action_i and action_j. The weight exp(-beta S) of both configurations changes by
+ * exp((beta_i - beta_j)(S_i - S_j)), which is at least 1 when the configuration with the larger
+ * action is at the larger coupling */
+pub fn swap_probability(beta_i: f64, beta_j: f64, action_i: f64, action_j: f64) -> f64 {
+    return ((beta_i - beta_j) * (action_i - action_j)).exp().min(1.0);
+}
+
+/* which replica holds the configuration at every coupling of a parallel tempering run. An exchange
+ * swaps the labels of two replicas, their lattices stay where they are */
+pub struct Ladder {
+    /* couplings in ascending order, pair k is betas[k] and betas[k + 1] */
+    pub betas: Vec<f64>,
+    /* replica at every coupling, and the coupling of every replica */
+    pub replica_at: Vec<usize>,
+    pub position_of: Vec<usize>,
+    /* exchanges proposed and accepted per pair */
+    pub proposals: Vec<usize>,
+    pub acceptances: Vec<usize>,
+    rounds: usize,
+}
+
+impl Ladder {
+    pub fn new(betas: Vec<f64>) -> Self {
+        let count = betas.len();
+        let pairs = count.saturating_sub(1);
+        return Self {
+            betas,
+            replica_at: (0..count).collect(),
+            position_of: (0..count).collect(),
+            proposals: vec![0; pairs],
+            acceptances: vec![0; pairs],
+            rounds: 0,
+        };
+    }
+
+    /* one round of exchanges, of the pairs starting at even couplings and of those starting at odd
+     * couplings in turn, so that no replica takes part in two proposals of a round. `actions` holds
+     * the total action of every replica. Returns the pairs whose replicas were exchanged */
+    pub fn propose_swaps(&mut self, actions: &[f64], rng: &mut Rng) -> Vec<usize> {
+        let mut swapped = Vec::new();
+        for pair in (self.rounds % 2..self.proposals.len()).step_by(2) {
+            let (lower, upper) = (self.replica_at[pair], self.replica_at[pair + 1]);
+            let probability = swap_probability(self.betas[pair], self.betas[pair + 1], actions[lower], actions[upper]);
+            self.proposals[pair] += 1;
+            if rng.f64() < probability {
+                self.acceptances[pair] += 1;
+                self.replica_at.swap(pair, pair + 1);
+                self.position_of[lower] = pair + 1;
+                self.position_of[upper] = pair;
+                swapped.push(pair);
+            }
+        }
+        self.rounds += 1;
+        return swapped;
+    }
+
+    /* fraction of the proposals of a pair that were accepted, None before the first proposal */
+    pub fn acceptance_rate(&self, pair: usize) -> Option<f64> {
+        return (self.proposals[pair] > 0).then(|| self.acceptances[pair] as f64 / self.proposals[pair] as f64);
+    }
+}